@@ -0,0 +1,118 @@
+//! An allocator that counts what it hands out, with the `alloc-stats`
+//! feature, so the REPL's `:time` can say what a line allocated. It only
+//! counts once a program installs it, as the `lisp` command does:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOC: oolisp::alloc::Tracing = oolisp::alloc::Tracing;
+//! ```
+//!
+//! Counts are kept per thread, so work on other threads doesn't throw off
+//! what the current one is measuring.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    // bytes this thread holds, which goes negative when it frees what
+    // another thread allocated, the most it has held since the last reset,
+    // and what it held at the reset
+    static LIVE: Cell<isize> = const { Cell::new(0) };
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+    static BASELINE: Cell<isize> = const { Cell::new(0) };
+}
+
+/// The system allocator, counting as it goes.
+pub struct Tracing;
+
+unsafe impl GlobalAlloc for Tracing {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        allocated(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        allocated(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        freed(layout.size());
+        System.dealloc(ptr, layout)
+    }
+
+    // counts as freeing the old block and allocating the new one
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        freed(layout.size());
+        allocated(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+fn allocated(size: usize) {
+    ALLOCATIONS.with(|count| count.set(count.get() + 1));
+    ALLOCATED.with(|bytes| bytes.set(bytes.get() + size));
+    let live = LIVE.with(|live| {
+        live.set(live.get() + size as isize);
+        live.get()
+    });
+    PEAK.with(|peak| peak.set(peak.get().max(live)));
+}
+
+fn freed(size: usize) {
+    LIVE.with(|live| live.set(live.get() - size as isize));
+}
+
+/// Allocations this thread has made since the last `reset`.
+pub fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+/// Bytes this thread has allocated since the last `reset`, whether or not
+/// they have been freed since.
+pub fn bytes_allocated() -> usize {
+    ALLOCATED.with(Cell::get)
+}
+
+/// The most bytes this thread has held at once since the last `reset`,
+/// beyond what it held at it.
+pub fn peak_bytes() -> usize {
+    (PEAK.with(Cell::get) - BASELINE.with(Cell::get)).max(0) as usize
+}
+
+/// Starts this thread's counts over from now.
+pub fn reset() {
+    ALLOCATIONS.with(|count| count.set(0));
+    ALLOCATED.with(|bytes| bytes.set(0));
+    let live = LIVE.with(Cell::get);
+    BASELINE.with(|baseline| baseline.set(live));
+    PEAK.with(|peak| peak.set(live));
+}
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOC: Tracing = Tracing;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_counts_allocations() {
+        reset();
+        let kept = vec![0_u8; 1 << 20];
+        let grown = vec![0_u8; 1 << 10].repeat(2);
+        assert_eq!(allocations(), 3);
+        assert_eq!(bytes_allocated(), (1 << 20) + (3 << 10));
+        drop(kept);
+        assert_eq!(peak_bytes(), (1 << 20) + (3 << 10));
+
+        reset();
+        assert_eq!((allocations(), bytes_allocated(), peak_bytes()), (0, 0, 0));
+        drop(grown);
+        assert_eq!(peak_bytes(), 0);
+    }
+}
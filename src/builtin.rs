@@ -1,89 +1,376 @@
 use crate::{
-    add_builtin, eval, to_num, to_qexpr, to_str, to_sym, Lenv, Lerr, LerrType, Llambda, Lval,
+    add_builtin, add_pure_builtin, add_value, cmp_numbers, diff,
+    env::{Frames, Lookup, ScopeGuard},
+    eval, features, not_a_seq, optimize, seq_build, seq_holds, seq_iter, seq_kind, seq_len,
+    to_bytes, to_num, to_qexpr, to_str, to_sym, truth, version, Arity, Contract, Lenv, Lerr,
+    LerrType, Lfun, Llambda, Lval, SeqKind,
 };
+use std::{borrow::Cow, cmp::Ordering, ptr};
 
+/// Registers every builtin. Embedders wanting a smaller language can call
+/// the `register_*` groups they need instead.
 pub fn init_builtins(env: &mut Lenv) {
-    add_builtin(env, "!", builtin_not);
-    add_builtin(env, "+", builtin_add);
-    add_builtin(env, "-", builtin_sub);
-    add_builtin(env, "*", builtin_mul);
-    add_builtin(env, "/", builtin_div);
-    add_builtin(env, "%", builtin_mod);
-
-    add_builtin(env, "head", builtin_head);
-    add_builtin(env, "tail", builtin_tail);
-    add_builtin(env, "list", builtin_list);
-    add_builtin(env, "eval", builtin_eval);
-    add_builtin(env, "join", builtin_join);
-    add_builtin(env, "concat", builtin_concat);
-
-    add_builtin(env, "\\", builtin_lambda);
-    add_builtin(env, "def", builtin_def);
-    add_builtin(env, "=", builtin_var);
-
-    add_builtin(env, "if", builtin_if);
-
-    add_builtin(env, "die", builtin_err);
-
-    add_builtin(env, "<", builtin_lt);
-    add_builtin(env, ">", builtin_gt);
-    add_builtin(env, ">=", builtin_gte);
-    add_builtin(env, "<=", builtin_lte);
-    add_builtin(env, "==", builtin_eq);
-    add_builtin(env, "!=", builtin_ne);
-    add_builtin(env, "&&", builtin_and);
-    add_builtin(env, "||", builtin_or);
-}
-
-fn builtin_op(sym: &str, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    // cast everything into a number
-    let numbers = operands
-        .into_iter()
-        .map(to_num)
-        .collect::<Option<Vec<f64>>>()
-        .ok_or(Lerr::new(
-            LerrType::BadNum,
-            format!("Function {} can operate only on numbers", sym),
-        ))?;
+    register_arithmetic(env);
+    register_lists(env);
+    register_meta(env);
+    register_strings(env);
+    register_bytes(env);
+    register_random(env);
+    register_definitions(env);
+    register_control(env);
+    register_comparison(env);
+}
+
+pub fn register_arithmetic(env: &mut Lenv) {
+    add_pure_builtin(env, "!", builtin_not, (1, None));
+    add_pure_builtin(env, "+", builtin_add, (1, None));
+    add_pure_builtin(env, "-", builtin_sub, (1, None));
+    add_pure_builtin(env, "*", builtin_mul, (1, None));
+    add_pure_builtin(env, "/", builtin_div, (1, None));
+    add_pure_builtin(env, "%", builtin_mod, (1, None));
+    add_pure_builtin(env, "div-mod", builtin_div_mod, (2, Some(2)));
+    add_pure_builtin(env, "mod", builtin_mod_floor, (2, Some(2)));
+    add_pure_builtin(env, "pow", builtin_pow, (2, Some(2)));
+    add_pure_builtin(env, "min", builtin_min, (1, None));
+    add_pure_builtin(env, "max", builtin_max, (1, None));
+    add_pure_builtin(env, "abs", builtin_abs, (1, Some(1)));
+    add_pure_builtin(env, "floor", builtin_floor, (1, Some(1)));
+    add_pure_builtin(env, "ceil", builtin_ceil, (1, Some(1)));
+    add_pure_builtin(env, "round", builtin_round, (1, Some(1)));
+    add_pure_builtin(env, "sqrt", builtin_sqrt, (1, Some(1)));
+    add_pure_builtin(env, "exp", builtin_exp, (1, Some(1)));
+    add_pure_builtin(env, "log", builtin_log, (1, Some(1)));
+    add_pure_builtin(env, "nan?", builtin_is_nan, (1, Some(1)));
+    add_pure_builtin(env, "finite?", builtin_is_finite, (1, Some(1)));
+}
+
+pub fn register_lists(env: &mut Lenv) {
+    add_pure_builtin(env, "head", builtin_head, (1, Some(1)));
+    add_pure_builtin(env, "tail", builtin_tail, (1, Some(1)));
+    add_pure_builtin(env, "len", builtin_len, (1, Some(1)));
+    add_pure_builtin(env, "nth", builtin_nth, (2, Some(2)));
+    add_pure_builtin(env, "take", builtin_take, (2, Some(2)));
+    add_pure_builtin(env, "drop", builtin_drop, (2, Some(2)));
+    add_pure_builtin(env, "member?", builtin_is_member, (2, Some(2)));
+    add_pure_builtin(env, "last", builtin_last, (1, Some(1)));
+    add_pure_builtin(env, "list", builtin_list, (0, None));
+    add_pure_builtin(env, "range", builtin_range, (1, Some(3)));
+    add_pure_builtin(env, "repeat", builtin_repeat, (2, Some(2)));
+    add_pure_builtin(env, "values", builtin_values, (0, None));
+    add_pure_builtin(env, "join", builtin_join, (2, None));
+    add_pure_builtin(env, "sort-any", builtin_sort_any, (1, Some(1)));
+    add_pure_builtin(env, "reverse", builtin_reverse, (1, Some(1)));
+    add_pure_builtin(env, "sort", builtin_sort, (1, Some(2)));
+    add_pure_builtin(env, "unique", builtin_unique, (1, Some(1)));
+    add_pure_builtin(env, "argmax", builtin_argmax, (1, Some(1)));
+    add_pure_builtin(env, "argmin", builtin_argmin, (1, Some(1)));
+    add_pure_builtin(env, "argmax-by", builtin_argmax_by, (2, Some(2)));
+    add_pure_builtin(env, "argmin-by", builtin_argmin_by, (2, Some(2)));
+    add_pure_builtin(env, "partition-by", builtin_partition_by, (2, Some(2)));
+    add_pure_builtin(env, "split-when", builtin_split_when, (2, Some(2)));
+    add_pure_builtin(env, "map", builtin_map, (2, Some(2)));
+    add_pure_builtin(env, "filter", builtin_filter, (2, Some(2)));
+    add_pure_builtin(env, "fold", builtin_fold, (3, Some(3)));
+    add_builtin(env, "for-each", builtin_for_each, (2, Some(2)));
+}
+
+/// `eval` plus the build introspection values.
+pub fn register_meta(env: &mut Lenv) {
+    add_builtin(env, "eval", builtin_eval, (1, Some(1)));
+    add_builtin(env, "with-env", builtin_with_env, (2, Some(2)));
+    add_builtin(env, "with-env-only", builtin_with_env_only, (2, Some(2)));
+    add_builtin(env, "explain", builtin_explain, (1, Some(1)));
+    add_builtin(env, "time-limit", builtin_time_limit, (2, Some(2)));
+    add_builtin(env, "bench", builtin_bench, (2, Some(2)));
+
+    // plain values rather than functions so `(version)` evaluates to them
+    add_value(env, "version", Lval::Str(version()));
+    add_value(
+        env,
+        "features",
+        Lval::Qexpr(features().into_iter().map(Lval::Str).collect()),
+    );
+    add_thunk(env, "env-size", builtin_env_size);
+    add_thunk(env, "stats", builtin_stats);
+    add_thunk(env, "stats-reset", builtin_stats_reset);
+    add_builtin(env, "host", builtin_host, (1, None));
+    add_builtin(env, "print", builtin_print, (0, None));
+    add_builtin(env, "println", builtin_println, (0, None));
+    add_pure_builtin(env, "fn-args", builtin_fn_args, (1, Some(1)));
+    add_pure_builtin(env, "fn-body", builtin_fn_body, (1, Some(1)));
+    add_pure_builtin(env, "fn-arity", builtin_fn_arity, (1, Some(1)));
+    add_pure_builtin(env, "type", builtin_type, (1, Some(1)));
+    add_pure_builtin(env, "number?", builtin_is_number, (1, Some(1)));
+    add_pure_builtin(env, "string?", builtin_is_string, (1, Some(1)));
+    add_pure_builtin(env, "symbol?", builtin_is_symbol, (1, Some(1)));
+    add_pure_builtin(env, "list?", builtin_is_list, (1, Some(1)));
+    add_pure_builtin(env, "fun?", builtin_is_fun, (1, Some(1)));
+    add_builtin(env, "fn-contracts", builtin_fn_contracts, (1, Some(1)));
+    add_builtin(env, "pure?", builtin_is_pure, (1, Some(1)));
+    add_builtin(env, "source-of", builtin_source_of, (1, Some(1)));
+
+    #[cfg(all(feature = "parser", not(target_arch = "wasm32")))]
+    add_builtin(env, "load", builtin_load, (1, Some(1)));
+    #[cfg(feature = "cache")]
+    add_builtin(env, "cache-clear", builtin_cache_clear, (0, None));
+}
+
+pub fn register_strings(env: &mut Lenv) {
+    add_pure_builtin(env, "concat", builtin_concat, (1, None));
+    add_pure_builtin(env, "pad-left", builtin_pad_left, (2, Some(3)));
+    add_pure_builtin(env, "pad-right", builtin_pad_right, (2, Some(3)));
+    add_pure_builtin(env, "center", builtin_center, (2, Some(3)));
+    add_pure_builtin(env, "string-repeat", builtin_string_repeat, (2, Some(2)));
+    add_pure_builtin(env, "table", builtin_table, (1, Some(2)));
+    add_pure_builtin(env, "str-len", builtin_str_len, (1, Some(1)));
+    add_pure_builtin(env, "str-concat", builtin_str_concat, (0, None));
+    add_pure_builtin(env, "str-slice", builtin_str_slice, (3, Some(3)));
+    add_pure_builtin(env, "str-upper", builtin_str_upper, (1, Some(1)));
+    add_pure_builtin(env, "str-lower", builtin_str_lower, (1, Some(1)));
+    add_pure_builtin(env, "str-split", builtin_str_split, (2, Some(2)));
+    add_pure_builtin(env, "str-contains", builtin_str_contains, (2, Some(2)));
+}
+
+/// Byte vectors and their text encodings.
+pub fn register_bytes(env: &mut Lenv) {
+    add_pure_builtin(env, "bytes", builtin_bytes, (1, Some(1)));
+    add_pure_builtin(env, "str->bytes", builtin_str_to_bytes, (1, Some(1)));
+    add_pure_builtin(env, "bytes->str", builtin_bytes_to_str, (1, Some(1)));
+    add_pure_builtin(
+        env,
+        "bytes->str-lossy",
+        builtin_bytes_to_str_lossy,
+        (1, Some(1)),
+    );
+    add_pure_builtin(env, "bytes-len", builtin_bytes_len, (1, Some(1)));
+    add_pure_builtin(env, "bytes-slice", builtin_bytes_slice, (3, Some(3)));
+    add_pure_builtin(env, "hex-encode", builtin_hex_encode, (1, Some(1)));
+    add_pure_builtin(env, "hex-decode", builtin_hex_decode, (1, Some(1)));
+    add_pure_builtin(env, "base64-encode", builtin_base64_encode, (1, Some(1)));
+    add_pure_builtin(env, "base64-decode", builtin_base64_decode, (1, Some(1)));
+}
+
+/// Randomness drawn from the environment's seedable generator.
+pub fn register_random(env: &mut Lenv) {
+    add_thunk(env, "random", builtin_random);
+    add_builtin(env, "seed", builtin_seed, (1, Some(1)));
+    add_builtin(env, "shuffle", builtin_shuffle, (1, Some(1)));
+    add_builtin(env, "sample", builtin_sample, (2, Some(2)));
+    add_builtin(env, "choice", builtin_choice, (1, Some(1)));
+}
+
+/// Lambdas and everything that binds names.
+pub fn register_definitions(env: &mut Lenv) {
+    add_builtin(env, "\\", builtin_lambda, (2, Some(2)));
+    add_builtin(env, "letfn", builtin_letfn, (2, Some(2)));
+    add_builtin(env, "def", builtin_def, (2, None));
+    add_builtin(env, "def!", builtin_def_override, (2, None));
+    add_builtin(env, "fun", builtin_fun, (2, Some(2)));
+    add_builtin(env, "case-lambda", builtin_case_lambda, (1, None));
+    add_builtin(env, "undef", builtin_undef, (0, None));
+    add_builtin(env, "undef!", builtin_undef_override, (0, None));
+    add_builtin(env, "=", builtin_var, (2, None));
+    add_builtin(env, "let", builtin_let, (2, Some(2)));
+    add_builtin(env, "def-checked", builtin_def_checked, (2, Some(2)));
+    add_builtin(env, "fun-checked", builtin_fun_checked, (2, Some(2)));
+}
+
+pub fn register_control(env: &mut Lenv) {
+    add_pure_builtin(env, "if", builtin_if, (3, Some(3)));
+    add_pure_builtin(env, "and", builtin_and_then, (0, None));
+    add_pure_builtin(env, "or", builtin_or_else, (0, None));
+    add_pure_builtin(env, "not", builtin_falsy, (1, Some(1)));
+    add_pure_builtin(env, "do", builtin_do, (0, None));
+    add_builtin(env, "die", builtin_err, (1, Some(1)));
+    add_builtin(env, "error", builtin_error, (1, Some(1)));
+    add_builtin(env, "assert", builtin_assert, (2, Some(2)));
+
+    #[cfg(all(feature = "parser", not(target_arch = "wasm32")))]
+    add_thunk(env, "repl", builtin_repl);
+}
+
+// binds `fun` behind a zero argument lambda, so `(sym)` calls it rather
+// than evaluating to the function itself
+fn add_thunk(env: &mut Lenv, sym: &str, fun: crate::Lfun) {
+    add_value(
+        env,
+        sym,
+        Lval::Lambda(Llambda::new(
+            vec![],
+            vec![Lval::Fun(fun), Lval::Sexpr(vec![])],
+            Frames::caller(),
+        )),
+    );
+}
+
+pub fn register_comparison(env: &mut Lenv) {
+    add_pure_builtin(env, "<", builtin_lt, (2, Some(2)));
+    add_pure_builtin(env, ">", builtin_gt, (2, Some(2)));
+    add_pure_builtin(env, ">=", builtin_gte, (2, Some(2)));
+    add_pure_builtin(env, "<=", builtin_lte, (2, Some(2)));
+    add_pure_builtin(env, "==", builtin_eq, (2, Some(2)));
+    add_pure_builtin(env, "!=", builtin_ne, (2, Some(2)));
+    add_pure_builtin(env, "identical?", builtin_identical, (2, Some(2)));
+    add_pure_builtin(env, "approx=", builtin_approx_eq, (2, None));
+    add_pure_builtin(env, "diff", builtin_diff, (2, Some(2)));
+    add_pure_builtin(env, "&&", builtin_and, (2, Some(2)));
+    add_pure_builtin(env, "||", builtin_or, (2, Some(2)));
+}
+
+fn builtin_op(env: &mut Lenv, sym: &str, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let numbers = number_operands(sym, operands)?;
 
     // handle unary functions
     if numbers.len() == 1 {
-        if "-" == sym {
-            return Ok(Lval::Num(-numbers[0]));
-        } else if "!" == sym {
-            let n = if numbers[0] == 0_f64 { 1_f64 } else { 0_f64 };
-            return Ok(Lval::Num(n));
-        } else {
-            return Ok(Lval::Num(numbers[0]));
-        }
+        return Ok(match (sym, numbers[0]) {
+            ("-", Number::Int(n)) => n.checked_neg().map_or(Lval::Num(-(n as f64)), Lval::Int),
+            ("-", Number::Float(n)) => Lval::Num(-n),
+            ("!", n) => truth(n.float() == 0_f64),
+            (_, n) => n.lval(),
+        });
     }
 
     let mut x = numbers[0];
     let mut i = 1;
 
-    // apply the symbol over each operand
+    // apply the symbol over each operand, whole numbers staying whole
+    // while they can
     while i < numbers.len() {
         let y = numbers[i];
-        match sym {
-            "-" => x -= y,
-            "*" => x *= y,
-            "%" => x %= y,
-            "/" => {
-                if y == 0_f64 {
-                    return Err(Lerr::new(
-                        LerrType::DivZero,
-                        format!("You cannot divide {}, or any number, by 0", x),
-                    ));
-                } else {
-                    x /= y;
-                }
+        if sym == "/" && y.float() == 0_f64 {
+            return Err(Lerr::new(
+                LerrType::DivZero,
+                format!("You cannot divide {}, or any number, by 0", x.lval()),
+            ));
+        }
+
+        if let (Number::Int(a), Number::Int(b)) = (x, y) {
+            let whole = match sym {
+                "-" => a.checked_sub(b),
+                "*" => a.checked_mul(b),
+                "%" => a.checked_rem(b),
+                "/" => a.checked_rem(b).filter(|r| *r == 0).and(a.checked_div(b)),
+                _ => a.checked_add(b),
+            };
+            if let Some(n) = whole {
+                x = Number::Int(n);
+                i += 1;
+                continue;
+            }
+        }
+
+        let (before, y) = (x.float(), y.float());
+        let result = match sym {
+            "-" => before - y,
+            "*" => before * y,
+            "%" => before % y,
+            "/" => before / y,
+            _ => before + y,
+        };
+        if let Some(problem) = fold_problem(sym, i + 1, before, y, result) {
+            if env.strict_arithmetic() {
+                return Err(Lerr::new(LerrType::BadNum, problem));
             }
-            _ => x += y,
+            env.warn(problem);
         }
+        x = Number::Float(result);
         i += 1;
     }
 
-    Ok(Lval::Num(x))
+    Ok(x.lval())
+}
+
+// the operands as numbers, of which there has to be at least one
+fn number_operands(sym: &str, operands: Vec<Lval>) -> Result<Vec<Number>, Lerr> {
+    // cast everything into a number
+    let numbers = operands
+        .into_iter()
+        .map(Number::from_lval)
+        .collect::<Option<Vec<Number>>>()
+        .ok_or(Lerr::new(
+            LerrType::BadNum,
+            format!("Function {} can operate only on numbers", sym),
+        ))?;
+
+    if numbers.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function {} needed at least 1 number but was given none",
+                sym
+            ),
+        ));
+    }
+    Ok(numbers)
+}
+
+// a number as arithmetic sees it
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn from_lval(lval: Lval) -> Option<Number> {
+        match lval {
+            Lval::Int(n) => Some(Number::Int(n)),
+            Lval::Num(n) => Some(Number::Float(n)),
+            _ => None,
+        }
+    }
+
+    fn float(self) -> f64 {
+        match self {
+            Number::Int(n) => n as f64,
+            Number::Float(n) => n,
+        }
+    }
+
+    fn lval(self) -> Lval {
+        match self {
+            Number::Int(n) => Lval::Int(n),
+            Number::Float(n) => Lval::Num(n),
+        }
+    }
+}
+
+/// What went wrong at one step of an arithmetic fold, if anything: a
+/// finite `x` and `y` overflowing to infinity or to NaN, or a product or
+/// quotient of nonzero numbers underflowing to 0. Step n is the one that
+/// applied the nth operand.
+pub fn fold_problem(sym: &str, step: usize, x: f64, y: f64, result: f64) -> Option<String> {
+    let problem = if !x.is_finite() || !y.is_finite() {
+        return None;
+    } else if result.is_infinite() {
+        "overflowed"
+    } else if result.is_nan() {
+        "is not a number"
+    } else if result == 0.0 && x != 0.0 && y != 0.0 && (sym == "*" || sym == "/") {
+        "underflowed to 0"
+    } else {
+        return None;
+    };
+
+    Some(format!(
+        "step {} of '{}': {} {} {} {}",
+        step,
+        sym,
+        short_num(x),
+        sym,
+        short_num(y),
+        problem
+    ))
+}
+
+// very big and very small numbers in exponent form, which is how anyone
+// running into overflow will have written them
+fn short_num(n: f64) -> String {
+    if n != 0.0 && !(1e-6..1e16).contains(&n.abs()) {
+        format!("{:e}", n)
+    } else {
+        format!("{}", n)
+    }
 }
 
 fn builtin_ord(sym: &str, operands: Vec<Lval>) -> Result<Lval, Lerr> {
@@ -99,38 +386,47 @@ fn builtin_ord(sym: &str, operands: Vec<Lval>) -> Result<Lval, Lerr> {
         ));
     }
 
-    // cast everything into a number
-    let numbers = operands
-        .into_iter()
-        .map(to_num)
-        .collect::<Option<Vec<f64>>>()
-        .ok_or(Lerr::new(
-            LerrType::BadNum,
-            format!("Function {} can operate only on numbers", sym),
-        ))?;
+    // name whatever isn't a number, or a boolean for `&&` and `||`
+    let logical = matches!(sym, "&&" | "||");
+    if let Some(other) = operands.iter().find(|operand| match operand {
+        Lval::Num(_) | Lval::Int(_) => false,
+        Lval::Bool(_) => !logical,
+        _ => true,
+    }) {
+        let needed = if logical {
+            "a number or boolean"
+        } else {
+            "a number"
+        };
+        return Err(Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed {} but was given {}",
+                sym,
+                needed,
+                other.summary()
+            ),
+        ));
+    }
 
-    let x = numbers[0];
-    let y = numbers[1];
+    // whole numbers and floats compared exactly, NaN unordered
+    let ordering = cmp_numbers(&operands[0], &operands[1]);
 
     // these are for booleans
-    let a = if x == 0_f64 { false } else { true };
-    let b = if y == 0_f64 { false } else { true };
+    let a = operands[0].is_truthy();
+    let b = operands[1].is_truthy();
 
     let r = match sym {
-        ">" => x > y,
-        "<" => x < y,
-        ">=" => x >= y,
-        "<=" => x <= y,
+        ">" => ordering == Some(Ordering::Greater),
+        "<" => ordering == Some(Ordering::Less),
+        ">=" => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        "<=" => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
         "&&" => a && b,
         "||" => a || b,
         _ => false,
     };
 
-    if r {
-        Ok(Lval::Num(1_f64))
-    } else {
-        Ok(Lval::Num(0_f64))
-    }
+    Ok(truth(r))
 }
 
 fn builtin_eq(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
@@ -142,11 +438,179 @@ fn builtin_eq(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
         ));
     }
 
-    if operands[0] == operands[1] {
-        Ok(Lval::Num(1_f64))
-    } else {
-        Ok(Lval::Num(0_f64))
+    Ok(truth(operands[0] == operands[1]))
+}
+
+// (identical? a b) is `==` except that NaN is identical to NaN
+fn builtin_identical(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("identical?", &operands, 2)?;
+    let same = identical(&operands[0], &operands[1]);
+    Ok(truth(same))
+}
+
+fn identical(a: &Lval, b: &Lval) -> bool {
+    match (a, b) {
+        (Lval::Num(a), Lval::Num(b)) => a == b || (a.is_nan() && b.is_nan()),
+        (Lval::Sexpr(a), Lval::Sexpr(b)) | (Lval::Qexpr(a), Lval::Qexpr(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| identical(a, b))
+        }
+        _ => a == b,
+    }
+}
+
+fn builtin_is_nan(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let n = number_operand("nan?", &operands)?;
+    Ok(truth(n.is_nan()))
+}
+
+fn builtin_is_finite(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let n = number_operand("finite?", &operands)?;
+    Ok(truth(n.is_finite()))
+}
+
+// the one number a predicate like `nan?` was given
+fn number_operand(sym: &str, operands: &[Lval]) -> Result<f64, Lerr> {
+    check_arg_count(sym, operands, 1)?;
+    match operands[0] {
+        Lval::Num(n) => Ok(n),
+        Lval::Int(n) => Ok(n as f64),
+        ref other => Err(Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed a number but was given {}",
+                sym,
+                other.summary()
+            ),
+        )),
+    }
+}
+
+/// How close two numbers must be for `approx=`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tolerance {
+    /// Within `relative` of the larger magnitude, or within `absolute`,
+    /// which is what lets tiny numbers match zero.
+    Within { relative: f64, absolute: f64 },
+    /// At most this many representable doubles apart.
+    Ulps(u64),
+}
+
+/// The default `:rel` tolerance for `approx=`.
+pub const APPROX_RELATIVE: f64 = 1e-9;
+/// The default `:abs` tolerance for `approx=`.
+pub const APPROX_ABSOLUTE: f64 = 1e-12;
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance::Within {
+            relative: APPROX_RELATIVE,
+            absolute: APPROX_ABSOLUTE,
+        }
+    }
+}
+
+/// Whether `x` and `y` are equal within `tolerance`. NaN is never close to
+/// anything, and infinities are only close to themselves.
+pub fn approx_eq(x: f64, y: f64, tolerance: Tolerance) -> bool {
+    if x == y {
+        return true;
+    }
+    if !x.is_finite() || !y.is_finite() {
+        return false;
     }
+
+    match tolerance {
+        Tolerance::Within { relative, absolute } => {
+            let diff = (x - y).abs();
+            diff <= absolute || diff <= relative * x.abs().max(y.abs())
+        }
+        Tolerance::Ulps(ulps) => {
+            // doubles of one sign are ordered like their bit patterns, and
+            // differing signs only meet at zero, which == already caught
+            x.is_sign_negative() == y.is_sign_negative()
+                && (x.to_bits() as i64 - y.to_bits() as i64).unsigned_abs() <= ulps
+        }
+    }
+}
+
+// (approx= x y) with options :rel r, :abs a or :ulps n after the numbers
+fn builtin_approx_eq(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() < 2 || !operands.len().is_multiple_of(2) {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function approx= needed 2 numbers and option pairs but was given {} args",
+                operands.len()
+            ),
+        ));
+    }
+
+    let numbers = operands
+        .iter()
+        .cloned()
+        .map(to_num)
+        .collect::<Vec<Option<f64>>>();
+    let (x, y) = match (numbers[0], numbers[1]) {
+        (Some(x), Some(y)) => (x, y),
+        _ => {
+            return Err(Lerr::new(
+                LerrType::BadNum,
+                String::from("Function approx= can operate only on numbers"),
+            ))
+        }
+    };
+
+    let (mut relative, mut absolute) = (APPROX_RELATIVE, APPROX_ABSOLUTE);
+    let mut ulps = None;
+    let mut within = false;
+    for option in operands[2..].chunks(2) {
+        let value = to_num(option[1].clone()).filter(|n| *n >= 0.0);
+        match (&option[0], value) {
+            (Lval::Keyword(key), Some(n)) if key == "rel" => {
+                relative = n;
+                within = true;
+            }
+            (Lval::Keyword(key), Some(n)) if key == "abs" => {
+                absolute = n;
+                within = true;
+            }
+            (Lval::Keyword(key), _) if key == "ulps" => {
+                ulps = Some(int_operand("approx=", &option[1], u32::MAX as usize)?)
+            }
+            (Lval::Keyword(key), None) if key == "rel" || key == "abs" => {
+                return Err(Lerr::new(
+                    LerrType::BadNum,
+                    format!(
+                        "Function approx= needed a non-negative tolerance for :{} but was given {}",
+                        key,
+                        option[1].summary()
+                    ),
+                ))
+            }
+            (other, _) => {
+                return Err(Lerr::new(
+                    LerrType::BadOp,
+                    format!(
+                        "Function approx= takes options :rel, :abs or :ulps but was given {}",
+                        other.summary()
+                    ),
+                ))
+            }
+        }
+    }
+
+    let tolerance = match ulps {
+        Some(_) if within => {
+            return Err(Lerr::new(
+                LerrType::BadOp,
+                String::from("Function approx= cannot mix :ulps with :rel or :abs"),
+            ))
+        }
+        Some(ulps) => Tolerance::Ulps(ulps as u64),
+        None => Tolerance::Within { relative, absolute },
+    };
+
+    Ok(truth(approx_eq(x, y, tolerance)))
 }
 
 fn builtin_ne(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
@@ -158,11 +622,7 @@ fn builtin_ne(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
         ));
     }
 
-    if operands[0] == operands[1] {
-        Ok(Lval::Num(0_f64))
-    } else {
-        Ok(Lval::Num(1_f64))
-    }
+    Ok(truth(operands[0] != operands[1]))
 }
 
 fn builtin_gt(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
@@ -189,81 +649,304 @@ fn builtin_or(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     builtin_ord("||", operands)
 }
 
-fn builtin_not(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    builtin_op("!", operands)
+fn builtin_not(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    match operands.as_slice() {
+        [Lval::Bool(b)] => Ok(truth(!b)),
+        _ => builtin_op(env, "!", operands),
+    }
 }
 
-fn builtin_add(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    builtin_op("+", operands)
+fn builtin_add(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_op(env, "+", operands)
 }
 
-fn builtin_sub(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    builtin_op("-", operands)
+fn builtin_sub(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_op(env, "-", operands)
 }
 
-fn builtin_mul(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    builtin_op("*", operands)
+fn builtin_mul(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_op(env, "*", operands)
 }
 
-fn builtin_mod(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    builtin_op("%", operands)
+fn builtin_mod(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_op(env, "%", operands)
 }
 
-fn builtin_div(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    builtin_op("/", operands)
+fn builtin_div(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_op(env, "/", operands)
 }
 
-fn builtin_if(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    if operands.len() != 3 {
+// (div-mod a b) gives the quotient rounded down and the remainder, which
+// takes the sign of b, as values
+fn builtin_div_mod(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("div-mod", &operands, 2)?;
+    if let (Lval::Int(a), Lval::Int(b)) = (&operands[0], &operands[1]) {
+        if let Some((quotient, remainder)) = int_div_mod(*a, *b) {
+            return Ok(values(vec![Lval::Int(quotient), Lval::Int(remainder)]));
+        }
+    }
+    let (a, b) = match (to_num(operands[0].clone()), to_num(operands[1].clone())) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+            return Err(Lerr::new(
+                LerrType::BadNum,
+                "Function div-mod can operate only on numbers".to_string(),
+            ))
+        }
+    };
+    if b == 0_f64 {
         return Err(Lerr::new(
-            LerrType::IncorrectParamCount,
-            format!("Function if needed 3 arg but was given {}", operands.len()),
+            LerrType::DivZero,
+            format!("You cannot divide {}, or any number, by 0", operands[0]),
         ));
     }
 
-    let conditional = to_num(operands[0].clone()).ok_or(Lerr::new(
-        LerrType::WrongType,
-        format!(
-            "Function if needed conditional but was given {:?}",
-            operands[0]
-        ),
-    ))?;
+    let quotient = (a / b).floor();
+    Ok(values(vec![
+        Lval::Num(quotient),
+        Lval::Num(a - b * quotient),
+    ]))
+}
 
-    let then = to_qexpr(operands[1].clone()).ok_or(Lerr::new(
-        LerrType::WrongType,
-        format!(
-            "Function if needed qexpr for Then but was given {:?}",
-            operands[1]
-        ),
-    ))?;
+// (mod a b) is the remainder of dividing a by b rounded down, which takes
+// the sign of b, unlike `%`
+fn builtin_mod_floor(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("mod", &operands, 2)?;
+    let numbers = number_operands("mod", operands)?;
+    if numbers[1].float() == 0_f64 {
+        return Err(Lerr::new(
+            LerrType::DivZero,
+            format!(
+                "You cannot divide {}, or any number, by 0",
+                numbers[0].lval()
+            ),
+        ));
+    }
 
-    let els = to_qexpr(operands[2].clone()).ok_or(Lerr::new(
-        LerrType::WrongType,
-        format!(
-            "Function if needed qexpr for Else but was given {:?}",
-            operands[2]
-        ),
-    ))?;
+    if let (Number::Int(a), Number::Int(b)) = (numbers[0], numbers[1]) {
+        if let Some((_, remainder)) = int_div_mod(a, b) {
+            return Ok(Lval::Int(remainder));
+        }
+        // only i64::MIN by -1 overflows, leaving nothing over
+        return Ok(Lval::Int(0));
+    }
+    let (a, b) = (numbers[0].float(), numbers[1].float());
+    Ok(Lval::Num(a - b * (a / b).floor()))
+}
+
+// (pow base exponent), whole while a whole base has a whole, non-negative
+// exponent and the result fits
+fn builtin_pow(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("pow", &operands, 2)?;
+    let numbers = number_operands("pow", operands)?;
+    if let (Number::Int(base), Number::Int(exponent)) = (numbers[0], numbers[1]) {
+        let whole = match exponent {
+            0..=0xffff_ffff => base.checked_pow(exponent as u32),
+            _ => None,
+        };
+        if let Some(n) = whole {
+            return Ok(Lval::Int(n));
+        }
+    }
 
-    if conditional == 0_f64 {
-        eval::eval(env, Lval::Sexpr(els))
+    let (base, exponent) = (numbers[0].float(), numbers[1].float());
+    let result = base.powf(exponent);
+    if let Some(problem) = fold_problem("pow", 2, base, exponent, result) {
+        if env.strict_arithmetic() {
+            return Err(Lerr::new(LerrType::BadNum, problem));
+        }
+        env.warn(problem);
+    }
+    Ok(Lval::Num(result))
+}
+
+// (min a b ...) and (max a b ...) give back the smallest or largest
+// operand as it was, the first of any that tie. NaN anywhere makes NaN
+fn builtin_min(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    extreme("min", operands, Ordering::Less)
+}
+
+fn builtin_max(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    extreme("max", operands, Ordering::Greater)
+}
+
+fn extreme(sym: &str, operands: Vec<Lval>, keep: Ordering) -> Result<Lval, Lerr> {
+    let numbers = number_operands(sym, operands)?;
+    let mut best = numbers[0].lval();
+    for n in &numbers {
+        let n = n.lval();
+        match cmp_numbers(&n, &best) {
+            Some(ordering) if ordering == keep => best = n,
+            Some(_) => {}
+            None => return Ok(Lval::Num(f64::NAN)),
+        }
+    }
+    Ok(best)
+}
+
+fn builtin_abs(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    Ok(match unary_number("abs", operands)? {
+        Number::Int(n) => n
+            .checked_abs()
+            .map_or(Lval::Num((n as f64).abs()), Lval::Int),
+        Number::Float(n) => Lval::Num(n.abs()),
+    })
+}
+
+// `floor`, `ceil` and `round` make floats whole numbers, as long as they
+// fit in one. `round` takes halves away from zero
+fn builtin_floor(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    rounded("floor", operands, f64::floor)
+}
+
+fn builtin_ceil(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    rounded("ceil", operands, f64::ceil)
+}
+
+fn builtin_round(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    rounded("round", operands, f64::round)
+}
+
+fn rounded(sym: &str, operands: Vec<Lval>, round: fn(f64) -> f64) -> Result<Lval, Lerr> {
+    // 2^63, the first float past i64::MAX
+    const BOUND: f64 = 9_223_372_036_854_775_808.0;
+    Ok(match unary_number(sym, operands)? {
+        Number::Int(n) => Lval::Int(n),
+        Number::Float(n) => match round(n) {
+            n if (-BOUND..BOUND).contains(&n) => Lval::Int(n as i64),
+            n => Lval::Num(n),
+        },
+    })
+}
+
+// `sqrt`, `exp` and `log` always give floats. `log` is the natural log.
+// Square roots and logs of negative numbers are errors rather than NaN
+fn builtin_sqrt(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let n = non_negative("sqrt", operands)?;
+    Ok(Lval::Num(n.sqrt()))
+}
+
+fn builtin_exp(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    Ok(Lval::Num(unary_number("exp", operands)?.float().exp()))
+}
+
+fn builtin_log(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let n = non_negative("log", operands)?;
+    Ok(Lval::Num(n.ln()))
+}
+
+// the one number a math function was given
+fn unary_number(sym: &str, operands: Vec<Lval>) -> Result<Number, Lerr> {
+    check_arg_count(sym, &operands, 1)?;
+    Ok(number_operands(sym, operands)?[0])
+}
+
+fn non_negative(sym: &str, operands: Vec<Lval>) -> Result<f64, Lerr> {
+    let n = unary_number(sym, operands)?;
+    if n.float() < 0_f64 {
+        return Err(Lerr::new(
+            LerrType::BadNum,
+            format!(
+                "Function {} needed a number no less than 0 but was given {}",
+                sym,
+                n.lval()
+            ),
+        ));
+    }
+    Ok(n.float())
+}
+
+// `a` divided by `b` rounded down, and the remainder, `None` dividing by 0
+// or overflowing
+fn int_div_mod(a: i64, b: i64) -> Option<(i64, i64)> {
+    let (quotient, remainder) = (a.checked_div(b)?, a.checked_rem(b)?);
+    if remainder != 0 && (remainder < 0) != (b < 0) {
+        Some((quotient - 1, remainder + b))
     } else {
-        eval::eval(env, Lval::Sexpr(then))
+        Some((quotient, remainder))
+    }
+}
+
+/// `if` called as a function, its operands already evaluated. Written out
+/// as `(if ...)` it's the special form in `eval` instead, which leaves the
+/// branch it doesn't take alone.
+pub fn builtin_if(env: &mut Lenv, mut operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("if", &operands, 3)?;
+
+    let branch = if operands[0].is_truthy() { 1 } else { 2 };
+    match operands.swap_remove(branch) {
+        Lval::Qexpr(branch) => eval::eval(env, Lval::Sexpr(branch)),
+        value => Ok(value),
+    }
+}
+
+/// `and` called as a function, its operands already evaluated: the first
+/// false one, else the last, and `true` given none. Written out as `(and ...)`
+/// it's the special form in `eval`, which stops at the first false one.
+pub fn builtin_and_then(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    Ok(short_circuit(operands, false).unwrap_or(truth(true)))
+}
+
+/// `or` called as a function: the first true operand, else the last, and
+/// `false` given none. The special form stops at the first true one.
+pub fn builtin_or_else(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    Ok(short_circuit(operands, true).unwrap_or(truth(false)))
+}
+
+/// `do` called as a function: its last operand, `nil` given none. Written
+/// out as `(do ...)` it's the special form in `eval`, which makes the last
+/// operand a tail call.
+pub fn builtin_do(_env: &mut Lenv, mut operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    Ok(operands.pop().unwrap_or(Lval::Nil))
+}
+
+// the first operand as truthy as `stop`, else the last, `None` if empty
+fn short_circuit(operands: Vec<Lval>, stop: bool) -> Option<Lval> {
+    let mut last = None;
+    for operand in operands {
+        if operand.is_truthy() == stop {
+            return Some(operand);
+        }
+        last = Some(operand);
     }
+    last
+}
+
+// (not x) is true for the values `if` takes as false, false for everything
+// else
+fn builtin_falsy(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("not", &operands, 1)?;
+    Ok(truth(!operands[0].is_truthy()))
 }
 
 fn builtin_err(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     let err = to_str(operands[0].clone()).ok_or(Lerr::new(
         LerrType::WrongType,
         format!(
-            "Function die needed qexpr for Else but was given {:?}",
-            operands[0]
+            "Function die needed qexpr for Else but was given {}",
+            operands[0].summary()
         ),
     ))?;
 
     Err(Lerr::new(LerrType::Interrupt, err))
 }
 
+// (error message) fails with `message` written out as `print` would
+fn builtin_error(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("error", &operands, 1)?;
+    Err(Lerr::new(LerrType::Interrupt, printed(&operands)))
+}
+
+// (assert condition message) is nil when the condition is true, and fails
+// like `error` otherwise
+fn builtin_assert(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("assert", &operands, 2)?;
+    match operands[0].is_truthy() {
+        true => Ok(Lval::Nil),
+        false => Err(Lerr::new(LerrType::Interrupt, printed(&operands[1..]))),
+    }
+}
+
 fn builtin_head(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     // we want only one arguement
     if operands.len() != 1 {
@@ -277,23 +960,14 @@ fn builtin_head(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     }
 
     let arg = &operands[0];
-    // need a list/qexpr to work with
-    match arg {
-        Lval::Qexpr(qexpr) => {
-            if qexpr.len() == 0 {
-                Err(Lerr::new(
-                    LerrType::EmptyList,
-                    format!("Function head was given empty list"),
-                ))
-            } else {
-                Ok(qexpr[0].clone())
-            }
-        }
-        _ => Err(Lerr::new(
-            LerrType::WrongType,
-            format!("Function head needed Qexpr but was given {:?}", arg),
-        )),
-    }
+    // need a sequence to work with
+    let mut items = seq_iter(arg).ok_or_else(|| not_a_seq("head", arg))?;
+    items.next().ok_or_else(|| {
+        Lerr::new(
+            LerrType::EmptyList,
+            "Function head was given empty list".to_string(),
+        )
+    })
 }
 
 fn builtin_tail(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
@@ -309,29 +983,220 @@ fn builtin_tail(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     }
 
     let arg = &operands[0];
-    // need a list/qexpr to work with
-    match arg {
-        Lval::Qexpr(qexpr) => {
-            if qexpr.len() == 0 {
-                Err(Lerr::new(
-                    LerrType::EmptyList,
-                    format!("Function tail was given empty list"),
-                ))
-            } else {
-                Ok(Lval::Qexpr(qexpr[1..].to_vec()))
-            }
+    // need a sequence to work with, giving back the same kind
+    let kind = seq_kind(arg).ok_or_else(|| not_a_seq("tail", arg))?;
+    if seq_len(arg) == Some(0) {
+        return Err(Lerr::new(
+            LerrType::EmptyList,
+            "Function tail was given empty list".to_string(),
+        ));
+    }
+    seq_build(kind, seq_iter(arg).into_iter().flatten().skip(1).collect())
+}
+
+fn builtin_len(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("len", &operands, 1)?;
+    let arg = &operands[0];
+    let len = seq_len(arg).ok_or_else(|| not_a_seq("len", arg))?;
+    Ok(Lval::Int(len as i64))
+}
+
+// (nth i list) is the item at i, counting from 0
+fn builtin_nth(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("nth", &operands, 2)?;
+    let arg = &operands[1];
+    let len = seq_len(arg).ok_or_else(|| not_a_seq("nth", arg))?;
+    let index = match to_num(operands[0].clone()) {
+        Some(n) if n >= 0.0 && n.fract() == 0.0 => n,
+        _ => {
+            return Err(Lerr::new(
+                LerrType::BadNum,
+                format!(
+                    "Function nth needed a whole number index but was given {}",
+                    operands[0].summary()
+                ),
+            ))
         }
-        _ => Err(Lerr::new(
-            LerrType::WrongType,
-            format!("Function tail needed Qexpr but was given {:?}", arg),
-        )),
+    };
+    if len == 0 {
+        return Err(Lerr::new(
+            LerrType::EmptyList,
+            "Function nth was given empty list".to_string(),
+        ));
     }
+    if index >= len as f64 {
+        return Err(Lerr::new(
+            LerrType::BadNum,
+            format!(
+                "Function nth was given index {} but the list only has {} items",
+                index, len
+            ),
+        ));
+    }
+    Ok(seq_iter(arg)
+        .into_iter()
+        .flatten()
+        .nth(index as usize)
+        .expect("index checked against the length"))
+}
+
+// (take n list) is the first n items, all of them if there are fewer, in
+// the same kind of sequence
+fn builtin_take(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("take", &operands, 2)?;
+    let (kind, items) = seq_operand("take", &operands[1])?;
+    let n = int_operand("take", &operands[0], usize::MAX)?.min(items.len());
+    seq_build(kind, items[..n].to_vec())
+}
+
+// (drop n list) is what is left after the first n items
+fn builtin_drop(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("drop", &operands, 2)?;
+    let (kind, items) = seq_operand("drop", &operands[1])?;
+    let n = int_operand("drop", &operands[0], usize::MAX)?.min(items.len());
+    seq_build(kind, items[n..].to_vec())
+}
+
+// (member? x list) is whether any item equals x, a character of a string
+// or a byte
+fn builtin_is_member(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("member?", &operands, 2)?;
+    let mut items = seq_iter(&operands[1]).ok_or_else(|| not_a_seq("member?", &operands[1]))?;
+    Ok(Lval::Bool(items.any(|item| item == operands[0])))
+}
+
+fn builtin_last(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("last", &operands, 1)?;
+    let arg = &operands[0];
+    let items = seq_iter(arg).ok_or_else(|| not_a_seq("last", arg))?;
+    items.last().ok_or_else(|| {
+        Lerr::new(
+            LerrType::EmptyList,
+            "Function last was given empty list".to_string(),
+        )
+    })
 }
 
 fn builtin_list(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     Ok(Lval::Qexpr(operands))
 }
 
+// (range end), (range start end) or (range start end step) lists the
+// numbers from start, 0 unless given, up to but not including end, step
+// apart, 1 unless given. Whole numbers give whole numbers
+fn builtin_range(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_range("range", &operands, 1, 3)?;
+    if operands.len() == 1 {
+        let end = int_operand("range", &operands[0], usize::MAX)?;
+        env.check_value_size("range", end)?;
+        return Ok(Lval::Qexpr((0..end).map(|n| Lval::Int(n as i64)).collect()));
+    }
+
+    let mut numbers = number_operands("range", operands)?;
+    let step = match numbers.len() {
+        3 => numbers.pop().unwrap(),
+        _ => Number::Int(1),
+    };
+    let (start, end) = (numbers[0], numbers[1]);
+    let away = |span: f64, step: f64| span != 0.0 && (span > 0.0) != (step > 0.0);
+    if step.float() == 0.0 || away(end.float() - start.float(), step.float()) {
+        return Err(Lerr::new(
+            LerrType::BadNum,
+            format!(
+                "Function range needed a step from {} towards {} but was given {}",
+                start.lval().summary(),
+                end.lval().summary(),
+                step.lval().summary()
+            ),
+        ));
+    }
+
+    let items = match (start, end, step) {
+        (Number::Int(start), Number::Int(end), Number::Int(step)) => {
+            let (span, step) = (i128::from(end) - i128::from(start), i128::from(step));
+            let len = (span.abs() + step.abs() - 1) / step.abs();
+            env.check_value_size("range", len.min(usize::MAX as i128) as usize)?;
+            (0..len)
+                .map(|i| Lval::Int((i128::from(start) + i * step) as i64))
+                .collect()
+        }
+        (start, end, step) => {
+            let (start, end, step) = (start.float(), end.float(), step.float());
+            if !(start.is_finite() && end.is_finite() && step.is_finite()) {
+                return Err(Lerr::new(
+                    LerrType::BadNum,
+                    format!(
+                        "Function range needed finite numbers but was given {:?}, {:?} and {:?}",
+                        start, end, step
+                    ),
+                ));
+            }
+            // `as` saturates, which the size check then turns down
+            let len = ((end - start) / step).ceil() as usize;
+            env.check_value_size("range", len)?;
+            (0..len)
+                .map(|i| Lval::Num(start + i as f64 * step))
+                .collect()
+        }
+    };
+    Ok(Lval::Qexpr(items))
+}
+
+// (repeat n value) lists value n times
+fn builtin_repeat(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("repeat", &operands, 2)?;
+    let times = int_operand("repeat", &operands[0], usize::MAX)?;
+    env.check_value_size("repeat", times)?;
+    Ok(Lval::Qexpr(vec![operands[1].clone(); times]))
+}
+
+pub fn builtin_values(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    Ok(values(operands))
+}
+
+/// Several results returned at once. They're kept together as a call to
+/// `values` itself, which the reader can't produce, so nothing written in a
+/// program is mistaken for them.
+pub fn values(items: Vec<Lval>) -> Lval {
+    let mut form = Vec::with_capacity(items.len() + 1);
+    form.push(Lval::Fun(builtin_values));
+    form.extend(items);
+    Lval::Sexpr(form)
+}
+
+/// The results inside `lval`, if it came from `values`.
+pub fn as_values(lval: &Lval) -> Option<&[Lval]> {
+    match lval {
+        Lval::Sexpr(items) => match items.first() {
+            Some(Lval::Fun(f)) if ptr::fn_addr_eq(*f, builtin_values as Lfun) => Some(&items[1..]),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether `fun` takes `values` as they are. Everything else is given only
+/// the first value, with a warning, as `first_value` does.
+pub fn takes_values(fun: Lfun) -> bool {
+    [builtin_values as Lfun, builtin_def, builtin_var]
+        .iter()
+        .any(|f| ptr::fn_addr_eq(*f, fun))
+}
+
+/// `lval`, or its first value, `nil` if it has none, when it came from
+/// `values` where one value was expected.
+pub fn first_value(env: &mut Lenv, lval: Lval) -> Lval {
+    let first = match as_values(&lval) {
+        Some(values) => values.first().cloned().unwrap_or(Lval::Nil),
+        None => return lval,
+    };
+    env.warn(format!(
+        "{} was given where one value was expected, keeping {}",
+        lval, first
+    ));
+    first
+}
+
 fn builtin_eval(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     // we only want to evaluate one arguement
     if operands.len() != 1 {
@@ -347,325 +1212,4218 @@ fn builtin_eval(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     let arg = &operands[0];
     match arg {
         Lval::Qexpr(qexpr) => eval::eval(env, Lval::Sexpr(qexpr[..].to_vec())),
+        Lval::Str(source) => eval_source(env, source),
         _ => eval::eval(env, arg.clone()),
     }
 }
 
-fn builtin_join(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    // need at least 2 arguements
-    if operands.len() < 2 {
+// (with-env [:x 1 :y 2] [+ x y]) evaluates the body with the bindings in a
+// frame of their own over the current scope
+fn builtin_with_env(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let (frame, body) = with_env_operands("with-env", operands)?;
+    let mut scope = ScopeGuard::new(env, frame);
+    eval::eval(&mut scope, body)
+}
+
+// like with-env, hiding everything but the builtins besides the bindings
+fn builtin_with_env_only(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let (frame, body) = with_env_operands("with-env-only", operands)?;
+    let mut scope = ScopeGuard::isolated(env, frame);
+    eval::eval(&mut scope, body)
+}
+
+// the bindings, as `[:name value ...]` or `[[name value] ...]`, in a frame,
+// and the body ready to evaluate
+fn with_env_operands(sym: &str, operands: Vec<Lval>) -> Result<(Lookup, Lval), Lerr> {
+    check_arg_count(sym, &operands, 2)?;
+    expect_all_qexpr(&operands, sym)?;
+    let bindings = qexpr_items(&operands[0]);
+    let pairs: Option<Vec<(String, Lval)>> = if bindings.iter().all(|b| matches!(b, Lval::Qexpr(_)))
+    {
+        bindings
+            .into_iter()
+            .map(|binding| match to_qexpr(binding)?.as_slice() {
+                [Lval::Sym(name), value] => Some((name.clone(), value.clone())),
+                _ => None,
+            })
+            .collect()
+    } else if bindings.len().is_multiple_of(2) {
+        bindings
+            .chunks(2)
+            .map(|pair| match pair {
+                [Lval::Keyword(name), value] => Some((name.clone(), value.clone())),
+                _ => None,
+            })
+            .collect()
+    } else {
+        None
+    };
+    let pairs = pairs.ok_or_else(|| {
+        Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed bindings like [:name value] or [[name value]] but was given {}",
+                sym,
+                operands[0].summary()
+            ),
+        )
+    })?;
+
+    let mut frame = Lookup::new();
+    for (name, value) in pairs {
+        frame.insert(name, value);
+    }
+    Ok((frame, Lval::Sexpr(qexpr_items(&operands[1]))))
+}
+
+// (time-limit ms [expr]) evaluates expr, giving up after ms milliseconds
+fn builtin_time_limit(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("time-limit", &operands, 2)?;
+    let millis = match to_num(operands[0].clone()) {
+        Some(millis) if millis >= 0.0 => millis,
+        _ => {
+            return Err(Lerr::new(
+                LerrType::BadNum,
+                format!(
+                    "Function time-limit needed a number of milliseconds but was given {}",
+                    operands[0].summary()
+                ),
+            ))
+        }
+    };
+    expect_all_qexpr(&operands[1..], "time-limit")?;
+
+    env.push_deadline(millis);
+    let result = builtin_eval(env, operands[1..].to_vec());
+    env.pop_deadline();
+    result
+}
+
+// runs before timing starts, so caches and the like are warm
+const BENCH_WARMUPS: usize = 3;
+
+/// Timings from `bench`, in milliseconds, and what the last run gave.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bench {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub result: Lval,
+}
+
+/// Evaluates `expr` a few times to warm up and then `iterations` times by
+/// the env's clock. An error in any run stops the benchmark.
+pub fn bench(env: &mut Lenv, iterations: usize, expr: &Lval) -> Result<Bench, Lerr> {
+    // a timing is kept for each iteration
+    env.check_value_size("bench", iterations)?;
+    let run = |env: &mut Lenv, what: &str, i: usize| {
+        builtin_eval(env, vec![expr.clone()]).map_err(|e| {
+            Lerr::new(
+                e.etype.clone(),
+                format!("bench failed on {} {}: {}", what, i, e.message),
+            )
+        })
+    };
+
+    for i in 1..=BENCH_WARMUPS {
+        run(env, "warmup", i)?;
+    }
+
+    let mut result = Lval::Nil;
+    let mut timings = Vec::with_capacity(iterations);
+    for i in 1..=iterations {
+        let start = env.now();
+        result = run(env, "iteration", i)?;
+        timings.push(env.now() - start);
+    }
+
+    Ok(Bench {
+        min: timings.iter().copied().fold(f64::INFINITY, f64::min),
+        max: timings.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        mean: mean(&timings),
+        median: median(&timings),
+        result,
+    })
+}
+
+fn mean(nums: &[f64]) -> f64 {
+    nums.iter().sum::<f64>() / nums.len() as f64
+}
+
+// the middle value, or the mean of the middle two
+fn median(nums: &[f64]) -> f64 {
+    let mut sorted = nums.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+// (bench n [expr]) times n runs of expr, giving
+// [[min ms] [max ms] [mean ms] [median ms] [result value]]
+fn builtin_bench(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("bench", &operands, 2)?;
+    let iterations = match to_num(operands[0].clone()) {
+        Some(n) if n >= 1.0 && n.fract() == 0.0 => n as usize,
+        _ => {
+            return Err(Lerr::new(
+                LerrType::BadNum,
+                format!(
+                    "Function bench needed a positive whole number of iterations but was given {}",
+                    operands[0].summary()
+                ),
+            ))
+        }
+    };
+    expect_all_qexpr(&operands[1..], "bench")?;
+
+    let bench = bench(env, iterations, &operands[1])?;
+    let pair = |key: &str, value: Lval| Lval::Qexpr(vec![Lval::Sym(key.to_string()), value]);
+    Ok(Lval::Qexpr(vec![
+        pair("min", Lval::Num(bench.min)),
+        pair("max", Lval::Num(bench.max)),
+        pair("mean", Lval::Num(bench.mean)),
+        pair("median", Lval::Num(bench.median)),
+        pair("result", bench.result),
+    ]))
+}
+
+// keeps the trace of a runaway program readable
+const EXPLAIN_MAX_STEPS: usize = 100;
+const EXPLAIN_MAX_WIDTH: usize = 120;
+
+fn builtin_explain(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 {
         return Err(Lerr::new(
             LerrType::IncorrectParamCount,
             format!(
-                "Function join needed 2 arg but was given {}",
+                "Function explain needed 1 arg but was given {}",
                 operands.len()
             ),
         ));
     }
 
-    // cast everything into a qexppr
-    let qexprs = operands
-        .into_iter()
-        .map(to_qexpr)
-        .collect::<Option<Vec<_>>>()
-        .ok_or(Lerr::new(
-            LerrType::WrongType,
-            format!("Function join needed Qexpr but was given"),
-        ))?;
+    let mut expr = match to_qexpr(operands[0].clone()) {
+        Some(mut qexpr) if qexpr.len() == 1 => qexpr.remove(0),
+        Some(qexpr) => Lval::Sexpr(qexpr),
+        None => {
+            return Err(Lerr::new(
+                LerrType::WrongType,
+                format!(
+                    "Function explain needed qexpr but was given {}",
+                    operands[0].summary()
+                ),
+            ))
+        }
+    };
 
-    // push each elements from each arguements into one qexpr
-    let mut joined = vec![];
-    for qexp in qexprs {
-        for item in qexp {
-            joined.push(item);
+    let mut trace = vec![explain_step(&expr)];
+    while trace.len() < EXPLAIN_MAX_STEPS {
+        match eval::step(env, &expr) {
+            Ok(Some(next)) => {
+                trace.push(explain_step(&next));
+                expr = next;
+            }
+            Ok(None) => return Ok(Lval::Qexpr(trace.into_iter().map(Lval::Str).collect())),
+            Err(err) => {
+                return Err(Lerr::new(
+                    err.etype,
+                    format!(
+                        "Function explain failed at step {}; {}; trace: {}",
+                        // the first entry is the expression before any step
+                        trace.len(),
+                        err.message,
+                        trace.join(" => ")
+                    ),
+                ));
+            }
         }
     }
 
-    Ok(Lval::Qexpr(joined))
+    trace.push(format!("... stopped after {} steps", EXPLAIN_MAX_STEPS));
+    Ok(Lval::Qexpr(trace.into_iter().map(Lval::Str).collect()))
 }
 
-fn builtin_concat(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    // need at least 1 arguements
-    if operands.len() < 1 {
+fn explain_step(expr: &Lval) -> String {
+    let rendered = format!("{:?}", expr);
+    match rendered.char_indices().nth(EXPLAIN_MAX_WIDTH) {
+        Some((cut, _)) => format!("{}...", &rendered[..cut]),
+        None => rendered,
+    }
+}
+
+#[cfg(feature = "parser")]
+fn eval_source(env: &mut Lenv, source: &str) -> Result<Lval, Lerr> {
+    let limits = crate::parser::ParseLimits::for_env(env);
+    let (_, expr) = crate::parser::parse_with_limits(source, &limits).map_err(|e| {
+        Lerr::new(
+            LerrType::Syntax,
+            format!("Function eval could not parse {:?}; {}", source, e),
+        )
+    })?;
+    eval::eval(env, expr)
+}
+
+#[cfg(not(feature = "parser"))]
+fn eval_source(_env: &mut Lenv, source: &str) -> Result<Lval, Lerr> {
+    Err(Lerr::new(
+        LerrType::Syntax,
+        format!(
+            "Function eval was given {:?} but the parser is not available in this build",
+            source
+        ),
+    ))
+}
+
+#[cfg(all(feature = "parser", not(target_arch = "wasm32")))]
+fn builtin_repl(env: &mut Lenv, _operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    crate::repl::break_session(env)
+}
+
+// (load "file.lsp") evaluates a file's forms here, giving the last value
+#[cfg(all(feature = "parser", not(target_arch = "wasm32")))]
+fn builtin_load(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("load", &operands, 1)?;
+    match &operands[0] {
+        Lval::Str(path) => crate::interpreter::load(env, std::path::Path::new(path)),
+        other => Err(Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function load needed a Str path but was given {}",
+                other.summary()
+            ),
+        )),
+    }
+}
+
+#[cfg(feature = "cache")]
+fn builtin_cache_clear(env: &mut Lenv, _operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // the cache `load` reads through, if there is one
+    let cache = match env.parse_cache() {
+        Some(cache) => cache,
+        None => return Ok(Lval::Int(0)),
+    };
+    let cache = cache.borrow();
+    cache
+        .clear()
+        .map(|removed| Lval::Int(removed as i64))
+        .map_err(|e| {
+            Lerr::new(
+                LerrType::Io,
+                format!(
+                    "Function cache-clear could not clear {}; {}",
+                    cache.dir().display(),
+                    e
+                ),
+            )
+        })
+}
+
+fn builtin_join(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // need at least 2 arguements
+    if operands.len() < 2 {
         return Err(Lerr::new(
             LerrType::IncorrectParamCount,
             format!(
-                "Function join needed >= 1 arg but was given {}",
+                "Function join needed 2 arg but was given {}",
                 operands.len()
             ),
         ));
     }
 
-    // cast everything into a qexppr
-    let strings = operands
-        .into_iter()
-        .map(to_str)
-        .collect::<Option<Vec<_>>>()
-        .ok_or(Lerr::new(
+    // everything has to be the same kind of sequence as the first
+    let kind = seq_kind(&operands[0]).ok_or_else(|| not_a_seq("join", &operands[0]))?;
+    if let Some(i) = operands
+        .iter()
+        .position(|operand| seq_kind(operand) != Some(kind))
+    {
+        return Err(Lerr::new(
             LerrType::WrongType,
-            format!("Function join needed Strings but was given"),
-        ))?;
-
-    // push each elements from each arguements into one string
-    let mut concatted = String::from("");
-    for string in strings {
-        concatted = format!("{}{}", concatted, string);
+            format!(
+                "Function join needed a {} for argument {} but was given {}",
+                kind,
+                i + 1,
+                operands[i].summary()
+            ),
+        ));
     }
 
-    Ok(Lval::Str(concatted))
-}
+    let size = operands
+        .iter()
+        .map(|seq| seq_len(seq).unwrap_or(0))
+        .fold(0_usize, usize::saturating_add);
+    env.check_value_size("join", size)?;
 
-fn builtin_def(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    builtin_assign("def", env, operands)
+    // push each elements from each arguements into one sequence
+    let joined = operands
+        .iter()
+        .flat_map(|seq| seq_iter(seq).into_iter().flatten());
+    seq_build(kind, joined.collect())
 }
 
-fn builtin_var(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    builtin_assign("=", env, operands)
+// (diff a b) gives a [path left right] record for each place a and b differ
+fn builtin_diff(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("diff", &operands, 2)?;
+    Ok(Lval::Qexpr(
+        diff::diff(&operands[0], &operands[1])
+            .into_iter()
+            .map(|difference| {
+                Lval::Qexpr(vec![
+                    Lval::Qexpr(difference.path),
+                    difference.left,
+                    difference.right,
+                ])
+            })
+            .collect(),
+    ))
 }
 
-fn builtin_assign(sym: &str, env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    // need at least an arguement list and a value
-    if operands.len() < 2 {
+fn builtin_sort_any(_env: &mut Lenv, mut operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 {
         return Err(Lerr::new(
             LerrType::IncorrectParamCount,
             format!(
-                "Function def needed 2 args but was given {}",
+                "Function sort-any needed 1 arg but was given {}",
                 operands.len()
             ),
         ));
     }
 
-    let args = operands[0].clone();
+    let mut items = match operands.remove(0) {
+        Lval::Qexpr(items) => items,
+        arg => {
+            return Err(Lerr::new(
+                LerrType::WrongType,
+                format!(
+                    "Function sort-any needed Qexpr but was given {}",
+                    arg.summary()
+                ),
+            ))
+        }
+    };
 
-    // need each argument to be a symbol
-    let args = to_qexpr(args)
-        .ok_or(Lerr::new(
-            LerrType::WrongType,
-            format!("Function def needed Qexpr but was given {:?}", operands[0]),
-        ))?
-        .into_iter()
-        .map(to_sym)
-        .collect::<Option<Vec<String>>>()
-        .ok_or(Lerr::new(
-            LerrType::WrongType,
-            format!("Function def needed a param list of all Symbols"),
-        ))?;
+    // sort_by can't fail, so hold onto the first error and stop comparing
+    let mut failure = None;
+    items.sort_by(|a, b| {
+        if failure.is_some() {
+            return Ordering::Equal;
+        }
+        a.cmp_total(b).unwrap_or_else(|e| {
+            failure = Some(e);
+            Ordering::Equal
+        })
+    });
 
-    // need to have the same number of args and values to assign
-    if args.len() != operands.len() - 1 {
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(Lval::Qexpr(items)),
+    }
+}
+
+fn builtin_reverse(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("reverse", &operands, 1)?;
+    let (kind, items) = seq_operand("reverse", &operands[0])?;
+    seq_build(kind, items.iter().rev().cloned().collect())
+}
+
+// (sort [items]) puts numbers or strings in ascending order, NaN after
+// every other number. (sort [items] before) orders anything, by a function
+// saying whether its first argument goes before its second. Either way
+// items that tie keep their order
+fn builtin_sort(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_range("sort", &operands, 1, 2)?;
+    let (kind, items) = seq_operand("sort", &operands[0])?;
+    let sorted = match operands.get(1) {
+        None => sort_plain(&items)?,
+        Some(before) => {
+            expect_function("sort", before)?;
+            merge_sort(&items, &mut |a, b| {
+                eval::apply(env, before, vec![a.clone(), b.clone()]).map(|goes| goes.is_truthy())
+            })?
+        }
+    };
+    seq_build(kind, sorted)
+}
+
+fn sort_plain(items: &[Lval]) -> Result<Vec<Lval>, Lerr> {
+    // numbers sort with numbers and strings with strings, nothing else
+    let kind = |item: &Lval| match item {
+        Lval::Num(_) | Lval::Int(_) => Some("numbers"),
+        Lval::Str(_) => Some("strings"),
+        _ => None,
+    };
+    let first = items.first().and_then(kind);
+    if let Some(odd) = items
+        .iter()
+        .find(|item| kind(item).is_none() || kind(item) != first)
+    {
         return Err(Lerr::new(
-            LerrType::IncorrectParamCount,
+            LerrType::WrongType,
             format!(
-                "Function def needed to assign {} values but was passed {}",
-                args.len(),
-                operands.len() - 1
+                "Function sort needed all numbers or all strings, or a function to order them, but was given {}",
+                odd.summary()
             ),
         ));
     }
+    // always Ok with only numbers or only strings, NaN going last
+    let mut items = items.to_vec();
+    items.sort_by(|a, b| a.cmp_total(b).unwrap_or(Ordering::Equal));
+    Ok(items)
+}
+
+// a stable sort asking only whether an item goes before one it came after,
+// which can't go wrong however `before` answers
+fn merge_sort(
+    items: &[Lval],
+    before: &mut dyn FnMut(&Lval, &Lval) -> Result<bool, Lerr>,
+) -> Result<Vec<Lval>, Lerr> {
+    if items.len() < 2 {
+        return Ok(items.to_vec());
+    }
+    let (left, right) = items.split_at(items.len() / 2);
+    let (left, right) = (merge_sort(left, before)?, merge_sort(right, before)?);
 
-    // assign each arg to a corresponding value
-    for (i, arg) in args.into_iter().enumerate() {
-        if sym == "def" {
-            env.insert_last(&arg, operands[i + 1].clone());
+    let mut merged = Vec::with_capacity(items.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        let next = if before(r, l)? {
+            right.next()
         } else {
-            env.insert(&arg, operands[i + 1].clone());
+            left.next()
+        };
+        merged.extend(next);
+    }
+    merged.extend(left.chain(right));
+    Ok(merged)
+}
+
+// (unique [items]) drops each item equal to one before it
+fn builtin_unique(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("unique", &operands, 1)?;
+    let (kind, items) = seq_operand("unique", &operands[0])?;
+    let mut kept: Vec<Lval> = vec![];
+    for item in items.iter() {
+        if !kept.contains(item) {
+            kept.push(item.clone());
         }
     }
+    seq_build(kind, kept)
+}
 
-    Ok(Lval::Sexpr(vec![]))
+fn builtin_argmax(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("argmax", &operands, 1)?;
+    let numbers = to_numbers("argmax", list_operand("argmax", &operands[0])?)?;
+    extreme_index("argmax", &numbers, Ordering::Greater)
 }
 
-fn builtin_lambda(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    if operands.len() != 2 {
-        return Err(Lerr::new(
-            LerrType::IncorrectParamCount,
-            format!("Function \\ needed 2 arg but was given {}", operands.len()),
-        ));
+fn builtin_argmin(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("argmin", &operands, 1)?;
+    let numbers = to_numbers("argmin", list_operand("argmin", &operands[0])?)?;
+    extreme_index("argmin", &numbers, Ordering::Less)
+}
+
+fn builtin_argmax_by(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let keys = keys_of(env, "argmax-by", &operands)?;
+    extreme_index("argmax-by", &keys, Ordering::Greater)
+}
+
+fn builtin_argmin_by(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let keys = keys_of(env, "argmin-by", &operands)?;
+    extreme_index("argmin-by", &keys, Ordering::Less)
+}
+
+// the items of any sequence, with its kind to build the result from. A
+// list's own items are lent rather than copied
+fn seq_operand<'a>(sym: &str, operand: &'a Lval) -> Result<(SeqKind, Cow<'a, [Lval]>), Lerr> {
+    match (operand, seq_iter(operand)) {
+        (Lval::Qexpr(items), _) => Ok((SeqKind::Qexpr, Cow::Borrowed(items))),
+        (_, Some(items)) => Ok((seq_kind(operand).unwrap(), Cow::Owned(items.collect()))),
+        (_, None) => Err(not_a_seq(sym, operand)),
     }
+}
 
-    // needs all arguements to be qexpr
-    let results = operands
-        .into_iter()
-        .map(to_qexpr)
-        .collect::<Option<Vec<_>>>()
-        .ok_or(Lerr::new(
+fn list_operand<'a>(sym: &str, operand: &'a Lval) -> Result<&'a [Lval], Lerr> {
+    match operand {
+        Lval::Qexpr(items) => Ok(items),
+        other => Err(Lerr::new(
             LerrType::WrongType,
-            format!("Function \\ needed a Qexpr for arguments and a Qexpr for body"),
-        ))?;
+            format!(
+                "Function {} needed Qexpr but was given {}",
+                sym,
+                other.summary()
+            ),
+        )),
+    }
+}
+
+// the numbers in `items`, or a `BadNum` error naming the first item that
+// isn't one and where it was
+fn to_numbers(sym: &str, items: &[Lval]) -> Result<Vec<f64>, Lerr> {
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| match to_num(item.clone()) {
+            Some(n) => Ok(n),
+            None => Err(Lerr::new(
+                LerrType::BadNum,
+                format!(
+                    "Function {} needed numbers but item {} was {}",
+                    sym,
+                    i,
+                    item.summary()
+                ),
+            )),
+        })
+        .collect()
+}
 
-    let args = results[0].clone();
-    // need each argument to be a symbol
-    let args = args
+// `(sym f [items])`: f applied to each item, which has to give a number.
+// Errors from f say which item it failed on
+fn keys_of(env: &mut Lenv, sym: &str, operands: &[Lval]) -> Result<Vec<f64>, Lerr> {
+    check_arg_count(sym, operands, 2)?;
+    let items = list_operand(sym, &operands[1])?;
+
+    let mut keys = Vec::with_capacity(items.len());
+    for (i, key) in apply_each(env, sym, &operands[0], items)?
         .into_iter()
-        .map(to_sym)
-        .collect::<Option<Vec<String>>>()
-        .ok_or(Lerr::new(
-            LerrType::WrongType,
-            format!("Function \\ needed a param list of all Symbols"),
-        ))?;
+        .enumerate()
+    {
+        match (to_num(key.clone()), key) {
+            (Some(n), _) => keys.push(n),
+            (None, other) => {
+                return Err(Lerr::new(
+                    LerrType::BadNum,
+                    format!(
+                        "Function {} needed a number key but item {} gave {}",
+                        sym,
+                        i,
+                        other.summary()
+                    ),
+                ))
+            }
+        }
+    }
+    Ok(keys)
+}
 
-    let body = results[1].clone();
-    let new_env = env.peek().unwrap().clone();
-    let lambda = Llambda::new(args, body, new_env);
+// `f` applied to each of `items` in turn, stopping at the first error,
+// which says which item it failed on
+fn apply_each(env: &mut Lenv, sym: &str, f: &Lval, items: &[Lval]) -> Result<Vec<Lval>, Lerr> {
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| eval::apply(env, f, vec![item.clone()]).map_err(|e| failed_on(sym, i, e)))
+        .collect()
+}
+
+fn failed_on(sym: &str, i: usize, e: Lerr) -> Lerr {
+    Lerr {
+        trace: e.trace,
+        untraced: e.untraced,
+        ..Lerr::new(
+            e.etype,
+            format!("Function {} failed on item {}: {}", sym, i, e.message),
+        )
+    }
+}
+
+// fails unless `f` can be called, checked up front so an empty list
+// doesn't hide the mistake
+fn expect_function(sym: &str, f: &Lval) -> Result<(), Lerr> {
+    match f {
+        Lval::Fun(_) | Lval::Native(_) | Lval::Lambda(_) | Lval::CaseLambda(_) => Ok(()),
+        other => Err(Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed a function but was given {}",
+                sym,
+                other.summary()
+            ),
+        )),
+    }
+}
+
+// (map f [items]) applies f to each item, in the same kind of sequence
+// when every result fits in one, strings for a string and 0 to 255 for
+// bytes, and in a list otherwise
+fn builtin_map(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("map", &operands, 2)?;
+    expect_function("map", &operands[0])?;
+    let (kind, items) = seq_operand("map", &operands[1])?;
+    let results = apply_each(env, "map", &operands[0], &items)?;
+    if results.iter().all(|result| seq_holds(kind, result)) {
+        seq_build(kind, results)
+    } else {
+        Ok(Lval::Qexpr(results))
+    }
+}
+
+// (filter f [items]) keeps the items f is true for, in the same kind of
+// sequence
+fn builtin_filter(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("filter", &operands, 2)?;
+    expect_function("filter", &operands[0])?;
+    let (kind, items) = seq_operand("filter", &operands[1])?;
+    let keep = apply_each(env, "filter", &operands[0], &items)?;
+
+    seq_build(
+        kind,
+        items
+            .iter()
+            .zip(&keep)
+            .filter(|(_, keep)| keep.is_truthy())
+            .map(|(item, _)| item.clone())
+            .collect(),
+    )
+}
+
+// (fold f init [items]) gives (f (f init item0) item1) and so on
+fn builtin_fold(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("fold", &operands, 3)?;
+    expect_function("fold", &operands[0])?;
+    let (_, items) = seq_operand("fold", &operands[2])?;
+
+    let mut acc = operands[1].clone();
+    for (i, item) in items.iter().enumerate() {
+        acc = eval::apply(env, &operands[0], vec![acc, item.clone()])
+            .map_err(|e| failed_on("fold", i, e))?;
+    }
+    Ok(acc)
+}
+
+// (for-each f [items]) calls f on each item only for what it does, giving
+// (). A function that takes two arguments and not one is given each item's
+// index as well.
+fn builtin_for_each(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("for-each", &operands, 2)?;
+    expect_function("for-each", &operands[0])?;
+    let (_, items) = seq_operand("for-each", &operands[1])?;
+
+    let with_index = takes_index(&operands[0]);
+    for (i, item) in items.iter().enumerate() {
+        let args = match with_index {
+            true => vec![item.clone(), Lval::Int(i as i64)],
+            false => vec![item.clone()],
+        };
+        eval::apply(env, &operands[0], args).map_err(|e| failed_on("for-each", i, e))?;
+    }
+    Ok(Lval::Nil)
+}
+
+// whether `f` is a lambda that can be called with two arguments but not one
+fn takes_index(f: &Lval) -> bool {
+    let clauses = match f {
+        Lval::Lambda(lambda) => std::slice::from_ref(lambda),
+        Lval::CaseLambda(clauses) => clauses.as_slice(),
+        _ => return false,
+    };
+    let accepts = |n| {
+        clauses.iter().any(|lambda| {
+            let (min, max) = lambda.arity();
+            min <= n && max.is_none_or(|max| n <= max)
+        })
+    };
+    accepts(2) && !accepts(1)
+}
+
+// (partition-by f [items]) splits items into runs that f gives equal
+// results for
+fn builtin_partition_by(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("partition-by", &operands, 2)?;
+    let items = list_operand("partition-by", &operands[1])?;
+    let keys = apply_each(env, "partition-by", &operands[0], items)?;
+
+    let mut runs: Vec<Lval> = vec![];
+    for (i, item) in items.iter().enumerate() {
+        match runs.last_mut() {
+            Some(Lval::Qexpr(run)) if keys[i] == keys[i - 1] => run.push(item.clone()),
+            _ => runs.push(Lval::Qexpr(vec![item.clone()])),
+        }
+    }
+    Ok(Lval::Qexpr(runs))
+}
+
+// (split-when f [items]) starts a new group at each item f is true for
+fn builtin_split_when(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("split-when", &operands, 2)?;
+    let items = list_operand("split-when", &operands[1])?;
+    let splits = apply_each(env, "split-when", &operands[0], items)?;
+
+    let mut groups: Vec<Lval> = vec![];
+    for (item, split) in items.iter().zip(&splits) {
+        match groups.last_mut() {
+            Some(Lval::Qexpr(group)) if !split.is_truthy() => group.push(item.clone()),
+            _ => groups.push(Lval::Qexpr(vec![item.clone()])),
+        }
+    }
+    Ok(Lval::Qexpr(groups))
+}
+
+// where `keep` is how an item compares to the best so far to replace it,
+// so ties go to the first
+fn extreme_index(sym: &str, numbers: &[f64], keep: Ordering) -> Result<Lval, Lerr> {
+    if numbers.is_empty() {
+        return Err(Lerr::new(
+            LerrType::EmptyList,
+            format!("Function {} was given empty list", sym),
+        ));
+    }
+
+    let mut best = 0;
+    for (i, n) in numbers.iter().enumerate().skip(1) {
+        if n.partial_cmp(&numbers[best]) == Some(keep) {
+            best = i;
+        }
+    }
+    Ok(Lval::Int(best as i64))
+}
+
+fn builtin_concat(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // need at least 1 arguements
+    if operands.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function join needed >= 1 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    // cast everything into a qexppr
+    let strings = operands
+        .into_iter()
+        .map(to_str)
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Lerr::new(
+            LerrType::WrongType,
+            "Function join needed Strings but was given".to_string(),
+        ))?;
+
+    // push each elements from each arguements into one string
+    let mut concatted = String::from("");
+    for string in strings {
+        concatted = format!("{}{}", concatted, string);
+    }
+
+    Ok(Lval::Str(concatted))
+}
+
+// strings are measured and sliced in characters rather than bytes
+fn builtin_str_len(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("str-len", &operands, 1)?;
+    let s = str_operand("str-len", &operands[0])?;
+    Ok(Lval::Int(s.chars().count() as i64))
+}
+
+fn builtin_str_concat(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let strings = operands
+        .iter()
+        .map(|operand| str_operand("str-concat", operand))
+        .collect::<Result<Vec<_>, Lerr>>()?;
+    let size = strings.iter().map(|s| s.chars().count()).sum();
+    env.check_value_size("str-concat", size)?;
+    Ok(Lval::Str(strings.concat()))
+}
+
+// (str-slice s start end) gives characters start up to but not including end
+fn builtin_str_slice(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("str-slice", &operands, 3)?;
+    let s = str_operand("str-slice", &operands[0])?;
+    let start = int_operand("str-slice", &operands[1], usize::MAX)?;
+    let end = int_operand("str-slice", &operands[2], usize::MAX)?;
+    let len = s.chars().count();
+    if start > end || end > len {
+        return Err(Lerr::new(
+            LerrType::BadNum,
+            format!(
+                "Function str-slice needed 0 <= start <= end <= {} for {:?} but was given {} and {}",
+                len, s, start, end
+            ),
+        ));
+    }
+    Ok(Lval::Str(s.chars().skip(start).take(end - start).collect()))
+}
+
+fn builtin_str_upper(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("str-upper", &operands, 1)?;
+    Ok(Lval::Str(
+        str_operand("str-upper", &operands[0])?.to_uppercase(),
+    ))
+}
+
+fn builtin_str_lower(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("str-lower", &operands, 1)?;
+    Ok(Lval::Str(
+        str_operand("str-lower", &operands[0])?.to_lowercase(),
+    ))
+}
+
+// (str-split s separator) gives the pieces between separators, or every
+// character when the separator is empty
+fn builtin_str_split(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("str-split", &operands, 2)?;
+    let s = str_operand("str-split", &operands[0])?;
+    let separator = str_operand("str-split", &operands[1])?;
+    let pieces = match separator.as_str() {
+        "" => s.chars().map(|c| Lval::Str(c.to_string())).collect(),
+        separator => s
+            .split(separator)
+            .map(|piece| Lval::Str(piece.into()))
+            .collect(),
+    };
+    Ok(Lval::Qexpr(pieces))
+}
+
+fn builtin_str_contains(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("str-contains", &operands, 2)?;
+    let s = str_operand("str-contains", &operands[0])?;
+    let needle = str_operand("str-contains", &operands[1])?;
+    Ok(truth(s.contains(&needle)))
+}
+
+/// The most characters the text builtins will produce, which keeps a typo'd
+/// width from asking for gigabytes.
+pub const MAX_TEXT_WIDTH: usize = 1 << 20;
+
+#[derive(Clone, Copy)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+// fits `s` into exactly `width` characters, filling in around it or cutting
+// off the end of anything longer
+fn align(s: &str, width: usize, fill: char, align: Align) -> String {
+    let len = s.chars().count();
+    if len >= width {
+        return s.chars().take(width).collect();
+    }
+
+    let gap = width - len;
+    let (before, after) = match align {
+        Align::Left => (0, gap),
+        Align::Right => (gap, 0),
+        Align::Center => (gap / 2, gap - gap / 2),
+    };
+    let fill = |n| std::iter::repeat_n(fill, n);
+    fill(before).chain(s.chars()).chain(fill(after)).collect()
+}
+
+// (pad-left s width [fill]) and friends
+fn pad(sym: &str, operands: &[Lval], direction: Align) -> Result<Lval, Lerr> {
+    check_arg_range(sym, operands, 2, 3)?;
+    let s = str_operand(sym, &operands[0])?;
+    let width = int_operand(sym, &operands[1], MAX_TEXT_WIDTH)?;
+    let fill = match operands.get(2) {
+        None => ' ',
+        Some(fill) => {
+            let fill = str_operand(sym, fill)?;
+            let mut chars = fill.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => {
+                    return Err(Lerr::new(
+                        LerrType::WrongType,
+                        format!(
+                            "Function {} needed a single character to pad with but was given {:?}",
+                            sym, fill
+                        ),
+                    ))
+                }
+            }
+        }
+    };
+    Ok(Lval::Str(align(&s, width, fill, direction)))
+}
+
+fn builtin_pad_left(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    pad("pad-left", &operands, Align::Right)
+}
+
+fn builtin_pad_right(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    pad("pad-right", &operands, Align::Left)
+}
+
+fn builtin_center(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    pad("center", &operands, Align::Center)
+}
+
+fn builtin_string_repeat(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("string-repeat", &operands, 2)?;
+    let s = str_operand("string-repeat", &operands[0])?;
+    let times = int_operand("string-repeat", &operands[1], usize::MAX)?;
+    env.check_value_size("string-repeat", s.chars().count().saturating_mul(times))?;
+    Ok(Lval::Str(s.repeat(times)))
+}
+
+// (table [[row] [row]] [max-width]) lines up the cells of each row in
+// columns as wide as their widest cell, numbers on the right and everything
+// else on the left, cutting cells down to max-width if given. Short rows
+// just end early.
+fn builtin_table(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_range("table", &operands, 1, 2)?;
+    expect_all_qexpr(&operands[..1], "table")?;
+    let max_width = match operands.get(1) {
+        Some(max) => int_operand("table", max, MAX_TEXT_WIDTH)?,
+        None => MAX_TEXT_WIDTH,
+    };
+
+    let rows = qexpr_items(&operands[0]);
+    expect_all_qexpr(&rows, "table")?;
+    let rows = rows
+        .iter()
+        .map(|row| {
+            qexpr_items(row)
+                .into_iter()
+                .map(|cell| match cell {
+                    Lval::Str(s) => (s, Align::Left),
+                    Lval::Num(_) | Lval::Int(_) => (format!("{:?}", cell), Align::Right),
+                    other => (format!("{:?}", other), Align::Left),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths: Vec<usize> = vec![];
+    for row in &rows {
+        for (i, (cell, _)) in row.iter().enumerate() {
+            let width = cell.chars().count().min(max_width);
+            match widths.get_mut(i) {
+                Some(widest) => *widest = (*widest).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+
+    let lines = rows
+        .iter()
+        .map(|row| {
+            let cells = row
+                .iter()
+                .zip(&widths)
+                .map(|((cell, direction), width)| align(cell, *width, ' ', *direction))
+                .collect::<Vec<_>>();
+            cells.join(" | ").trim_end().to_string()
+        })
+        .collect::<Vec<_>>();
+    Ok(Lval::Str(lines.join("\n")))
+}
+
+fn check_arg_range(sym: &str, operands: &[Lval], min: usize, max: usize) -> Result<(), Lerr> {
+    if (min..=max).contains(&operands.len()) {
+        Ok(())
+    } else {
+        Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function {} needed {} to {} args but was given {}",
+                sym,
+                min,
+                max,
+                operands.len()
+            ),
+        ))
+    }
+}
+
+fn check_arg_count(sym: &str, operands: &[Lval], count: usize) -> Result<(), Lerr> {
+    if operands.len() == count {
+        Ok(())
+    } else {
+        Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function {} needed {} arg but was given {}",
+                sym,
+                count,
+                operands.len()
+            ),
+        ))
+    }
+}
+
+fn bytes_operand(sym: &str, operand: &Lval) -> Result<Vec<u8>, Lerr> {
+    to_bytes(operand.clone()).ok_or_else(|| {
+        Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed Bytes but was given {}",
+                sym,
+                operand.summary()
+            ),
+        )
+    })
+}
+
+fn str_operand(sym: &str, operand: &Lval) -> Result<String, Lerr> {
+    to_str(operand.clone()).ok_or_else(|| {
+        Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed a String but was given {}",
+                sym,
+                operand.summary()
+            ),
+        )
+    })
+}
+
+/// Checks every operand is a Qexpr, naming the first one that isn't by its
+/// 1-based position.
+fn expect_all_qexpr(operands: &[Lval], sym: &str) -> Result<(), Lerr> {
+    match operands
+        .iter()
+        .position(|operand| to_qexpr(operand.clone()).is_none())
+    {
+        None => Ok(()),
+        Some(i) => Err(Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed a Qexpr for argument {} but was given {}",
+                sym,
+                i + 1,
+                operands[i].summary()
+            ),
+        )),
+    }
+}
+
+/// The names in a param list, naming the first item that isn't a Symbol by
+/// its 1-based position.
+fn expect_all_sym(params: &[Lval], sym: &str) -> Result<Vec<String>, Lerr> {
+    params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            to_sym(param.clone()).ok_or_else(|| {
+                Lerr::new(
+                    LerrType::WrongType,
+                    format!(
+                        "Function {} needed a Symbol for param {} but was given {}",
+                        sym,
+                        i + 1,
+                        param.summary()
+                    ),
+                )
+            })
+        })
+        .collect()
+}
+
+// a lambda's parameters, all symbols with `:` only ever before the last
+fn expect_params(params: &[Lval], sym: &str) -> Result<Vec<String>, Lerr> {
+    let args = expect_all_sym(params, sym)?;
+    check_rest_param(sym, &args)?;
+    Ok(args)
+}
+
+// `:` can only come once, just before the parameter taking the rest of the
+// arguments
+fn check_rest_param(sym: &str, args: &[String]) -> Result<(), Lerr> {
+    match args.iter().position(|arg| arg == ":") {
+        Some(i) if i + 2 != args.len() || args[i + 1] == ":" => Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function {} needed exactly one parameter after : but was given [{}]",
+                sym,
+                args.join(" ")
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// the items of an operand already checked to be a Qexpr
+fn qexpr_items(operand: &Lval) -> Vec<Lval> {
+    to_qexpr(operand.clone()).unwrap_or_default()
+}
+
+// a whole number no bigger than `max`
+fn int_operand(sym: &str, operand: &Lval, max: usize) -> Result<usize, Lerr> {
+    match *operand {
+        Lval::Int(n) if n >= 0 && n as u64 <= max as u64 => Ok(n as usize),
+        Lval::Num(n) if n.fract() == 0.0 && n >= 0.0 && n <= max as f64 => Ok(n as usize),
+        _ => Err(Lerr::new(
+            LerrType::BadNum,
+            format!(
+                "Function {} needed a whole number from 0 to {} but was given {}",
+                sym,
+                max,
+                operand.summary()
+            ),
+        )),
+    }
+}
+
+fn builtin_bytes(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("bytes", &operands, 1)?;
+    let items = to_qexpr(operands[0].clone()).ok_or_else(|| {
+        Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function bytes needed a Qexpr of numbers but was given {}",
+                operands[0].summary()
+            ),
+        )
+    })?;
+    env.check_value_size("bytes", items.len())?;
+
+    items
+        .iter()
+        .map(|item| int_operand("bytes", item, 255).map(|byte| byte as u8))
+        .collect::<Result<Vec<u8>, Lerr>>()
+        .map(Lval::Bytes)
+}
+
+fn builtin_str_to_bytes(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("str->bytes", &operands, 1)?;
+    let s = str_operand("str->bytes", &operands[0])?;
+    Ok(Lval::Bytes(s.into_bytes()))
+}
+
+fn builtin_bytes_to_str(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("bytes->str", &operands, 1)?;
+    let bytes = bytes_operand("bytes->str", &operands[0])?;
+    String::from_utf8(bytes).map(Lval::Str).map_err(|e| {
+        Lerr::new(
+            LerrType::BadNum,
+            format!(
+                "Function bytes->str was given invalid UTF-8 at byte {}",
+                e.utf8_error().valid_up_to()
+            ),
+        )
+    })
+}
+
+fn builtin_bytes_to_str_lossy(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("bytes->str-lossy", &operands, 1)?;
+    let bytes = bytes_operand("bytes->str-lossy", &operands[0])?;
+    Ok(Lval::Str(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+fn builtin_bytes_len(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("bytes-len", &operands, 1)?;
+    let bytes = bytes_operand("bytes-len", &operands[0])?;
+    Ok(Lval::Int(bytes.len() as i64))
+}
+
+// `(bytes-slice b start end)`, end exclusive
+fn builtin_bytes_slice(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("bytes-slice", &operands, 3)?;
+    let bytes = bytes_operand("bytes-slice", &operands[0])?;
+    let end = int_operand("bytes-slice", &operands[2], bytes.len())?;
+    let start = int_operand("bytes-slice", &operands[1], end)?;
+    Ok(Lval::Bytes(bytes[start..end].to_vec()))
+}
+
+fn builtin_hex_encode(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("hex-encode", &operands, 1)?;
+    let bytes = bytes_operand("hex-encode", &operands[0])?;
+    Ok(Lval::Str(
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect(),
+    ))
+}
+
+fn builtin_hex_decode(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("hex-decode", &operands, 1)?;
+    let hex = str_operand("hex-decode", &operands[0])?;
+    let invalid = || {
+        Lerr::new(
+            LerrType::BadNum,
+            format!("Function hex-decode was given invalid hex {:?}", hex),
+        )
+    };
+
+    if hex.len() % 2 != 0 {
+        return Err(invalid());
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect::<Result<Vec<u8>, Lerr>>()
+        .map(Lval::Bytes)
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn builtin_base64_encode(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("base64-encode", &operands, 1)?;
+    let bytes = bytes_operand("base64-encode", &operands[0])?;
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0_u32, |group, (i, byte)| {
+            group | u32::from(*byte) << (16 - 8 * i)
+        });
+        // a chunk of n bytes needs n + 1 digits, the rest is padding
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    Ok(Lval::Str(encoded))
+}
+
+fn builtin_base64_decode(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("base64-decode", &operands, 1)?;
+    let text = str_operand("base64-decode", &operands[0])?;
+    base64_decode(&text).map(Lval::Bytes).ok_or_else(|| {
+        Lerr::new(
+            LerrType::BadNum,
+            format!("Function base64-decode was given invalid base64 {:?}", text),
+        )
+    })
+}
+
+/// Decodes padded standard base64, `None` if `text` isn't valid.
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let digits = text.trim_end_matches('=');
+    if text.len() - digits.len() > 2 {
+        return None;
+    }
+
+    let mut decoded = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.as_bytes().chunks(4) {
+        let mut group = 0_u32;
+        for (i, digit) in chunk.iter().enumerate() {
+            let value = BASE64_ALPHABET.iter().position(|c| c == digit)?;
+            group |= (value as u32) << (18 - 6 * i);
+        }
+        // n digits carry n - 1 whole bytes
+        for i in 0..chunk.len() - 1 {
+            decoded.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+fn qexpr_operand(sym: &str, operand: &Lval) -> Result<Vec<Lval>, Lerr> {
+    to_qexpr(operand.clone()).ok_or_else(|| {
+        Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed a Qexpr but was given {}",
+                sym,
+                operand.summary()
+            ),
+        )
+    })
+}
+
+// uniform in [0, 1)
+fn builtin_random(env: &mut Lenv, _operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    Ok(Lval::Num(
+        (env.next_random() >> 11) as f64 / (1_u64 << 53) as f64,
+    ))
+}
+
+fn builtin_seed(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("seed", &operands, 1)?;
+    let seed = int_operand("seed", &operands[0], u32::MAX as usize)?;
+    env.seed(seed as u64);
+    Ok(Lval::Nil)
+}
+
+// the first `n` items of `items` become a uniform random selection, in
+// random order (a partial Fisher-Yates shuffle)
+fn shuffle_prefix(env: &mut Lenv, items: &mut [Lval], n: usize) {
+    for i in 0..n {
+        let j = i + env.random_below(items.len() - i);
+        items.swap(i, j);
+    }
+}
+
+fn builtin_shuffle(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("shuffle", &operands, 1)?;
+    let mut items = qexpr_operand("shuffle", &operands[0])?;
+    let len = items.len();
+    shuffle_prefix(env, &mut items, len);
+    Ok(Lval::Qexpr(items))
+}
+
+// `(sample n list)`, n distinct items
+fn builtin_sample(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("sample", &operands, 2)?;
+    let mut items = qexpr_operand("sample", &operands[1])?;
+    let n = int_operand("sample", &operands[0], usize::MAX)?;
+    if n > items.len() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function sample needed at least {} items but was given {}",
+                n,
+                items.len()
+            ),
+        ));
+    }
+
+    shuffle_prefix(env, &mut items, n);
+    items.truncate(n);
+    Ok(Lval::Qexpr(items))
+}
+
+fn builtin_choice(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("choice", &operands, 1)?;
+    let mut items = qexpr_operand("choice", &operands[0])?;
+    if items.is_empty() {
+        return Err(Lerr::new(
+            LerrType::EmptyList,
+            "Function choice was given empty list".to_string(),
+        ));
+    }
+
+    let i = env.random_below(items.len());
+    Ok(items.swap_remove(i))
+}
+
+fn builtin_def(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_assign("def", env, operands)
+}
+
+// (def! [+] f) is def for names taken by builtins, which def refuses
+fn builtin_def_override(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_assign("def!", env, operands)
+}
+
+fn builtin_var(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_assign("=", env, operands)
+}
+
+// fails if defining `name` globally would replace a builtin, see
+// `Lenv::reserve`
+fn check_unreserved(sym: &str, env: &Lenv, name: &str) -> Result<(), Lerr> {
+    if !env.is_reserved(name) {
+        return Ok(());
+    }
+    let (action, escape) = if sym == "undef" {
+        ("remove", "undef!")
+    } else {
+        ("redefine", "def!")
+    };
+    Err(Lerr::new(
+        LerrType::ReservedSymbol,
+        format!(
+            "Function {} can't {} the builtin {:?}, {} can",
+            sym, action, name, escape
+        ),
+    ))
+}
+
+fn builtin_assign(sym: &str, env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // need at least an arguement list and a value
+    if operands.len() < 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function {} needed 2 args but was given {}",
+                sym,
+                operands.len()
+            ),
+        ));
+    }
+
+    expect_all_qexpr(&operands[..1], sym)?;
+    let mut operands = operands.into_iter();
+    let patterns = qexpr_items(&operands.next().unwrap());
+
+    // need to have the same number of args and values to assign
+    if patterns.len() != operands.len() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function {} needed to assign {} values but was passed {}",
+                sym,
+                patterns.len(),
+                operands.len()
+            ),
+        ));
+    }
+
+    let mut bindings = vec![];
+    for (i, (pattern, value)) in patterns.into_iter().zip(operands).enumerate() {
+        destructure(sym, env, i + 1, pattern, value, &mut bindings)?;
+    }
+
+    // assign each arg to a corresponding value, keeping to any contracts
+    let global = sym != "=" || env.depth() == 1;
+    for (arg, value) in &bindings {
+        if global && sym != "def!" {
+            check_unreserved(sym, env, arg)?;
+        }
+        if let Some(contract) = env.contract(arg) {
+            eval::check_contract(env, &contract, arg, value)?;
+        }
+    }
+    for (arg, value) in bindings {
+        if sym == "=" {
+            env.insert(&arg, value)?;
+        } else {
+            env.insert_last(&arg, value)?;
+        }
+        if sym == "def!" {
+            env.unreserve(&arg);
+        }
+    }
+
+    Ok(Lval::Nil)
+}
+
+// (let [x 1 y (+ x 1)] [+ x y]) binds each name to its value in a frame of
+// its own, in order so later values can use earlier names, and evaluates the
+// body there. The frame is gone afterwards, whether or not anything failed
+fn builtin_let(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("let", &operands, 2)?;
+    expect_all_qexpr(&operands, "let")?;
+    let bindings = qexpr_items(&operands[0]);
+    if !bindings.len().is_multiple_of(2) {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function let needed pairs of names and values but was given {} items",
+                bindings.len()
+            ),
+        ));
+    }
+
+    let mut scope = ScopeGuard::new(env, Lookup::new());
+    let mut pairs = bindings.into_iter();
+    let mut param = 0;
+    while let (Some(pattern), Some(value)) = (pairs.next(), pairs.next()) {
+        param += 1;
+        let value = eval::eval(&mut scope, value)?;
+        let mut bound = vec![];
+        destructure("let", &mut scope, param, pattern, value, &mut bound)?;
+        for (name, value) in bound {
+            scope.insert(&name, value)?;
+        }
+    }
+    eval::eval(&mut scope, Lval::Sexpr(qexpr_items(&operands[1])))
+}
+
+// matches `pattern`, a symbol or a list of patterns, against `value`, where
+// a list of patterns unpacks a list or `values` of the same length
+fn destructure(
+    sym: &str,
+    env: &mut Lenv,
+    param: usize,
+    pattern: Lval,
+    value: Lval,
+    bindings: &mut Vec<(String, Lval)>,
+) -> Result<(), Lerr> {
+    let patterns =
+        match pattern {
+            Lval::Sym(name) => {
+                bindings.push((name, first_value(env, value)));
+                return Ok(());
+            }
+            Lval::Qexpr(patterns) => patterns,
+            other => {
+                return Err(Lerr::new(
+                    LerrType::WrongType,
+                    format!(
+                    "Function {} needed a Symbol or a list of them for param {} but was given {}",
+                    sym, param, other.summary()
+                ),
+                ))
+            }
+        };
+
+    let items =
+        match (as_values(&value).map(<[Lval]>::to_vec), value) {
+            (Some(values), _) => values,
+            (None, Lval::Qexpr(items)) => items,
+            (None, other) => {
+                return Err(Lerr::new(
+                    LerrType::WrongType,
+                    format!(
+                    "Function {} needed a list or values to unpack for param {} but was given {}",
+                    sym, param, other.summary()
+                ),
+                ))
+            }
+        };
+    if items.len() != patterns.len() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function {} needed {} values to unpack for param {} but was given {}",
+                sym,
+                patterns.len(),
+                param,
+                items.len()
+            ),
+        ));
+    }
+
+    for (pattern, item) in patterns.into_iter().zip(items) {
+        destructure(sym, env, param, pattern, item, bindings)?;
+    }
+    Ok(())
+}
+
+fn builtin_undef(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_remove("undef", env, operands)
+}
+
+// (undef! [+]) is undef for names taken by builtins, which undef refuses
+fn builtin_undef_override(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_remove("undef!", env, operands)
+}
+
+fn builtin_remove(sym: &str, env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    expect_all_qexpr(&operands, sym)?;
+    let syms = operands
+        .iter()
+        .map(|operand| expect_all_sym(&qexpr_items(operand), sym))
+        .collect::<Result<Vec<_>, Lerr>>()?;
+
+    let syms = syms.into_iter().flatten().collect::<Vec<_>>();
+    if sym == "undef" {
+        for name in &syms {
+            check_unreserved(sym, env, name)?;
+        }
+    }
+    for name in syms {
+        env.remove_last(&name).ok_or_else(|| {
+            Lerr::new(
+                LerrType::UnboundSymbol,
+                format!("Function {} was given {:?} which is not defined", sym, name),
+            )
+        })?;
+        env.unreserve(&name);
+    }
+
+    Ok(Lval::Nil)
+}
+
+fn builtin_env_size(env: &mut Lenv, _operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    Ok(Lval::Qexpr(vec![
+        Lval::Int(env.bindings() as i64),
+        Lval::Int(env.approximate_bytes() as i64),
+    ]))
+}
+
+// `(stats)` itself counts, as a builtin and a lambda call
+fn builtin_stats(env: &mut Lenv, _operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let counters = env.counters();
+    let entry = |name: &str, value: Lval| Lval::Qexpr(vec![Lval::Keyword(name.to_string()), value]);
+    let count = |n: u64| Lval::Int(n as i64);
+    let errors = counters
+        .errors
+        .iter()
+        .map(|(etype, n)| entry(&format!("{:?}", etype), count(*n)))
+        .collect();
+
+    Ok(Lval::Qexpr(vec![
+        entry("expressions", count(counters.expressions)),
+        entry("builtin-calls", count(counters.builtin_calls)),
+        entry("lambda-calls", count(counters.lambda_calls)),
+        entry("errors", Lval::Qexpr(errors)),
+        entry("peak-depth", count(counters.peak_depth)),
+    ]))
+}
+
+fn builtin_stats_reset(env: &mut Lenv, _operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    env.reset_counters();
+    Ok(Lval::Nil)
+}
+
+// (print "x =" 1) writes `x = 1`, strings without their quotes, wherever
+// the environment's writer sends it
+fn builtin_print(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    env.write_output(&printed(&operands));
+    Ok(Lval::Nil)
+}
+
+fn builtin_println(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    env.write_output(&(printed(&operands) + "\n"));
+    Ok(Lval::Nil)
+}
+
+fn printed(operands: &[Lval]) -> String {
+    operands
+        .iter()
+        .map(|operand| match operand {
+            Lval::Str(s) => s.clone(),
+            other => other.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn builtin_host(env: &mut Lenv, mut operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            "Function host needed a handler name but was given 0 args".to_string(),
+        ));
+    }
+
+    let name = str_operand("host", &operands[0])?;
+    let handler = env.host_handler(&name).ok_or_else(|| {
+        let registered = env.host_handlers();
+        Lerr::new(
+            LerrType::UnboundSymbol,
+            format!(
+                "Function host has no handler named {:?}; registered handlers: {}",
+                name,
+                if registered.is_empty() {
+                    "none".to_string()
+                } else {
+                    registered.join(", ")
+                }
+            ),
+        )
+    })?;
+
+    operands.remove(0);
+    handler(operands).map_err(|message| Lerr::new(LerrType::Interrupt, message))
+}
+
+// every clause of a lambda, one unless it is a case-lambda, and none for
+// builtins, which can't be looked inside
+fn function_operand(sym: &str, operands: &[Lval]) -> Result<Vec<Llambda>, Lerr> {
+    check_arg_count(sym, operands, 1)?;
+    match &operands[0] {
+        Lval::Lambda(lambda) => Ok(vec![lambda.clone()]),
+        Lval::CaseLambda(clauses) => Ok(clauses.clone()),
+        Lval::Fun(_) | Lval::Native(_) => Ok(vec![]),
+        other => Err(Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed a function but was given {}",
+                sym,
+                other.summary()
+            ),
+        )),
+    }
+}
+
+// lambdas are pure when everything their body can reach is
+fn builtin_is_pure(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let clauses = function_operand("pure?", &operands)?;
+    let pure = match &operands[0] {
+        Lval::Fun(fun) => env.is_pure(*fun),
+        Lval::Native(_) => false,
+        _ => clauses
+            .iter()
+            .all(|lambda| optimize::lambda_is_pure(env, lambda)),
+    };
+    Ok(truth(pure))
+}
+
+// a case-lambda shows its first clause
+fn builtin_fn_args(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let args = function_operand("fn-args", &operands)?
+        .first()
+        .map(|lambda| lambda.args().iter().cloned().map(Lval::Sym).collect())
+        .unwrap_or_default();
+    Ok(Lval::Qexpr(args))
+}
+
+fn builtin_fn_body(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let body = function_operand("fn-body", &operands)?
+        .first()
+        .map(|lambda| lambda.body().to_vec())
+        .unwrap_or_default();
+    Ok(Lval::Qexpr(body))
+}
+
+// a builtin reports what it was registered as taking, and a case-lambda a
+// list of arities, one for each clause
+fn builtin_fn_arity(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let to_arity = |(min, max): Arity| {
+        Lval::Qexpr(vec![
+            Lval::Int(min as i64),
+            max.map(|max| Lval::Int(max as i64))
+                .unwrap_or_else(|| Lval::Qexpr(vec![])),
+        ])
+    };
+    let clauses = function_operand("fn-arity", &operands)?;
+    Ok(match (&operands[0], clauses.first()) {
+        (Lval::CaseLambda(_), _) => Lval::Qexpr(
+            clauses
+                .iter()
+                .map(|lambda| to_arity(lambda.arity()))
+                .collect(),
+        ),
+        (_, Some(lambda)) => to_arity(lambda.arity()),
+        (Lval::Fun(fun), None) => to_arity(env.arity(*fun).unwrap_or((0, None))),
+        (_, None) => to_arity((0, None)),
+    })
+}
+
+// what `type` calls each kind of value
+fn type_name(lval: &Lval) -> &'static str {
+    match lval {
+        Lval::Num(_) | Lval::Int(_) => "number",
+        Lval::Sym(_) => "symbol",
+        Lval::Str(_) => "string",
+        Lval::Sexpr(_) => "sexpr",
+        Lval::Qexpr(_) => "qexpr",
+        Lval::Fun(_) | Lval::Native(_) => "function",
+        Lval::Lambda(_) | Lval::CaseLambda(_) => "lambda",
+        Lval::Bytes(_) => "bytes",
+        Lval::Keyword(_) => "keyword",
+        Lval::Bool(_) => "boolean",
+        Lval::Nil => "nil",
+    }
+}
+
+fn builtin_type(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("type", &operands, 1)?;
+    Ok(Lval::Str(type_name(&operands[0]).to_string()))
+}
+
+fn builtin_is_number(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    is_type("number?", &operands, |lval| type_name(lval) == "number")
+}
+
+fn builtin_is_string(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    is_type("string?", &operands, |lval| type_name(lval) == "string")
+}
+
+fn builtin_is_symbol(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    is_type("symbol?", &operands, |lval| type_name(lval) == "symbol")
+}
+
+fn builtin_is_list(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    is_type("list?", &operands, |lval| {
+        matches!(lval, Lval::Sexpr(_) | Lval::Qexpr(_))
+    })
+}
+
+fn builtin_is_fun(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    is_type("fun?", &operands, |lval| {
+        matches!(type_name(lval), "function" | "lambda")
+    })
+}
+
+// whether the one operand of the predicate `sym` is of the type asked about
+fn is_type(sym: &str, operands: &[Lval], test: fn(&Lval) -> bool) -> Result<Lval, Lerr> {
+    check_arg_count(sym, operands, 1)?;
+    Ok(truth(test(&operands[0])))
+}
+
+fn builtin_fn_contracts(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let contracts = function_operand("fn-contracts", &operands)?
+        .first()
+        .map(|lambda| {
+            lambda
+                .contracts()
+                .iter()
+                .map(|(name, contract)| {
+                    Lval::Qexpr(vec![
+                        Lval::Sym(name.clone()),
+                        Lval::Sym(contract.label.clone()),
+                    ])
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Lval::Qexpr(contracts))
+}
+
+// (source-of [name]) lists where name was defined, most recent first
+fn builtin_source_of(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("source-of", &operands, 1)?;
+    expect_all_qexpr(&operands, "source-of")?;
+    let names = expect_all_sym(&qexpr_items(&operands[0]), "source-of")?;
+    let name = match names.as_slice() {
+        [name] => name,
+        _ => {
+            return Err(Lerr::new(
+                LerrType::IncorrectParamCount,
+                format!(
+                    "Function source-of needed one Symbol but was given {}",
+                    names.len()
+                ),
+            ))
+        }
+    };
+
+    let origins = env.origins(name).ok_or_else(|| {
+        Lerr::new(
+            LerrType::UnboundSymbol,
+            format!("{:?} has not been defined", name),
+        )
+    })?;
+    Ok(Lval::Qexpr(
+        origins
+            .into_iter()
+            .map(|origin| Lval::Str(origin.to_string()))
+            .collect(),
+    ))
+}
+
+fn builtin_lambda(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!("Function \\ needed 2 arg but was given {}", operands.len()),
+        ));
+    }
+
+    // needs all arguements to be qexpr, each argument a symbol
+    expect_all_qexpr(&operands, "\\")?;
+    let args = expect_params(&qexpr_items(&operands[0]), "\\")?;
+
+    let body = qexpr_items(&operands[1]);
+    let new_env = env.capture();
+    let lambda = Llambda::new(args, body, new_env);
+
+    Ok(Lval::Lambda(lambda))
+}
+
+// (case-lambda [[x] [body]] [[x y] [body]] ...) is one function with a
+// clause for each number of arguments, see `eval::call_case` for which is
+// picked
+fn builtin_case_lambda(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            "Function case-lambda needed at least 1 clause but was given 0".to_string(),
+        ));
+    }
+
+    let mut clauses: Vec<Llambda> = vec![];
+    for clause in &operands {
+        let (args, body) = match clause {
+            Lval::Qexpr(items) if items.len() == 2 => {
+                expect_all_qexpr(items, "case-lambda")?;
+                (qexpr_items(&items[0]), qexpr_items(&items[1]))
+            }
+            other => {
+                return Err(Lerr::new(
+                    LerrType::WrongType,
+                    format!(
+                        "Function case-lambda needed [[params] [body]] clauses but was given {}",
+                        other.summary()
+                    ),
+                ))
+            }
+        };
+        let lambda = Llambda::new(expect_params(&args, "case-lambda")?, body, env.capture());
+        if clauses.iter().any(|other| other.arity() == lambda.arity()) {
+            return Err(Lerr::new(
+                LerrType::WrongType,
+                format!(
+                    "Function case-lambda was given two clauses taking {} arg(s)",
+                    lambda.arity_label()
+                ),
+            ));
+        }
+        clauses.push(lambda);
+    }
+
+    Ok(Lval::CaseLambda(clauses))
+}
+
+// a `[name predicate]` pair, with the predicate evaluated
+fn to_contract(sym: &str, env: &mut Lenv, pair: &Lval) -> Result<(String, Contract), Lerr> {
+    let (name, predicate) = match pair {
+        Lval::Qexpr(items) if items.len() == 2 => (to_sym(items[0].clone()), items[1].clone()),
+        _ => (None, Lval::Sexpr(vec![])),
+    };
+    let name = name.ok_or_else(|| {
+        Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed a [name predicate] pair but was given {}",
+                sym,
+                pair.summary()
+            ),
+        )
+    })?;
+
+    let label = format!("{:?}", predicate);
+    let predicate = eval::eval(env, predicate)?;
+    match predicate {
+        Lval::Fun(_) | Lval::Native(_) | Lval::Lambda(_) | Lval::CaseLambda(_) => {
+            Ok((name, Contract { label, predicate }))
+        }
+        other => Err(Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed a function for the contract on {} but was given {}",
+                sym,
+                name,
+                other.summary()
+            ),
+        )),
+    }
+}
+
+// (def-checked [name predicate] value) defines name, holding it and every
+// later definition of it to the predicate
+fn builtin_def_checked(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("def-checked", &operands, 2)?;
+    let (name, contract) = to_contract("def-checked", env, &operands[0])?;
+    check_unreserved("def-checked", env, &name)?;
+    eval::check_contract(env, &contract, &name, &operands[1])?;
+
+    env.insert_last(&name, operands[1].clone())?;
+    env.set_contract_last(&name, contract);
+    Ok(Lval::Nil)
+}
+
+// (fun [name param param] [body]) defines a function globally, as
+// (def [name] (\ [param param] [body])) would
+fn builtin_fun(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("fun", &operands, 2)?;
+    expect_all_qexpr(&operands, "fun")?;
+
+    let (name, args) = to_signature("fun", operands[0].clone())?;
+    check_unreserved("fun", env, &name)?;
+    let lambda = Llambda::new(args, qexpr_items(&operands[1]), env.capture());
+    env.insert_last(&name, Lval::Lambda(lambda))?;
+    Ok(Lval::Nil)
+}
+
+// (fun-checked [name param [param predicate]] [body]) defines a function
+// whose arguments are checked on every call
+fn builtin_fun_checked(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    check_arg_count("fun-checked", &operands, 2)?;
+    expect_all_qexpr(&operands, "fun-checked")?;
+
+    let mut signature = qexpr_items(&operands[0]);
+    if signature.is_empty() {
+        return Err(Lerr::new(
+            LerrType::EmptyList,
+            "Function fun-checked was given an empty signature".to_string(),
+        ));
+    }
+    let name = expect_all_sym(&signature[..1], "fun-checked")?.remove(0);
+    check_unreserved("fun-checked", env, &name)?;
+
+    let mut args = vec![];
+    let mut contracts = vec![];
+    for param in signature.drain(1..) {
+        match param {
+            Lval::Sym(arg) => args.push(arg),
+            pair => {
+                let (arg, contract) = to_contract("fun-checked", env, &pair)?;
+                args.push(arg.clone());
+                contracts.push((arg, contract));
+            }
+        }
+    }
+
+    check_rest_param("fun-checked", &args)?;
+    let mut lambda = Llambda::new(args, qexpr_items(&operands[1]), env.capture());
+    lambda.contracts = contracts;
+    env.insert_last(&name, Lval::Lambda(lambda))?;
+    Ok(Lval::Nil)
+}
+
+// splits a `[name arg1 arg2]` signature into the name and its params
+fn to_signature(sym: &str, signature: Lval) -> Result<(String, Vec<String>), Lerr> {
+    let names = to_qexpr(signature.clone()).ok_or_else(|| {
+        Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed a Qexpr signature but was given {}",
+                sym,
+                signature.summary()
+            ),
+        )
+    })?;
+    let mut names = expect_all_sym(&names, sym)?;
+
+    if names.is_empty() {
+        return Err(Lerr::new(
+            LerrType::EmptyList,
+            format!("Function {} was given an empty signature", sym),
+        ));
+    }
+
+    let name = names.remove(0);
+    check_rest_param(sym, &names)?;
+    Ok((name, names))
+}
+
+fn builtin_letfn(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function letfn needed 2 args but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    let definitions = to_qexpr(operands[0].clone()).ok_or_else(|| {
+        Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function letfn needed a Qexpr of definitions but was given {}",
+                operands[0].summary()
+            ),
+        )
+    })?;
+
+    let body = to_qexpr(operands[1].clone()).ok_or_else(|| {
+        Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function letfn needed a Qexpr for body but was given {}",
+                operands[1].summary()
+            ),
+        )
+    })?;
+
+    // need a body for every signature
+    if definitions.len() % 2 != 0 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            "Function letfn needed a body for every signature".to_string(),
+        ));
+    }
+
+    let mut functions = vec![];
+    for pair in definitions.chunks(2) {
+        let (name, args) = to_signature("letfn", pair[0].clone())?;
+        let body = to_qexpr(pair[1].clone()).ok_or_else(|| {
+            Lerr::new(
+                LerrType::WrongType,
+                format!(
+                    "Function letfn needed a Qexpr body for {} but was given {}",
+                    name,
+                    pair[1].summary()
+                ),
+            )
+        })?;
+        functions.push((name, args, body));
+    }
+
+    // every helper goes into one shared frame so they can call each other
+    let mut scope = ScopeGuard::new(env, Lookup::new());
+    for (name, args, body) in functions {
+        let lambda = Llambda::new(args, body, scope.capture());
+        scope.insert(&name, Lval::Lambda(lambda))?;
+    }
+
+    eval::eval(&mut scope, Lval::Sexpr(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{env::init_env, to_lambda, Lfun};
+
+    #[cfg(feature = "parser")]
+    fn eval_str(env: &mut Lenv, input: &str) -> Result<Lval, Lerr> {
+        let limits = crate::parser::ParseLimits::for_env(env);
+        eval::eval(
+            env,
+            crate::parser::parse_with_limits(input, &limits).unwrap().1,
+        )
+    }
+
+    #[cfg(feature = "parser")]
+    fn to_bool(lval: Lval) -> Option<bool> {
+        match lval {
+            Lval::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_limits_time() {
+        use std::{cell::Cell, rc::Rc};
+
+        // time moves on a millisecond every time anyone looks
+        let env = &mut init_env();
+        let now = Rc::new(Cell::new(0_f64));
+        let ticks = now.clone();
+        env.set_clock(move || {
+            ticks.set(ticks.get() + 1.0);
+            ticks.get()
+        });
+        eval_str(
+            env,
+            "def [count] (\\ [n] [if (== n 0) [0] [+ 1 (count (- n 1))]])",
+        )
+        .unwrap();
+
+        assert_eq!(
+            eval_str(env, "time-limit 1000 [count 10]"),
+            Ok(Lval::Num(10_f64))
+        );
+
+        let etype = |env: &mut Lenv, input: &str| eval_str(env, input).map_err(|err| err.etype);
+        assert_eq!(
+            etype(env, "time-limit 20 [count 100]"),
+            Err(LerrType::Timeout)
+        );
+        // the inner limit is tighter, then the outer one is
+        assert_eq!(
+            etype(env, "time-limit 1000 [time-limit 20 [count 100]]"),
+            Err(LerrType::Timeout)
+        );
+        assert_eq!(
+            etype(env, "time-limit 20 [time-limit 1000 [count 100]]"),
+            Err(LerrType::Timeout)
+        );
+
+        // nothing is left behind to time out later work
+        let before = now.get();
+        assert_eq!(eval_str(env, "count 100"), Ok(Lval::Num(100_f64)));
+        assert!(now.get() == before);
+        assert_eq!(etype(env, "time-limit -1 [1]"), Err(LerrType::BadNum));
+        assert_eq!(etype(env, "time-limit 1 1"), Err(LerrType::WrongType));
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_benches() {
+        use std::{cell::Cell, rc::Rc};
+
+        // each run takes the next of these many milliseconds
+        let env = &mut init_env();
+        let times = [0.0, 5.0, 5.0, 6.0, 6.0, 9.0, 9.0, 11.0];
+        let reads = Rc::new(Cell::new(0));
+        let counter = reads.clone();
+        env.set_clock(move || {
+            counter.set(counter.get() + 1);
+            times[(counter.get() - 1) % times.len()]
+        });
+
+        assert_eq!(
+            eval_str(env, "bench 4 [+ 1 2]").unwrap(),
+            eval_str(
+                env,
+                "head [[[min 1] [max 5] [mean 2.75] [median 2.5] [result 3]]]"
+            )
+            .unwrap()
+        );
+        // the warmups aren't timed
+        assert_eq!(reads.get(), 8);
+
+        assert_eq!(median(&[3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(mean(&[3.0, 1.0, 2.0]), 2.0);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_stops_benching_on_errors() {
+        let env = &mut init_env();
+        // counts the runs, failing on the fifth
+        eval_str(env, "def [n] 0").unwrap();
+        eval_str(env, "def [then] (\\ [_ x] [x])").unwrap();
+        let err = eval_str(
+            env,
+            "bench 10 [then (= [n] (+ n 1)) (head (if (< n 5) [[1]] [[]]))]",
+        )
+        .unwrap_err();
+        assert_eq!(err.etype, LerrType::EmptyList);
+        assert_eq!(
+            err.message,
+            "bench failed on iteration 2: Function head was given empty list"
+        );
+
+        for input in &[
+            "bench 0 [1]",
+            "bench 1.5 [1]",
+            "bench -2 [1]",
+            "bench [1] [1]",
+        ] {
+            assert_eq!(eval_str(env, input).unwrap_err().etype, LerrType::BadNum);
+        }
+        assert_eq!(
+            eval_str(env, "bench 1 1").unwrap_err().etype,
+            LerrType::WrongType
+        );
+
+        // more timings than a value may hold fail before any are kept
+        assert_eq!(
+            eval_str(env, "bench 100000000000000000 [1]")
+                .unwrap_err()
+                .etype,
+            LerrType::SizeLimit
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_limits_time_by_the_real_clock() {
+        let env = &mut init_env();
+        assert_eq!(
+            eval_str(env, "time-limit 60000 [+ 1 2]"),
+            Ok(Lval::Num(3_f64))
+        );
+        assert_eq!(
+            eval_str(env, "time-limit 0 [+ 1 (+ 2 3)]").map_err(|err| err.etype),
+            Err(LerrType::Timeout)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_defines_functions_with_fun() {
+        let env = &mut init_env();
+        assert_eq!(
+            eval_str(env, "fun [fact n] [if (== n 0) [1] [* n (fact (- n 1))]]").unwrap(),
+            Lval::Nil
+        );
+        assert_eq!(eval_str(env, "fact 5").unwrap(), Lval::Num(120_f64));
+
+        // the definition is global even from inside a lambda
+        eval_str(env, "(\\ [x] [fun [inner y] [+ y y]]) 1").unwrap();
+        assert_eq!(eval_str(env, "inner 2").unwrap(), Lval::Num(4_f64));
+
+        let etype = |env: &mut Lenv, input: &str| eval_str(env, input).unwrap_err().etype;
+        assert_eq!(etype(env, "fun [] [1]"), LerrType::EmptyList);
+        assert_eq!(etype(env, "fun [f 1] [1]"), LerrType::WrongType);
+        assert_eq!(etype(env, "fun 1 [1]"), LerrType::WrongType);
+        assert_eq!(etype(env, "fun [f] [1] [2]"), LerrType::IncorrectParamCount);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_keeps_builtins_from_being_redefined() {
+        let env = &mut init_env();
+        let etype = |env: &mut Lenv, input: &str| eval_str(env, input).unwrap_err().etype;
+        for input in &[
+            "def [+] 5",
+            "def [[x [y head]]] [1 [2 3]]",
+            "= [+] 5",
+            "fun [head xs] [xs]",
+            "def-checked [tail (\\ [f] [1])] 1",
+            "fun-checked [len xs] [0]",
+        ] {
+            assert_eq!(etype(env, input), LerrType::ReservedSymbol, "{}", input);
+        }
+        assert_eq!(
+            eval_str(env, "def [+] 5").unwrap_err().message(),
+            "Function def can't redefine the builtin \"+\", def! can"
+        );
+        assert_eq!(env.get("x"), None);
+        assert_eq!(eval_str(env, "+ 1 2"), Ok(Lval::Int(3)));
+
+        // shadowing one for a while is fine, as is replacing it on purpose
+        assert_eq!(eval_str(env, "let [+ -] [+ 5 1]"), Ok(Lval::Int(4)));
+        assert_eq!(eval_str(env, "(\\ [x] [= [+] x]) 1"), Ok(Lval::Nil));
+        eval_str(env, "def! [+] -").unwrap();
+        assert_eq!(eval_str(env, "+ 5 1"), Ok(Lval::Int(4)));
+        eval_str(env, "def [+] *").unwrap();
+        assert_eq!(eval_str(env, "+ 5 1"), Ok(Lval::Int(5)));
+
+        // builtins an application adds are kept the same way
+        add_builtin(env, "answer", |_, _| Ok(Lval::Int(42)), (0, Some(0)));
+        assert_eq!(etype(env, "def [answer] 41"), LerrType::ReservedSymbol);
+        eval_str(env, "undef! [answer]").unwrap();
+        eval_str(env, "def [answer] 41").unwrap();
+
+        // and only undef! removes one, so a slip can't take + away
+        assert_eq!(etype(env, "undef [-]"), LerrType::ReservedSymbol);
+        assert_eq!(
+            eval_str(env, "undef [-]").unwrap_err().message(),
+            "Function undef can't remove the builtin \"-\", undef! can"
+        );
+        assert_eq!(eval_str(env, "- 5 1"), Ok(Lval::Int(4)));
+        // a builtin replaced with def! is an ordinary name again
+        eval_str(env, "undef [+]").unwrap();
+        assert_eq!(env.get("+"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_returns_and_destructures_values() {
+        let env = &mut init_env();
+        let shown = |env: &mut Lenv, input| eval_str(env, input).unwrap().to_string();
+        assert_eq!(shown(env, "div-mod 17 5"), "(values 3 2)");
+        assert_eq!(shown(env, "div-mod -17 5"), "(values -4 3)");
+        assert_eq!(
+            eval_str(env, "div-mod 1 0").unwrap_err().etype,
+            LerrType::DivZero
+        );
+
+        eval_str(env, "def [[q r]] (div-mod 17 5)").unwrap();
+        assert_eq!(env.get("q"), Some(Lval::Num(3_f64)));
+        assert_eq!(env.get("r"), Some(Lval::Num(2_f64)));
+        assert!(env.take_warnings().is_empty());
+
+        // anywhere else only the first value is used
+        assert_eq!(
+            eval_str(env, "+ 1 (div-mod 17 5)").unwrap(),
+            Lval::Num(4_f64)
+        );
+        eval_str(env, "def [x] (div-mod 9 2)").unwrap();
+        assert_eq!(env.get("x"), Some(Lval::Num(4_f64)));
+        assert_eq!(
+            env.take_warnings(),
+            vec![
+                "(values 3 2) was given where one value was expected, keeping 3",
+                "(values 4 1) was given where one value was expected, keeping 4",
+            ]
+        );
+
+        // patterns nest, and unpack plain lists too
+        eval_str(env, "def [[a [b c]] [d e]] (values 1 (values 2 3)) [4 [5]]").unwrap();
+        assert_eq!(
+            ["a", "b", "c", "d", "e"].map(|name| env.get(name).unwrap().to_string()),
+            ["1", "2", "3", "4", "[5]"]
+        );
+        assert_eq!(shown(env, "list 0 (values 1 2)"), "[0 1]");
+        assert_eq!(env.take_warnings().len(), 1);
+
+        let etype = |env: &mut Lenv, input: &str| eval_str(env, input).unwrap_err().etype;
+        assert_eq!(
+            etype(env, "def [[a b]] (values 1)"),
+            LerrType::IncorrectParamCount
+        );
+        assert_eq!(etype(env, "def [[a b]] 5"), LerrType::WrongType);
+        assert_eq!(etype(env, "def [1] 5"), LerrType::WrongType);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_checks_contracts() {
+        let env = &mut init_env();
+        eval_str(env, "def [positive] (\\ [n] [> n 0])").unwrap();
+        eval_str(env, "def [small] (\\ [n] [< n 10])").unwrap();
+        let violation = |env: &mut Lenv, input: &str| {
+            let err = eval_str(env, input).unwrap_err();
+            assert_eq!(err.etype, LerrType::ContractViolation, "{}", input);
+            err.message
+        };
+
+        // at definition time, and again whenever the binding changes
+        assert_eq!(eval_str(env, "def-checked [n positive] 5"), Ok(Lval::Nil));
+        assert_eq!(
+            violation(env, "def [n] -1"),
+            "n needed to satisfy positive but was given -1"
+        );
+        assert_eq!(eval_str(env, "n"), Ok(Lval::Num(5_f64)));
+        assert!(eval_str(env, "= [n] 6").is_ok());
+        assert_eq!(
+            violation(env, "def-checked [m (\\ [x] [== x 0])] 2"),
+            "m needed to satisfy (\\ [x] [== x 0]) but was given 2"
+        );
+        assert!(eval_str(env, "m").is_err());
+
+        // and on every call, naming the parameter
+        eval_str(env, "fun-checked [add [a positive] b [c small]] [+ a b c]").unwrap();
+        assert_eq!(eval_str(env, "add 1 -2 3"), Ok(Lval::Num(2_f64)));
+        assert_eq!(
+            violation(env, "add 1 2 30"),
+            "c needed to satisfy small but was given 30"
+        );
+        assert_eq!(
+            violation(env, "add -1 2 3"),
+            "a needed to satisfy positive but was given -1"
+        );
+        assert_eq!(
+            violation(env, "(add 1) 2 30"),
+            "c needed to satisfy small but was given 30"
+        );
+        assert_eq!(
+            eval_str(env, "fn-contracts add").map(|lval| format!("{:?}", lval)),
+            Ok("[[a positive] [c small]]".to_string())
+        );
+
+        // builtins make contracts too, anything else doesn't
+        eval_str(env, "fun-checked [zero [z !]] [z]").unwrap();
+        assert_eq!(eval_str(env, "zero 0"), Ok(Lval::Num(0_f64)));
+        violation(env, "zero 1");
+        assert_eq!(
+            eval_str(env, "def-checked [k 5] 5").unwrap_err().etype,
+            LerrType::WrongType
+        );
+    }
+
+    #[test]
+    fn it_finds_problems_in_folds() {
+        let cases = vec![
+            (
+                "+",
+                2,
+                1e308,
+                1e308,
+                "step 2 of '+': 1e308 + 1e308 overflowed",
+            ),
+            (
+                "-",
+                3,
+                -1e308,
+                1e308,
+                "step 3 of '-': -1e308 - 1e308 overflowed",
+            ),
+            (
+                "*",
+                2,
+                1e308,
+                1e308,
+                "step 2 of '*': 1e308 * 1e308 overflowed",
+            ),
+            (
+                "*",
+                2,
+                1e-200,
+                1e-200,
+                "step 2 of '*': 1e-200 * 1e-200 underflowed to 0",
+            ),
+            (
+                "/",
+                2,
+                1e300,
+                1e-10,
+                "step 2 of '/': 1e300 / 1e-10 overflowed",
+            ),
+            (
+                "/",
+                4,
+                1e-300,
+                1e100,
+                "step 4 of '/': 1e-300 / 1e100 underflowed to 0",
+            ),
+            ("%", 2, 1.0, 0.0, "step 2 of '%': 1 % 0 is not a number"),
+        ];
+        for (sym, step, x, y, expected) in cases {
+            let result = match sym {
+                "+" => x + y,
+                "-" => x - y,
+                "*" => x * y,
+                "/" => x / y,
+                _ => x % y,
+            };
+            assert_eq!(fold_problem(sym, step, x, y, result).unwrap(), expected);
+        }
+
+        // fine results, and problems already there before this step
+        assert_eq!(fold_problem("+", 2, 1.0, -1.0, 0.0), None);
+        assert_eq!(fold_problem("*", 2, 0.0, 5.0, 0.0), None);
+        assert_eq!(fold_problem("*", 3, f64::INFINITY, 0.0, f64::NAN), None);
+    }
+
+    #[test]
+    fn it_warns_or_fails_on_overflow() {
+        let operands = || vec![Lval::Num(1e308), Lval::Num(1e308), Lval::Num(0_f64)];
+
+        let env = &mut init_env();
+        let result = builtin_mul(env, operands()).unwrap();
+        assert!(to_num(result).unwrap().is_nan());
+        assert_eq!(
+            env.take_warnings(),
+            vec!["step 2 of '*': 1e308 * 1e308 overflowed"]
+        );
+        assert!(env.take_warnings().is_empty());
+
+        env.set_strict_arithmetic(true);
+        let err = builtin_mul(env, operands()).unwrap_err();
+        assert_eq!(err.etype, LerrType::BadNum);
+        assert_eq!(err.message, "step 2 of '*': 1e308 * 1e308 overflowed");
+        assert_eq!(
+            builtin_add(env, vec![Lval::Num(0.1), Lval::Num(0.2)]).map(to_num),
+            Ok(Some(0.1 + 0.2))
+        );
+        assert!(env.take_warnings().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_keeps_whole_numbers_whole() {
+        let env = &mut init_env();
+        // `Debug` tells `3` from `3.0`, which `==` doesn't
+        let cases = [
+            ("+ 1 2", "3"),
+            ("+ 1 2.0", "3.0"),
+            ("+ 1.5 1.5", "3.0"),
+            ("- 5 7", "-2"),
+            ("- 5", "-5"),
+            ("- 2.5", "-2.5"),
+            ("* 3 4", "12"),
+            ("* 3 0.5", "1.5"),
+            ("* 2.5 2", "5.0"),
+            ("/ 10 2", "5"),
+            ("/ 10 4", "2.5"),
+            ("/ 10.0 2", "5.0"),
+            ("/ -9 3", "-3"),
+            ("/ 1 3 3", "0.1111111111111111"),
+            ("% 7 3", "1"),
+            ("% -7 3", "-1"),
+            ("% 7.5 2", "1.5"),
+            ("! 0", "true"),
+            ("! 2.5", "false"),
+            // overflowing goes over to floats rather than wrapping
+            ("+ 9223372036854775807 1", "9.223372036854776e18"),
+            ("* 4611686018427387904 2", "9.223372036854776e18"),
+            ("- -9223372036854775808", "9.223372036854776e18"),
+            ("/ -9223372036854775808 -1", "9.223372036854776e18"),
+            ("+ 9223372036854775807 1 -1", "9.223372036854776e18"),
+            ("== 1 1.0", "true"),
+            ("== [1 [2]] [1.0 [2.0]]", "true"),
+            ("== 2 2.5", "false"),
+            ("!= 2 2.0", "false"),
+            ("< 1 1.5", "true"),
+            (">= 2 2.0", "true"),
+            ("<= -0.5 -1", "false"),
+            // compared exactly, though both sides are the same float
+            ("== 9007199254740993 9007199254740992.0", "false"),
+            ("> 9007199254740993 9007199254740992.0", "true"),
+            ("< 9223372036854775807 9223372036854775808.0", "true"),
+            ("div-mod 7 -2", "(values -4 -1)"),
+            ("div-mod 7.0 2", "(values 3.0 1.0)"),
+            ("len [1 2]", "2"),
+            ("sort-any [2.5 1 2.0 1.5 2]", "[1 1.5 2.0 2 2.5]"),
+            ("head (bytes [7.0 8])", "7"),
+        ];
+        for (input, expected) in cases {
+            let result = eval_str(env, input).unwrap();
+            assert_eq!(format!("{:?}", result), expected, "{}", input);
+        }
+
+        for input in ["/ 1 0", "/ 1 0.0", "/ 1.5 0", "div-mod 1 0"] {
+            assert_eq!(
+                eval_str(env, input).unwrap_err().etype,
+                LerrType::DivZero,
+                "{}",
+                input
+            );
+        }
+        assert!(env.take_warnings().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_does_math() {
+        let env = &mut init_env();
+        let cases = [
+            ("mod 7 3", "1"),
+            ("mod -7 3", "2"),
+            ("mod 7 -3", "-2"),
+            ("mod 7.5 2", "1.5"),
+            ("mod -1.5 1", "0.5"),
+            ("mod -9223372036854775808 -1", "0"),
+            ("pow 2 10", "1024"),
+            ("pow -3 3", "-27"),
+            ("pow 2 -1", "0.5"),
+            ("pow 4 0.5", "2.0"),
+            ("pow 2.0 3", "8.0"),
+            ("pow 2 64", "1.8446744073709552e19"),
+            ("min 3", "3"),
+            ("min 3 1.5 2", "1.5"),
+            ("max 3 1.5 2", "3"),
+            ("max 2 2.0", "2"),
+            ("min -1 -1.0", "-1"),
+            ("abs -4", "4"),
+            ("abs -4.5", "4.5"),
+            ("abs -9223372036854775808", "9.223372036854776e18"),
+            ("floor 2.7", "2"),
+            ("floor -2.5", "-3"),
+            ("ceil 2.1", "3"),
+            ("ceil -2.5", "-2"),
+            ("round 2.5", "3"),
+            ("round -2.5", "-3"),
+            ("round 2.4", "2"),
+            ("floor 7", "7"),
+            ("round 1e300", "1e300"),
+            ("sqrt 16", "4.0"),
+            ("sqrt 2.25", "1.5"),
+            ("sqrt 0", "0.0"),
+            ("exp 0", "1.0"),
+            ("log 1", "0.0"),
+            ("log 0", "-inf"),
+            ("round (* 100 (log (exp 2)))", "200"),
+        ];
+        for (input, expected) in cases {
+            let result = eval_str(env, input).unwrap();
+            assert_eq!(format!("{:?}", result), expected, "{}", input);
+        }
+        assert!(env.take_warnings().is_empty());
+
+        let errors = [
+            ("mod 1 0", LerrType::DivZero),
+            ("mod 1.5 0.0", LerrType::DivZero),
+            ("mod 1", LerrType::IncorrectParamCount),
+            ("pow 1 2 3", LerrType::IncorrectParamCount),
+            ("pow 2 \"x\"", LerrType::BadNum),
+            ("max 1 [2]", LerrType::BadNum),
+            ("floor :k", LerrType::BadNum),
+            ("sqrt -1", LerrType::BadNum),
+            ("log -0.5", LerrType::BadNum),
+            ("exp 1 2", LerrType::IncorrectParamCount),
+        ];
+        for (input, etype) in errors {
+            assert_eq!(eval_str(env, input).unwrap_err().etype, etype, "{}", input);
+        }
+        for fun in &[builtin_min as Lfun, builtin_abs] {
+            let err = fun(env, vec![]).unwrap_err();
+            assert_eq!(err.etype, LerrType::IncorrectParamCount);
+        }
+        assert_eq!(
+            eval_str(env, "sqrt -1").unwrap_err().message,
+            "Function sqrt needed a number no less than 0 but was given -1"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_builds_ranges_and_repeats() {
+        let env = &mut init_env();
+        let cases = [
+            ("range 5", "[0 1 2 3 4]"),
+            ("range 0", "[]"),
+            ("range 2 5", "[2 3 4]"),
+            ("range 0 10 3", "[0 3 6 9]"),
+            ("range 5 0 -2", "[5 3 1]"),
+            ("range 3 3", "[]"),
+            ("range 0 1 0.25", "[0.0 0.25 0.5 0.75]"),
+            ("range 1.5 4", "[1.5 2.5 3.5]"),
+            (
+                "range 9223372036854775806 9223372036854775807",
+                "[9223372036854775806]",
+            ),
+            ("repeat 3 \"x\"", "[\"x\" \"x\" \"x\"]"),
+            ("repeat 2 [1 2]", "[[1 2] [1 2]]"),
+            ("repeat 0 1", "[]"),
+        ];
+        for (input, expected) in cases {
+            let result = eval_str(env, input).unwrap();
+            assert_eq!(format!("{:?}", result), expected, "{}", input);
+        }
+
+        let errors = [
+            ("range -1", LerrType::BadNum),
+            ("range 2.5", LerrType::BadNum),
+            ("range 0 5 0", LerrType::BadNum),
+            ("range 0 5 -1", LerrType::BadNum),
+            ("range 5 0", LerrType::BadNum),
+            ("range 0 1e400", LerrType::BadNum),
+            ("range 1 :k", LerrType::BadNum),
+            ("range 1 2 3 4", LerrType::IncorrectParamCount),
+            ("repeat -1 \"x\"", LerrType::BadNum),
+            ("repeat 1.5 \"x\"", LerrType::BadNum),
+            ("repeat 1", LerrType::IncorrectParamCount),
+            ("range 1e12", LerrType::SizeLimit),
+            ("range 0 1 1e-12", LerrType::SizeLimit),
+            (
+                "range -9223372036854775808 9223372036854775807",
+                LerrType::SizeLimit,
+            ),
+            ("repeat 1e12 0", LerrType::SizeLimit),
+        ];
+        for (input, etype) in errors {
+            assert_eq!(eval_str(env, input).unwrap_err().etype, etype, "{}", input);
+        }
+        assert_eq!(
+            eval_str(env, "range 5 0").unwrap_err().message,
+            "Function range needed a step from 5 towards 0 but was given 1"
+        );
+    }
+
+    #[test]
+    fn it_compares_approximately() {
+        let within = Tolerance::default();
+        let exact = Tolerance::Within {
+            relative: 0.0,
+            absolute: 0.0,
+        };
+        let tiny = f64::MIN_POSITIVE / 4.0;
+        let cases = vec![
+            (0.1 * 3.0, 0.3, within, true),
+            (0.0, -0.0, exact, true),
+            (0.0, 1e-13, within, true),
+            (0.0, 1e-13, exact, false),
+            (tiny, -tiny, within, true),
+            (tiny, -tiny, Tolerance::Ulps(1_000), false),
+            (tiny, tiny * 2.0, Tolerance::Ulps(1 << 50), true),
+            (1e300, 1e300 * (1.0 + 1e-12), within, true),
+            (1e300, 1.001e300, within, false),
+            (-1.0, 1.0, within, false),
+            (1.0, 1.0 + f64::EPSILON, Tolerance::Ulps(1), true),
+            (1.0, 1.0 + 2.0 * f64::EPSILON, Tolerance::Ulps(1), false),
+            (f64::INFINITY, f64::INFINITY, exact, true),
+            (f64::INFINITY, f64::MAX, within, false),
+            (f64::NAN, f64::NAN, Tolerance::Ulps(u64::MAX), false),
+        ];
+
+        for (x, y, tolerance, expected) in cases {
+            assert_eq!(
+                approx_eq(x, y, tolerance),
+                expected,
+                "{} ~ {} with {:?}",
+                x,
+                y,
+                tolerance
+            );
+            assert_eq!(approx_eq(y, x, tolerance), expected);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_has_approx_eq() {
+        let env = &mut init_env();
+        let truth = |env: &mut Lenv, input: &str| eval_str(env, input).map(to_bool);
+
+        assert_eq!(truth(env, "== (* 0.1 3) 0.3"), Ok(Some(false)));
+        assert_eq!(truth(env, "approx= (* 0.1 3) 0.3"), Ok(Some(true)));
+        assert_eq!(truth(env, "approx= 100 101 :rel 0.01"), Ok(Some(true)));
+        assert_eq!(truth(env, "approx= 100 101 :abs 0.5"), Ok(Some(false)));
+        assert_eq!(
+            truth(env, "approx= 1 (+ 1 0.0000000000000002) :ulps 1"),
+            Ok(Some(true))
+        );
+
+        let etype = |env: &mut Lenv, input: &str| eval_str(env, input).unwrap_err().etype;
+        assert_eq!(etype(env, "approx= 1 \"1\""), LerrType::BadNum);
+        assert_eq!(etype(env, "approx= 1"), LerrType::IncorrectParamCount);
+        assert_eq!(
+            etype(env, "approx= 1 1 :rel"),
+            LerrType::IncorrectParamCount
+        );
+        assert_eq!(etype(env, "approx= 1 1 :rel -1"), LerrType::BadNum);
+        assert_eq!(etype(env, "approx= 1 1 :near 1"), LerrType::BadOp);
+        assert_eq!(etype(env, "approx= 1 1 :ulps 2 :abs 1"), LerrType::BadOp);
+    }
+
+    #[test]
+    fn it_correctly_uses_head() {
+        let env = &mut init_env();
+        let expr = Lval::Qexpr(vec![
+            Lval::Sym(String::from("+")),
+            Lval::Num(1_f64),
+            Lval::Sexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Num(1_f64),
+            ]),
+        ]);
+        assert_eq!(
+            builtin_head(env, vec![expr.clone()]).unwrap(),
+            Lval::Sym(String::from("+"))
+        );
+
+        let _ = builtin_head(env, vec![])
+            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
+
+        let _ = builtin_head(env, vec![Lval::Sym(String::from("+"))])
+            .map_err(|err| assert_eq!(err.etype, LerrType::WrongType));
+
+        let _ = builtin_head(env, vec![Lval::Qexpr(vec![])])
+            .map_err(|err| assert_eq!(err.etype, LerrType::EmptyList));
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_correctly_uses_len_nth_and_last() {
+        let env = &mut init_env();
+        assert_eq!(eval_str(env, "len [1 2 3]").unwrap(), Lval::Num(3_f64));
+        assert_eq!(eval_str(env, "len []").unwrap(), Lval::Num(0_f64));
+        assert_eq!(eval_str(env, "len \"héllo\"").unwrap(), Lval::Num(5_f64));
+        assert_eq!(
+            eval_str(env, "nth 0 [a b c]").unwrap(),
+            Lval::Sym("a".into())
+        );
+        assert_eq!(
+            eval_str(env, "nth 2 [a b c]").unwrap(),
+            Lval::Sym("c".into())
+        );
+        assert_eq!(
+            eval_str(env, "nth 1 #bytes[0aff]").unwrap(),
+            Lval::Num(255_f64)
+        );
+        assert_eq!(
+            eval_str(env, "last [a b c]").unwrap(),
+            Lval::Sym("c".into())
+        );
+        assert_eq!(
+            eval_str(env, "last \"abc\"").unwrap(),
+            Lval::Str("c".into())
+        );
+
+        for (input, etype) in &[
+            ("len [1] [2]", LerrType::IncorrectParamCount),
+            ("len 1", LerrType::WrongType),
+            ("nth [1 2]", LerrType::IncorrectParamCount),
+            ("nth 0 1", LerrType::WrongType),
+            ("nth 3 [a b c]", LerrType::BadNum),
+            ("nth -1 [a b c]", LerrType::BadNum),
+            ("nth 0.5 [a b c]", LerrType::BadNum),
+            ("nth [0] [a b c]", LerrType::BadNum),
+            ("nth 0 []", LerrType::EmptyList),
+            ("last :a", LerrType::WrongType),
+            ("last []", LerrType::EmptyList),
+        ] {
+            assert_eq!(eval_str(env, input).unwrap_err().etype, *etype, "{}", input);
+        }
+        assert_eq!(
+            builtin_last(env, vec![]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_takes_drops_and_finds_members() {
+        let env = &mut init_env();
+        let shown = |env: &mut Lenv, input| eval_str(env, input).unwrap().to_string();
+        assert_eq!(shown(env, "take 2 [a b c]"), "[a b]");
+        assert_eq!(shown(env, "take 5 [a b c]"), "[a b c]");
+        assert_eq!(shown(env, "drop 1 [a b c]"), "[b c]");
+        assert_eq!(shown(env, "drop 5 [a b c]"), "[]");
+        assert_eq!(shown(env, "take 2 \"héllo\""), "\"hé\"");
+        assert_eq!(shown(env, "drop 2 #bytes[\"0a0b0c\"]"), "#bytes[\"0c\"]");
+        assert_eq!(shown(env, "member? :b [:a :b :c]"), "true");
+        assert_eq!(shown(env, "member? :d [:a :b :c]"), "false");
+        assert_eq!(shown(env, "member? \"é\" \"héllo\""), "true");
+        assert_eq!(shown(env, "member? 11 #bytes[\"0a0b\"]"), "true");
+
+        for (input, etype) in &[
+            ("take 1", LerrType::IncorrectParamCount),
+            ("take -1 [a]", LerrType::BadNum),
+            ("drop 0.5 [a]", LerrType::BadNum),
+            ("drop 1 :a", LerrType::WrongType),
+            ("member? 1 2", LerrType::WrongType),
+        ] {
+            assert_eq!(eval_str(env, input).unwrap_err().etype, *etype, "{}", input);
+        }
+    }
+
+    #[test]
+    fn it_correctly_uses_tail() {
+        let env = &mut init_env();
+        let expr = Lval::Qexpr(vec![
+            Lval::Sym(String::from("+")),
+            Lval::Num(1_f64),
+            Lval::Sexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Num(1_f64),
+            ]),
+        ]);
+        assert_eq!(
+            builtin_tail(env, vec![expr.clone()]).unwrap(),
+            Lval::Qexpr(vec![
+                Lval::Num(1_f64),
+                Lval::Sexpr(vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Num(1_f64),
+                    Lval::Num(1_f64),
+                ])
+            ])
+        );
+        let _ = builtin_tail(env, vec![])
+            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
+
+        let _ = builtin_tail(env, vec![Lval::Sym(String::from("+"))])
+            .map_err(|err| assert_eq!(err.etype, LerrType::WrongType));
+
+        let _ = builtin_tail(env, vec![Lval::Qexpr(vec![])])
+            .map_err(|err| assert_eq!(err.etype, LerrType::EmptyList));
+    }
+
+    #[test]
+    fn it_correctly_uses_list() {
+        let env = &mut init_env();
+        let expr = vec![
+            Lval::Sym(String::from("+")),
+            Lval::Num(1_f64),
+            Lval::Sexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Num(1_f64),
+            ]),
+        ];
+        assert_eq!(
+            builtin_list(env, expr.clone()).unwrap(),
+            Lval::Qexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Sexpr(vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Num(1_f64),
+                    Lval::Num(1_f64),
+                ])
+            ])
+        );
+        assert_eq!(
+            builtin_list(
+                env,
+                vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Num(1_f64),
+                    Lval::Num(1_f64),
+                ]
+            )
+            .unwrap(),
+            Lval::Qexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Num(1_f64),
+            ])
+        );
+        assert_eq!(builtin_list(env, vec![]).unwrap(), Lval::Qexpr(vec![]));
+        assert_eq!(
+            builtin_list(env, vec![Lval::Sym(String::from("+"))]).unwrap(),
+            Lval::Qexpr(vec![Lval::Sym(String::from("+")),])
+        );
+        assert_eq!(
+            builtin_list(env, vec![Lval::Sexpr(vec![])]).unwrap(),
+            Lval::Qexpr(vec![Lval::Sexpr(vec![]),])
+        );
+    }
+
+    #[test]
+    fn it_correctly_uses_eval() {
+        let env = &mut init_env();
+        let expr = Lval::Qexpr(vec![
+            Lval::Sym(String::from("+")),
+            Lval::Num(1_f64),
+            Lval::Sexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Num(1_f64),
+            ]),
+        ]);
+        assert_eq!(
+            builtin_eval(env, vec![expr.clone()]).unwrap(),
+            Lval::Num(3_f64)
+        );
+
+        let _ = builtin_eval(env, vec![expr.clone(), expr.clone()])
+            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
+
+        let _ = builtin_eval(env, vec![])
+            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
+
+        assert_eq!(
+            builtin_eval(env, vec![Lval::Sym(String::from("-"))]).unwrap(),
+            Lval::Fun(builtin_sub)
+        );
+        assert_eq!(
+            builtin_eval(env, vec![Lval::Sexpr(vec![Lval::Sym(String::from("-"))])]).unwrap(),
+            Lval::Fun(builtin_sub)
+        );
+        assert_eq!(
+            builtin_eval(env, vec![Lval::Qexpr(vec![])]).unwrap(),
+            Lval::Nil
+        );
+    }
+
+    #[cfg(feature = "parser")]
+    #[test]
+    fn it_correctly_evals_strings() {
+        let env = &mut init_env();
+        assert_eq!(
+            builtin_eval(env, vec![Lval::Str(String::from("(+ 1 2)"))]).unwrap(),
+            Lval::Num(3_f64)
+        );
+        assert_eq!(
+            builtin_eval(env, vec![Lval::Str(String::from("* 2 (+ 1 2)"))]).unwrap(),
+            Lval::Num(6_f64)
+        );
+
+        let err = builtin_eval(env, vec![Lval::Str(String::from("(+ 1 2"))]).unwrap_err();
+        assert_eq!(err.etype, LerrType::Syntax);
+    }
+
+    #[cfg(not(feature = "parser"))]
+    #[test]
+    fn it_refuses_to_eval_strings_without_parser() {
+        let env = &mut init_env();
+        let err = builtin_eval(env, vec![Lval::Str(String::from("(+ 1 2)"))]).unwrap_err();
+        assert_eq!(err.etype, LerrType::Syntax);
+        assert!(err.message.contains("parser is not available"));
+    }
+
+    #[test]
+    fn it_introspects_the_build() {
+        let env = &mut init_env();
+        assert_eq!(
+            eval::eval(env, Lval::Sexpr(vec![Lval::Sym(String::from("version"))])).unwrap(),
+            Lval::Str(String::from(env!("CARGO_PKG_VERSION")))
+        );
+
+        let features =
+            eval::eval(env, Lval::Sexpr(vec![Lval::Sym(String::from("features"))])).unwrap();
+        assert_eq!(to_qexpr(features).unwrap().len(), crate::features().len());
+    }
+
+    #[cfg(feature = "parser")]
+    fn explain(env: &mut Lenv, input: &str) -> Result<Vec<String>, Lerr> {
+        eval_str(env, input).map(|trace| {
+            to_qexpr(trace)
+                .unwrap()
+                .into_iter()
+                .map(|step| to_str(step).unwrap())
+                .collect()
+        })
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_explains_evaluation() {
+        let env = &mut init_env();
+        assert_eq!(
+            explain(env, "explain [(+ 1 (* 2 3) (- 10 (/ 8 2)))]").unwrap(),
+            vec![
+                "(+ 1 (* 2 3) (- 10 (/ 8 2)))",
+                "(+ 1 6 (- 10 (/ 8 2)))",
+                "(+ 1 6 (- 10 4))",
+                "(+ 1 6 6)",
+                "13",
+            ]
+        );
+        assert_eq!(explain(env, "explain [5]").unwrap(), vec!["5"]);
+
+        eval_str(env, "def [x] 2").unwrap();
+        assert_eq!(explain(env, "explain [x]").unwrap(), vec!["x", "2"]);
+        assert_eq!(
+            explain(env, "explain [(if (- x 2) [nope] (+ x 1))]").unwrap(),
+            vec!["(if (- x 2) [nope] (+ x 1))", "(if 0 [nope] (+ x 1))", "3"]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_explains_up_to_the_failure() {
+        let env = &mut init_env();
+        let err = explain(env, "explain [(+ (* 2 3) (- 4 1) (head []))]").unwrap_err();
+        assert_eq!(err.etype, LerrType::EmptyList);
+        assert_eq!(
+            err.message,
+            "Function explain failed at step 3; Function head was given empty list; \
+             trace: (+ (* 2 3) (- 4 1) (head [])) => (+ 6 (- 4 1) (head [])) => (+ 6 3 (head []))"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_limits_explanations() {
+        let env = &mut init_env();
+        let long = format!("explain [(+ {})]", vec!["(+ 1)"; 150].join(" "));
+        let trace = explain(env, &long).unwrap();
+        assert_eq!(trace.len(), EXPLAIN_MAX_STEPS + 1);
+        assert_eq!(trace[EXPLAIN_MAX_STEPS], "... stopped after 100 steps");
+
+        let wide = format!("explain [(+ {})]", vec!["1"; 100].join(" "));
+        let trace = explain(env, &wide).unwrap();
+        assert!(trace[0].ends_with("..."));
+        assert_eq!(trace[0].chars().count(), EXPLAIN_MAX_WIDTH + 3);
+        assert_eq!(trace[1], "100");
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_reports_and_limits_env_size() {
+        let env = &mut init_env();
+        let sizes = |env: &mut Lenv| {
+            to_qexpr(eval_str(env, "(env-size)").unwrap())
+                .unwrap()
+                .into_iter()
+                .map(|n| to_num(n).unwrap() as usize)
+                .collect::<Vec<_>>()
+        };
+
+        let before = sizes(env);
+        eval_str(env, "def [a b] 1 \"two\"").unwrap();
+        let after = sizes(env);
+        assert_eq!(after[0], before[0] + 2);
+        assert!(after[1] > before[1]);
+
+        env.set_max_bindings(Some(after[0]));
+        let err = eval_str(env, "def [c] 3").unwrap_err();
+        assert_eq!(err.etype, LerrType::EnvLimit);
+        // calling a lambda binds its args in its own frame, which still works
+        assert_eq!(eval_str(env, "(\\ [x] [x]) 1").unwrap(), Lval::Num(1_f64));
+
+        eval_str(env, "undef [a]").unwrap();
+        eval_str(env, "def [c] 3").unwrap();
+        assert_eq!(sizes(env)[0], after[0]);
+
+        let err = eval_str(env, "undef [a]").unwrap_err();
+        assert_eq!(err.etype, LerrType::UnboundSymbol);
+        let err = eval_str(env, "undef a").unwrap_err();
+        assert_eq!(err.etype, LerrType::UnboundSymbol);
+        let err = eval_str(env, "undef 1").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_round_trips_bytes() {
+        let env = &mut init_env();
+        eval_str(env, "def [data] (bytes [0 1 127 128 222 173 190 239 255])").unwrap();
+
+        let hex = eval_str(env, "hex-encode data").unwrap();
+        assert_eq!(to_str(hex).unwrap(), "00017f80deadbeefff");
+        assert_eq!(
+            eval_str(env, "hex-decode (hex-encode data)").unwrap(),
+            env.get("data").unwrap()
+        );
+        assert_eq!(
+            eval_str(env, "hex-decode \"DEADbeef\"").unwrap(),
+            Lval::Bytes(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+
+        for (plain, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ]
+        .iter()
+        {
+            let input = format!("base64-encode (str->bytes \"{}\")", plain);
+            assert_eq!(to_str(eval_str(env, &input).unwrap()).unwrap(), *encoded);
+            let input = format!("base64-decode \"{}\"", encoded);
+            assert_eq!(
+                eval_str(env, &input).unwrap(),
+                Lval::Bytes(plain.as_bytes().to_vec())
+            );
+        }
+        assert_eq!(
+            eval_str(env, "base64-decode (base64-encode data)").unwrap(),
+            env.get("data").unwrap()
+        );
+
+        assert_eq!(
+            eval_str(env, "bytes-slice data 4 8").unwrap(),
+            Lval::Bytes(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(eval_str(env, "bytes-len data").unwrap(), Lval::Num(9_f64));
+        assert_eq!(
+            format!("{:?}", env.get("data").unwrap()),
+            "#bytes[\"0001 7f80 dead beef ff\"]"
+        );
+        assert_eq!(
+            format!("{:?}", Lval::Bytes(vec![0; 40])),
+            format!("#bytes[\"{}…\"]", vec!["0000"; 16].join(" "))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_rejects_bad_bytes() {
+        let env = &mut init_env();
+        eval_str(env, "def [bad] (bytes [104 105 255])").unwrap();
+
+        let err = eval_str(env, "bytes->str bad").unwrap_err();
+        assert_eq!(err.etype, LerrType::BadNum);
+        assert!(err.message.contains("invalid UTF-8 at byte 2"));
+        assert_eq!(
+            to_str(eval_str(env, "bytes->str-lossy bad").unwrap()).unwrap(),
+            "hi\u{fffd}"
+        );
+        assert_eq!(
+            to_str(eval_str(env, "bytes->str (str->bytes \"héllo\")").unwrap()).unwrap(),
+            "héllo"
+        );
+
+        for input in [
+            "bytes [256]",
+            "bytes [1.5]",
+            "bytes [-1]",
+            "bytes-slice bad 2 1",
+            "bytes-slice bad 0 4",
+            "hex-decode \"abc\"",
+            "hex-decode \"zz\"",
+            "base64-decode \"Zm9\"",
+            "base64-decode \"Z===\"",
+            "base64-decode \"Zm9!\"",
+        ]
+        .iter()
+        {
+            let err = eval_str(env, input).unwrap_err();
+            assert_eq!(err.etype, LerrType::BadNum, "{}", input);
+        }
+
+        let err = eval_str(env, "bytes-len \"hi\"").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        let err = eval_str(env, "str->bytes bad").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_introspects_functions() {
+        let env = &mut init_env();
+        let sym = |s: &str| Lval::Sym(String::from(s));
+        let arity = |min: f64, max: Option<f64>| {
+            Lval::Qexpr(vec![
+                Lval::Num(min),
+                max.map(Lval::Num).unwrap_or_else(|| Lval::Qexpr(vec![])),
+            ])
+        };
+
+        eval_str(env, "def [add] (\\ [a b] [+ a b])").unwrap();
+        assert_eq!(
+            eval_str(env, "fn-args add").unwrap(),
+            Lval::Qexpr(vec![sym("a"), sym("b")])
+        );
+        assert_eq!(
+            eval_str(env, "fn-body add").unwrap(),
+            Lval::Qexpr(vec![sym("+"), sym("a"), sym("b")])
+        );
+        assert_eq!(
+            eval_str(env, "fn-arity add").unwrap(),
+            arity(2.0, Some(2.0))
+        );
+
+        eval_str(env, "def [all] (\\ [first : rest] [rest])").unwrap();
+        assert_eq!(
+            eval_str(env, "fn-args all").unwrap(),
+            Lval::Qexpr(vec![sym("first"), sym(":"), sym("rest")])
+        );
+        assert_eq!(eval_str(env, "fn-arity all").unwrap(), arity(1.0, None));
+
+        // partially applied, only b is left
+        assert_eq!(
+            eval_str(env, "fn-args (add 1)").unwrap(),
+            Lval::Qexpr(vec![sym("b")])
+        );
+        assert_eq!(
+            eval_str(env, "fn-arity (add 1)").unwrap(),
+            arity(1.0, Some(1.0))
+        );
+
+        assert_eq!(eval_str(env, "fn-args +").unwrap(), Lval::Qexpr(vec![]));
+        assert_eq!(eval_str(env, "fn-body +").unwrap(), Lval::Qexpr(vec![]));
+        assert_eq!(eval_str(env, "fn-arity +").unwrap(), arity(1.0, None));
+        assert_eq!(eval_str(env, "fn-arity list").unwrap(), arity(0.0, None));
+        assert_eq!(
+            eval_str(env, "fn-arity range").unwrap(),
+            arity(1.0, Some(3.0))
+        );
+        assert_eq!(
+            eval_str(env, "fn-arity head").unwrap(),
+            arity(1.0, Some(1.0))
+        );
+        assert_eq!(
+            eval_str(env, "fn-arity nth").unwrap(),
+            arity(2.0, Some(2.0))
+        );
+
+        let err = eval_str(env, "fn-args 1").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        let err = eval_str(env, "fn-arity add add").unwrap_err();
+        assert_eq!(err.etype, LerrType::IncorrectParamCount);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_tells_types_apart() {
+        let env = &mut init_env();
+        eval_str(env, "def [id] (\\ [x] [x])").unwrap();
+        eval_str(env, "def [either] (case-lambda [[x] [x]] [[x y] [y]])").unwrap();
+        let types = [
+            ("1", "number"),
+            ("1.5", "number"),
+            ("(head [a])", "symbol"),
+            ("\"a\"", "string"),
+            ("(head [()])", "sexpr"),
+            ("[1 2]", "qexpr"),
+            ("head", "function"),
+            ("id", "lambda"),
+            ("either", "lambda"),
+            ("#bytes[00]", "bytes"),
+            (":key", "keyword"),
+            ("true", "boolean"),
+            ("nil", "nil"),
+        ];
+        for (value, expected) in &types {
+            let given = eval_str(env, &format!("type {}", value)).unwrap();
+            assert_eq!(given, Lval::Str(expected.to_string()), "{}", value);
+        }
+
+        let is = |env: &mut Lenv, predicate: &str, value: &str| match eval_str(
+            env,
+            &format!("{} {}", predicate, value),
+        )
+        .unwrap()
+        {
+            Lval::Bool(b) => b,
+            other => panic!("{:?}", other),
+        };
+        let predicates = [
+            ("number?", &["1", "1.5"][..]),
+            ("string?", &["\"a\""][..]),
+            ("symbol?", &["(head [a])"][..]),
+            ("list?", &["(head [()])", "[1 2]"][..]),
+            ("fun?", &["head", "id", "either"][..]),
+        ];
+        for (predicate, yes) in &predicates {
+            for (value, _) in &types {
+                assert_eq!(
+                    is(env, predicate, value),
+                    yes.contains(value),
+                    "{} {}",
+                    predicate,
+                    value
+                );
+            }
+        }
+
+        for call in &["type 1 2", "fun? head head"] {
+            let err = eval_str(env, call).unwrap_err();
+            assert_eq!(err.etype, LerrType::IncorrectParamCount, "{}", call);
+        }
+        // a builtin called on nothing evaluates to itself, so go around that
+        let err = builtin_type(env, vec![]).unwrap_err();
+        assert_eq!(err.etype, LerrType::IncorrectParamCount);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_knows_pure_functions() {
+        let env = &mut init_env();
+        let pure = |env: &mut Lenv, f: &str| eval_str(env, &format!("pure? {}", f)).unwrap();
+        for f in &["+", "head", "concat", "==", "hex-encode", "if"] {
+            assert_eq!(pure(env, f), Lval::Bool(true), "{}", f);
+        }
+        for f in &["random", "seed", "def", "eval", "die", "error", "source-of"] {
+            assert_eq!(pure(env, f), Lval::Bool(false), "{}", f);
+        }
+
+        eval_str(
+            env,
+            "def [fib] (\\ [n] [if (< n 2) [n] [+ (fib (- n 1)) (fib (- n 2))]])",
+        )
+        .unwrap();
+        eval_str(env, "def [noisy] (\\ [n] [+ n (random)])").unwrap();
+        eval_str(env, "def [calls-noisy] (\\ [n] [* 2 (noisy n)])").unwrap();
+        assert_eq!(pure(env, "fib"), Lval::Bool(true));
+        assert_eq!(pure(env, "noisy"), Lval::Bool(false));
+        assert_eq!(pure(env, "calls-noisy"), Lval::Bool(false));
+
+        let err = eval_str(env, "pure? 1").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+    }
+
+    #[cfg(feature = "parser")]
+    fn nums(lval: Lval) -> Vec<f64> {
+        to_qexpr(lval)
+            .unwrap()
+            .into_iter()
+            .map(|n| to_num(n).unwrap())
+            .collect()
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_shuffles_reproducibly() {
+        let env = &mut init_env();
+        eval_str(env, "seed 42").unwrap();
+        let first = nums(eval_str(env, "shuffle [1 2 3 4 5 6 7 8]").unwrap());
+        assert_eq!(first, vec![6.0, 3.0, 4.0, 5.0, 2.0, 8.0, 7.0, 1.0]);
+
+        eval_str(env, "seed 42").unwrap();
+        assert_eq!(
+            nums(eval_str(env, "shuffle [1 2 3 4 5 6 7 8]").unwrap()),
+            first
+        );
+
+        // same elements, whatever the order
+        for _ in 0..20 {
+            let mut shuffled = nums(eval_str(env, "shuffle [1 2 3 4 5 6 7 8]").unwrap());
+            shuffled.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(shuffled, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        }
+        assert_eq!(eval_str(env, "shuffle []").unwrap(), Lval::Qexpr(vec![]));
+
+        let random = to_num(eval_str(env, "(random)").unwrap()).unwrap();
+        assert!((0.0..1.0).contains(&random));
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_samples_and_chooses() {
+        let env = &mut init_env();
+        for _ in 0..20 {
+            let mut sample = nums(eval_str(env, "sample 3 [1 2 3 4 5]").unwrap());
+            assert_eq!(sample.len(), 3);
+            sample.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            sample.dedup();
+            assert_eq!(sample.len(), 3);
+            assert!(sample.iter().all(|n| (1.0..=5.0).contains(n)));
+
+            let choice = to_num(eval_str(env, "choice [1 2 3]").unwrap()).unwrap();
+            assert!([1.0, 2.0, 3.0].contains(&choice));
+        }
+        assert_eq!(
+            nums(eval_str(env, "sample 0 [1 2]").unwrap()),
+            Vec::<f64>::new()
+        );
+        assert_eq!(nums(eval_str(env, "sample 2 [1 2]").unwrap()).len(), 2);
+
+        let err = eval_str(env, "sample 3 [1 2]").unwrap_err();
+        assert_eq!(err.etype, LerrType::IncorrectParamCount);
+        let err = eval_str(env, "choice []").unwrap_err();
+        assert_eq!(err.etype, LerrType::EmptyList);
+        let err = eval_str(env, "shuffle 1").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_diffs_values() {
+        let env = &mut init_env();
+        assert_eq!(
+            eval_str(env, "diff [1 [2 3] 4] [1 [2 5]]").unwrap(),
+            eval_str(env, "head [[[[1 1] 3 5] [[2] 4 :missing]]]").unwrap()
+        );
+        assert_eq!(
+            eval_str(env, "diff [a [b 1]] [a [b 1]]").unwrap(),
+            Lval::Qexpr(vec![])
+        );
+        assert_eq!(
+            eval_str(env, "diff 1 2").unwrap(),
+            eval_str(env, "head [[[[] 1 2]]]").unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_sorts_any_values() {
+        let env = &mut init_env();
+        assert_eq!(
+            eval_str(env, "sort-any [[2] \"b\" b 3 [1 9] \"a\" 1 [1]]").unwrap(),
+            eval_str(env, "head [[1 3 b \"a\" \"b\" [1] [1 9] [2]]]").unwrap()
+        );
+        assert_eq!(eval_str(env, "sort-any []").unwrap(), Lval::Qexpr(vec![]));
+
+        let err = eval_str(env, "sort-any (list 1 head)").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        let err = eval_str(env, "sort-any 1").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_reverses_sorts_and_dedups() {
+        let env = &mut init_env();
+        let shown = |env: &mut Lenv, input| format!("{:?}", eval_str(env, input).unwrap());
+        assert_eq!(shown(env, "reverse [1 [2 3] a]"), "[a [2 3] 1]");
+        assert_eq!(shown(env, "reverse []"), "[]");
+
+        assert_eq!(shown(env, "sort [3 1.5 -2 1]"), "[-2 1 1.5 3]");
+        assert_eq!(
+            shown(env, "sort [\"b\" \"ab\" \"a\"]"),
+            "[\"a\" \"ab\" \"b\"]"
+        );
+        assert_eq!(shown(env, "sort (list 2 (- 1e400 1e400) 1)"), "[1 2 nan]");
+        assert_eq!(shown(env, "sort [] >"), "[]");
+        assert_eq!(shown(env, "sort [1 3 2] >"), "[3 2 1]");
+        // ties keep their order
+        assert_eq!(
+            shown(
+                env,
+                "sort [[b 2] [a 1] [c 2] [d 1]] (\\ [x y] [< (eval (tail x)) (eval (tail y))])"
+            ),
+            "[[a 1] [d 1] [b 2] [c 2]]"
+        );
+
+        assert_eq!(shown(env, "unique [1 2 1 3 2.0 [1] [1]]"), "[1 2 3 [1]]");
+        assert_eq!(shown(env, "unique []"), "[]");
+
+        // strings and bytes come back as strings and bytes
+        assert_eq!(shown(env, "reverse \"abc\""), "\"cba\"");
+        assert_eq!(shown(env, "reverse #bytes[\"dead\"]"), "#bytes[\"adde\"]");
+        assert_eq!(shown(env, "sort \"banana\""), "\"aaabnn\"");
+        assert_eq!(shown(env, "sort #bytes[03 01 02] >"), "#bytes[\"0302 01\"]");
+        assert_eq!(shown(env, "unique \"banana\""), "\"ban\"");
+        assert_eq!(shown(env, "unique #bytes[01 01 02]"), "#bytes[\"0102\"]");
+
+        let errors = [
+            ("sort [1 \"a\"]", LerrType::WrongType),
+            ("sort [a b]", LerrType::WrongType),
+            ("sort [1 2] 3", LerrType::WrongType),
+            ("sort [1 2] (\\ [x] [x])", LerrType::IncorrectParamCount),
+            ("sort [1 2] > 3", LerrType::IncorrectParamCount),
+            ("reverse 1", LerrType::WrongType),
+            ("unique :k", LerrType::WrongType),
+            ("unique [1] [2]", LerrType::IncorrectParamCount),
+        ];
+        for (input, etype) in errors {
+            assert_eq!(eval_str(env, input).unwrap_err().etype, etype, "{}", input);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_groups_runs_and_splits() {
+        let env = &mut init_env();
+        let shown = |env: &mut Lenv, input| format!("{:?}", eval_str(env, input).unwrap());
+        eval_str(env, "def [odd] (\\ [x] [% x 2])").unwrap();
+
+        assert_eq!(
+            shown(env, "partition-by odd [1 1 2 4 5]"),
+            "[[1 1] [2 4] [5]]"
+        );
+        assert_eq!(shown(env, "partition-by odd [1 3 5]"), "[[1 3 5]]");
+        assert_eq!(
+            shown(env, "partition-by odd [1 2 3 4]"),
+            "[[1] [2] [3] [4]]"
+        );
+        assert_eq!(
+            shown(env, "partition-by head [[a 1] [a 2] [b 3]]"),
+            "[[[a 1] [a 2]] [[b 3]]]"
+        );
+        assert_eq!(shown(env, "partition-by odd []"), "[]");
+
+        eval_str(env, "def [zero] (\\ [x] [== x 0])").unwrap();
+        assert_eq!(shown(env, "split-when zero [1 0 2 0]"), "[[1] [0 2] [0]]");
+        assert_eq!(shown(env, "split-when zero [0 1 2]"), "[[0 1 2]]");
+        assert_eq!(shown(env, "split-when zero [1 2 3]"), "[[1 2 3]]");
+        assert_eq!(shown(env, "split-when zero [0 0 0]"), "[[0] [0] [0]]");
+        assert_eq!(shown(env, "split-when zero []"), "[]");
+
+        let err = eval_str(env, "partition-by head [[a] [] [b]]").unwrap_err();
+        assert_eq!(err.etype, LerrType::EmptyList);
+        assert!(err
+            .message
+            .starts_with("Function partition-by failed on item 1: "));
+        let err = eval_str(env, "split-when odd [1 :x]").unwrap_err();
+        assert_eq!(err.etype, LerrType::BadNum);
+        assert!(err
+            .message
+            .starts_with("Function split-when failed on item 1: "));
+        assert_eq!(
+            eval_str(env, "split-when zero 1").unwrap_err().etype,
+            LerrType::WrongType
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_evaluates_with_given_bindings() {
+        let env = &mut init_env();
+        let shown = |env: &mut Lenv, input| eval_str(env, input).unwrap().to_string();
+        eval_str(env, "def [x] 10").unwrap();
+        eval_str(env, "def [y] 20").unwrap();
+
+        // the bindings shadow globals, which stay visible otherwise
+        assert_eq!(shown(env, "with-env [:x 1] [+ x y]"), "21");
+        assert_eq!(shown(env, "with-env [[x 1] [y 2]] [+ x y]"), "3");
+        assert_eq!(shown(env, "(\\ [z] [with-env [:x 1] [+ x z]]) 5"), "6");
+
+        // only builtins besides the bindings, and nothing defined survives
+        assert_eq!(shown(env, "with-env-only [:x 1] [+ x 2]"), "3");
+        let err = eval_str(env, "with-env-only [:x 1] [+ x y]").unwrap_err();
+        assert_eq!(err.etype, LerrType::UnboundSymbol);
+        eval_str(env, "with-env-only [] [def [w] 1]").unwrap();
+        assert!(env.get("w").is_none());
+
+        // failures leave the environment as it was
+        assert!(eval_str(env, "with-env [:x 1] [head []]").is_err());
+        assert!(eval_str(env, "with-env-only [:x 1] [head []]").is_err());
+        assert_eq!(shown(env, "+ x y"), "30");
+        assert_eq!(env.depth(), 1);
+
+        let etype = |env: &mut Lenv, input: &str| eval_str(env, input).unwrap_err().etype;
+        assert_eq!(etype(env, "with-env [:x] [x]"), LerrType::WrongType);
+        assert_eq!(etype(env, "with-env [x 1] [x]"), LerrType::WrongType);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_binds_names_for_the_length_of_a_let() {
+        let env = &mut init_env();
+        let shown = |env: &mut Lenv, input| eval_str(env, input).unwrap().to_string();
+        eval_str(env, "def [x] 10").unwrap();
+
+        // later bindings see earlier ones, and shadow globals
+        assert_eq!(shown(env, "let [x 1 y (+ x 1)] [+ x y]"), "3");
+        assert_eq!(shown(env, "let [[a b] [1 2] y x] [list a b y]"), "[1 2 10]");
+        assert_eq!(shown(env, "let [y 2] [let [y (* y 3)] [y]]"), "6");
+        assert_eq!(shown(env, "(let [n 5] [\\ [m] [+ n m]]) 1"), "6");
+        assert_eq!(shown(env, "let [] [x]"), "10");
+
+        // the bindings are gone afterwards, failure or not
+        assert_eq!(shown(env, "x"), "10");
+        assert!(env.get("y").is_none());
+        let err = eval_str(env, "let [y 1] [head []]").unwrap_err();
+        assert_eq!(err.etype, LerrType::EmptyList);
+        assert!(env.get("y").is_none());
+        assert_eq!(env.depth(), 1);
+
+        let err = eval_str(env, "let [a 1 b] [a]").unwrap_err();
+        assert_eq!(err.etype, LerrType::IncorrectParamCount);
+        assert!(err.message.contains("pairs of names and values"));
+        let err = eval_str(env, "let [1 2] [1]").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        assert!(env.get("a").is_none());
+        assert_eq!(env.depth(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_maps_filters_and_folds() {
+        let env = &mut init_env();
+        let shown = |env: &mut Lenv, input| eval_str(env, input).unwrap().to_string();
+        eval_str(env, "def [odd] (\\ [x] [% x 2])").unwrap();
+
+        assert_eq!(shown(env, "map (\\ [x] [* x x]) [1 2 3]"), "[1 4 9]");
+        assert_eq!(shown(env, "map head [[a b] [c]]"), "[a c]");
+        assert_eq!(shown(env, "filter odd [1 2 3 4 5]"), "[1 3 5]");
+        assert_eq!(shown(env, "filter odd [2 4]"), "[]");
+        assert_eq!(shown(env, "fold + 0 [1 2 3 4]"), "10");
+        assert_eq!(
+            shown(env, "fold (\\ [acc x] [join (list x) acc]) [] [1 2 3]"),
+            "[3 2 1]"
+        );
+        for empty in &["map odd []", "filter odd []"] {
+            assert_eq!(shown(env, empty), "[]");
+        }
+        assert_eq!(shown(env, "fold + 7 []"), "7");
+
+        // strings and bytes are sequences too
+        assert_eq!(shown(env, "map (\\ [c] [join c c]) \"ab\""), "\"aabb\"");
+        assert_eq!(
+            shown(env, "map (\\ [b] [* b 2]) #bytes[\"0102\"]"),
+            "#bytes[\"0204\"]"
+        );
+        // results that can't go back in the sequence give a list
+        assert_eq!(
+            shown(env, "map (\\ [c] [list c]) \"ab\""),
+            "[[\"a\"] [\"b\"]]"
+        );
+        assert_eq!(
+            shown(env, "map (\\ [b] [* b 200]) #bytes[\"0102\"]"),
+            "[200 400]"
+        );
+        assert_eq!(shown(env, "filter (\\ [c] [!= c \"b\"]) \"abc\""), "\"ac\"");
+        assert_eq!(shown(env, "filter odd #bytes[\"0102\"]"), "#bytes[\"01\"]");
+        assert_eq!(shown(env, "fold + 0 #bytes[\"0102\"]"), "3");
+
+        let err = eval_str(env, "map head [[a] [] [b]]").unwrap_err();
+        assert_eq!(err.etype, LerrType::EmptyList);
+        assert!(err.message.starts_with("Function map failed on item 1: "));
+        let err = eval_str(env, "fold + 0 [1 :x]").unwrap_err();
+        assert_eq!(err.etype, LerrType::BadNum);
+        assert!(err.message.starts_with("Function fold failed on item 1: "));
+
+        let etype = |env: &mut Lenv, input: &str| eval_str(env, input).unwrap_err().etype;
+        assert_eq!(etype(env, "map 1 []"), LerrType::WrongType);
+        assert_eq!(etype(env, "filter [odd] [1]"), LerrType::WrongType);
+        assert_eq!(etype(env, "fold \"+\" 0 []"), LerrType::WrongType);
+        assert_eq!(etype(env, "map odd 1"), LerrType::WrongType);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_fails_with_errors_and_asserts_from_lisp() {
+        let env = &mut init_env();
+        eval_str(
+            env,
+            "fun [check-sum xs expected] [assert (== (fold + 0 xs) expected) (list :sum xs)]",
+        )
+        .unwrap();
+        assert_eq!(eval_str(env, "check-sum [1 2 3] 6"), Ok(Lval::Nil));
+
+        let err = eval_str(env, "check-sum [1 2 3] 7").unwrap_err();
+        assert_eq!(err.etype, LerrType::Interrupt);
+        assert_eq!(err.message, "[:sum [1 2 3]]");
+        assert_eq!(err.trace(), ["assert", "check-sum"]);
+
+        let err = eval_str(env, "if (> 1 2) [1] [error \"went the wrong way\"]").unwrap_err();
+        assert_eq!(err.etype, LerrType::Interrupt);
+        assert_eq!(err.message, "went the wrong way");
+
+        let etype = |env: &mut Lenv, input: &str| eval_str(env, input).unwrap_err().etype;
+        assert_eq!(etype(env, "error 1 2"), LerrType::IncorrectParamCount);
+        assert_eq!(etype(env, "assert true"), LerrType::IncorrectParamCount);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_runs_a_function_for_each_item() {
+        let env = &mut init_env();
+        let calls = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let seen = calls.clone();
+        crate::add_native(env, "seen", move |_, operands| {
+            seen.borrow_mut().push(Lval::Qexpr(operands));
+            Ok(Lval::Num(0_f64))
+        });
+        let taken = || Lval::Qexpr(calls.borrow_mut().drain(..).collect()).to_string();
+
+        assert_eq!(eval_str(env, "for-each seen [1 [2] c]"), Ok(Lval::Nil));
+        assert_eq!(taken(), "[[1] [[2]] [c]]");
+        eval_str(env, "for-each (\\ [x i] [seen i x]) [a b]").unwrap();
+        assert_eq!(taken(), "[[0 a] [1 b]]");
+        eval_str(
+            env,
+            "for-each (case-lambda [[x] [seen x]] [[x i] [seen i]]) [a]",
+        )
+        .unwrap();
+        assert_eq!(taken(), "[[a]]");
+        eval_str(env, "for-each seen []").unwrap();
+        assert_eq!(taken(), "[]");
+
+        // stops at the first failure, which says where it was
+        let err = eval_str(env, "for-each (\\ [x] [seen (head x)]) [[1] [] [2]]").unwrap_err();
+        assert_eq!(err.etype, LerrType::EmptyList);
+        assert!(err
+            .message
+            .starts_with("Function for-each failed on item 1: "));
+        assert_eq!(taken(), "[[1]]");
+        let err = eval_str(env, "for-each 1 []").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+    }
+
+    #[test]
+    fn it_sums_up_operands_in_errors() {
+        let env = &mut init_env();
+        let big = Lval::Qexpr(vec![Lval::Str("item".to_string()); 1_000_000]);
+        let err = builtin_str_upper(env, vec![big]).unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        assert!(err.message.len() < 200, "{}", err.message);
+        assert!(err
+            .message
+            .starts_with("Function str-upper needed a String but was given [\"item\" \"item\""));
+        assert!(err.message.ends_with('…'));
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_prints_through_the_writer() {
+        let env = &mut init_env();
+        let printed = std::rc::Rc::new(std::cell::RefCell::new(String::new()));
+        let out = printed.clone();
+        env.set_writer(move |text| out.borrow_mut().push_str(text));
+
+        assert_eq!(eval_str(env, "print \"x =\" 1 [a \"b\"] :k"), Ok(Lval::Nil));
+        builtin_println(env, vec![]).unwrap();
+        eval_str(env, "for-each println [\"one\" 2]").unwrap();
+        assert_eq!(*printed.borrow(), "x = 1 [a \"b\"] :k\none\n2\n");
+        assert_eq!(env.take_output(), "");
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_dispatches_case_lambdas_on_arg_count() {
+        let env = &mut init_env();
+        let shown = |env: &mut Lenv, input| eval_str(env, input).unwrap().to_string();
+        eval_str(
+            env,
+            "def [f] (case-lambda [[x] [list :one x]] [[x y] [list :two x y]] [[x : rest] [list :many x rest]])",
+        )
+        .unwrap();
+
+        assert_eq!(shown(env, "f 1"), "[:one 1]");
+        assert_eq!(shown(env, "f 1 2"), "[:two 1 2]");
+        assert_eq!(shown(env, "f 1 2 3"), "[:many 1 [2 3]]");
+        assert_eq!(shown(env, "fn-arity f"), "[[1 1] [2 2] [1 []]]");
+        assert_eq!(
+            shown(env, "f"),
+            "(case-lambda [[x] [list :one x]] [[x y] [list :two x y]] [[x : rest] [list :many x rest]])"
+        );
+
+        // too few args wait for the clause needing the fewest more
+        eval_str(
+            env,
+            "def [g] (case-lambda [[x y] [- x y]] [[x y z] [+ x y z]])",
+        )
+        .unwrap();
+        assert_eq!(shown(env, "(g 10) 3"), "7");
+        assert_eq!(shown(env, "map (g 10) [1 2]"), "[9 8]");
+
+        let err = eval_str(env, "g 1 2 3 4").unwrap_err();
+        assert_eq!(err.etype, LerrType::IncorrectParamCount);
+        assert_eq!(
+            err.message,
+            "Function case-lambda has clauses taking 2, 3 arg(s) but was given 4"
+        );
+
+        // map calls with one arg and fold with two
+        assert_eq!(shown(env, "map f [1 2]"), "[[:one 1] [:one 2]]");
+        assert_eq!(shown(env, "fold f 0 [1]"), "[:two 0 1]");
+
+        let etype = |env: &mut Lenv, input: &str| eval_str(env, input).unwrap_err().etype;
+        assert_eq!(
+            etype(env, "case-lambda [[x] [x]] [[y] [y]]"),
+            LerrType::WrongType
+        );
+        assert_eq!(etype(env, "case-lambda [x] [x]"), LerrType::WrongType);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_handles_special_floats_with_and_without_literals() {
+        // the same checks either way, building the values by arithmetic
+        // when they can't be written
+        for allow in [false, true] {
+            let env = &mut init_env();
+            env.set_special_floats(allow);
+            let (inf, nan) = if allow {
+                ("+inf", "nan")
+            } else {
+                eval_str(env, "def [big] (* 1e308 10)").unwrap();
+                eval_str(env, "def [not-a-number] (- big big)").unwrap();
+                ("big", "not-a-number")
+            };
+            let truth = |env: &mut Lenv, input: String| to_bool(eval_str(env, &input).unwrap());
+
+            assert_eq!(truth(env, format!("nan? {}", nan)), Some(true));
+            assert_eq!(truth(env, format!("nan? {}", inf)), Some(false));
+            assert_eq!(truth(env, format!("finite? {}", inf)), Some(false));
+            assert_eq!(truth(env, format!("finite? {}", nan)), Some(false));
+            assert_eq!(truth(env, "finite? 1.5".to_string()), Some(true));
+            assert_eq!(truth(env, format!("== {0} {0}", nan)), Some(false));
+            assert_eq!(truth(env, format!("identical? {0} {0}", nan)), Some(true));
+            assert_eq!(
+                truth(env, format!("identical? [1 {0}] [1 {0}]", nan)),
+                Some(true)
+            );
+            assert_eq!(truth(env, format!("identical? {} 1", nan)), Some(false));
+            assert_eq!(truth(env, format!("== {0} {0}", inf)), Some(true));
+
+            let sorted = eval_str(
+                env,
+                &format!("sort-any (list {} 1 (- 0 {}) {})", nan, inf, inf),
+            );
+            assert_eq!(format!("{:?}", sorted.unwrap()), "[-inf 1 +inf nan]");
+            assert_eq!(
+                eval_str(env, "nan? :a").unwrap_err().etype,
+                LerrType::WrongType
+            );
+        }
+
+        let env = &mut init_env();
+        assert_eq!(
+            eval_str(env, "nan").unwrap_err().etype,
+            LerrType::UnboundSymbol
+        );
+        env.set_special_floats(true);
+        assert_eq!(format!("{:?}", eval_str(env, "- 0 nan").unwrap()), "nan");
+        assert_eq!(
+            format!("{:?}", eval_str(env, "eval \"-inf\"").unwrap()),
+            "-inf"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_finds_extreme_indices() {
+        let env = &mut init_env();
+        let index = |env: &mut Lenv, input: &str| eval_str(env, input).unwrap();
+
+        assert_eq!(index(env, "argmax [3 9 2 9]"), Lval::Num(1_f64));
+        assert_eq!(index(env, "argmin [3 1 2 1]"), Lval::Num(1_f64));
+        assert_eq!(index(env, "argmax [-3 -1 -2]"), Lval::Num(1_f64));
+        assert_eq!(index(env, "argmin [-3 -1 -7.5]"), Lval::Num(2_f64));
+        assert_eq!(index(env, "argmax [4]"), Lval::Num(0_f64));
+        assert_eq!(index(env, "argmin [4]"), Lval::Num(0_f64));
 
-    Ok(Lval::Lambda(lambda))
-}
+        eval_str(env, "def [neg] (\\ [x] [- x])").unwrap();
+        assert_eq!(index(env, "argmax-by neg [3 1 2 1]"), Lval::Num(1_f64));
+        assert_eq!(index(env, "argmin-by neg [3 1 9 9]"), Lval::Num(2_f64));
+        assert_eq!(index(env, "argmax-by - [1 5]"), Lval::Num(0_f64));
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{env::init_env, to_lambda};
+        let err = eval_str(env, "argmax []").unwrap_err();
+        assert_eq!(err.etype, LerrType::EmptyList);
+        let err = eval_str(env, "argmin-by neg []").unwrap_err();
+        assert_eq!(err.etype, LerrType::EmptyList);
+        let err = eval_str(env, "argmin [1 \"two\" 3]").unwrap_err();
+        assert_eq!(err.etype, LerrType::BadNum);
+        assert_eq!(
+            err.message,
+            "Function argmin needed numbers but item 1 was \"two\""
+        );
 
-    fn empty_fun(_env: &mut Lenv, _operands: Vec<Lval>) -> Result<Lval, Lerr> {
-        Ok(Lval::Sexpr(vec![]))
+        // the key function's own error, saying where it happened
+        eval_str(env, "def [inverse] (\\ [x] [/ 1 x])").unwrap();
+        let err = eval_str(env, "argmax-by inverse [4 2 0 1]").unwrap_err();
+        assert_eq!(err.etype, LerrType::DivZero);
+        assert!(err
+            .message
+            .starts_with("Function argmax-by failed on item 2: "));
+        let err = eval_str(env, "argmax-by head [[1] [a]]").unwrap_err();
+        assert_eq!(
+            err.message,
+            "Function argmax-by needed a number key but item 1 gave a"
+        );
+        let err = eval_str(env, "argmax-by 1 [1]").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
     }
 
     #[test]
-    fn it_correctly_uses_head() {
+    #[cfg(feature = "parser")]
+    fn it_works_on_strings() {
         let env = &mut init_env();
-        let expr = Lval::Qexpr(vec![
-            Lval::Sym(String::from("+")),
-            Lval::Num(1_f64),
-            Lval::Sexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Num(1_f64),
-            ]),
-        ]);
+        let shown = |env: &mut Lenv, input| eval_str(env, input).unwrap().to_string();
+        assert_eq!(shown(env, "str-len \"héllo wörld\""), "11");
+        assert_eq!(shown(env, "str-concat \"ab\" \"\" \"çd\""), "\"abçd\"");
+        assert_eq!(builtin_str_concat(env, vec![]), Ok(Lval::Str("".into())));
+        assert_eq!(shown(env, "str-slice \"日本語です\" 1 3"), "\"本語\"");
+        assert_eq!(shown(env, "str-slice \"abc\" 3 3"), "\"\"");
+        assert_eq!(shown(env, "str-upper \"straße\""), "\"STRASSE\"");
+        assert_eq!(shown(env, "str-lower \"ÀBC\""), "\"àbc\"");
         assert_eq!(
-            builtin_head(env, vec![expr.clone()]).unwrap(),
-            Lval::Sym(String::from("+"))
+            shown(env, "str-split \"a,b,,ç\" \",\""),
+            "[\"a\" \"b\" \"\" \"ç\"]"
         );
+        assert_eq!(shown(env, "str-split \"añb\" \"\""), "[\"a\" \"ñ\" \"b\"]");
+        assert_eq!(shown(env, "str-contains \"crème\" \"èm\""), "true");
+        assert_eq!(shown(env, "str-contains \"creme\" \"èm\""), "false");
 
-        let _ = builtin_head(env, vec![])
-            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
+        let err = eval_str(env, "str-slice \"héé\" 2 4").unwrap_err();
+        assert_eq!(err.etype, LerrType::BadNum);
+        assert_eq!(
+            err.message,
+            "Function str-slice needed 0 <= start <= end <= 3 for \"héé\" but was given 2 and 4"
+        );
+        let etype = |env: &mut Lenv, input: &str| eval_str(env, input).unwrap_err().etype;
+        assert_eq!(etype(env, "str-slice \"abc\" 2 1"), LerrType::BadNum);
+        assert_eq!(etype(env, "str-slice \"abc\" -1 1"), LerrType::BadNum);
+        assert_eq!(etype(env, "str-len 5"), LerrType::WrongType);
+        assert_eq!(etype(env, "str-split \"a\" [b]"), LerrType::WrongType);
+        let err = eval_str(env, "str-concat \"a\" :b").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        assert_eq!(
+            err.message,
+            "Function str-concat needed a String but was given :b"
+        );
+    }
 
-        let _ = builtin_head(env, vec![Lval::Sym(String::from("+"))])
-            .map_err(|err| assert_eq!(err.etype, LerrType::WrongType));
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_pads_text() {
+        let env = &mut init_env();
+        let text = |env: &mut Lenv, input: &str| to_str(eval_str(env, input).unwrap()).unwrap();
+        assert_eq!(text(env, "pad-left \"ab\" 5"), "   ab");
+        assert_eq!(text(env, "pad-right \"ab\" 5 \".\""), "ab...");
+        assert_eq!(text(env, "center \"ab\" 5 \"*\""), "*ab**");
+        assert_eq!(text(env, "pad-left \"héé\" 4 \"é\""), "éhéé");
+        // too long is cut down to size
+        assert_eq!(text(env, "pad-right \"héllo\" 3"), "hél");
+        assert_eq!(text(env, "string-repeat \"-=\" 3"), "-=-=-=");
+        assert_eq!(text(env, "string-repeat \"x\" 0"), "");
 
-        let _ = builtin_head(env, vec![Lval::Qexpr(vec![])])
-            .map_err(|err| assert_eq!(err.etype, LerrType::EmptyList));
+        let etype = |env: &mut Lenv, input: &str| eval_str(env, input).unwrap_err().etype;
+        assert_eq!(etype(env, "pad-left \"a\" -1"), LerrType::BadNum);
+        assert_eq!(etype(env, "pad-left \"a\" 1.5"), LerrType::BadNum);
+        assert_eq!(etype(env, "pad-left \"a\" 3 \"ab\""), LerrType::WrongType);
+        assert_eq!(etype(env, "pad-left \"a\" 3 \"\""), LerrType::WrongType);
+        assert_eq!(etype(env, "center \"a\""), LerrType::IncorrectParamCount);
+        assert_eq!(etype(env, "string-repeat \"ab\" -2"), LerrType::BadNum);
+        assert_eq!(etype(env, "string-repeat \"ab\" 0.5"), LerrType::BadNum);
     }
 
     #[test]
-    fn it_correctly_uses_tail() {
+    #[cfg(feature = "parser")]
+    fn it_limits_the_size_of_values_builtins_make() {
         let env = &mut init_env();
-        let expr = Lval::Qexpr(vec![
-            Lval::Sym(String::from("+")),
-            Lval::Num(1_f64),
-            Lval::Sexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Num(1_f64),
-            ]),
-        ]);
+        // well past anything a program normally builds
         assert_eq!(
-            builtin_tail(env, vec![expr.clone()]).unwrap(),
-            Lval::Qexpr(vec![
-                Lval::Num(1_f64),
-                Lval::Sexpr(vec![
-                    Lval::Sym(String::from("+")),
-                    Lval::Num(1_f64),
-                    Lval::Num(1_f64),
-                ])
-            ])
+            eval_str(env, "len (string-repeat \"abcd\" 1000000)"),
+            Ok(Lval::Num(4_000_000_f64))
         );
-        let _ = builtin_tail(env, vec![])
-            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
 
-        let _ = builtin_tail(env, vec![Lval::Sym(String::from("+"))])
-            .map_err(|err| assert_eq!(err.etype, LerrType::WrongType));
+        env.set_max_value_size(Some(4));
+        let fits = |env: &mut Lenv, input: &str| eval_str(env, input).is_ok();
+        let etype = |env: &mut Lenv, input: &str| eval_str(env, input).unwrap_err().etype;
+        assert!(fits(env, "string-repeat \"ab\" 2"));
+        assert_eq!(etype(env, "string-repeat \"ab\" 3"), LerrType::SizeLimit);
+        assert!(fits(env, "join [1 2] [3] [4]"));
+        assert_eq!(etype(env, "join [1 2] [3] [4 5]"), LerrType::SizeLimit);
+        assert!(fits(env, "join \"héé\" \"!\""));
+        assert_eq!(etype(env, "join \"héé\" \"!!\""), LerrType::SizeLimit);
+        assert!(fits(env, "bytes [1 2 3 4]"));
+        assert_eq!(etype(env, "bytes [1 2 3 4 5]"), LerrType::SizeLimit);
+        assert_eq!(
+            eval_str(env, "string-repeat \"ab\" 1e15")
+                .unwrap_err()
+                .message,
+            "Function string-repeat would make a value of size 2000000000000000, over the limit of 4"
+        );
 
-        let _ = builtin_tail(env, vec![Lval::Qexpr(vec![])])
-            .map_err(|err| assert_eq!(err.etype, LerrType::EmptyList));
+        env.set_max_value_size(None);
+        assert!(fits(env, "join [1 2] [3] [4 5]"));
     }
 
     #[test]
-    fn it_correctly_uses_list() {
+    #[cfg(feature = "parser")]
+    fn it_lays_out_tables() {
         let env = &mut init_env();
-        let expr = vec![
-            Lval::Sym(String::from("+")),
-            Lval::Num(1_f64),
-            Lval::Sexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Num(1_f64),
-            ]),
-        ];
-        assert_eq!(
-            builtin_list(env, expr.clone()).unwrap(),
-            Lval::Qexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Sexpr(vec![
-                    Lval::Sym(String::from("+")),
-                    Lval::Num(1_f64),
-                    Lval::Num(1_f64),
-                ])
-            ])
-        );
-        assert_eq!(
-            builtin_list(
+        let table = to_str(
+            eval_str(
                 env,
-                vec![
-                    Lval::Sym(String::from("+")),
-                    Lval::Num(1_f64),
-                    Lval::Num(1_f64),
-                ]
+                "table [[\"name\" \"qty\"] [\"crème brûlée\" 2] [\"tea\" 10.5 \"hot\"]]",
             )
             .unwrap(),
-            Lval::Qexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Num(1_f64),
-            ])
-        );
-        assert_eq!(builtin_list(env, vec![]).unwrap(), Lval::Qexpr(vec![]));
+        )
+        .unwrap();
         assert_eq!(
-            builtin_list(env, vec![Lval::Sym(String::from("+"))]).unwrap(),
-            Lval::Qexpr(vec![Lval::Sym(String::from("+")),])
+            table,
+            "name         | qty\n\
+             crème brûlée |    2\n\
+             tea          | 10.5 | hot"
         );
+
+        let table =
+            to_str(eval_str(env, "table [[\"über-long cell\" x] [\"é\" [1 2]]] 6").unwrap())
+                .unwrap();
+        assert_eq!(table, "über-l | x\né      | [1 2]");
+
+        assert_eq!(eval_str(env, "table []").unwrap(), Lval::Str(String::new()));
         assert_eq!(
-            builtin_list(env, vec![Lval::Sexpr(vec![])]).unwrap(),
-            Lval::Qexpr(vec![Lval::Sexpr(vec![]),])
+            eval_str(env, "table [1]").unwrap_err().etype,
+            LerrType::WrongType
         );
     }
 
     #[test]
-    fn it_correctly_uses_eval() {
+    fn it_treats_every_sequence_alike() {
         let env = &mut init_env();
-        let expr = Lval::Qexpr(vec![
-            Lval::Sym(String::from("+")),
-            Lval::Num(1_f64),
-            Lval::Sexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Num(1_f64),
-            ]),
-        ]);
-        assert_eq!(
-            builtin_eval(env, vec![expr.clone()]).unwrap(),
-            Lval::Num(3_f64)
-        );
+        let text = |s: &str| Lval::Str(s.to_string());
+        let nums = |ns: &[f64]| Lval::Qexpr(ns.iter().map(|n| Lval::Num(*n)).collect());
+        let sequences = [
+            (
+                nums(&[1.0, 2.0]),
+                Lval::Num(1.0),
+                nums(&[2.0]),
+                nums(&[1.0, 2.0, 1.0, 2.0]),
+            ),
+            (text("hé"), text("h"), text("é"), text("héhé")),
+            (
+                Lval::Bytes(vec![1, 2]),
+                Lval::Num(1.0),
+                Lval::Bytes(vec![2]),
+                Lval::Bytes(vec![1, 2, 1, 2]),
+            ),
+        ];
 
-        let _ = builtin_eval(env, vec![expr.clone(), expr.clone()])
-            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
+        fn same(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+            Ok(operands[0].clone())
+        }
+        let head_seq = |seq: &Lval| {
+            seq_build(
+                seq_kind(seq).unwrap(),
+                vec![seq_iter(seq).unwrap().next().unwrap()],
+            )
+            .unwrap()
+        };
+        for (seq, head, tail, joined) in sequences {
+            assert_eq!(builtin_head(env, vec![seq.clone()]), Ok(head));
+            assert_eq!(builtin_tail(env, vec![seq.clone()]), Ok(tail.clone()));
+            assert_eq!(
+                builtin_join(env, vec![seq.clone(), seq.clone()]),
+                Ok(joined)
+            );
 
-        let _ = builtin_eval(env, vec![])
-            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
+            let empty = seq_build(seq_kind(&seq).unwrap(), vec![]).unwrap();
+            for fun in &[builtin_head as Lfun, builtin_tail] {
+                let err = fun(env, vec![empty.clone()]).unwrap_err();
+                assert_eq!(err.etype, LerrType::EmptyList);
+            }
 
-        assert_eq!(
-            builtin_eval(env, vec![Lval::Sym(String::from("-"))]).unwrap(),
-            Lval::Fun(empty_fun)
-        );
-        assert_eq!(
-            builtin_eval(env, vec![Lval::Sexpr(vec![Lval::Sym(String::from("-"))])]).unwrap(),
-            Lval::Fun(empty_fun)
-        );
-        assert_eq!(
-            builtin_eval(env, vec![Lval::Qexpr(vec![])]).unwrap(),
-            Lval::Sexpr(vec![])
-        );
+            let items = seq_iter(&seq).unwrap().collect::<Vec<_>>();
+            let n = Lval::Int(1);
+            assert_eq!(builtin_len(env, vec![seq.clone()]), Ok(Lval::Int(2)));
+            assert_eq!(
+                builtin_nth(env, vec![n.clone(), seq.clone()]),
+                Ok(items[1].clone())
+            );
+            let reversed = seq_build(
+                seq_kind(&seq).unwrap(),
+                items.iter().rev().cloned().collect(),
+            );
+            assert_eq!(builtin_reverse(env, vec![seq.clone()]), reversed);
+            assert_eq!(
+                builtin_take(env, vec![n.clone(), seq.clone()]),
+                Ok(head_seq(&seq))
+            );
+            assert_eq!(builtin_drop(env, vec![n, seq.clone()]), Ok(tail));
+            for item in &items {
+                let found = builtin_is_member(env, vec![item.clone(), seq.clone()]);
+                assert_eq!(found, Ok(Lval::Bool(true)));
+            }
+            let found = builtin_is_member(env, vec![Lval::Keyword("k".into()), seq.clone()]);
+            assert_eq!(found, Ok(Lval::Bool(false)));
+
+            // map keeps the kind when every result fits and gives a list
+            // otherwise, filter keeps the kind, and fold sees every item
+            assert_eq!(
+                builtin_map(env, vec![Lval::Fun(same), seq.clone()]),
+                Ok(seq.clone())
+            );
+            let list = Lval::Fun(builtin_list);
+            let listed = items.iter().map(|item| Lval::Qexpr(vec![item.clone()]));
+            assert_eq!(
+                builtin_map(env, vec![list.clone(), seq.clone()]),
+                Ok(Lval::Qexpr(listed.collect()))
+            );
+            assert_eq!(
+                builtin_filter(env, vec![list.clone(), seq.clone()]),
+                Ok(seq.clone())
+            );
+            assert_eq!(
+                builtin_filter(env, vec![Lval::Fun(builtin_is_fun), seq.clone()]),
+                Ok(empty)
+            );
+            let folded = items
+                .into_iter()
+                .fold(Lval::Nil, |acc, item| Lval::Qexpr(vec![acc, item]));
+            assert_eq!(
+                builtin_fold(env, vec![list, Lval::Nil, seq.clone()]),
+                Ok(folded)
+            );
+            let err = builtin_join(env, vec![seq, Lval::Int(5)]).unwrap_err();
+            assert!(err.message.ends_with("for argument 2 but was given 5"));
+        }
+
+        let not_a_seq = "needed a Qexpr, String or Bytes but was given :k";
+        for fun in &[builtin_head as Lfun, builtin_tail] {
+            let err = fun(env, vec![Lval::Keyword("k".into())]).unwrap_err();
+            assert_eq!(err.etype, LerrType::WrongType);
+            assert!(err.message.ends_with(not_a_seq));
+        }
+        let err = builtin_join(env, vec![Lval::Keyword("k".into()), nums(&[])]).unwrap_err();
+        assert!(err.message.ends_with(not_a_seq));
+        for fun in &[builtin_len as Lfun, builtin_reverse] {
+            let err = fun(env, vec![Lval::Keyword("k".into())]).unwrap_err();
+            assert!(err.message.ends_with(not_a_seq));
+        }
+        for fun in &[
+            builtin_nth as Lfun,
+            builtin_take,
+            builtin_drop,
+            builtin_is_member,
+        ] {
+            let err = fun(env, vec![Lval::Int(0), Lval::Keyword("k".into())]).unwrap_err();
+            assert!(err.message.ends_with(not_a_seq));
+        }
+        for fun in &[builtin_map as Lfun, builtin_filter] {
+            let err = fun(
+                env,
+                vec![Lval::Fun(builtin_list), Lval::Keyword("k".into())],
+            );
+            assert!(err.unwrap_err().message.ends_with(not_a_seq));
+        }
+        let operands = vec![
+            Lval::Fun(builtin_list),
+            Lval::Nil,
+            Lval::Keyword("k".into()),
+        ];
+        let err = builtin_fold(env, operands).unwrap_err();
+        assert!(err.message.ends_with(not_a_seq));
     }
 
     #[test]
@@ -709,6 +5467,17 @@ mod tests {
         let _ = builtin_join(env, vec![expr.clone(), Lval::Sym(String::from("+"))])
             .map_err(|err| assert_eq!(err.etype, LerrType::WrongType));
 
+        let err = builtin_join(
+            env,
+            vec![expr.clone(), expr.clone(), Lval::Int(5), expr.clone()],
+        )
+        .unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        assert_eq!(
+            err.message,
+            "Function join needed a Qexpr for argument 3 but was given 5"
+        );
+
         assert_eq!(
             builtin_join(env, vec![expr.clone(), Lval::Qexpr(vec![])]).unwrap(),
             Lval::Qexpr(vec![
@@ -760,7 +5529,7 @@ mod tests {
                 ]
             )
             .unwrap(),
-            Lval::Sexpr(vec![])
+            Lval::Nil
         );
         assert_eq!(
             crate::eval::eval(env, Lval::Sym(String::from("a"))).unwrap(),
@@ -802,12 +5571,38 @@ mod tests {
             vec![Lval::Qexpr(vec![Lval::Num(1_f64)]), Lval::Num(1_f64)],
         )
         .map_err(|err| assert_eq!(err.etype, LerrType::WrongType));
+
+        let err = builtin_def(
+            env,
+            vec![
+                Lval::Qexpr(vec![Lval::Sym(String::from("a")), Lval::Num(1_f64)]),
+                Lval::Num(1_f64),
+                Lval::Num(2_f64),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        assert!(err.message.contains("param 2 but was given 1"));
+
+        let err = builtin_var(env, vec![Lval::Int(1), Lval::Int(1)]).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Function = needed a Qexpr for argument 1 but was given 1"
+        );
     }
 
     //(\ {a b} {* a b}) 1 2
     #[test]
     fn it_correctly_uses_lambda() {
         let env = &mut init_env();
+        let err = builtin_lambda(
+            env,
+            vec![Lval::Qexpr(vec![]), Lval::Sym(String::from("body"))],
+        )
+        .unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        assert!(err.message.contains("argument 2 but was given body"));
+
         assert!(to_lambda(
             &builtin_lambda(
                 env,
@@ -851,47 +5646,58 @@ mod tests {
         let env = &mut init_env();
         assert_eq!(
             builtin_lt(env, vec![Lval::Num(1_f64), Lval::Num(2_f64)]).unwrap(),
-            Lval::Num(1_f64)
+            Lval::Bool(true)
         );
         assert_eq!(
             builtin_lt(env, vec![Lval::Num(2_f64), Lval::Num(1_f64)]).unwrap(),
-            Lval::Num(0_f64)
+            Lval::Bool(false)
         );
 
         assert_eq!(
             builtin_gt(env, vec![Lval::Num(1_f64), Lval::Num(2_f64)]).unwrap(),
-            Lval::Num(0_f64)
+            Lval::Bool(false)
         );
         assert_eq!(
             builtin_gt(env, vec![Lval::Num(2_f64), Lval::Num(1_f64)]).unwrap(),
-            Lval::Num(1_f64)
+            Lval::Bool(true)
         );
 
         assert_eq!(
             builtin_gte(env, vec![Lval::Num(1_f64), Lval::Num(2_f64)]).unwrap(),
-            Lval::Num(0_f64)
+            Lval::Bool(false)
         );
         assert_eq!(
             builtin_gte(env, vec![Lval::Num(2_f64), Lval::Num(1_f64)]).unwrap(),
-            Lval::Num(1_f64)
+            Lval::Bool(true)
         );
         assert_eq!(
             builtin_gte(env, vec![Lval::Num(2_f64), Lval::Num(2_f64)]).unwrap(),
-            Lval::Num(1_f64)
+            Lval::Bool(true)
         );
 
         assert_eq!(
             builtin_lte(env, vec![Lval::Num(1_f64), Lval::Num(2_f64)]).unwrap(),
-            Lval::Num(1_f64)
+            Lval::Bool(true)
         );
         assert_eq!(
             builtin_lte(env, vec![Lval::Num(2_f64), Lval::Num(1_f64)]).unwrap(),
-            Lval::Num(0_f64)
+            Lval::Bool(false)
         );
         assert_eq!(
             builtin_lte(env, vec![Lval::Num(2_f64), Lval::Num(2_f64)]).unwrap(),
-            Lval::Num(1_f64)
+            Lval::Bool(true)
+        );
+
+        let err = builtin_lt(env, vec![Lval::Num(1_f64), Lval::Str("2".into())]).unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        assert_eq!(
+            err.message,
+            "Function < needed a number but was given \"2\""
         );
+        let err = builtin_gte(env, vec![Lval::Qexpr(vec![]), Lval::Num(1_f64)]).unwrap_err();
+        assert_eq!(err.message, "Function >= needed a number but was given []");
+        let err = builtin_gt(env, vec![Lval::Num(1_f64)]).unwrap_err();
+        assert_eq!(err.etype, LerrType::IncorrectParamCount);
     }
 
     #[test]
@@ -922,4 +5728,171 @@ mod tests {
             Lval::Num(9_f64)
         );
     }
+
+    #[cfg(feature = "parser")]
+    #[test]
+    fn it_correctly_uses_letfn() {
+        let env = &mut init_env();
+        let frames = env.iter().count();
+
+        assert_eq!(
+            eval_str(
+                env,
+                "letfn [[square x] [* x x] [cube x] [* x (square x)]] [cube 3]"
+            )
+            .unwrap(),
+            Lval::Num(27_f64)
+        );
+        assert_eq!(env.iter().count(), frames);
+        assert!(env.get("square").is_none());
+        assert!(env.get("cube").is_none());
+
+        // helpers can call each other in either direction
+        assert_eq!(
+            eval_str(
+                env,
+                "letfn [[even n] [if (== n 0) [1] [odd (- n 1)]] [odd n] [if (== n 0) [0] [even (- n 1)]]] [even 10]"
+            )
+            .unwrap(),
+            Lval::Num(1_f64)
+        );
+        assert_eq!(env.iter().count(), frames);
+    }
+
+    #[cfg(feature = "parser")]
+    #[test]
+    fn it_cleans_up_letfn_after_errors() {
+        let env = &mut init_env();
+        let frames = env.iter().count();
+
+        let err = eval_str(env, "letfn [[broken x] [head x]] [broken 1]").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        assert_eq!(env.iter().count(), frames);
+        assert!(env.get("broken").is_none());
+
+        let err = eval_str(env, "letfn [[f x] [x]] [nope]").unwrap_err();
+        assert_eq!(err.etype, LerrType::UnboundSymbol);
+        assert_eq!(env.iter().count(), frames);
+
+        let err = eval_str(env, "letfn [[f x]] [f 1]").unwrap_err();
+        assert_eq!(err.etype, LerrType::IncorrectParamCount);
+        let err = eval_str(env, "letfn [[1 x] [x]] [1]").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        assert_eq!(env.iter().count(), frames);
+    }
+
+    fn env_with(register: fn(&mut Lenv)) -> Lenv {
+        let mut env = Lenv::new();
+        env.push(Lookup::new());
+        register(&mut env);
+        env
+    }
+
+    fn call(env: &mut Lenv, sym: &str, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+        let mut sexpr = vec![Lval::Sym(String::from(sym))];
+        sexpr.extend(operands);
+        eval::eval(env, Lval::Sexpr(sexpr))
+    }
+
+    fn assert_registered(env: &Lenv, symbols: &[&str]) {
+        for sym in symbols {
+            assert!(env.get(sym).is_some(), "{} is missing", sym);
+        }
+    }
+
+    #[test]
+    fn it_registers_arithmetic() {
+        let env = &mut env_with(register_arithmetic);
+        assert_registered(env, &["!", "+", "-", "*", "/", "%"]);
+        assert_eq!(
+            call(env, "*", vec![Lval::Num(6_f64), Lval::Num(7_f64)]).unwrap(),
+            Lval::Num(42_f64)
+        );
+    }
+
+    #[test]
+    fn it_registers_lists() {
+        let env = &mut env_with(register_lists);
+        assert_registered(env, &["head", "tail", "list", "join"]);
+        assert_eq!(
+            call(env, "list", vec![Lval::Num(1_f64)]).unwrap(),
+            Lval::Qexpr(vec![Lval::Num(1_f64)])
+        );
+
+        // nothing outside the group sneaks in
+        assert!(env.get("eval").is_none());
+        assert!(env.get("+").is_none());
+        let _ = call(env, "eval", vec![Lval::Qexpr(vec![])])
+            .map_err(|err| assert_eq!(err.etype, LerrType::UnboundSymbol));
+    }
+
+    #[test]
+    fn it_registers_meta() {
+        let env = &mut env_with(register_meta);
+        assert_registered(env, &["eval", "version", "features"]);
+        assert_eq!(
+            call(env, "eval", vec![Lval::Qexpr(vec![Lval::Num(1_f64)])]).unwrap(),
+            Lval::Num(1_f64)
+        );
+    }
+
+    #[test]
+    fn it_registers_strings() {
+        let env = &mut env_with(register_strings);
+        assert_registered(env, &["concat"]);
+        assert_eq!(
+            call(
+                env,
+                "concat",
+                vec![Lval::Str(String::from("a")), Lval::Str(String::from("b"))]
+            )
+            .unwrap(),
+            Lval::Str(String::from("ab"))
+        );
+    }
+
+    #[test]
+    fn it_registers_definitions() {
+        let env = &mut env_with(register_definitions);
+        assert_registered(env, &["\\", "letfn", "def", "="]);
+        call(
+            env,
+            "def",
+            vec![
+                Lval::Qexpr(vec![Lval::Sym(String::from("x"))]),
+                Lval::Num(1_f64),
+            ],
+        )
+        .unwrap();
+        assert_eq!(env.get("x"), Some(Lval::Num(1_f64)));
+    }
+
+    #[test]
+    fn it_registers_control() {
+        let env = &mut env_with(register_control);
+        assert_registered(env, &["if", "die"]);
+        assert_eq!(
+            call(
+                env,
+                "if",
+                vec![
+                    Lval::Num(0_f64),
+                    Lval::Qexpr(vec![Lval::Num(1_f64)]),
+                    Lval::Qexpr(vec![Lval::Num(2_f64)]),
+                ]
+            )
+            .unwrap(),
+            Lval::Num(2_f64)
+        );
+    }
+
+    #[test]
+    fn it_registers_comparison() {
+        let env = &mut env_with(register_comparison);
+        assert_registered(env, &["<", ">", ">=", "<=", "==", "!=", "&&", "||"]);
+        assert_eq!(
+            call(env, "<", vec![Lval::Num(1_f64), Lval::Num(2_f64)]).unwrap(),
+            Lval::Bool(true)
+        );
+    }
 }
@@ -0,0 +1,460 @@
+use crate::{
+    parser::Incremental,
+    visitor::{walk, ListKind, LvalVisitor},
+    Lerr, LerrType, Lfun, Llambda, Lval, NativeFn, Span, VERSION,
+};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+// changes with the encoding, so older entries are parsed again rather than
+// read wrong
+const MAGIC: &[u8] = b"oolisp-parse-cache 5\n";
+const EXTENSION: &str = "lvc";
+
+/// Parses source files, keeping the parsed forms on disk keyed by a hash of
+/// the file contents so unchanged files are not parsed again.
+///
+/// An entry that is unreadable, corrupt or written by another version of
+/// the crate is ignored and the file is parsed as if it were not there.
+pub struct ParseCache {
+    dir: PathBuf,
+    parses: usize,
+}
+
+impl ParseCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        ParseCache {
+            dir: dir.into(),
+            parses: 0,
+        }
+    }
+
+    /// `$XDG_CACHE_HOME/lisp`, falling back to `~/.cache/lisp`.
+    pub fn default_dir() -> Option<PathBuf> {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+            .map(|cache| cache.join("lisp"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// How many times a file actually had to be parsed.
+    pub fn parses(&self) -> usize {
+        self.parses
+    }
+
+    /// The top level forms of the file at `path`.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<Vec<Lval>, Lerr> {
+        self.load_spanned(path)
+            .map(|(_, forms)| forms.into_iter().map(|(_, form)| form).collect())
+    }
+
+    /// Like `load`, along with where in the file each form is and the
+    /// file's source, so whatever evaluates them can say which line a form
+    /// is on.
+    pub fn load_spanned(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<(String, Vec<(Span, Lval)>), Lerr> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|e| {
+            Lerr::new(
+                LerrType::Io,
+                format!("could not read {}; {}", path.display(), e),
+            )
+        })?;
+
+        let hash = fnv1a(source.as_bytes());
+        let entry = self.dir.join(format!("{:016x}.{}", hash, EXTENSION));
+        if let Some(forms) = fs::read(&entry).ok().and_then(|bytes| decode(&bytes, hash)) {
+            return Ok((source, forms));
+        }
+
+        self.parses += 1;
+        let forms = parse_spanned_forms(&source)?;
+        if let Some(bytes) = encode(&forms, hash) {
+            // the cache is only ever an optimisation, failing to fill it is fine
+            let _ = fs::create_dir_all(&self.dir).and_then(|_| fs::write(&entry, bytes));
+        }
+        Ok((source, forms))
+    }
+
+    /// Removes every entry, returning how many there were.
+    pub fn clear(&self) -> io::Result<usize> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+
+        let mut removed = 0;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == EXTENSION) {
+                fs::remove_file(path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Every top level form in `source`, failing on the first that doesn't parse.
+pub fn parse_forms(source: &str) -> Result<Vec<Lval>, Lerr> {
+    parse_spanned_forms(source).map(|forms| forms.into_iter().map(|(_, form)| form).collect())
+}
+
+/// Like `parse_forms`, along with where in `source` each form is.
+pub fn parse_spanned_forms(source: &str) -> Result<Vec<(Span, Lval)>, Lerr> {
+    let mut forms = Incremental::new();
+    forms.push_str(source);
+    forms.finish();
+
+    let mut parsed = vec![];
+    while let Some((span, form)) = forms.next_spanned_form() {
+        parsed.push((span, form?));
+    }
+    Ok(parsed)
+}
+
+// stable across builds and platforms, unlike std's DefaultHasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+const TAG_NUM: u8 = 0;
+const TAG_SYM: u8 = 1;
+const TAG_STR: u8 = 2;
+const TAG_SEXPR: u8 = 3;
+const TAG_QEXPR: u8 = 4;
+const TAG_BYTES: u8 = 5;
+const TAG_KEYWORD: u8 = 6;
+const TAG_INT: u8 = 7;
+const TAG_BOOL: u8 = 8;
+const TAG_NIL: u8 = 9;
+
+// `None` if the forms hold something the parser never produces. Their spans
+// come after them, as start and end offsets
+fn encode(forms: &[(Span, Lval)], hash: u64) -> Option<Vec<u8>> {
+    let mut encoder = Encoder {
+        bytes: MAGIC.to_vec(),
+        encodable: true,
+    };
+    encoder.write_bytes(VERSION.as_bytes());
+    encoder.bytes.extend_from_slice(&hash.to_le_bytes());
+    encoder.write_len(forms.len());
+    for (_, form) in forms {
+        walk(form, &mut encoder);
+    }
+    for (span, _) in forms {
+        encoder.write_len(span.start);
+        encoder.write_len(span.end);
+    }
+
+    if encoder.encodable {
+        Some(encoder.bytes)
+    } else {
+        None
+    }
+}
+
+// lists are written as their tag and length, followed by their items, which
+// is exactly the order `walk` visits them in
+struct Encoder {
+    bytes: Vec<u8>,
+    encodable: bool,
+}
+
+impl Encoder {
+    fn write_len(&mut self, len: usize) {
+        self.bytes.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_len(bytes.len());
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+impl LvalVisitor for Encoder {
+    fn visit_num(&mut self, num: f64) {
+        self.bytes.push(TAG_NUM);
+        self.bytes.extend_from_slice(&num.to_le_bytes());
+    }
+
+    fn visit_int(&mut self, int: i64) {
+        self.bytes.push(TAG_INT);
+        self.bytes.extend_from_slice(&int.to_le_bytes());
+    }
+
+    fn visit_sym(&mut self, sym: &str) {
+        self.bytes.push(TAG_SYM);
+        self.write_bytes(sym.as_bytes());
+    }
+
+    fn visit_str(&mut self, s: &str) {
+        self.bytes.push(TAG_STR);
+        self.write_bytes(s.as_bytes());
+    }
+
+    fn visit_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.push(TAG_BYTES);
+        self.write_bytes(bytes);
+    }
+
+    fn visit_keyword(&mut self, keyword: &str) {
+        self.bytes.push(TAG_KEYWORD);
+        self.write_bytes(keyword.as_bytes());
+    }
+
+    fn visit_bool(&mut self, b: bool) {
+        self.bytes.push(TAG_BOOL);
+        self.bytes.push(u8::from(b));
+    }
+
+    fn visit_nil(&mut self) {
+        self.bytes.push(TAG_NIL);
+    }
+
+    fn visit_fun(&mut self, _fun: Lfun) {
+        self.encodable = false;
+    }
+
+    fn visit_native(&mut self, _native: &NativeFn) {
+        self.encodable = false;
+    }
+
+    fn visit_lambda(&mut self, _lambda: &Llambda) {
+        self.encodable = false;
+    }
+
+    fn visit_list(&mut self, kind: ListKind, items: &[Lval]) {
+        self.bytes.push(match kind {
+            ListKind::Sexpr => TAG_SEXPR,
+            ListKind::Qexpr => TAG_QEXPR,
+        });
+        self.write_len(items.len());
+    }
+}
+
+fn decode(bytes: &[u8], hash: u64) -> Option<Vec<(Span, Lval)>> {
+    let mut reader = Reader(bytes);
+    if reader.take(MAGIC.len())? != MAGIC
+        || reader.bytes()? != VERSION.as_bytes()
+        || reader.u64()? != hash
+    {
+        return None;
+    }
+
+    let forms = reader.list()?;
+    let spans = (0..forms.len())
+        .map(|_| {
+            let start = reader.u32()?;
+            Some(Span {
+                start,
+                end: reader.u32()?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if reader.0.is_empty() {
+        Some(spans.into_iter().zip(forms).collect())
+    } else {
+        None
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<usize> {
+        let mut word = [0; 4];
+        word.copy_from_slice(self.take(4)?);
+        Some(u32::from_le_bytes(word) as usize)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let mut word = [0; 8];
+        word.copy_from_slice(self.take(8)?);
+        Some(u64::from_le_bytes(word))
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+
+    fn list(&mut self) -> Option<Vec<Lval>> {
+        let len = self.u32()?;
+        // every element takes at least one byte, so a corrupt length can't
+        // make us allocate more than the entry itself
+        if len > self.0.len() {
+            return None;
+        }
+        (0..len).map(|_| self.lval()).collect()
+    }
+
+    fn lval(&mut self) -> Option<Lval> {
+        match self.take(1)?[0] {
+            TAG_NUM => self.u64().map(|bits| Lval::Num(f64::from_bits(bits))),
+            TAG_INT => self.u64().map(|bits| Lval::Int(bits as i64)),
+            TAG_SYM => self.string().map(Lval::Sym),
+            TAG_STR => self.string().map(Lval::Str),
+            TAG_SEXPR => self.list().map(Lval::Sexpr),
+            TAG_QEXPR => self.list().map(Lval::Qexpr),
+            TAG_KEYWORD => self.string().map(Lval::Keyword),
+            TAG_BYTES => self.bytes().map(|bytes| Lval::Bytes(bytes.to_vec())),
+            TAG_BOOL => match self.take(1)?[0] {
+                0 => Some(Lval::Bool(false)),
+                1 => Some(Lval::Bool(true)),
+                _ => None,
+            },
+            TAG_NIL => Some(Lval::Nil),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a fresh directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("oolisp-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const PRELUDE: &str =
+        "(def [sq] (\\ [x] [* x x]))\n(def [greeting] \"hi\")\n(sq 2.5e-3)\n[:width 8 true nil]";
+
+    fn write_source(dir: &TempDir, source: &str) -> PathBuf {
+        let path = dir.0.join("prelude.lsp");
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    #[test]
+    fn it_skips_parsing_unchanged_files() {
+        let dir = TempDir::new("cache-hit");
+        let path = write_source(&dir, PRELUDE);
+        let mut cache = ParseCache::new(dir.0.join("cache"));
+
+        let first = cache.load(&path).unwrap();
+        assert_eq!(cache.parses(), 1);
+        assert_eq!(first.len(), 4);
+
+        let second = cache.load(&path).unwrap();
+        assert_eq!(cache.parses(), 1);
+        assert_eq!(first, second);
+
+        // a fresh cache over the same directory still hits
+        let mut reopened = ParseCache::new(dir.0.join("cache"));
+        assert_eq!(reopened.load(&path).unwrap(), first);
+        assert_eq!(reopened.parses(), 0);
+
+        // editing the file misses
+        write_source(&dir, "(+ 1 2)");
+        assert_eq!(cache.load(&path).unwrap().len(), 1);
+        assert_eq!(cache.parses(), 2);
+    }
+
+    #[test]
+    fn it_falls_back_on_bad_entries() {
+        let dir = TempDir::new("cache-corrupt");
+        let path = write_source(&dir, PRELUDE);
+        let mut cache = ParseCache::new(dir.0.join("cache"));
+        let expected = cache.load(&path).unwrap();
+
+        let entry = fs::read_dir(cache.dir())
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let good = fs::read(&entry).unwrap();
+
+        let header = MAGIC.len() + 4 + VERSION.len() + 8;
+        let truncated = good[..good.len() - 3].to_vec();
+        let mut bad_tag = good.clone();
+        bad_tag[header + 4] = 0xff;
+        let mut huge_count = good.clone();
+        huge_count[header..header + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut other_version = good.clone();
+        other_version.splice(
+            MAGIC.len() + 4..MAGIC.len() + 4 + VERSION.len(),
+            b"0.0.0".to_vec(),
+        );
+        other_version.splice(MAGIC.len()..MAGIC.len() + 4, 5_u32.to_le_bytes().to_vec());
+
+        for (parses, bad) in vec![truncated, bad_tag, huge_count, other_version, vec![]]
+            .into_iter()
+            .enumerate()
+        {
+            fs::write(&entry, bad).unwrap();
+            assert_eq!(cache.load(&path).unwrap(), expected);
+            assert_eq!(cache.parses(), parses + 2);
+        }
+    }
+
+    #[test]
+    fn it_reports_unparsable_and_missing_files() {
+        let dir = TempDir::new("cache-errors");
+        let mut cache = ParseCache::new(dir.0.join("cache"));
+
+        let path = write_source(&dir, "(+ 1 2) (+ 1");
+        let _ = cache
+            .load(&path)
+            .map_err(|err| assert_eq!(err.etype, LerrType::Syntax));
+        assert_eq!(cache.clear().unwrap(), 0);
+
+        let _ = cache
+            .load(dir.0.join("missing.lsp"))
+            .map_err(|err| assert_eq!(err.etype, LerrType::Io));
+    }
+
+    #[test]
+    fn it_clears_entries() {
+        let dir = TempDir::new("cache-clear");
+        let mut cache = ParseCache::new(dir.0.join("cache"));
+        assert_eq!(cache.clear().unwrap(), 0);
+
+        cache.load(write_source(&dir, PRELUDE)).unwrap();
+        cache.load(write_source(&dir, "(+ 1 2)")).unwrap();
+        assert_eq!(cache.clear().unwrap(), 2);
+
+        cache.load(write_source(&dir, PRELUDE)).unwrap();
+        assert_eq!(cache.parses(), 3);
+    }
+}
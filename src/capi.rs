@@ -0,0 +1,291 @@
+//! A C interface for embedding the interpreter in non-Rust hosts.
+//!
+//! Every function accepts null pointers, treating them as an error or a no
+//! op, and none of them let a panic unwind into the caller. Results come
+//! back as JSON objects of the form
+//! `{"value": "3", "error": null, "output": ["Warning: ..."]}`, where
+//! `error` is `{"type": "BadNum", "message": "..."}` on failure. Strings
+//! returned to the host must be given back to `lisp_string_free`.
+use crate::{
+    eval::panic_message,
+    interpreter::{ErrorPolicy, Interpreter},
+    Lerr, Lval,
+};
+use std::{
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+/// A new interpreter with every builtin, to be released with `lisp_free`.
+/// Null only if setting up panicked.
+#[no_mangle]
+pub extern "C" fn lisp_new() -> *mut Interpreter {
+    panic::catch_unwind(|| Box::into_raw(Box::new(Interpreter::new()))).unwrap_or(ptr::null_mut())
+}
+
+/// Releases an interpreter from `lisp_new`.
+///
+/// # Safety
+///
+/// `interp` must be null or from `lisp_new`, and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn lisp_free(interp: *mut Interpreter) {
+    if !interp.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(interp))));
+    }
+}
+
+/// Evaluates every form in `source`, giving the last value or the first
+/// error as JSON. Never null.
+///
+/// # Safety
+///
+/// `interp` must be null or a live interpreter from `lisp_new`, and
+/// `source` null or a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lisp_eval(interp: *mut Interpreter, source: *const c_char) -> *mut c_char {
+    let json = match (interp.as_mut(), c_str(source)) {
+        (None, _) => error_json("Null", "no interpreter was given", &[]),
+        (_, Err(message)) => error_json("Null", message, &[]),
+        (Some(interpreter), Ok(source)) => {
+            panic::catch_unwind(AssertUnwindSafe(|| eval_json(interpreter, source))).unwrap_or_else(
+                |payload| error_json("Panic", &panic_message(payload.as_ref()), &[]),
+            )
+        }
+    };
+
+    // JSON escapes control characters, so there is never a NUL inside
+    CString::new(json).unwrap_or_default().into_raw()
+}
+
+/// Releases a string returned by `lisp_eval`.
+///
+/// # Safety
+///
+/// `s` must be null or from `lisp_eval`, and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn lisp_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Binds `name` to a number globally. Returns 0 on success and -1 if
+/// anything was null or the binding failed.
+///
+/// # Safety
+///
+/// As for `lisp_eval`, with `name` in place of `source`.
+#[no_mangle]
+pub unsafe extern "C" fn lisp_define_number(
+    interp: *mut Interpreter,
+    name: *const c_char,
+    value: f64,
+) -> c_int {
+    define(interp, name, Lval::Num(value))
+}
+
+/// Binds `name` to a copy of the string `value` globally. Returns 0 on
+/// success and -1 if anything was null or the binding failed.
+///
+/// # Safety
+///
+/// As for `lisp_eval`, with `name` and `value` in place of `source`.
+#[no_mangle]
+pub unsafe extern "C" fn lisp_define_string(
+    interp: *mut Interpreter,
+    name: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    match c_str(value) {
+        Ok(value) => define(interp, name, Lval::Str(value.to_string())),
+        Err(_) => -1,
+    }
+}
+
+unsafe fn define(interp: *mut Interpreter, name: *const c_char, lval: Lval) -> c_int {
+    let (interpreter, name) = match (interp.as_mut(), c_str(name)) {
+        (Some(interpreter), Ok(name)) => (interpreter, name),
+        _ => return -1,
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| {
+        interpreter.env_mut().insert_last(name, lval)
+    })) {
+        Ok(Ok(())) => 0,
+        _ => -1,
+    }
+}
+
+unsafe fn c_str<'a>(s: *const c_char) -> Result<&'a str, &'static str> {
+    if s.is_null() {
+        return Err("no string was given");
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| "the string given wasn't UTF-8")
+}
+
+fn eval_json(interpreter: &mut Interpreter, source: &str) -> String {
+    let mut report = interpreter.eval_program(source, ErrorPolicy::StopAtFirst);
+    let output = interpreter
+        .env_mut()
+        .take_warnings()
+        .into_iter()
+        .map(|warning| format!("Warning: {}", warning))
+        .collect::<Vec<_>>();
+
+    match report.failures.pop() {
+        Some(failure) => lerr_json(&failure.error, &output),
+        None => {
+            let value = report
+                .last
+                .map_or_else(|| "nil".to_string(), |lval| format!("{:?}", lval));
+            format!(
+                "{{\"value\": {}, \"error\": null, \"output\": {}}}",
+                json_string(&value),
+                json_strings(&output)
+            )
+        }
+    }
+}
+
+fn lerr_json(error: &Lerr, output: &[String]) -> String {
+    error_json(&format!("{:?}", error.etype), &error.message, output)
+}
+
+fn error_json(etype: &str, message: &str, output: &[String]) -> String {
+    format!(
+        "{{\"value\": null, \"error\": {{\"type\": {}, \"message\": {}}}, \"output\": {}}}",
+        json_string(etype),
+        json_string(message),
+        json_strings(output)
+    )
+}
+
+fn json_strings(strings: &[String]) -> String {
+    let strings = strings.iter().map(|s| json_string(s)).collect::<Vec<_>>();
+    format!("[{}]", strings.join(", "))
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // evaluates through the C interface, freeing what it allocates
+    fn eval(interp: *mut Interpreter, source: &str) -> String {
+        let source = CString::new(source).unwrap();
+        unsafe {
+            let result = lisp_eval(interp, source.as_ptr());
+            let json = CStr::from_ptr(result).to_str().unwrap().to_string();
+            lisp_string_free(result);
+            json
+        }
+    }
+
+    #[test]
+    fn it_evaluates_through_c() {
+        let interp = lisp_new();
+        assert_eq!(
+            eval(interp, "(def [x] 20) (+ x 1)"),
+            r#"{"value": "21", "error": null, "output": []}"#
+        );
+        assert_eq!(
+            eval(interp, "(concat \"a\" \"b\")"),
+            r#"{"value": "\"ab\"", "error": null, "output": []}"#
+        );
+        assert_eq!(
+            json_string("say \"hi\"\\\n\t\u{1}é"),
+            r#""say \"hi\"\\\n\t\u0001é""#
+        );
+        assert_eq!(
+            eval(interp, "(* 1e308 10)"),
+            r#"{"value": "+inf", "error": null, "output": ["Warning: step 2 of '*': 1e308 * 10 overflowed"]}"#
+        );
+
+        let name = CString::new("n").unwrap();
+        let text = CString::new("héllo").unwrap();
+        unsafe {
+            assert_eq!(lisp_define_number(interp, name.as_ptr(), 2.5), 0);
+            assert_eq!(
+                eval(interp, "(* n 2)"),
+                r#"{"value": "5.0", "error": null, "output": []}"#
+            );
+            assert_eq!(lisp_define_string(interp, name.as_ptr(), text.as_ptr()), 0);
+            assert!(eval(interp, "n").starts_with(r#"{"value": "\"héllo\"""#));
+            lisp_free(interp);
+        }
+    }
+
+    #[test]
+    fn it_reports_errors_through_c() {
+        let interp = lisp_new();
+        assert_eq!(
+            eval(interp, "(head [])"),
+            r#"{"value": null, "error": {"type": "EmptyList", "message": "Function head was given empty list"}, "output": []}"#
+        );
+        assert!(eval(interp, "(+ 1 2").contains(r#""type": "Syntax""#));
+        unsafe { lisp_free(interp) };
+    }
+
+    #[test]
+    fn it_catches_panics() {
+        let interp = lisp_new();
+        unsafe { &mut *interp }.register_host_handler("boom", |_| panic!("host blew up"));
+        // builtins catch their own panics, this boundary is for the rest
+        assert_eq!(
+            eval(interp, "(host \"boom\")"),
+            r#"{"value": null, "error": {"type": "Internal", "message": "builtin host panicked: host blew up"}, "output": []}"#
+        );
+
+        // and the interpreter is still usable
+        assert!(eval(interp, "(+ 1 1)").starts_with(r#"{"value": "2""#));
+        unsafe { lisp_free(interp) };
+    }
+
+    #[test]
+    fn it_tolerates_nulls() {
+        unsafe {
+            let result = lisp_eval(ptr::null_mut(), ptr::null());
+            assert!(CStr::from_ptr(result)
+                .to_str()
+                .unwrap()
+                .contains("\"Null\""));
+            lisp_string_free(result);
+
+            let interp = lisp_new();
+            let result = lisp_eval(interp, ptr::null());
+            assert!(CStr::from_ptr(result)
+                .to_str()
+                .unwrap()
+                .contains("\"Null\""));
+            lisp_string_free(result);
+            assert_eq!(lisp_define_number(interp, ptr::null(), 1.0), -1);
+            assert_eq!(lisp_define_number(ptr::null_mut(), ptr::null(), 1.0), -1);
+            let name = CString::new("s").unwrap();
+            assert_eq!(lisp_define_string(interp, name.as_ptr(), ptr::null()), -1);
+            lisp_free(interp);
+
+            lisp_free(ptr::null_mut());
+            lisp_string_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Counts deep copies of large values, so tests can catch ones made by
+//! accident. Only built with the `clone-audit` feature: the hooks cost a
+//! walk over every value they see.
+use crate::Span;
+
+/// Below this many bytes, as `env::deep_size` measures, a clone isn't
+/// counted unless the threshold is changed.
+pub const DEFAULT_CLONE_THRESHOLD: usize = 4096;
+
+/// How many large clones a report keeps the details of. Later ones are
+/// still counted.
+pub const CLONES_KEPT: usize = 32;
+
+/// One large value copied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LargeClone {
+    /// the place in the interpreter that copied it, like `"eval_symbol"`
+    pub hook: &'static str,
+    pub bytes: usize,
+    /// the call being evaluated at the time, when evaluating with spans
+    pub span: Option<Span>,
+}
+
+/// Large clones since the audit was last reset.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CloneAuditReport {
+    pub threshold: usize,
+    pub large_clones: u64,
+    /// the first `CLONES_KEPT` of them
+    pub clones: Vec<LargeClone>,
+}
+
+impl Default for CloneAuditReport {
+    fn default() -> Self {
+        CloneAuditReport {
+            threshold: DEFAULT_CLONE_THRESHOLD,
+            large_clones: 0,
+            clones: vec![],
+        }
+    }
+}
+
+impl CloneAuditReport {
+    pub fn is_clean(&self) -> bool {
+        self.large_clones == 0
+    }
+
+    pub(crate) fn record(&mut self, hook: &'static str, bytes: usize, span: Option<Span>) {
+        if bytes < self.threshold {
+            return;
+        }
+        self.large_clones += 1;
+        if self.clones.len() < CLONES_KEPT {
+            self.clones.push(LargeClone { hook, bytes, span });
+        }
+    }
+}
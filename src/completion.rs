@@ -0,0 +1,199 @@
+//! Answers for editors about the names bound in an environment:
+//! completions, signatures and where definitions are. Each is a query only,
+//! leaving the environment as it was.
+pub use crate::Arity;
+use crate::{
+    env::{Lookup, Origin},
+    Lenv, Lval, Span,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{cell::Ref, collections::HashSet};
+
+/// What a name is bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CompletionKind {
+    Builtin,
+    /// a lambda or a case-lambda
+    Lambda,
+    Value,
+}
+
+/// A bound name starting with what was typed.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CompletionItem {
+    pub name: String,
+    pub kind: CompletionKind,
+    /// the first line of the comment above its definition in a file
+    pub doc: Option<String>,
+    /// one for each clause, none when that isn't known, as for builtins
+    /// taking varying numbers of arguments
+    pub arities: Vec<Arity>,
+}
+
+/// How to call a function.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Signature {
+    pub name: String,
+    /// the parameters of each clause, `:` marking the one that collects the
+    /// rest. Empty for builtins.
+    pub params: Vec<Vec<String>>,
+    pub arities: Vec<Arity>,
+    /// the whole comment above its definition in a file
+    pub doc: Option<String>,
+}
+
+/// Every name visible in `env` starting with `prefix`, sorted. A name
+/// bound in more than one scope is described by the innermost binding.
+pub fn complete(env: &Lenv, prefix: &str) -> Vec<CompletionItem> {
+    let mut seen = HashSet::new();
+    let mut items = vec![];
+    for lookup in env.iter() {
+        for (name, lval) in lookup.iter() {
+            if name.starts_with(prefix) && seen.insert(name.clone()) {
+                items.push(CompletionItem {
+                    name: name.clone(),
+                    kind: kind(lval),
+                    doc: doc(&lookup, name).and_then(|doc| doc.lines().next().map(str::to_string)),
+                    arities: arities(env, lval),
+                });
+            }
+        }
+    }
+    items.sort_by(|a, b| a.name.cmp(&b.name));
+    items
+}
+
+/// How to call the function bound to `name`, `None` if it isn't bound to
+/// one.
+pub fn signature_help(env: &Lenv, name: &str) -> Option<Signature> {
+    let lookup = innermost(env, name)?;
+    let lval = lookup.get(name)?;
+    let params = match lval {
+        Lval::Lambda(lambda) => vec![lambda.args().to_vec()],
+        Lval::CaseLambda(clauses) => clauses
+            .iter()
+            .map(|lambda| lambda.args().to_vec())
+            .collect(),
+        Lval::Fun(_) | Lval::Native(_) => vec![],
+        _ => return None,
+    };
+    Some(Signature {
+        name: name.to_string(),
+        params,
+        arities: arities(env, lval),
+        doc: doc(&lookup, name),
+    })
+}
+
+/// The file and bytes of the form that last defined `name`, when that was
+/// read from a file.
+pub fn definition_span(env: &Lenv, name: &str) -> Option<(String, Span)> {
+    let lookup = innermost(env, name)?;
+    match lookup.origins(name).last()? {
+        Origin::File { path, span, .. } => Some((path.clone(), *span)),
+        _ => None,
+    }
+}
+
+fn innermost<'a>(env: &'a Lenv, name: &str) -> Option<Ref<'a, Lookup>> {
+    env.iter().find(|lookup| lookup.contains_key(name))
+}
+
+fn kind(lval: &Lval) -> CompletionKind {
+    match lval {
+        Lval::Fun(_) | Lval::Native(_) => CompletionKind::Builtin,
+        Lval::Lambda(_) | Lval::CaseLambda(_) => CompletionKind::Lambda,
+        _ => CompletionKind::Value,
+    }
+}
+
+fn arities(env: &Lenv, lval: &Lval) -> Vec<Arity> {
+    match lval {
+        Lval::Lambda(lambda) => vec![lambda.arity()],
+        Lval::CaseLambda(clauses) => clauses.iter().map(|lambda| lambda.arity()).collect(),
+        Lval::Fun(fun) => env.arity(*fun).into_iter().collect(),
+        _ => vec![],
+    }
+}
+
+fn doc(lookup: &Lookup, name: &str) -> Option<String> {
+    match lookup.origins(name).last()? {
+        Origin::File { doc, .. } => doc.clone(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{ErrorPolicy, Interpreter};
+    use std::fs;
+
+    #[test]
+    fn it_completes_from_the_innermost_binding() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval_str("def [sq] 4").unwrap();
+        interpreter.eval_str("def [sqrt-2] 1.41").unwrap();
+
+        // a local `sq` hides the global one
+        let square = interpreter.eval_str("\\ [x] [* x x]").unwrap();
+        interpreter.env_mut().push(Lookup::new());
+        interpreter.env_mut().insert("sq", square).unwrap();
+
+        let items = interpreter.complete("sq");
+        let described: Vec<_> = items
+            .iter()
+            .map(|item| (item.name.as_str(), item.kind, item.arities.clone()))
+            .collect();
+        assert_eq!(
+            described,
+            vec![
+                ("sq", CompletionKind::Lambda, vec![(1, Some(1))]),
+                ("sqrt", CompletionKind::Builtin, vec![(1, Some(1))]),
+                ("sqrt-2", CompletionKind::Value, vec![]),
+            ]
+        );
+
+        let head = interpreter.signature_help("head").unwrap();
+        assert_eq!((head.params, head.arities), (vec![], vec![(1, Some(1))]));
+        let range = interpreter.signature_help("range").unwrap();
+        assert_eq!(range.arities, vec![(1, Some(3))]);
+        assert_eq!(interpreter.signature_help("sqrt-2"), None);
+        assert!(interpreter.complete("no-such-").is_empty());
+    }
+
+    #[test]
+    fn it_finds_docs_and_definitions_in_files() {
+        let path = std::env::temp_dir().join(format!("oolisp-docs-{}.lsp", std::process::id()));
+        let source = "; Squares a number.\n;; Any number at all.\n(def [sq] (\\ [x] [* x x]))\n\n(def [two] 2)";
+        fs::write(&path, source).unwrap();
+        let mut interpreter = Interpreter::new();
+        let report = interpreter.eval_file(&path, ErrorPolicy::StopAtFirst);
+        let _ = fs::remove_file(&path);
+        assert!(report.unwrap().is_ok());
+
+        assert_eq!(
+            interpreter.complete("sq")[0].doc.as_deref(),
+            Some("Squares a number.")
+        );
+        let sq = interpreter.signature_help("sq").unwrap();
+        assert_eq!(sq.params, vec![vec!["x".to_string()]]);
+        assert_eq!(
+            sq.doc.as_deref(),
+            Some("Squares a number.\nAny number at all.")
+        );
+        assert_eq!(interpreter.complete("two")[0].doc, None);
+
+        let start = source.find("(def [sq]").unwrap();
+        let end = source.find("\n\n").unwrap();
+        assert_eq!(
+            interpreter.definition_span("sq"),
+            Some((path.display().to_string(), Span { start, end }))
+        );
+        assert_eq!(interpreter.definition_span("head"), None);
+    }
+}
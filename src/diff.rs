@@ -0,0 +1,250 @@
+use crate::Lval;
+
+/// How deep `diff` looks before reporting a whole subtree as one difference.
+pub const MAX_DIFF_DEPTH: usize = 64;
+
+/// How many differences `diff` reports before it stops looking.
+pub const MAX_DIFFERENCES: usize = 100;
+
+/// One place two values disagree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    /// list indices, or keys through association lists, from the top down
+    pub path: Vec<Lval>,
+    pub left: Lval,
+    pub right: Lval,
+}
+
+/// Stands in for the side of a difference that has nothing there.
+pub fn missing() -> Lval {
+    Lval::Keyword("missing".to_string())
+}
+
+/// Where `left` and `right` differ, in the order they appear. Lists are
+/// compared element by element, with a length mismatch reported once at the
+/// first index only one side has. Lists made entirely of `[key value]` pairs
+/// with distinct symbol, keyword or string keys are compared by key. Equal
+/// values give no differences.
+pub fn diff(left: &Lval, right: &Lval) -> Vec<Difference> {
+    let mut differences = vec![];
+    let mut pending = vec![Step::Compare(vec![], left, right)];
+
+    while let Some(step) = pending.pop() {
+        if differences.len() == MAX_DIFFERENCES {
+            break;
+        }
+
+        let (path, a, b) = match step {
+            Step::Report(difference) => {
+                differences.push(difference);
+                continue;
+            }
+            Step::Compare(path, Lval::Sexpr(a), Lval::Sexpr(b))
+            | Step::Compare(path, Lval::Qexpr(a), Lval::Qexpr(b))
+                if path.len() < MAX_DIFF_DEPTH =>
+            {
+                (path, a, b)
+            }
+            Step::Compare(path, left, right) => {
+                if left != right {
+                    differences.push(report(path, left.clone(), right.clone()));
+                }
+                continue;
+            }
+        };
+
+        let mut steps = vec![];
+        match (alist(a), alist(b)) {
+            (Some(a), Some(b)) => {
+                for (key, left) in &a {
+                    let path = extend(&path, (*key).clone());
+                    steps.push(match b.iter().find(|(other, _)| other == key) {
+                        Some((_, right)) => Step::Compare(path, left, right),
+                        None => Step::Report(report(path, (*left).clone(), missing())),
+                    });
+                }
+                for (key, right) in &b {
+                    if !a.iter().any(|(other, _)| other == key) {
+                        let path = extend(&path, (*key).clone());
+                        steps.push(Step::Report(report(path, missing(), (*right).clone())));
+                    }
+                }
+            }
+            _ => {
+                for (i, (left, right)) in a.iter().zip(b).enumerate() {
+                    steps.push(Step::Compare(
+                        extend(&path, Lval::Int(i as i64)),
+                        left,
+                        right,
+                    ));
+                }
+                let shorter = a.len().min(b.len());
+                if a.len() != b.len() {
+                    steps.push(Step::Report(report(
+                        extend(&path, Lval::Int(shorter as i64)),
+                        a.get(shorter).cloned().unwrap_or_else(missing),
+                        b.get(shorter).cloned().unwrap_or_else(missing),
+                    )));
+                }
+            }
+        }
+
+        // backwards so they come off the stack in order
+        pending.extend(steps.into_iter().rev());
+    }
+
+    differences
+}
+
+// work left for `diff`, kept in one stack so differences come out in order
+enum Step<'a> {
+    Compare(Vec<Lval>, &'a Lval, &'a Lval),
+    Report(Difference),
+}
+
+fn report(path: Vec<Lval>, left: Lval, right: Lval) -> Difference {
+    Difference { path, left, right }
+}
+
+fn extend(path: &[Lval], step: Lval) -> Vec<Lval> {
+    let mut path = path.to_vec();
+    path.push(step);
+    path
+}
+
+// the entries of an association list, if `items` is one
+fn alist(items: &[Lval]) -> Option<Vec<(&Lval, &Lval)>> {
+    let mut entries: Vec<(&Lval, &Lval)> = vec![];
+    for item in items {
+        let (key, value) = match item {
+            Lval::Qexpr(pair) if pair.len() == 2 => (&pair[0], &pair[1]),
+            _ => return None,
+        };
+        let usable = matches!(key, Lval::Sym(_) | Lval::Keyword(_) | Lval::Str(_));
+        if !usable || entries.iter().any(|(other, _)| *other == key) {
+            return None;
+        }
+        entries.push((key, value));
+    }
+    (!entries.is_empty()).then_some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(n: f64) -> Lval {
+        Lval::Num(n)
+    }
+
+    fn sym(s: &str) -> Lval {
+        Lval::Sym(s.to_string())
+    }
+
+    fn list(items: Vec<Lval>) -> Lval {
+        Lval::Qexpr(items)
+    }
+
+    #[test]
+    fn it_finds_nothing_between_equal_values() {
+        let value = list(vec![num(1.0), list(vec![Lval::Str("a".into()), sym("b")])]);
+        assert_eq!(diff(&value, &value.clone()), vec![]);
+        assert_eq!(diff(&list(vec![]), &list(vec![])), vec![]);
+    }
+
+    #[test]
+    fn it_finds_nested_differences() {
+        let left = list(vec![
+            num(1.0),
+            list(vec![
+                num(2.0),
+                Lval::Str("same".into()),
+                Lval::Str("x".into()),
+            ]),
+            list(vec![list(vec![sym("deep")])]),
+        ]);
+        let right = list(vec![
+            num(1.0),
+            list(vec![
+                num(3.0),
+                Lval::Str("same".into()),
+                Lval::Str("y".into()),
+            ]),
+            list(vec![list(vec![Lval::Sexpr(vec![sym("deep")])])]),
+        ]);
+
+        assert_eq!(
+            diff(&left, &right),
+            vec![
+                report(vec![num(1.0), num(0.0)], num(2.0), num(3.0)),
+                report(
+                    vec![num(1.0), num(2.0)],
+                    Lval::Str("x".into()),
+                    Lval::Str("y".into())
+                ),
+                report(
+                    vec![num(2.0), num(0.0), num(0.0)],
+                    sym("deep"),
+                    Lval::Sexpr(vec![sym("deep")])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reports_length_mismatches_once() {
+        let left = list(vec![list(vec![num(1.0), num(2.0)]), num(3.0), num(4.0)]);
+        let right = list(vec![list(vec![num(1.0), num(9.0)])]);
+        assert_eq!(
+            diff(&left, &right),
+            vec![
+                report(vec![num(0.0), num(1.0)], num(2.0), num(9.0)),
+                report(vec![num(1.0)], num(3.0), missing()),
+            ]
+        );
+        assert_eq!(
+            diff(&list(vec![]), &list(vec![sym("a")])),
+            vec![report(vec![num(0.0)], missing(), sym("a"))]
+        );
+    }
+
+    #[test]
+    fn it_follows_keys_through_alists() {
+        let pair = |key: &str, value: Lval| list(vec![sym(key), value]);
+        let left = list(vec![
+            pair("a", num(1.0)),
+            pair("b", num(2.0)),
+            pair("c", num(3.0)),
+        ]);
+        let right = list(vec![
+            pair("c", num(3.0)),
+            pair("b", num(5.0)),
+            pair("d", num(4.0)),
+        ]);
+
+        assert_eq!(
+            diff(&left, &right),
+            vec![
+                report(vec![sym("a")], num(1.0), missing()),
+                report(vec![sym("b")], num(2.0), num(5.0)),
+                report(vec![sym("d")], missing(), num(4.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_output_bounded() {
+        let many = |n: f64| list((0..500).map(|_| num(n)).collect());
+        assert_eq!(diff(&many(1.0), &many(2.0)).len(), MAX_DIFFERENCES);
+
+        let mut left = num(1.0);
+        let mut right = num(2.0);
+        for _ in 0..MAX_DIFF_DEPTH + 10 {
+            left = list(vec![left]);
+            right = list(vec![right]);
+        }
+        let differences = diff(&left, &right);
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].path.len(), MAX_DIFF_DEPTH);
+    }
+}
@@ -1,80 +1,1210 @@
-use crate::{builtin::init_builtins, Lval};
-use std::collections::HashMap;
+#[cfg(feature = "cache")]
+use crate::cache::ParseCache;
+#[cfg(feature = "clone-audit")]
+use crate::clone_audit::CloneAuditReport;
+use crate::{
+    builtin::init_builtins,
+    visitor::{walk, ListKind, LvalVisitor},
+    Arity, Contract, Lerr, LerrType, Lfun, Llambda, Lval, NativeFn, Span, SpanTree,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::{self, Write};
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    collections::{hash_map::RandomState, BTreeMap, HashMap, HashSet},
+    fmt,
+    hash::{BuildHasher, Hasher},
+    mem,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[derive(Clone)]
 pub struct Lenv {
+    // the innermost local frame, which links to the rest of them. Shared
+    // with the lambdas made in those frames, see `capture`
     head: LinkedEnv,
+    // owned rather than shared, so clones of an environment define things
+    // apart from each other
+    global: Option<RefCell<Lookup>>,
+    max_bindings: Option<usize>,
+    // boxed so lambdas, which each carry an environment, stay small
+    state: Box<State>,
+}
+
+// everything about an environment besides its bindings
+#[derive(Clone)]
+struct State {
+    hosts: BTreeMap<String, HostHandler>,
+    rng: u64,
+    origin: Origin,
+    strict_arithmetic: bool,
+    special_floats: bool,
+    warnings: Vec<String>,
+    // every file `load` has read into this environment, first load first
+    loaded: Vec<PathBuf>,
+    // where `load` gets parsed files from, shared by every clone. Unset
+    // until the first load, which uses the default directory
+    #[cfg(feature = "cache")]
+    parse_cache: Option<Rc<RefCell<ParseCache>>>,
+    // where `print` goes, and what it wrote on the web with nowhere to go
+    writer: Option<Writer>,
+    output: String,
+    #[cfg(debug_assertions)]
+    stats: EvalStats,
+    clock: Option<Clock>,
+    // tightest last, in milliseconds by the clock
+    deadlines: Vec<f64>,
+    counters: Counters,
+    // the last result rendered for someone to see, kept to show in full
+    last_result: Option<Lval>,
+    // current scope depth, and how many evaluations are under way
+    scopes: usize,
+    nesting: usize,
+    // evaluations left before giving up, and how many may be under way at
+    // once, where those are limited
+    fuel: Option<u64>,
+    max_nesting: Option<usize>,
+    // the fuel each evaluation from the top starts with, if it's refilled
+    max_steps: Option<u64>,
+    // elements or characters a builtin may build one value out of
+    max_value_size: Option<usize>,
+    // builtins registered as pure, everything else is assumed impure.
+    // Shared, since it's only written while registering builtins
+    pure: Rc<Vec<Lfun>>,
+    // the arguments each builtin takes, as registered. Shared like `pure`
+    arities: Rc<Vec<(Lfun, Arity)>>,
+    // names of the builtins `def` and its kin won't replace
+    reserved: Rc<HashSet<String>>,
+    // where the code under evaluation came from, innermost last, `None`
+    // where that isn't known. Empty unless evaluating with spans
+    sites: Vec<Option<Rc<SpanTree>>>,
+    #[cfg(feature = "clone-audit")]
+    clone_audit: CloneAuditReport,
+}
+
+/// How many elements, characters or bytes a builtin may build a single
+/// value out of unless told otherwise. Kept low in the browser, where a
+/// runaway allocation takes the whole page down with it.
+#[cfg(target_arch = "wasm32")]
+pub const DEFAULT_MAX_VALUE_SIZE: usize = 1 << 20;
+#[cfg(not(target_arch = "wasm32"))]
+pub const DEFAULT_MAX_VALUE_SIZE: usize = 1 << 28;
+
+/// How much fuel each evaluation from the top starts with unless told
+/// otherwise. Capped in the browser, where a program that never stops
+/// hangs the page, and unlimited elsewhere.
+#[cfg(target_arch = "wasm32")]
+pub const DEFAULT_MAX_STEPS: Option<u64> = Some(10_000_000);
+#[cfg(not(target_arch = "wasm32"))]
+pub const DEFAULT_MAX_STEPS: Option<u64> = None;
+
+/// Milliseconds since some fixed point, for measuring time limits.
+pub type Clock = Rc<dyn Fn() -> f64>;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+fn default_now() -> f64 {
+    performance_now()
+}
+
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+fn default_now() -> f64 {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_secs_f64()
+        * 1000.0
+}
+
+#[cfg(target_arch = "wasm32")]
+fn default_write(output: &mut String, text: &str) {
+    output.push_str(text);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_write(_output: &mut String, text: &str) {
+    let mut stdout = io::stdout();
+    // nothing sensible to do about a closed stdout from inside a program
+    let _ = stdout
+        .write_all(text.as_bytes())
+        .and_then(|_| stdout.flush());
+}
+
+/// Scope bookkeeping since the stats were last reset, for catching frames
+/// that are pushed and never popped. Only tracked in debug builds.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EvalStats {
+    pub max_scope_depth: usize,
+    pub frames_pushed: usize,
+    pub frames_popped: usize,
+}
+
+/// Running totals of the interpreter's work since they were last reset,
+/// cheap enough to keep in every build.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Counters {
+    /// every value passed to `eval`, atoms included
+    pub expressions: u64,
+    pub builtin_calls: u64,
+    /// lambdas given all their arguments, so their body ran
+    pub lambda_calls: u64,
+    /// errors that ended an evaluation, by type, in the order each type
+    /// first turned up
+    pub errors: Vec<(LerrType, u64)>,
+    /// the most scopes stacked at once, the global one included
+    pub peak_depth: u64,
+}
+
+/// Where text printed by lisp code goes, see `Lenv::set_writer`.
+pub type Writer = Rc<dyn Fn(&str)>;
+
+/// An embedder supplied command reachable from lisp through `host`.
+pub type HostHandler = Rc<dyn Fn(Vec<Lval>) -> Result<Lval, String>>;
+
+type LinkedEnv = Option<Rc<Env>>;
+
+/// Where a global binding was defined.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Origin {
+    Builtin,
+    /// the REPL line with this history number
+    Repl(usize),
+    /// a form in a source file, starting on `line`
+    File {
+        path: String,
+        line: usize,
+        /// the bytes of the whole form
+        span: Span,
+        /// the `;` comment lines just above the form, without the `;`s
+        doc: Option<String>,
+    },
+    Unknown,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::Builtin => write!(f, "builtin"),
+            Origin::Repl(index) => write!(f, "repl entry #{}", index),
+            Origin::File { path, line, .. } => write!(f, "{}:{}", path, line),
+            Origin::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// How many warnings an environment holds on to before dropping new ones.
+pub const WARNINGS_KEPT: usize = 64;
+
+/// How many definitions of one name a frame remembers the origin of.
+pub const ORIGINS_KEPT: usize = 4;
+
+/// A single frame of bindings which remembers the order names were first
+/// defined in, so listings of the environment are reproducible.
+#[derive(Clone, Default)]
+pub struct Lookup {
+    // bindings shared with other frames after `share`, read through until
+    // one of their names is written, which copies them in first
+    base: Option<Rc<Lookup>>,
+    order: Vec<String>,
+    values: HashMap<String, Lval>,
+    // oldest first, only for names that were given one
+    origins: HashMap<String, Vec<Origin>>,
+    contracts: HashMap<String, Contract>,
+}
+
+impl Lookup {
+    pub fn new() -> Self {
+        Lookup::default()
+    }
+
+    /// Redefining a name keeps its original position.
+    pub fn insert(&mut self, key: String, lval: Lval) -> Option<Lval> {
+        self.own(&key);
+        if !self.values.contains_key(&key) {
+            self.order.push(key.clone());
+        }
+        self.values.insert(key, lval)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Lval> {
+        match self.values.get(key) {
+            Some(lval) => Some(lval),
+            None => self.base.as_ref()?.values.get(key),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Lval> {
+        self.own(key);
+        let removed = self.values.remove(key)?;
+        self.order.retain(|name| name != key);
+        self.origins.remove(key);
+        self.contracts.remove(key);
+        Some(removed)
+    }
+
+    /// Holds every later value bound to `key` in this frame to `contract`.
+    pub fn set_contract(&mut self, key: &str, contract: Contract) {
+        self.own(key);
+        self.contracts.insert(key.to_owned(), contract);
+    }
+
+    pub fn contract(&self, key: &str) -> Option<&Contract> {
+        match self.contracts.get(key) {
+            Some(contract) => Some(contract),
+            None => self.base.as_ref()?.contracts.get(key),
+        }
+    }
+
+    /// Notes where `key` was just defined, forgetting the oldest origin
+    /// past `ORIGINS_KEPT`.
+    pub fn record_origin(&mut self, key: &str, origin: Origin) {
+        self.own(key);
+        let origins = self.origins.entry(key.to_owned()).or_default();
+        origins.push(origin);
+        if origins.len() > ORIGINS_KEPT {
+            origins.remove(0);
+        }
+    }
+
+    /// Where `key` was defined, oldest first.
+    pub fn origins(&self, key: &str) -> &[Origin] {
+        match (self.values.contains_key(key), &self.base) {
+            (false, Some(base)) => base.origins(key),
+            _ => self.origins.get(key).map_or(&[], Vec::as_slice),
+        }
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.values.contains_key(key) || self.base.as_ref().is_some_and(|b| b.contains_key(key))
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len() + self.base.as_ref().map_or(0, |base| base.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Names in the order they were first defined.
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &String> {
+        self.base
+            .iter()
+            .flat_map(|base| base.order.iter())
+            .chain(self.order.iter())
+    }
+
+    /// Bindings in the order they were first defined.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Lval)> {
+        self.keys()
+            .filter_map(move |key| Some((key, self.get(key)?)))
+    }
+
+    /// Moves every binding into a frame shared by this one and its clones,
+    /// making clones cheap. Each clone copies the shared bindings in the
+    /// first time it redefines or removes one of them; new names are added
+    /// alongside without copying.
+    pub fn share(&mut self) {
+        let mut flat = mem::take(self);
+        flat.unshare();
+        self.base = Some(Rc::new(flat));
+    }
+
+    // the bindings whose latest definition was registering them as a
+    // builtin
+    fn builtins(&self) -> Lookup {
+        let mut builtins = Lookup::new();
+        for (key, lval) in self.iter() {
+            if self.origins(key).last() == Some(&Origin::Builtin) {
+                builtins.insert(key.clone(), lval.clone());
+                builtins.record_origin(key, Origin::Builtin);
+            }
+        }
+        builtins
+    }
+
+    // copies the shared bindings in before `key` is changed, if it is one
+    // of theirs
+    fn own(&mut self, key: &str) {
+        let shared = match &self.base {
+            Some(base) => !self.values.contains_key(key) && base.contains_key(key),
+            None => false,
+        };
+        if shared {
+            self.unshare();
+        }
+    }
+
+    fn unshare(&mut self) {
+        let base = match self.base.take() {
+            Some(base) => Rc::try_unwrap(base).unwrap_or_else(|base| (*base).clone()),
+            None => return,
+        };
+        let own = mem::replace(self, base);
+        self.order.extend(own.order);
+        self.values.extend(own.values);
+        self.origins.extend(own.origins);
+        self.contracts.extend(own.contracts);
+    }
 }
 
-type LinkedEnv = Option<Box<Env>>;
-pub type Lookup = HashMap<String, Lval>;
+impl fmt::Debug for Lookup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Where a visible symbol lives, `depth` 0 being the global frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeInfo {
+    pub depth: usize,
+}
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct Env {
-    lookup: Lookup,
+    lookup: RefCell<Lookup>,
     parent: LinkedEnv,
 }
 
-#[wasm_bindgen]
+/// The local frames a lambda was made in, which it sees when called along
+/// with the globals of wherever it is called. Shared with the environment
+/// and every copy of the lambda rather than copied, so the lambda sees
+/// what is bound in them later too.
+#[derive(Clone, Debug, Default)]
+pub struct Frames {
+    head: LinkedEnv,
+    // whether the caller's frames are seen instead
+    dynamic: bool,
+}
+
+impl Frames {
+    /// No frames of its own: a lambda made with these sees whatever its
+    /// caller does, like the builtins that are wrapped in a lambda to take
+    /// no arguments.
+    pub fn caller() -> Self {
+        Frames {
+            head: None,
+            dynamic: true,
+        }
+    }
+
+    /// No frames of its own: a lambda made with these sees only the
+    /// globals, like a function defined at the top level.
+    pub fn globals() -> Self {
+        Frames {
+            head: None,
+            dynamic: false,
+        }
+    }
+
+    /// Whether a lambda made with these sees its caller's frames, as with
+    /// `caller`.
+    pub fn sees_caller(&self) -> bool {
+        self.dynamic
+    }
+
+    /// Puts `lookup` in front of the frames, for a lambda given some of its
+    /// arguments.
+    pub fn push(&mut self, lookup: Lookup) {
+        self.head = Some(Rc::new(Env {
+            lookup: RefCell::new(lookup),
+            parent: self.head.take(),
+        }));
+    }
+
+    // whether `env` is the innermost of the frames
+    fn starts_at(&self, env: &Rc<Env>) -> bool {
+        self.head.as_ref().is_some_and(|head| Rc::ptr_eq(head, env))
+    }
+
+    pub fn get(&self, key: &str) -> Option<Lval> {
+        Iter {
+            next: self.head.as_deref(),
+            global: None,
+        }
+        .find_map(|lookup| lookup.get(key).cloned())
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Lenv {
     pub fn new() -> Self {
-        Lenv { head: None }
+        Lenv {
+            head: None,
+            global: None,
+            max_bindings: None,
+            state: Box::new(State {
+                hosts: BTreeMap::new(),
+                rng: RandomState::new().build_hasher().finish(),
+                origin: Origin::Unknown,
+                strict_arithmetic: false,
+                special_floats: false,
+                warnings: vec![],
+                loaded: vec![],
+                #[cfg(feature = "cache")]
+                parse_cache: None,
+                writer: None,
+                output: String::new(),
+                #[cfg(debug_assertions)]
+                stats: EvalStats::default(),
+                clock: None,
+                deadlines: vec![],
+                counters: Counters::default(),
+                last_result: None,
+                sites: vec![],
+                #[cfg(feature = "clone-audit")]
+                clone_audit: CloneAuditReport::default(),
+                scopes: 0,
+                nesting: 0,
+                fuel: None,
+                max_nesting: None,
+                max_steps: DEFAULT_MAX_STEPS,
+                max_value_size: Some(DEFAULT_MAX_VALUE_SIZE),
+                pure: Rc::new(vec![]),
+                arities: Rc::new(vec![]),
+                reserved: Rc::new(HashSet::new()),
+            }),
+        }
+    }
+
+    /// What `print` and `println` wrote since last asked, when no writer
+    /// was set on the web, where there is no stdout to write to.
+    pub fn take_output(&mut self) -> String {
+        mem::take(&mut self.state.output)
+    }
+
+    /// Fills the fuel up to `max` at the start of each evaluation from the
+    /// top, so no one evaluation can go on for longer, or stops refilling
+    /// it with `None`. `DEFAULT_MAX_STEPS` to begin with.
+    pub fn set_max_steps(&mut self, max: Option<u64>) {
+        self.state.max_steps = max;
+    }
+
+    pub fn max_steps(&self) -> Option<u64> {
+        self.state.max_steps
+    }
+
+    /// Names of every visible symbol, ordered as in `visible_symbols`.
+    pub fn symbols(&self) -> Vec<String> {
+        self.visible_symbols()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+impl Default for Lenv {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Lenv {
+    /// Pushes a frame, which is the global one if there are none yet.
     pub fn push(&mut self, lookup: Lookup) {
-        let new_env = Box::new(Env {
-            lookup,
-            parent: self.head.take(),
-        });
+        match self.global {
+            None => self.global = Some(RefCell::new(lookup)),
+            Some(_) => {
+                self.head = Some(Rc::new(Env {
+                    lookup: RefCell::new(lookup),
+                    parent: self.head.take(),
+                }))
+            }
+        }
+        self.state.scopes += 1;
+        let peak = &mut self.state.counters.peak_depth;
+        *peak = (*peak).max(self.state.scopes as u64);
 
-        self.head = Some(new_env);
+        #[cfg(debug_assertions)]
+        {
+            self.state.stats.frames_pushed += 1;
+            self.state.stats.max_scope_depth =
+                self.state.stats.max_scope_depth.max(self.state.scopes);
+        }
     }
 
+    /// Pops the innermost frame, the global one last. A frame a lambda
+    /// still holds on to is copied out of.
     pub fn pop(&mut self) -> Option<Lookup> {
-        self.head.take().map(|env| {
-            self.head = env.parent;
-            env.lookup
-        })
+        let popped = match self.head.take() {
+            Some(env) => {
+                self.head = env.parent.clone();
+                match Rc::try_unwrap(env) {
+                    Ok(env) => Some(env.lookup.into_inner()),
+                    Err(env) => {
+                        let lookup = env.lookup.borrow().clone();
+                        drop_frames(Some(env));
+                        Some(lookup)
+                    }
+                }
+            }
+            None => Some(self.global.take()?.into_inner()),
+        };
+        self.state.scopes -= 1;
+        #[cfg(debug_assertions)]
+        {
+            self.state.stats.frames_popped += 1;
+        }
+        popped
+    }
+
+    // `pop` for when the frame isn't wanted, which saves copying it
+    fn drop_frame(&mut self) {
+        match self.head.take() {
+            Some(env) => {
+                self.head = env.parent.clone();
+                drop_frames(Some(env));
+            }
+            None if self.global.take().is_some() => {}
+            None => return,
+        }
+        self.state.scopes -= 1;
+        #[cfg(debug_assertions)]
+        {
+            self.state.stats.frames_popped += 1;
+        }
+    }
+
+    /// How many frames are stacked up, the global one included.
+    pub fn depth(&self) -> usize {
+        self.iter().count()
     }
 
-    pub fn peek(&self) -> Option<&Lookup> {
-        self.head.as_ref().map(|env| &env.lookup)
+    /// What has happened to the scopes since `reset_stats`. Always zero in
+    /// release builds.
+    pub fn stats(&self) -> EvalStats {
+        #[cfg(debug_assertions)]
+        return self.state.stats;
+        #[cfg(not(debug_assertions))]
+        EvalStats::default()
     }
 
-    pub fn peek_mut(&mut self) -> Option<&mut Lookup> {
-        self.head.as_mut().map(|env| &mut env.lookup)
+    /// Starts the stats over, with the current depth as the high water mark.
+    pub fn reset_stats(&mut self) {
+        #[cfg(debug_assertions)]
+        {
+            self.state.stats = EvalStats {
+                max_scope_depth: self.state.scopes,
+                ..EvalStats::default()
+            };
+        }
+    }
+
+    /// The local frames, for a lambda made here to see when it is called.
+    /// Nothing is copied: the lambda shares them. Globals aren't included,
+    /// since they are looked up as they are when the lambda is called.
+    pub fn capture(&self) -> Frames {
+        Frames {
+            head: self.head.clone(),
+            dynamic: false,
+        }
     }
 
+    // unlinks every frame above the global one, keeping their count for
+    // `scopes` as they are only out of sight
+    fn hide_locals(&mut self) -> LinkedEnv {
+        self.head.take()
+    }
+
+    // swaps the global frame for `lookup`, giving back the one it replaced
+    fn swap_globals(&mut self, lookup: Lookup) -> Option<Lookup> {
+        let global = self.global.as_mut()?.get_mut();
+        Some(mem::replace(global, lookup))
+    }
+
+    fn restore_locals(&mut self, locals: LinkedEnv) {
+        self.head = locals;
+    }
+
+    pub fn peek(&self) -> Option<Ref<'_, Lookup>> {
+        match &self.head {
+            Some(env) => Some(env.lookup.borrow()),
+            None => self.global.as_ref().map(RefCell::borrow),
+        }
+    }
+
+    pub fn peek_mut(&mut self) -> Option<RefMut<'_, Lookup>> {
+        match &self.head {
+            Some(env) => Some(env.lookup.borrow_mut()),
+            None => self.global.as_ref().map(RefCell::borrow_mut),
+        }
+    }
+
+    /// Every frame, innermost first and the global one last.
     pub fn iter(&self) -> Iter<'_> {
         Iter {
             next: self.head.as_deref(),
+            global: self.global.as_ref(),
         }
     }
 
-    pub fn insert(&mut self, key: &str, lval: Lval) {
-        self.peek_mut()
-            .map(|node| node.insert(key.to_owned(), lval));
+    /// Binds `key` in the innermost frame. If that is the global frame the
+    /// binding is recorded as coming from the current origin, if known.
+    pub fn insert(&mut self, key: &str, lval: Lval) -> Result<(), Lerr> {
+        let bindings = self.bindings();
+        let max_bindings = self.max_bindings;
+        let origin = self.known_origin().filter(|_| self.head.is_none());
+        if let Some(mut lookup) = self.peek_mut() {
+            check_room(&lookup, key, bindings, max_bindings)?;
+            lookup.insert(key.to_owned(), lval);
+            if let Some(origin) = origin {
+                lookup.record_origin(key, origin);
+            }
+        }
+        Ok(())
     }
 
-    pub fn insert_last(&mut self, key: &str, lval: Lval) {
-        let mut i = self.head.as_mut();
+    /// Binds `key` in the outermost, global frame, recording it as coming
+    /// from the current origin, if known.
+    pub fn insert_last(&mut self, key: &str, lval: Lval) -> Result<(), Lerr> {
+        let bindings = self.bindings();
+        let max_bindings = self.max_bindings;
+        let origin = self.known_origin();
+        if let Some(lookup) = self.last_mut() {
+            check_room(lookup, key, bindings, max_bindings)?;
+            lookup.insert(key.to_owned(), lval);
+            if let Some(origin) = origin {
+                lookup.record_origin(key, origin);
+            }
+        }
+        Ok(())
+    }
+
+    // lambda parameters are bound with no origin set, which keeps calls
+    // from paying for provenance
+    fn known_origin(&self) -> Option<Origin> {
+        match self.state.origin {
+            Origin::Unknown => None,
+            ref origin => Some(origin.clone()),
+        }
+    }
+
+    /// Sets where global definitions made from now on are said to come
+    /// from, returning the previous origin.
+    pub fn set_origin(&mut self, origin: Origin) -> Origin {
+        mem::replace(&mut self.state.origin, origin)
+    }
+
+    /// The contract on the visible binding of `key`, if it has one.
+    pub fn contract(&self, key: &str) -> Option<Contract> {
+        self.iter()
+            .find(|lookup| lookup.contains_key(key))
+            .and_then(|lookup| lookup.contract(key).cloned())
+    }
+
+    /// Puts `contract` on the global binding of `key`.
+    pub fn set_contract_last(&mut self, key: &str, contract: Contract) {
+        if let Some(lookup) = self.last_mut() {
+            lookup.set_contract(key, contract);
+        }
+    }
 
-        while let Some(env) = i {
-            i = env.parent.as_mut();
-            if let None = i {
-                env.lookup.insert(key.to_owned(), lval.clone());
+    /// Where the visible binding of `key` was defined, most recent first.
+    /// `None` if `key` isn't bound, and empty if its origin wasn't recorded,
+    /// as with function parameters.
+    pub fn origins(&self, key: &str) -> Option<Vec<Origin>> {
+        self.iter()
+            .find(|lookup| lookup.contains_key(key))
+            .map(|lookup| lookup.origins(key).iter().rev().cloned().collect())
+    }
+
+    /// Shares the global frame between this environment and its clones,
+    /// so cloning it no longer copies every binding. See `Lookup::share`.
+    pub fn share_globals(&mut self) {
+        if let Some(lookup) = self.last_mut() {
+            lookup.share();
+        }
+    }
+
+    /// Restarts the random number generator somewhere arbitrary, so clones
+    /// don't all draw the same numbers.
+    pub fn reseed(&mut self) {
+        self.state.rng = RandomState::new().build_hasher().finish();
+    }
+
+    /// Unbinds `key` from the global frame, the counterpart to `insert_last`.
+    pub fn remove_last(&mut self, key: &str) -> Option<Lval> {
+        self.last_mut().and_then(|lookup| lookup.remove(key))
+    }
+
+    fn last_mut(&mut self) -> Option<&mut Lookup> {
+        self.global.as_mut().map(RefCell::get_mut)
+    }
+
+    /// Caps how many bindings all frames together may hold. Once reached,
+    /// binding a new name fails with `LerrType::EnvLimit`, while rebinding
+    /// an existing one still works.
+    pub fn set_max_bindings(&mut self, max: Option<usize>) {
+        self.max_bindings = max;
+    }
+
+    pub fn max_bindings(&self) -> Option<usize> {
+        self.max_bindings
+    }
+
+    /// Keeps `lval` as the result most recently shown, which a front end
+    /// might have cut short and need again in full.
+    pub fn set_last_result(&mut self, lval: Lval) {
+        self.state.last_result = Some(lval);
+    }
+
+    pub fn last_result(&self) -> Option<&Lval> {
+        self.state.last_result.as_ref()
+    }
+
+    /// Notes where the code about to be evaluated came from, `None` for
+    /// code with no known place in the source, like a lambda's body.
+    pub fn push_site(&mut self, site: Option<Rc<SpanTree>>) {
+        self.state.sites.push(site);
+    }
+
+    pub fn pop_site(&mut self) {
+        self.state.sites.pop();
+    }
+
+    /// Where the code being evaluated came from, if that's known.
+    pub fn site(&self) -> Option<&Rc<SpanTree>> {
+        self.state.sites.last().and_then(Option::as_ref)
+    }
+
+    /// How many sites are pushed, zero unless evaluating with spans.
+    pub fn site_depth(&self) -> usize {
+        self.state.sites.len()
+    }
+
+    /// Drops sites back to `depth`, for when a panic skipped their pops.
+    pub fn truncate_sites(&mut self, depth: usize) {
+        self.state.sites.truncate(depth);
+    }
+
+    /// Notes that `cloned` was just deep-copied at `hook`, which the
+    /// `clone-audit` feature counts if it's large. Does nothing otherwise.
+    #[cfg(feature = "clone-audit")]
+    pub fn audit_clone(&mut self, hook: &'static str, cloned: &Lval) {
+        let span = self.site().map(|site| site.span);
+        let bytes = deep_size(cloned);
+        self.state.clone_audit.record(hook, bytes, span);
+    }
+
+    #[cfg(not(feature = "clone-audit"))]
+    #[inline]
+    pub fn audit_clone(&mut self, _hook: &'static str, _cloned: &Lval) {}
+
+    /// Large clones since `reset_clone_audit`.
+    #[cfg(feature = "clone-audit")]
+    pub fn clone_audit_report(&self) -> CloneAuditReport {
+        self.state.clone_audit.clone()
+    }
+
+    /// Starts the audit over, counting clones of at least `threshold`
+    /// bytes from now on.
+    #[cfg(feature = "clone-audit")]
+    pub fn reset_clone_audit(&mut self, threshold: usize) {
+        self.state.clone_audit = CloneAuditReport {
+            threshold,
+            ..CloneAuditReport::default()
+        };
+    }
+
+    /// What the interpreter has done since `reset_counters`.
+    pub fn counters(&self) -> Counters {
+        self.state.counters.clone()
+    }
+
+    /// Starts the counters over, with the current depth as the peak.
+    pub fn reset_counters(&mut self) {
+        self.state.counters = Counters {
+            peak_depth: self.state.scopes as u64,
+            ..Counters::default()
+        };
+    }
+
+    /// Counts an expression starting to evaluate. One starting from the top
+    /// starts with its fuel refilled, if `set_max_steps` asked for that.
+    pub fn enter_eval(&mut self) {
+        if let (0, Some(max)) = (self.state.nesting, self.state.max_steps) {
+            self.state.fuel = Some(max);
+        }
+        self.state.counters.expressions += 1;
+        self.state.nesting += 1;
+    }
+
+    /// Counts an expression finishing, and its error if it was the
+    /// outermost one, so an error is counted once however far it travels.
+    pub fn leave_eval(&mut self, error: Option<&Lerr>) {
+        self.state.nesting = self.state.nesting.saturating_sub(1);
+        if let (0, Some(e)) = (self.state.nesting, error) {
+            let errors = &mut self.state.counters.errors;
+            match errors.iter_mut().find(|(etype, _)| *etype == e.etype) {
+                Some((_, count)) => *count += 1,
+                None => errors.push((e.etype.clone(), 1)),
             }
         }
     }
 
-    pub fn get(&self, key: &str) -> Option<Lval> {
-        let mut i = self.iter();
+    /// Spends one evaluation's worth of fuel on the evaluation just
+    /// entered, failing if there's none left or it nests deeper than the
+    /// limit, where those are set.
+    pub fn spend_eval(&mut self) -> Result<(), Lerr> {
+        match &mut self.state.fuel {
+            Some(0) => {
+                return Err(Lerr::new(
+                    LerrType::Budget,
+                    "evaluation ran out of fuel".to_string(),
+                ))
+            }
+            Some(fuel) => *fuel -= 1,
+            None => {}
+        }
+        match self.state.max_nesting {
+            Some(max) if self.state.nesting > max => Err(Lerr::new(
+                LerrType::TooDeep,
+                format!("evaluation nested more than {} deep", max),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Limits the evaluations from now on to `fuel`, each expression
+    /// costing one, or lifts the limit with `None`. `set_max_steps` refills
+    /// it with each evaluation from the top where it's set.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.state.fuel = fuel;
+    }
+
+    /// What's left of the fuel, if it's limited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.state.fuel
+    }
+
+    /// Limits how many evaluations can be under way at once, so runaway
+    /// recursion fails before it overflows the stack. Unlimited to begin
+    /// with.
+    pub fn set_max_nesting(&mut self, max: Option<usize>) {
+        self.state.max_nesting = max;
+    }
 
-        while let Some(env) = i.next() {
+    pub fn max_nesting(&self) -> Option<usize> {
+        self.state.max_nesting
+    }
+
+    /// Caps how many elements, characters or bytes a builtin may build one
+    /// value out of, `DEFAULT_MAX_VALUE_SIZE` to begin with. `None` lifts it.
+    pub fn set_max_value_size(&mut self, max: Option<usize>) {
+        self.state.max_value_size = max;
+    }
+
+    pub fn max_value_size(&self) -> Option<usize> {
+        self.state.max_value_size
+    }
+
+    /// Fails with `LerrType::SizeLimit` if `sym` building a value of `size`
+    /// elements would go over the limit. Builtins that make values bigger
+    /// than their arguments check here before allocating anything.
+    pub fn check_value_size(&self, sym: &str, size: usize) -> Result<(), Lerr> {
+        match self.state.max_value_size {
+            Some(max) if size > max => Err(Lerr::new(
+                LerrType::SizeLimit,
+                format!(
+                    "Function {} would make a value of size {}, over the limit of {}",
+                    sym, size, max
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// How many evaluations are under way, for putting things back after
+    /// one unwinds without finishing.
+    pub fn eval_nesting(&self) -> usize {
+        self.state.nesting
+    }
+
+    pub fn restore_eval_nesting(&mut self, nesting: usize) {
+        self.state.nesting = nesting;
+    }
+
+    pub fn count_builtin_call(&mut self) {
+        self.state.counters.builtin_calls += 1;
+    }
+
+    pub fn count_lambda_call(&mut self) {
+        self.state.counters.lambda_calls += 1;
+    }
+
+    /// Records that `fun` always gives the same result for the same
+    /// arguments and does nothing else, so calls to it can be folded ahead
+    /// of time.
+    pub fn mark_pure(&mut self, fun: Lfun) {
+        if !self.is_pure(fun) {
+            Rc::make_mut(&mut self.state.pure).push(fun);
+        }
+    }
+
+    /// Whether `fun` was registered as pure. Builtins are impure unless
+    /// they say otherwise.
+    pub fn is_pure(&self, fun: Lfun) -> bool {
+        self.state
+            .pure
+            .iter()
+            .any(|&pure| std::ptr::fn_addr_eq(pure, fun))
+    }
+
+    /// Records how many arguments the builtin `fun` takes, replacing what
+    /// was recorded before.
+    pub fn set_arity(&mut self, fun: Lfun, arity: Arity) {
+        let arities = Rc::make_mut(&mut self.state.arities);
+        arities.retain(|&(known, _)| !std::ptr::fn_addr_eq(known, fun));
+        arities.push((fun, arity));
+    }
+
+    /// How many arguments the builtin `fun` was registered as taking.
+    pub fn arity(&self, fun: Lfun) -> Option<Arity> {
+        self.state
+            .arities
+            .iter()
+            .find(|&&(known, _)| std::ptr::fn_addr_eq(known, fun))
+            .map(|&(_, arity)| arity)
+    }
+
+    /// Keeps `def`, `fun` and the like from replacing the global binding of
+    /// `key`, as `add_builtin` does for every builtin. `def!` still can.
+    pub fn reserve(&mut self, key: &str) {
+        if !self.is_reserved(key) {
+            Rc::make_mut(&mut self.state.reserved).insert(key.to_owned());
+        }
+    }
+
+    /// Lets `key` be defined over again, as after `def!` or `undef`.
+    pub fn unreserve(&mut self, key: &str) {
+        if self.is_reserved(key) {
+            Rc::make_mut(&mut self.state.reserved).remove(key);
+        }
+    }
+
+    pub fn is_reserved(&self, key: &str) -> bool {
+        self.state.reserved.contains(key)
+    }
+
+    /// Makes `handler` callable as `(host "name" args...)`, replacing any
+    /// handler already registered under `name`.
+    pub fn register_host_handler(
+        &mut self,
+        name: &str,
+        handler: impl Fn(Vec<Lval>) -> Result<Lval, String> + 'static,
+    ) {
+        self.state.hosts.insert(name.to_owned(), Rc::new(handler));
+    }
+
+    pub fn host_handler(&self, name: &str) -> Option<HostHandler> {
+        self.state.hosts.get(name).cloned()
+    }
+
+    /// Names of the registered host handlers, sorted.
+    pub fn host_handlers(&self) -> Vec<String> {
+        self.state.hosts.keys().cloned().collect()
+    }
+
+    /// Replaces the clock time limits and `bench` are measured with, which is
+    /// `performance.now()` on the web and a monotonic clock elsewhere.
+    pub fn set_clock(&mut self, clock: impl Fn() -> f64 + 'static) {
+        self.state.clock = Some(Rc::new(clock));
+    }
+
+    /// Starts a time limit `millis` from now. A limit inside another can
+    /// only tighten it. Every push needs a matching `pop_deadline`.
+    pub fn push_deadline(&mut self, millis: f64) {
+        let deadline = self.now() + millis;
+        let tightest = self
+            .state
+            .deadlines
+            .last()
+            .map_or(deadline, |d| d.min(deadline));
+        self.state.deadlines.push(tightest);
+    }
+
+    /// Milliseconds by the clock, from an arbitrary starting point.
+    pub fn now(&self) -> f64 {
+        match &self.state.clock {
+            Some(clock) => clock(),
+            None => default_now(),
+        }
+    }
+
+    pub fn pop_deadline(&mut self) {
+        self.state.deadlines.pop();
+    }
+
+    /// Fails with `LerrType::Timeout` once the nearest deadline has passed.
+    pub fn check_deadline(&self) -> Result<(), Lerr> {
+        match self.state.deadlines.last() {
+            Some(deadline) if self.now() > *deadline => Err(Lerr::new(
+                LerrType::Timeout,
+                format!(
+                    "the time limit ran out {} levels deep",
+                    self.state.deadlines.len()
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// With strict arithmetic, overflow, NaN and underflow part way through
+    /// `+ - * / %` are errors. Otherwise they are warnings and the
+    /// calculation carries on.
+    pub fn set_strict_arithmetic(&mut self, strict: bool) {
+        self.state.strict_arithmetic = strict;
+    }
+
+    pub fn strict_arithmetic(&self) -> bool {
+        self.state.strict_arithmetic
+    }
+
+    /// With special floats, source read for this environment may spell out
+    /// `+inf`, `-inf` and `nan` as numbers. Otherwise they are symbols like
+    /// any other, though arithmetic can still produce those values.
+    pub fn set_special_floats(&mut self, allow: bool) {
+        self.state.special_floats = allow;
+    }
+
+    pub fn special_floats(&self) -> bool {
+        self.state.special_floats
+    }
+
+    /// Notes something suspicious that wasn't worth failing over. Only the
+    /// first `WARNINGS_KEPT` are kept until they are taken.
+    pub fn warn(&mut self, warning: String) {
+        if self.state.warnings.len() < WARNINGS_KEPT {
+            self.state.warnings.push(warning);
+        }
+    }
+
+    /// The warnings since last asked, oldest first.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        mem::take(&mut self.state.warnings)
+    }
+
+    /// Notes that `path` was loaded, once however often it is.
+    pub fn record_load(&mut self, path: &Path) {
+        if !self.state.loaded.iter().any(|loaded| loaded == path) {
+            self.state.loaded.push(path.to_path_buf());
+        }
+    }
+
+    /// The files `load` has read into this environment, in the order they
+    /// were first loaded, so whatever ran them can tell when they change.
+    pub fn loaded_files(&self) -> &[PathBuf] {
+        &self.state.loaded
+    }
+
+    /// Has `load` keep parsed files in `cache` rather than the default
+    /// `ParseCache::default_dir()`.
+    #[cfg(feature = "cache")]
+    pub fn set_parse_cache(&mut self, cache: ParseCache) {
+        self.state.parse_cache = Some(Rc::new(RefCell::new(cache)));
+    }
+
+    /// The cache `load` parses through, opening the default one on first
+    /// use. `None` if there is no default directory to keep one in.
+    #[cfg(feature = "cache")]
+    pub fn parse_cache(&mut self) -> Option<Rc<RefCell<ParseCache>>> {
+        if self.state.parse_cache.is_none() {
+            let cache = ParseCache::new(ParseCache::default_dir()?);
+            self.state.parse_cache = Some(Rc::new(RefCell::new(cache)));
+        }
+        self.state.parse_cache.clone()
+    }
+
+    /// Sends what `print` and `println` write to `writer` rather than
+    /// stdout, or on the web, the buffer `take_output` empties.
+    pub fn set_writer(&mut self, writer: impl Fn(&str) + 'static) {
+        self.state.writer = Some(Rc::new(writer));
+    }
+
+    /// Writes `text` out for lisp code, where the writer says.
+    pub fn write_output(&mut self, text: &str) {
+        match &self.state.writer {
+            Some(writer) => writer(text),
+            None => default_write(&mut self.state.output, text),
+        }
+    }
+
+    /// Restarts the random number generator so what follows is
+    /// reproducible. Unseeded environments start somewhere arbitrary.
+    pub fn seed(&mut self, seed: u64) {
+        self.state.rng = seed;
+    }
+
+    /// The next number from the environment's generator (splitmix64).
+    pub fn next_random(&mut self) -> u64 {
+        self.state.rng = self.state.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A random index below `n`, which must not be 0.
+    pub fn random_below(&mut self, n: usize) -> usize {
+        ((u128::from(self.next_random()) * n as u128) >> 64) as usize
+    }
+
+    /// Bindings across every frame, shadowed ones included.
+    pub fn bindings(&self) -> usize {
+        self.iter().map(|lookup| lookup.len()).sum()
+    }
+
+    /// A rough estimate of the memory held by every binding.
+    pub fn approximate_bytes(&self) -> usize {
+        self.iter()
+            .map(|lookup| {
+                lookup
+                    .iter()
+                    .map(|(key, lval)| mem::size_of::<String>() + key.len() + deep_size(lval))
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Every symbol that can currently be looked up, in a stable order:
+    /// outermost frame first and innermost last, each frame in definition
+    /// order. For the global frame that means builtins in registration
+    /// order followed by user definitions. A shadowed name is only listed
+    /// once, at the position of its innermost binding.
+    pub fn visible_symbols(&self) -> Vec<(String, ScopeInfo)> {
+        let frames = self.iter().collect::<Vec<_>>();
+        let mut seen = HashSet::new();
+        let mut symbols = vec![];
+
+        // walk innermost first so the first sighting of a name is the one
+        // that wins, then flip it around for the final order
+        for (i, lookup) in frames.iter().enumerate() {
+            let depth = frames.len() - 1 - i;
+            for key in lookup.keys().rev() {
+                if seen.insert(key) {
+                    symbols.push((key.clone(), ScopeInfo { depth }));
+                }
+            }
+        }
+
+        symbols.reverse();
+        symbols
+    }
+
+    pub fn get(&self, key: &str) -> Option<Lval> {
+        for env in self.iter() {
             if let Some(v) = env.get(key) {
                 return Some(v.clone());
             }
@@ -84,30 +1214,253 @@ impl Lenv {
     }
 }
 
-impl Drop for Lenv {
+fn check_room(
+    lookup: &Lookup,
+    key: &str,
+    bindings: usize,
+    max_bindings: Option<usize>,
+) -> Result<(), Lerr> {
+    match max_bindings {
+        Some(max) if bindings >= max && !lookup.contains_key(key) => Err(Lerr::new(
+            LerrType::EnvLimit,
+            format!(
+                "could not define {:?}, the environment is limited to {} bindings",
+                key, max
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Approximately how many bytes `lval` and everything it holds occupy.
+pub fn deep_size(lval: &Lval) -> usize {
+    let mut sizer = Sizer(0);
+    walk(lval, &mut sizer);
+    sizer.0
+}
+
+struct Sizer(usize);
+
+impl LvalVisitor for Sizer {
+    fn visit_num(&mut self, _num: f64) {
+        self.0 += mem::size_of::<Lval>();
+    }
+
+    fn visit_int(&mut self, _int: i64) {
+        self.0 += mem::size_of::<Lval>();
+    }
+
+    fn visit_sym(&mut self, sym: &str) {
+        self.0 += mem::size_of::<Lval>() + sym.len();
+    }
+
+    fn visit_str(&mut self, s: &str) {
+        self.0 += mem::size_of::<Lval>() + s.len();
+    }
+
+    fn visit_bytes(&mut self, bytes: &[u8]) {
+        self.0 += mem::size_of::<Lval>() + bytes.len();
+    }
+
+    fn visit_keyword(&mut self, keyword: &str) {
+        self.0 += mem::size_of::<Lval>() + keyword.len();
+    }
+
+    fn visit_fun(&mut self, _fun: Lfun) {
+        self.0 += mem::size_of::<Lval>();
+    }
+
+    fn visit_native(&mut self, _native: &NativeFn) {
+        self.0 += mem::size_of::<Lval>();
+    }
+
+    // the captured frame isn't counted, it mostly mirrors bindings that are
+    // already counted where they were defined
+    fn visit_lambda(&mut self, lambda: &Llambda) {
+        self.0 += mem::size_of::<Lval>();
+        for arg in &lambda.args {
+            self.0 += mem::size_of::<String>() + arg.len();
+        }
+        for lval in &lambda.body {
+            walk(lval, self);
+        }
+    }
+
+    fn visit_list(&mut self, _kind: ListKind, _items: &[Lval]) {
+        self.0 += mem::size_of::<Lval>();
+    }
+}
+
+/// A frame pushed onto a `Lenv` that is popped again when the guard is
+/// dropped, so the scope is cleaned up on every exit path: a normal return,
+/// an early `?`, or a panic unwinding through it.
+pub struct ScopeGuard<'a> {
+    env: &'a mut Lenv,
+    // the frames to put back, if they were swapped out
+    hidden: Option<LinkedEnv>,
+    globals: Option<Lookup>,
+}
+
+impl<'a> ScopeGuard<'a> {
+    pub fn new(env: &'a mut Lenv, lookup: Lookup) -> Self {
+        env.push(lookup);
+        ScopeGuard {
+            env,
+            hidden: None,
+            globals: None,
+        }
+    }
+
+    /// Like `new`, except that nothing but the builtins is visible besides
+    /// `lookup`: local frames are hidden, and the global frame is swapped
+    /// for one holding only the bindings still as they were registered.
+    /// Globals defined meanwhile are dropped with the guard.
+    pub fn isolated(env: &'a mut Lenv, lookup: Lookup) -> Self {
+        let hidden = Some(env.hide_locals());
+        let builtins = env
+            .peek()
+            .map(|lookup| lookup.builtins())
+            .unwrap_or_default();
+        let globals = env.swap_globals(builtins);
+        env.push(lookup);
+        ScopeGuard {
+            env,
+            hidden,
+            globals,
+        }
+    }
+
+    /// Like `new` for a lambda's call: the frames the lambda was made in,
+    /// see `Lenv::capture`, take the place of the caller's until the guard
+    /// is dropped, with `lookup` in front of them. `Frames::caller` leaves
+    /// the caller's in place.
+    pub fn lexical(env: &'a mut Lenv, frames: &Frames, lookup: Lookup) -> Self {
+        let hidden = match frames.dynamic {
+            true => None,
+            false => Some(mem::replace(&mut env.head, frames.head.clone())),
+        };
+        env.push(lookup);
+        ScopeGuard {
+            env,
+            hidden,
+            globals: None,
+        }
+    }
+
+    /// Swaps the guarded frame, and the lambda's frames behind it, for
+    /// those of the lambda a tail call goes on to, as dropping the guard
+    /// and making a `lexical` one would without the stack growing. The
+    /// caller's frames come back when the guard is dropped. `frames` mustn't
+    /// be ones that see the caller's.
+    pub fn replace(&mut self, frames: &Frames, lookup: Lookup) {
+        debug_assert!(!frames.dynamic && self.globals.is_none());
+        self.env.drop_frame();
+        let head = mem::replace(&mut self.env.head, frames.head.clone());
+        match self.hidden {
+            None => self.hidden = Some(head),
+            Some(_) => drop_frames(head),
+        }
+        self.env.push(lookup);
+    }
+}
+
+impl Deref for ScopeGuard<'_> {
+    type Target = Lenv;
+
+    fn deref(&self) -> &Lenv {
+        self.env
+    }
+}
+
+impl DerefMut for ScopeGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Lenv {
+        self.env
+    }
+}
+
+impl Drop for ScopeGuard<'_> {
     fn drop(&mut self) {
-        let mut cur_link = self.head.take();
-        while let Some(mut boxed_env) = cur_link {
-            cur_link = boxed_env.parent.take();
+        self.env.drop_frame();
+        if let Some(globals) = self.globals.take() {
+            self.env.swap_globals(globals);
+        }
+        if let Some(hidden) = self.hidden.take() {
+            self.env.restore_locals(hidden);
         }
     }
 }
 
+impl Drop for Lenv {
+    fn drop(&mut self) {
+        drop_frames(self.head.take());
+    }
+}
+
+impl Drop for Frames {
+    fn drop(&mut self) {
+        drop_frames(self.head.take());
+    }
+}
+
+// frees a long chain of frames one at a time rather than recursively,
+// stopping at the first one still shared
+fn drop_frames(mut link: LinkedEnv) {
+    while let Some(env) = link {
+        let mut env = match Rc::try_unwrap(env) {
+            Ok(env) => env,
+            Err(env) if held_by_itself(&env) => {
+                // only lambdas bound in it keep it alive: dropping them
+                // breaks the cycle and leaves it to us
+                let lookup = env.lookup.take();
+                drop(lookup);
+                match Rc::try_unwrap(env) {
+                    Ok(env) => env,
+                    Err(_) => break,
+                }
+            }
+            Err(_) => break,
+        };
+        link = env.parent.take();
+    }
+}
+
+// whether the only handles on `env` besides the caller's are those of
+// lambdas bound in it that were made in it, as a function defined in a
+// local scope and bound there is
+fn held_by_itself(env: &Rc<Env>) -> bool {
+    let lookup = env.lookup.borrow();
+    let captured = lookup
+        .values
+        .values()
+        .flat_map(|lval| match lval {
+            Lval::Lambda(lambda) => std::slice::from_ref(lambda),
+            Lval::CaseLambda(clauses) => clauses.as_slice(),
+            _ => &[],
+        })
+        .filter(|lambda| lambda.env.starts_at(env))
+        .count();
+    captured > 0 && Rc::strong_count(env) == captured + 1
+}
+
 pub struct Iter<'a> {
     next: Option<&'a Env>,
+    global: Option<&'a RefCell<Lookup>>,
 }
 
 impl<'a> Iterator for Iter<'a> {
-    type Item = &'a Lookup;
+    type Item = Ref<'a, Lookup>;
     fn next(&mut self) -> Option<Self::Item> {
-        self.next.map(|env| {
-            self.next = env.parent.as_deref();
-            &env.lookup
-        })
+        match self.next {
+            Some(env) => {
+                self.next = env.parent.as_deref();
+                Some(env.lookup.borrow())
+            }
+            None => self.global.take().map(RefCell::borrow),
+        }
     }
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn init_env() -> Lenv {
     let mut env = Lenv::new();
     env.push(Lookup::new());
@@ -118,17 +1471,18 @@ pub fn init_env() -> Lenv {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::rc::Weak;
 
     #[test]
     fn it_nests_properly() {
         let mut env = Lenv::new();
         env.push(Lookup::new());
-        env.insert("abc", Lval::Num(1_f64));
-        env.insert("def", Lval::Num(2_f64));
+        env.insert("abc", Lval::Num(1_f64)).unwrap();
+        env.insert("def", Lval::Num(2_f64)).unwrap();
 
         env.push(Lookup::new());
-        env.insert("abc", Lval::Num(3_f64));
-        env.insert("ghi", Lval::Num(4_f64));
+        env.insert("abc", Lval::Num(3_f64)).unwrap();
+        env.insert("ghi", Lval::Num(4_f64)).unwrap();
 
         assert_eq!(env.get("def").unwrap().to_owned(), Lval::Num(2_f64));
         assert_eq!(env.get("abc").unwrap().to_owned(), Lval::Num(3_f64));
@@ -143,12 +1497,12 @@ mod test {
     fn it_inserts_last() {
         let mut env = Lenv::new();
         env.push(Lookup::new());
-        env.insert("abc", Lval::Num(1_f64));
-        env.insert_last("def", Lval::Num(2_f64));
+        env.insert("abc", Lval::Num(1_f64)).unwrap();
+        env.insert_last("def", Lval::Num(2_f64)).unwrap();
 
         env.push(Lookup::new());
-        env.insert("abc", Lval::Num(3_f64));
-        env.insert_last("jkl", Lval::Num(5_f64));
+        env.insert("abc", Lval::Num(3_f64)).unwrap();
+        env.insert_last("jkl", Lval::Num(5_f64)).unwrap();
 
         assert_eq!(env.get("def").unwrap().to_owned(), Lval::Num(2_f64));
         assert_eq!(env.get("abc").unwrap().to_owned(), Lval::Num(3_f64));
@@ -160,25 +1514,133 @@ mod test {
         assert_eq!(env.get("abc").unwrap().to_owned(), Lval::Num(1_f64));
     }
 
+    #[test]
+    fn it_pops_guarded_scopes() {
+        let mut env = init_env();
+        {
+            let mut scope = ScopeGuard::new(&mut env, Lookup::new());
+            scope.insert("a", Lval::Num(1_f64)).unwrap();
+            assert_eq!(scope.iter().count(), 2);
+            assert!(scope.get("a").is_some());
+        }
+        assert_eq!(env.iter().count(), 1);
+        assert!(env.get("a").is_none());
+
+        let unwound = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _scope = ScopeGuard::new(&mut env, Lookup::new());
+            panic!("builtin blew up");
+        }));
+        assert!(unwound.is_err());
+        assert_eq!(env.iter().count(), 1);
+    }
+
+    #[test]
+    fn it_frees_frames_their_own_lambdas_captured() {
+        // a function bound in the frame it was made in
+        fn bind_in_frame(env: &mut Lenv) -> Weak<Env> {
+            let frame = Rc::downgrade(env.head.as_ref().unwrap());
+            let lambda = Llambda::new(vec!["n".to_string()], vec![], env.capture());
+            env.insert("f", Lval::Lambda(lambda)).unwrap();
+            frame
+        }
+
+        let mut env = init_env();
+        env.push(Lookup::new());
+        let frame = bind_in_frame(&mut env);
+        drop(env);
+        assert!(frame.upgrade().is_none());
+
+        let mut env = init_env();
+        let (frame, kept) = {
+            let mut scope = ScopeGuard::new(&mut env, Lookup::new());
+            let frame = bind_in_frame(&mut scope);
+            (frame, scope.get("f").unwrap())
+        };
+        // still there for the copy that got out
+        assert!(frame.upgrade().is_some());
+        drop(kept);
+        assert!(frame.upgrade().is_none());
+
+        env.push(Lookup::new());
+        let frame = bind_in_frame(&mut env);
+        assert!(env.pop().is_some());
+        assert!(frame.upgrade().is_none());
+    }
+
+    #[test]
+    fn it_records_origins_of_global_bindings() {
+        let mut env = init_env();
+        assert_eq!(env.origins("head"), Some(vec![Origin::Builtin]));
+        assert_eq!(env.origins("nope"), None);
+
+        for index in 1..=ORIGINS_KEPT + 1 {
+            env.set_origin(Origin::Repl(index));
+            env.insert_last("a", Lval::Num(index as f64)).unwrap();
+        }
+        let origins = env.origins("a").unwrap();
+        assert_eq!(origins.len(), ORIGINS_KEPT);
+        assert_eq!(origins[0], Origin::Repl(ORIGINS_KEPT + 1));
+        assert_eq!(origins[ORIGINS_KEPT - 1], Origin::Repl(2));
+
+        // local bindings aren't definitions
+        env.push(Lookup::new());
+        env.insert("a", Lval::Num(0_f64)).unwrap();
+        assert_eq!(env.origins("a"), Some(vec![]));
+        env.pop();
+
+        env.remove_last("a");
+        env.insert("a", Lval::Num(0_f64)).unwrap();
+        assert_eq!(env.origins("a").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn it_lists_symbols_in_a_stable_order() {
+        let mut env = init_env();
+        let builtins = env.symbols();
+        assert_eq!(&builtins[..4], &["!", "+", "-", "*"]);
+
+        env.insert("zed", Lval::Num(1_f64)).unwrap();
+        env.insert("alpha", Lval::Num(2_f64)).unwrap();
+        env.insert("mid", Lval::Num(3_f64)).unwrap();
+
+        env.push(Lookup::new());
+        env.insert("inner", Lval::Num(4_f64)).unwrap();
+        env.insert("alpha", Lval::Num(5_f64)).unwrap();
+
+        // redefining keeps the original position
+        env.insert_last("zed", Lval::Num(6_f64)).unwrap();
+
+        let symbols = env.visible_symbols();
+        let (names, info): (Vec<_>, Vec<_>) = symbols[builtins.len()..].iter().cloned().unzip();
+        assert_eq!(names, vec!["zed", "mid", "inner", "alpha"]);
+        assert_eq!(
+            info.into_iter().map(|i| i.depth).collect::<Vec<_>>(),
+            vec![0, 0, 1, 1]
+        );
+
+        env.pop();
+        assert_eq!(&env.symbols()[builtins.len()..], &["zed", "alpha", "mid"]);
+    }
+
     #[test]
     fn it_grabs_from_higher_environments() {
         let mut env = Lenv::new();
         env.push(Lookup::new()); // base
-        env.insert("a", Lval::Num(1_f64));
-        env.insert_last("b", Lval::Num(2_f64));
+        env.insert("a", Lval::Num(1_f64)).unwrap();
+        env.insert_last("b", Lval::Num(2_f64)).unwrap();
 
         assert_eq!(env.get("a").unwrap().to_owned(), Lval::Num(1_f64));
         assert_eq!(env.get("b").unwrap().to_owned(), Lval::Num(2_f64));
 
         env.push(Lookup::new()); // 2nd
-        env.insert("f", Lval::Num(3_f64));
+        env.insert("f", Lval::Num(3_f64)).unwrap();
 
         assert_eq!(env.get("a").unwrap().to_owned(), Lval::Num(1_f64));
         assert_eq!(env.get("b").unwrap().to_owned(), Lval::Num(2_f64));
         assert_eq!(env.get("f").unwrap().to_owned(), Lval::Num(3_f64));
 
         env.push(Lookup::new()); // 3rd
-        env.insert("g", Lval::Num(4_f64));
+        env.insert("g", Lval::Num(4_f64)).unwrap();
 
         assert_eq!(env.get("a").unwrap().to_owned(), Lval::Num(1_f64));
         assert_eq!(env.get("b").unwrap().to_owned(), Lval::Num(2_f64));
@@ -194,4 +1656,56 @@ mod test {
         assert_eq!(env.get("a").unwrap().to_owned(), Lval::Num(1_f64));
         assert_eq!(env.get("b").unwrap().to_owned(), Lval::Num(2_f64));
     }
+
+    #[test]
+    fn it_estimates_its_size() {
+        let mut env = init_env();
+        let (bindings, bytes) = (env.bindings(), env.approximate_bytes());
+
+        env.insert_last("n", Lval::Num(1_f64)).unwrap();
+        assert_eq!(env.bindings(), bindings + 1);
+        let with_num = env.approximate_bytes();
+        assert!(with_num > bytes);
+
+        let long = Lval::Str("x".repeat(1000));
+        env.insert_last("s", long.clone()).unwrap();
+        assert!(env.approximate_bytes() >= with_num + 1000);
+
+        // deeper values cost more
+        let nested = Lval::Qexpr(vec![long.clone(), Lval::Qexpr(vec![long])]);
+        assert!(deep_size(&nested) > 2000);
+
+        // rebinding doesn't add a binding
+        env.insert_last("n", Lval::Num(2_f64)).unwrap();
+        assert_eq!(env.bindings(), bindings + 2);
+    }
+
+    #[test]
+    fn it_caps_bindings() {
+        let mut env = init_env();
+        let limit = env.bindings() + 2;
+        env.set_max_bindings(Some(limit));
+
+        env.insert_last("a", Lval::Num(1_f64)).unwrap();
+        env.insert("b", Lval::Num(2_f64)).unwrap();
+        assert_eq!(env.bindings(), limit);
+
+        let err = env.insert_last("c", Lval::Num(3_f64)).unwrap_err();
+        assert_eq!(err.etype, LerrType::EnvLimit);
+        let err = env.insert("c", Lval::Num(3_f64)).unwrap_err();
+        assert_eq!(err.etype, LerrType::EnvLimit);
+        assert!(env.get("c").is_none());
+
+        // existing names can still change
+        env.insert_last("a", Lval::Num(4_f64)).unwrap();
+        assert_eq!(env.get("a"), Some(Lval::Num(4_f64)));
+
+        // removing one makes room again
+        assert_eq!(env.remove_last("a"), Some(Lval::Num(4_f64)));
+        assert!(!env.symbols().contains(&String::from("a")));
+        env.insert_last("c", Lval::Num(3_f64)).unwrap();
+
+        env.set_max_bindings(None);
+        env.insert_last("d", Lval::Num(5_f64)).unwrap();
+    }
 }
@@ -1,16 +1,137 @@
-use crate::{Lenv, Lerr, LerrType, Llambda, Lval};
+use crate::{
+    builtin,
+    env::{Lookup, ScopeGuard},
+    truth, Contract, Lenv, Lerr, LerrType, Lfun, Llambda, Lval, NativeFn, Span, SpanTree,
+    TRACE_FRAMES,
+};
+use std::{
+    any::Any,
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    rc::Rc,
+};
 
 pub fn eval(env: &mut Lenv, expr: Lval) -> Result<Lval, Lerr> {
-    match expr {
-        Lval::Sym(s) => eval_symbol(env, s),
-        Lval::Sexpr(vec) => eval_sexpression(env, vec),
-        _ => Ok(expr),
+    env.enter_eval();
+    let mut result = match (env.spend_eval(), expr) {
+        (Err(e), _) => Err(e),
+        (_, Lval::Sym(s)) => eval_symbol(env, s),
+        (_, Lval::Sexpr(vec)) => match eval_sexpression(env, vec) {
+            Ok(Tail::Value(value)) => Ok(value),
+            Ok(Tail::Call(tail)) => tail.make(env),
+            Err(e) => Err(e),
+        },
+        (_, expr) => Ok(expr),
+    };
+    if let Err(e) = &mut result {
+        place(env, e);
+    }
+    env.leave_eval(result.as_ref().err());
+    result
+}
+
+// `eval`, except that a lambda call `expr` ends with is handed back rather
+// than made, for a caller ending with `expr` to make in its own place
+fn eval_tail(env: &mut Lenv, expr: Lval) -> Result<Tail, Lerr> {
+    env.enter_eval();
+    let mut result = match (env.spend_eval(), expr) {
+        (Err(e), _) => Err(e),
+        (_, Lval::Sym(s)) => eval_symbol(env, s).map(Tail::Value),
+        (_, Lval::Sexpr(vec)) => eval_sexpression(env, vec),
+        (_, expr) => Ok(Tail::Value(expr)),
+    };
+    if let Err(e) = &mut result {
+        place(env, e);
+    }
+    env.leave_eval(result.as_ref().err());
+    result
+}
+
+// gives `e` the span of the code being evaluated, unless it has one
+fn place(env: &Lenv, e: &mut Lerr) {
+    if e.span.is_none() {
+        e.span = env.site().map(|site| site.span);
+    }
+}
+
+// what evaluating an expression in tail position comes to
+enum Tail {
+    Value(Lval),
+    Call(Box<TailCall>),
+}
+
+// a lambda call left to whoever evaluated the expression it ended, so the
+// loop in `call` can make it in place of the call that body belongs to
+struct TailCall {
+    func: Llambda,
+    args: Vec<Lval>,
+    callee: Option<Callee>,
+    span: Option<Span>,
+}
+
+impl Tail {
+    fn call(func: Llambda, args: Vec<Lval>) -> Tail {
+        Tail::Call(Box::new(TailCall {
+            func,
+            args,
+            callee: None,
+            span: None,
+        }))
+    }
+}
+
+impl TailCall {
+    // makes the call as `eval_sexpression` would have, had it not been
+    // left for later
+    fn make(self: Box<Self>, env: &mut Lenv) -> Result<Lval, Lerr> {
+        let TailCall {
+            func,
+            args,
+            callee,
+            span,
+        } = *self;
+        at_site(env, None, |env| call(env, func, args)).map_err(|e| {
+            let mut e = traced(env, e, callee);
+            e.span = e.span.or(span);
+            e
+        })
+    }
+}
+
+/// Like `eval`, for `expr` read from source that `spans` gives the layout
+/// of, as `parser::span_tree` does. An error then carries the span of the
+/// innermost call it came out of that has a place in that source.
+pub fn eval_spanned(env: &mut Lenv, expr: Lval, spans: SpanTree) -> Result<Lval, Lerr> {
+    at_site(env, Some(Rc::new(spans)), |env| eval(env, expr))
+}
+
+// runs `f` with `site` as where the code it evaluates came from. Free
+// unless something is already keeping track
+fn at_site<T>(env: &mut Lenv, site: Option<Rc<SpanTree>>, f: impl FnOnce(&mut Lenv) -> T) -> T {
+    if site.is_none() && env.site_depth() == 0 {
+        return f(env);
     }
+    env.push_site(site);
+    let result = f(env);
+    env.pop_site();
+    result
+}
+
+// where item `i` of the `len` item list at `site` came from
+fn item_site(site: &Option<Rc<SpanTree>>, len: usize, i: usize) -> Option<Rc<SpanTree>> {
+    site.as_ref()
+        .filter(|site| site.children.len() == len)
+        .map(|site| site.children[i].clone())
 }
 
 fn eval_symbol(env: &mut Lenv, s: String) -> Result<Lval, Lerr> {
     match env.get(&s) {
-        Some(lval) => Ok(lval.clone()),
+        Some(lval) => {
+            // a copy of whatever the name is bound to, however big
+            env.audit_clone("eval_symbol", &lval);
+            Ok(lval)
+        }
         None => Err(Lerr::new(
             LerrType::UnboundSymbol,
             format!("{:?} has not been defined", s),
@@ -18,83 +139,592 @@ fn eval_symbol(env: &mut Lenv, s: String) -> Result<Lval, Lerr> {
     }
 }
 
-fn eval_sexpression(env: &mut Lenv, sexpr: Vec<Lval>) -> Result<Lval, Lerr> {
+fn eval_sexpression(env: &mut Lenv, sexpr: Vec<Lval>) -> Result<Tail, Lerr> {
+    if is_special(env, &sexpr, "if", builtin::builtin_if) {
+        return eval_if(env, sexpr);
+    }
+    if is_special(env, &sexpr, "and", builtin::builtin_and_then) {
+        return eval_short_circuit(env, sexpr, false).map(Tail::Value);
+    }
+    if is_special(env, &sexpr, "or", builtin::builtin_or_else) {
+        return eval_short_circuit(env, sexpr, true).map(Tail::Value);
+    }
+    if is_special(env, &sexpr, "do", builtin::builtin_do) {
+        return eval_do(env, sexpr);
+    }
+
+    let named = match sexpr.first() {
+        Some(Lval::Sym(name)) => Some(name.clone()),
+        _ => None,
+    };
+
     // evaluate each element
+    let site = env.site().cloned();
+    let len = sexpr.len();
     let results = sexpr
         .into_iter()
-        .map(|expr| eval(env, expr))
+        .enumerate()
+        .map(|(i, expr)| at_site(env, item_site(&site, len, i), |env| eval(env, expr)))
         .collect::<Result<Vec<Lval>, Lerr>>()?;
 
+    let callee = results.first().and_then(|head| Callee::new(named, head));
+    // what a call evaluates has no place in this source
+    let applied = at_site(env, None, |env| apply_results(env, results));
+    called(env, applied, callee)
+}
+
+// what came of calling `callee`: an error coming up through it, or a call
+// to it still to be made. Apart from `eval_sexpression` so that doesn't
+// take up more stack while its operands are evaluated
+fn called(env: &Lenv, applied: Result<Tail, Lerr>, callee: Option<Callee>) -> Result<Tail, Lerr> {
+    match applied {
+        Ok(Tail::Call(mut tail)) => {
+            tail.callee = callee;
+            tail.span = env.site().map(|site| site.span);
+            Ok(Tail::Call(tail))
+        }
+        Ok(value) => Ok(value),
+        Err(e) => Err(traced(env, e, callee)),
+    }
+}
+
+// `e` as it comes up through the call to `callee`, if that was a call
+fn traced(env: &Lenv, e: Lerr, callee: Option<Callee>) -> Lerr {
+    match callee {
+        Some(callee) => e.called_from(|| callee.describe(env)),
+        None => e,
+    }
+}
+
+// what a call shows up as in the trace of an error coming out of it
+enum Callee {
+    Name(String),
+    // a builtin called without its name, only looked up if it fails
+    Builtin(Lval),
+    Lambda(String),
+}
+
+impl Callee {
+    // `None` unless `head` is a function being called
+    fn new(name: Option<String>, head: &Lval) -> Option<Callee> {
+        if !matches!(
+            head,
+            Lval::Fun(_) | Lval::Native(_) | Lval::Lambda(_) | Lval::CaseLambda(_)
+        ) {
+            return None;
+        }
+        Some(match (name, head) {
+            (Some(name), _) => Callee::Name(name),
+            (None, Lval::Lambda(lambda)) => {
+                Callee::Lambda(format!("(\\ [{}] ...)", lambda.args.join(" ")))
+            }
+            (None, Lval::CaseLambda(_)) => Callee::Lambda(String::from("(case-lambda ...)")),
+            (None, builtin) => Callee::Builtin(builtin.clone()),
+        })
+    }
+
+    fn describe(self, env: &Lenv) -> String {
+        match self {
+            Callee::Name(name) | Callee::Lambda(name) => name,
+            Callee::Builtin(builtin) => builtin_name(env, &builtin),
+        }
+    }
+}
+
+// calls to lambdas are left to the caller, see `Tail`
+fn apply_results(env: &mut Lenv, results: Vec<Lval>) -> Result<Tail, Lerr> {
+    let mut results = results.into_iter();
+    let op = match results.next() {
+        // `()` has no value
+        None => return Ok(Tail::Value(Lval::Nil)),
+        Some(op) => op,
+    };
     if results.len() == 0 {
-        // if empty return empty
-        return Ok(Lval::Sexpr(results));
-    } else if results.len() == 1 {
         // if singular value return singular value
-        let op = results[0].clone();
         match op {
-            Lval::Lambda(lambda) => call(env, lambda, vec![]),
-            _ => Ok(op),
+            Lval::Lambda(lambda) => Ok(Tail::call(lambda, vec![])),
+            Lval::CaseLambda(clauses) => tail_case(clauses, vec![]),
+            _ => Ok(Tail::Value(op)),
         }
     } else {
-        let operands = (&results[1..]).to_vec();
+        env.check_deadline()?;
+        let operands = results.collect();
         // recognize a builtin function or a lambda
-        match results[0].clone() {
-            Lval::Fun(fun) => fun(env, operands),
-            Lval::Lambda(lambda) => call(env, lambda, operands),
+        match op {
+            Lval::Fun(fun) if builtin::takes_values(fun) => {
+                call_builtin(env, fun, operands).map(Tail::Value)
+            }
+            Lval::Fun(fun) => {
+                let operands = first_values(env, operands);
+                call_builtin(env, fun, operands).map(Tail::Value)
+            }
+            Lval::Native(native) => {
+                let operands = first_values(env, operands);
+                call_native(env, &native, operands).map(Tail::Value)
+            }
+            Lval::Lambda(lambda) => {
+                let operands = first_values(env, operands);
+                Ok(Tail::call(lambda, operands))
+            }
+            Lval::CaseLambda(clauses) => {
+                let operands = first_values(env, operands);
+                tail_case(clauses, operands)
+            }
             _ => Err(Lerr::new(
                 LerrType::BadOp,
-                format!("{:?} is not a valid operator", results[0]),
+                format!("{} is not a valid operator", op.summary()),
             )),
         }
     }
 }
 
-pub fn call(env: &mut Lenv, mut func: Llambda, mut args: Vec<Lval>) -> Result<Lval, Lerr> {
-    let given = args.len();
-    let total = func.args.len();
+// `values` among `operands` cut down to their first, see `builtin::first_value`
+fn first_values(env: &mut Lenv, operands: Vec<Lval>) -> Vec<Lval> {
+    operands
+        .into_iter()
+        .map(|operand| builtin::first_value(env, operand))
+        .collect()
+}
 
-    // load up all of the args
-    while args.len() != 0 {
-        // if too many args
-        if func.args.len() == 0 {
-            return Err(Lerr::new(
-                LerrType::IncorrectParamCount,
-                format!("Function needed {} arg(s) but was given {}", total, given),
-            ));
+// whether `sexpr` starts with `name` still bound to the builtin behind a
+// special form, which sees its operands before they're evaluated
+fn is_special(env: &Lenv, sexpr: &[Lval], name: &str, builtin: Lfun) -> bool {
+    match sexpr.first() {
+        Some(Lval::Sym(sym)) if sym == name => {
+            matches!(env.get(sym), Some(Lval::Fun(f)) if ptr::fn_addr_eq(f, builtin))
         }
-        // pop the first element
-        let sym = func.args[0].clone();
-        // preserve the rest
-        func.args = func.args[1..].to_vec();
+        _ => false,
+    }
+}
+
+// `(if condition then else)` evaluates the condition and then only the
+// branch it picks, a Q-Expression branch as the code inside it. The branch
+// is in tail position
+fn eval_if(env: &mut Lenv, mut sexpr: Vec<Lval>) -> Result<Tail, Lerr> {
+    if sexpr.len() != 4 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!("Function if needed 3 arg but was given {}", sexpr.len() - 1),
+        ));
+    }
+    env.count_builtin_call();
+    let site = env.site().cloned();
+    let [condition_site, then_site, else_site] = [1, 2, 3].map(|i| item_site(&site, 4, i));
+
+    let condition = sexpr.swap_remove(1);
+    let condition = at_site(env, condition_site, |env| eval(env, condition))?;
+    // the swap left the else branch where the condition was
+    let (branch, site) = if condition.is_truthy() {
+        (sexpr.swap_remove(2), then_site)
+    } else {
+        (sexpr.swap_remove(1), else_site)
+    };
+    at_site(env, site, |env| match branch {
+        Lval::Qexpr(items) => eval_tail(env, Lval::Sexpr(items)),
+        other => eval_tail(env, other),
+    })
+}
+
+// `(and ...)` and `(or ...)` evaluate their operands in order until one is
+// as truthy as `stop`, giving that one, else the last. With no operands
+// `and` gives `true` and `or` `false`
+fn eval_short_circuit(env: &mut Lenv, sexpr: Vec<Lval>, stop: bool) -> Result<Lval, Lerr> {
+    env.count_builtin_call();
+    let site = env.site().cloned();
+    let len = sexpr.len();
+    let mut last = truth(!stop);
+    for (i, operand) in sexpr.into_iter().enumerate().skip(1) {
+        last = at_site(env, item_site(&site, len, i), |env| eval(env, operand))?;
+        if last.is_truthy() == stop {
+            break;
+        }
+    }
+    Ok(last)
+}
+
+// `(do ...)` evaluates its operands in order and gives the last, `nil`
+// given none. The last is in tail position, so a body ending in a call can
+// loop
+fn eval_do(env: &mut Lenv, sexpr: Vec<Lval>) -> Result<Tail, Lerr> {
+    env.count_builtin_call();
+    let site = env.site().cloned();
+    let len = sexpr.len();
+    let mut operands = sexpr.into_iter().enumerate().skip(1);
+    let (i, last) = match operands.next_back() {
+        Some(last) => last,
+        None => return Ok(Tail::Value(Lval::Nil)),
+    };
+    for (i, operand) in operands {
+        at_site(env, item_site(&site, len, i), |env| eval(env, operand))?;
+    }
+    at_site(env, item_site(&site, len, i), |env| eval_tail(env, last))
+}
+
+/// Calls a builtin, turning a panic into a `LerrType::Internal` error so a
+/// bug in one builtin can't take the whole session down. The panic hook
+/// still reports the panic as usual. Scopes the builtin pushed are popped
+/// by their guards as the panic unwinds, which is what makes carrying on
+/// with the environment afterwards reasonable. Where panics abort, as on
+/// wasm, there is nothing to catch.
+pub fn call_builtin(env: &mut Lenv, fun: Lfun, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    guard_builtin(env, &Lval::Fun(fun), |env| fun(env, operands))
+}
+
+/// Calls a builtin made from a closure, as `call_builtin` does.
+pub fn call_native(env: &mut Lenv, native: &NativeFn, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    guard_builtin(env, &Lval::Native(native.clone()), |env| {
+        native(env, operands)
+    })
+}
+
+fn guard_builtin(
+    env: &mut Lenv,
+    builtin: &Lval,
+    f: impl FnOnce(&mut Lenv) -> Result<Lval, Lerr>,
+) -> Result<Lval, Lerr> {
+    env.count_builtin_call();
+    let nesting = env.eval_nesting();
+    let sites = env.site_depth();
+    panic::catch_unwind(AssertUnwindSafe(|| f(env))).unwrap_or_else(|payload| {
+        // evaluations the panic cut short never finished
+        env.restore_eval_nesting(nesting);
+        env.truncate_sites(sites);
+        Err(Lerr::new(
+            LerrType::Internal,
+            format!(
+                "builtin {} panicked: {}",
+                builtin_name(env, builtin),
+                panic_message(payload.as_ref())
+            ),
+        ))
+    })
+}
 
-        if sym == ":" {
-            if func.args.len() != 1 {
+/// The text a panic was raised with, where it has any.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "no message".to_string())
+}
+
+// looked up only once something has gone wrong, so calls don't pay for it
+pub(crate) fn builtin_name(env: &Lenv, builtin: &Lval) -> String {
+    env.symbols()
+        .into_iter()
+        .find(|name| env.get(name).as_ref() == Some(builtin))
+        .unwrap_or_else(|| "<anonymous>".to_string())
+}
+
+pub fn call(env: &mut Lenv, func: Llambda, args: Vec<Lval>) -> Result<Lval, Lerr> {
+    let (func, frame) = match bind(env, func, args)? {
+        Bound::All(func, frame) => (func, frame),
+        Bound::Some(func) => return Ok(Lval::Lambda(func)),
+    };
+    env.count_lambda_call();
+    let mut scope = ScopeGuard::lexical(env, &func.env, frame);
+    let mut body = func.body;
+
+    // a call the body ends with is made here, in this call's place, so
+    // loops written as recursion run in constant stack
+    let mut tails = TailCalls::default();
+    loop {
+        let next = match eval_tail(&mut scope, Lval::Sexpr(body)) {
+            Ok(Tail::Value(value)) => return Ok(value),
+            Ok(Tail::Call(tail)) => tails.enter(&mut scope, tail),
+            Err(e) => Err(e),
+        };
+        body = match next {
+            Ok(Next::Body(body)) => body,
+            Ok(Next::Done(value)) => return Ok(value),
+            Err(e) => return Err(tails.traced(&scope, e)),
+        };
+    }
+}
+
+// what `call` goes on to after a tail call
+enum Next {
+    Body(Vec<Lval>),
+    Done(Lval),
+}
+
+// a lambda given its args: all of them, bound in a frame for its body, or
+// only some, which leaves a lambda waiting for the rest
+enum Bound {
+    All(Llambda, Lookup),
+    Some(Llambda),
+}
+
+fn bind(env: &mut Lenv, mut func: Llambda, args: Vec<Lval>) -> Result<Bound, Lerr> {
+    let given = args.len();
+    let total = func.args.len();
+
+    // load up all of the args, moving each into a frame of their own
+    let mut frame = Lookup::new();
+    let mut args = args.into_iter();
+    loop {
+        let (sym, val) = match func.args.first().map(String::as_str) {
+            // whatever is left, even nothing, goes to the rest parameter
+            Some(":") => {
+                if func.args.len() != 2 {
+                    return Err(Lerr::new(
+                        LerrType::IncorrectParamCount,
+                        ": operator needs to be followed by arg".to_string(),
+                    ));
+                }
+                let sym = func.args.pop().unwrap_or_default();
+                func.args.clear();
+                (sym, Lval::Qexpr(args.by_ref().collect()))
+            }
+            Some(_) => match args.next() {
+                Some(val) => (func.args.remove(0), val),
+                None => break,
+            },
+            None if args.len() > 0 => {
                 return Err(Lerr::new(
                     LerrType::IncorrectParamCount,
-                    format!(": operator needs to be followed by arg"),
-                ));
+                    format!("Function needed {} arg(s) but was given {}", total, given),
+                ))
             }
+            None => break,
+        };
+        if let Some(contract) = func.contract(&sym) {
+            check_contract(env, contract, &sym, &val)?;
+        }
+        frame.insert(sym, val);
+    }
 
-            let sym = func.args[0].clone();
-            func.args = func.args[1..].to_vec();
-            func.env.insert(&sym, Lval::Qexpr(args));
-            // sinning but we know that it will need to break here
-            break;
-        } else {
-            let val = args[0].clone();
-            args = args[1..].to_vec();
-            func.env.insert(&sym, val);
+    if func.args.is_empty() {
+        Ok(Bound::All(func, frame))
+    } else {
+        if !frame.is_empty() {
+            func.env.push(frame);
         }
+        Ok(Bound::Some(func))
     }
+}
+
+// the calls a body handed back to be made in its place, the latest
+// `TRACE_FRAMES` of them, for the trace of an error coming out of the last
+#[derive(Default)]
+struct TailCalls {
+    callees: VecDeque<Callee>,
+    dropped: usize,
+}
+
+impl TailCalls {
+    // makes `tail` in place of the call `scope` was made for, leaving the
+    // body it evaluates to the caller unless it's made another way
+    fn enter(&mut self, scope: &mut ScopeGuard, tail: Box<TailCall>) -> Result<Next, Lerr> {
+        let TailCall {
+            func, args, callee, ..
+        } = *tail;
+        self.push(callee);
+        let (func, frame) = match bind(scope, func, args)? {
+            Bound::All(func, frame) => (func, frame),
+            Bound::Some(func) => return Ok(Next::Done(Lval::Lambda(func))),
+        };
+        scope.count_lambda_call();
+        // one that sees its caller's frames has to be called from them
+        if func.env.sees_caller() {
+            let mut inner = ScopeGuard::lexical(scope, &func.env, frame);
+            return eval(&mut inner, Lval::Sexpr(func.body)).map(Next::Done);
+        }
+        scope.replace(&func.env, frame);
+        Ok(Next::Body(func.body))
+    }
+
+    fn push(&mut self, callee: Option<Callee>) {
+        if let Some(callee) = callee {
+            if self.callees.len() == TRACE_FRAMES {
+                self.callees.pop_front();
+                self.dropped += 1;
+            }
+            self.callees.push_back(callee);
+        }
+    }
+
+    // `e` as it would have come up through each of the calls, had they
+    // been made one inside the other
+    fn traced(&mut self, env: &Lenv, mut e: Lerr) -> Lerr {
+        for callee in self.callees.drain(..).rev() {
+            e = e.called_from(|| callee.describe(env));
+        }
+        e.untraced += self.dropped;
+        e
+    }
+}
+
+/// Calls the clause of a case-lambda taking exactly as many args as it is
+/// given, else the variadic one needing the most of them. Given too few for
+/// any clause, the one needing the fewest more is partially applied and
+/// becomes an ordinary lambda, unless nothing at all is given, which leaves
+/// the case-lambda as it was.
+pub fn call_case(env: &mut Lenv, clauses: Vec<Llambda>, args: Vec<Lval>) -> Result<Lval, Lerr> {
+    match pick_clause(clauses, args.len())? {
+        Ok(clause) => call(env, clause, args),
+        Err(clauses) => Ok(Lval::CaseLambda(clauses)),
+    }
+}
+
+// `call_case` for a call in tail position
+fn tail_case(clauses: Vec<Llambda>, args: Vec<Lval>) -> Result<Tail, Lerr> {
+    match pick_clause(clauses, args.len())? {
+        Ok(clause) => Ok(Tail::call(clause, args)),
+        Err(clauses) => Ok(Tail::Value(Lval::CaseLambda(clauses))),
+    }
+}
+
+// the clause `call_case` calls, or all of them back if it calls none
+fn pick_clause(
+    mut clauses: Vec<Llambda>,
+    given: usize,
+) -> Result<Result<Llambda, Vec<Llambda>>, Lerr> {
+    let exact = clauses
+        .iter()
+        .position(|lambda| lambda.arity().1 == Some(given));
+    let variadic = || {
+        clauses
+            .iter()
+            .enumerate()
+            .filter(|(_, lambda)| matches!(lambda.arity(), (min, None) if min <= given))
+            .max_by_key(|(_, lambda)| lambda.arity().0)
+            .map(|(i, _)| i)
+    };
+    let waiting = || {
+        clauses
+            .iter()
+            .enumerate()
+            .filter(|(_, lambda)| lambda.arity().0 > given)
+            .min_by_key(|(_, lambda)| (lambda.arity().0, lambda.arity().1.is_none()))
+            .map(|(i, _)| i)
+    };
 
-    if func.args.len() == 0 {
-        env.push(func.env.peek().unwrap().clone());
-        let res = eval(env, Lval::Sexpr(func.body));
-        env.pop();
-        res
+    let picked = match exact.or_else(variadic) {
+        None if given == 0 => return Ok(Err(clauses)),
+        None => waiting(),
+        some => some,
+    };
+    match picked {
+        Some(i) => Ok(Ok(clauses.swap_remove(i))),
+        None => {
+            let arities: Vec<String> = clauses.iter().map(Llambda::arity_label).collect();
+            Err(Lerr::new(
+                LerrType::IncorrectParamCount,
+                format!(
+                    "Function case-lambda has clauses taking {} arg(s) but was given {}",
+                    arities.join(", "),
+                    given
+                ),
+            ))
+        }
+    }
+}
+
+/// Calls `f`, a builtin or a lambda, with `args` already evaluated, as
+/// builtins taking a function argument do.
+pub fn apply(env: &mut Lenv, f: &Lval, args: Vec<Lval>) -> Result<Lval, Lerr> {
+    match f {
+        Lval::Fun(fun) => call_builtin(env, *fun, args),
+        Lval::Native(native) => call_native(env, native, args),
+        Lval::Lambda(lambda) => {
+            env.audit_clone("apply", f);
+            call(env, lambda.clone(), args)
+        }
+        Lval::CaseLambda(clauses) => {
+            env.audit_clone("apply", f);
+            call_case(env, clauses.clone(), args)
+        }
+        other => Err(Lerr::new(
+            LerrType::WrongType,
+            format!("{} is not a function", other.summary()),
+        )),
+    }
+}
+
+/// Runs `contract`'s predicate on the value given for `name`, failing with
+/// a `ContractViolation` unless it returns `true` or a nonzero number.
+pub fn check_contract(
+    env: &mut Lenv,
+    contract: &Contract,
+    name: &str,
+    value: &Lval,
+) -> Result<(), Lerr> {
+    let verdict = match &contract.predicate {
+        f @ (Lval::Fun(_) | Lval::Native(_) | Lval::Lambda(_) | Lval::CaseLambda(_)) => {
+            apply(env, f, vec![value.clone()])?
+        }
+        other => {
+            return Err(Lerr::new(
+                LerrType::WrongType,
+                format!("contract {} is not a function", other.summary()),
+            ))
+        }
+    };
+
+    match verdict {
+        Lval::Bool(true) => Ok(()),
+        Lval::Num(n) if n != 0_f64 => Ok(()),
+        Lval::Int(n) if n != 0 => Ok(()),
+        _ => Err(Lerr::new(
+            LerrType::ContractViolation,
+            format!(
+                "{} needed to satisfy {} but was given {}",
+                name,
+                contract.label,
+                value.summary()
+            ),
+        )),
+    }
+}
+
+/// Performs a single reduction on `expr`: the leftmost innermost
+/// s-expression is evaluated and replaced by its value. A lone symbol is
+/// looked up. Returns `None` once `expr` is already a value.
+pub fn step(env: &mut Lenv, expr: &Lval) -> Result<Option<Lval>, Lerr> {
+    match expr {
+        Lval::Sym(_) => eval(env, expr.clone()).map(Some),
+        Lval::Sexpr(_) => {
+            let mut reduced = expr.clone();
+            if reduce_innermost(env, &mut reduced)? {
+                Ok(Some(reduced))
+            } else {
+                Ok(None)
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+// an empty s-expression is already a value, which also keeps results like
+// that of `def` from being stepped forever
+fn reduce_innermost(env: &mut Lenv, expr: &mut Lval) -> Result<bool, Lerr> {
+    let items = match expr {
+        Lval::Sexpr(items) if !items.is_empty() => items,
+        _ => return Ok(false),
+    };
+
+    // only the condition of an `if` is stepped through; the branch it
+    // picks is then evaluated in one go, and the other is never touched.
+    // Likewise for the first operand of `and` and `or`
+    let reducible = if is_special(env, items, "if", builtin::builtin_if)
+        || is_special(env, items, "and", builtin::builtin_and_then)
+        || is_special(env, items, "or", builtin::builtin_or_else)
+    {
+        items.len().min(2)
     } else {
-        Ok(Lval::Lambda(func))
+        items.len()
+    };
+    for item in items[..reducible].iter_mut() {
+        if reduce_innermost(env, item)? {
+            return Ok(true);
+        }
     }
+
+    let value = eval(env, Lval::Sexpr(std::mem::take(items)))?;
+    *expr = value;
+    Ok(true)
 }
 
 #[cfg(test)]
@@ -102,8 +732,60 @@ mod tests {
     use super::*;
     use crate::{env::init_env, to_lambda};
 
-    fn empty_fun(_env: &mut Lenv, _operands: Vec<Lval>) -> Result<Lval, Lerr> {
-        Ok(Lval::Sexpr(vec![]))
+    #[cfg(feature = "parser")]
+    fn eval_str(env: &mut Lenv, input: &str) -> Result<Lval, Lerr> {
+        eval(env, crate::parser::parse(input).unwrap().1)
+    }
+
+    #[test]
+    fn it_survives_panicking_builtins() {
+        let env = &mut init_env();
+        crate::add_builtin(env, "explode", |_, _| panic!("kaboom"), (0, None));
+        let call = |env: &mut Lenv, name: &str| {
+            eval(
+                env,
+                Lval::Sexpr(vec![Lval::Sym(name.to_string()), Lval::Num(1_f64)]),
+            )
+        };
+
+        let err = call(env, "explode").unwrap_err();
+        assert_eq!(err.etype, LerrType::Internal);
+        assert_eq!(err.message, "builtin explode panicked: kaboom");
+
+        // scopes opened on the way down were closed on the way back up
+        let depth = env.depth();
+        eval(
+            env,
+            Lval::Sexpr(vec![
+                Lval::Sym("def".to_string()),
+                Lval::Qexpr(vec![Lval::Sym("f".to_string())]),
+                Lval::Sexpr(vec![
+                    Lval::Sym("\\".to_string()),
+                    Lval::Qexpr(vec![Lval::Sym("x".to_string())]),
+                    Lval::Qexpr(vec![
+                        Lval::Sym("explode".to_string()),
+                        Lval::Sym("x".to_string()),
+                    ]),
+                ]),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(call(env, "f").unwrap_err().etype, LerrType::Internal);
+        assert_eq!(env.depth(), depth);
+        assert_eq!(env.get("x"), None);
+
+        // and everything else still works
+        assert_eq!(
+            eval(
+                env,
+                Lval::Sexpr(vec![
+                    Lval::Sym("+".to_string()),
+                    Lval::Num(1_f64),
+                    Lval::Num(2_f64)
+                ])
+            ),
+            Ok(Lval::Num(3_f64))
+        );
     }
 
     #[test]
@@ -121,25 +803,25 @@ mod tests {
         let env = &mut init_env();
         assert_eq!(
             eval(env, Lval::Sym(String::from("+"))).unwrap(),
-            Lval::Fun(empty_fun)
+            env.get("+").unwrap()
         );
         assert_eq!(
             eval(env, Lval::Sexpr(vec![Lval::Sym(String::from("*"))])).unwrap(),
-            Lval::Fun(empty_fun)
+            env.get("*").unwrap()
         );
     }
 
     #[test]
     fn it_handles_empty_expressions() {
         let env = &mut init_env();
-        assert_eq!(eval(env, Lval::Sexpr(vec![])).unwrap(), Lval::Sexpr(vec![]));
+        assert_eq!(eval(env, Lval::Sexpr(vec![])).unwrap(), Lval::Nil);
         assert_eq!(
             eval(
                 env,
                 Lval::Sexpr(vec![Lval::Sexpr(vec![Lval::Sexpr(vec![])])])
             )
             .unwrap(),
-            Lval::Sexpr(vec![])
+            Lval::Nil
         );
     }
 
@@ -209,14 +891,14 @@ mod tests {
                 ]),
             )
             .unwrap(),
-            Lval::Sexpr(vec![])
+            Lval::Nil
         );
         assert_eq!(
             eval_symbol(&mut env, String::from("a")).unwrap(),
             Lval::Num(1_f64)
         );
 
-        env.push(crate::Lookup::new());
+        env.push(Lookup::new());
         assert_eq!(
             eval(
                 &mut env,
@@ -227,7 +909,7 @@ mod tests {
                 ]),
             )
             .unwrap(),
-            Lval::Sexpr(vec![])
+            Lval::Nil
         );
         assert_eq!(
             eval_symbol(&mut env, String::from("a")).unwrap(),
@@ -248,7 +930,7 @@ mod tests {
                 ]),
             )
             .unwrap(),
-            Lval::Sexpr(vec![])
+            Lval::Nil
         );
         assert_eq!(
             eval_symbol(&mut env, String::from("a")).unwrap(),
@@ -268,8 +950,7 @@ mod tests {
     fn it_handles_lambdas() {
         let env = &mut init_env();
 
-        let immediately_invoked =
-            Llambda::new(vec![], vec![Lval::Num(71_f64)], env.peek().unwrap().clone());
+        let immediately_invoked = Llambda::new(vec![], vec![Lval::Num(71_f64)], env.capture());
         assert_eq!(
             eval(env, Lval::Sexpr(vec![Lval::Lambda(immediately_invoked)])).unwrap(),
             Lval::Num(71_f64)
@@ -283,7 +964,7 @@ mod tests {
                 Lval::Sym(String::from("a")),
                 Lval::Sym(String::from("a")),
             ],
-            env.peek().unwrap().clone(),
+            env.capture(),
         );
         assert_eq!(
             call(env, lambda, vec![Lval::Num(5_f64)]).unwrap(),
@@ -298,7 +979,7 @@ mod tests {
                 Lval::Sym(String::from("c")),
                 Lval::Sym(String::from("d")),
             ],
-            env.peek().unwrap().clone(),
+            env.capture(),
         );
         let new_lambda = call(env, lambda, vec![Lval::Num(15_f64)]).unwrap();
         assert_eq!(
@@ -307,6 +988,469 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "parser")]
+    #[test]
+    fn it_restores_scopes_after_calls() {
+        let env = &mut init_env();
+        let frames = env.iter().count();
+
+        eval_str(env, "def [inner] (\\ [x] [+ x 1])").unwrap();
+        eval_str(env, "def [outer] (\\ [x] [* 2 (inner x)])").unwrap();
+        assert_eq!(eval_str(env, "outer 4").unwrap(), Lval::Num(10_f64));
+        assert_eq!(env.iter().count(), frames);
+
+        eval_str(env, "def [bad] (\\ [x] [head x])").unwrap();
+        eval_str(env, "def [calls-bad] (\\ [x] [+ 1 (bad x)])").unwrap();
+        let err = eval_str(env, "calls-bad 4").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        assert_eq!(env.iter().count(), frames);
+
+        eval_str(
+            env,
+            "def [dive] (\\ [n] [if (== n 0) [bad n] [+ 1 (dive (- n 1))]])",
+        )
+        .unwrap();
+        let err = eval_str(env, "dive 50").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+        assert_eq!(env.iter().count(), frames);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_makes_tail_calls_without_growing_the_stack() {
+        let env = &mut init_env();
+        let frames = env.iter().count();
+        eval_str(
+            env,
+            "def [count] (\\ [n acc] [if (== n 0) [acc] [count (- n 1) (+ acc 1)]])",
+        )
+        .unwrap();
+        assert_eq!(eval_str(env, "count 100000 0"), Ok(Lval::Int(100_000)));
+        assert_eq!(env.iter().count(), frames);
+
+        // between functions, and through case-lambdas
+        eval_str(env, "def [evens] (\\ [n] [if (== n 0) [1] [odds (- n 1)]])").unwrap();
+        eval_str(env, "def [odds] (\\ [n] [if (== n 0) [0] [evens (- n 1)]])").unwrap();
+        assert_eq!(eval_str(env, "evens 10001"), Ok(Lval::Int(0)));
+        eval_str(
+            env,
+            "def [down] (case-lambda [[n] [down n 0]] [[n acc] [if (== n 0) [acc] [down (- n 1) (+ acc 2)]]])",
+        )
+        .unwrap();
+        assert_eq!(eval_str(env, "down 10000"), Ok(Lval::Int(20_000)));
+
+        // a tail call given too few args still gives a lambda back, and
+        // one to a lambda made along the way sees what that closed over
+        assert!(matches!(
+            eval_str(env, "(\\ [n] [count n]) 3"),
+            Ok(Lval::Lambda(_))
+        ));
+        assert_eq!(
+            eval_str(env, "(\\ [n] [(\\ [x] [+ x n]) 1]) 2"),
+            Ok(Lval::Int(3))
+        );
+        assert_eq!(env.iter().count(), frames);
+
+        // recursion that isn't a tail call still works, as deep as the
+        // stack allows
+        eval_str(
+            env,
+            "def [sum] (\\ [n] [if (== n 0) [0] [+ n (sum (- n 1))]])",
+        )
+        .unwrap();
+        assert_eq!(eval_str(env, "sum 50"), Ok(Lval::Int(1275)));
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_traces_the_calls_an_error_came_up_through() {
+        let env = &mut init_env();
+        eval_str(env, "def [leaf] (\\ [x] [+ x nope])").unwrap();
+        eval_str(env, "def [middle] (\\ [x] [* 2 (leaf x)])").unwrap();
+        eval_str(env, "def [top] (\\ [x] [- (middle x) 1])").unwrap();
+
+        let err = eval_str(env, "top 1").unwrap_err();
+        assert_eq!(err.etype, LerrType::UnboundSymbol);
+        assert_eq!(err.trace(), ["leaf", "middle", "top"]);
+        assert!(err
+            .to_string()
+            .ends_with("\n  called from leaf\n  called from middle\n  called from top"));
+        assert!(!format!("{:?}", err).contains("called from"));
+        assert!(format!("{:#?}", err).ends_with("called from top"));
+
+        // builtins and lambdas without a name have frames too, and failing
+        // before the call is made leaves the call out
+        let err = eval_str(env, "(\\ [xs] [head xs]) []").unwrap_err();
+        assert_eq!(err.trace(), ["head", "(\\ [xs] ...)"]);
+        let err = eval_str(env, "+ 1 (leaf 2)").unwrap_err();
+        assert_eq!(err.trace(), ["leaf"]);
+
+        // only the innermost frames of a deep recursion are kept
+        eval_str(
+            env,
+            "def [down] (\\ [n] [if (== n 0) [nope] [down (- n 1)]])",
+        )
+        .unwrap();
+        let err = eval_str(env, "down 100").unwrap_err();
+        assert_eq!(err.trace().len(), crate::TRACE_FRAMES);
+        assert!(err.to_string().ends_with("\n  … and 37 more"));
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_short_circuits_and_and_or() {
+        let env = &mut init_env();
+        let num = |n: f64| Ok(Lval::Num(n));
+        let truth = |b: bool| Ok(Lval::Bool(b));
+
+        // what comes after the deciding operand would be unbound
+        assert_eq!(eval_str(env, "or 1 (nope)"), num(1_f64));
+        assert_eq!(eval_str(env, "or 0 [] (+ 1 2) (nope)"), num(3_f64));
+        assert_eq!(eval_str(env, "and 0 (nope)"), num(0_f64));
+        assert_eq!(eval_str(env, "and 1 (> 1 2) (nope)"), truth(false));
+        assert_eq!(eval_str(env, "and 1 2 3"), num(3_f64));
+        assert_eq!(eval_str(env, "or 0 ()"), Ok(Lval::Nil));
+        assert_eq!(
+            eval_str(env, "and 1 (nope)").unwrap_err().etype,
+            LerrType::UnboundSymbol
+        );
+
+        assert_eq!(eval_str(env, "(and)"), truth(true));
+        assert_eq!(eval_str(env, "(or)"), truth(false));
+        assert_eq!(eval_str(env, "and []"), Ok(Lval::Qexpr(vec![])));
+        assert_eq!(eval_str(env, "not []"), truth(true));
+        assert_eq!(eval_str(env, "not \"\""), truth(false));
+        assert_eq!(
+            eval_str(env, "not 1 2").unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+
+        // as values they're ordinary functions
+        eval_str(env, "def [either] or").unwrap();
+        assert_eq!(eval_str(env, "either 0 5"), num(5_f64));
+        assert_eq!(
+            eval_str(env, "either 1 (nope)").unwrap_err().etype,
+            LerrType::UnboundSymbol
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_has_booleans() {
+        let env = &mut init_env();
+        let shown = |env: &mut Lenv, input| format!("{:?}", eval_str(env, input).unwrap());
+        assert_eq!(shown(env, "list true false (< 1 2)"), "[true false true]");
+
+        // false, and still 0 and empty lists, are all that `if` takes as false
+        for (input, expected) in [
+            ("if false [1] [2]", "2"),
+            ("if true [1] [2]", "1"),
+            ("if 0 [1] [2]", "2"),
+            ("if [] [1] [2]", "2"),
+            ("if \"\" [1] [2]", "1"),
+            ("not false", "true"),
+            ("not 0", "true"),
+            ("! true", "false"),
+            ("&& true 0", "false"),
+            ("|| false 1", "true"),
+            ("and true false 1", "false"),
+            ("or false 0 true", "true"),
+            ("== true true", "true"),
+            ("== true 1", "false"),
+            ("sort-any [true 1 false]", "[1 false true]"),
+        ] {
+            assert_eq!(shown(env, input), expected, "{}", input);
+        }
+
+        assert_eq!(
+            eval_str(env, "+ true 1").unwrap_err().etype,
+            LerrType::BadNum
+        );
+        assert_eq!(
+            eval_str(env, "< false true").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            eval_str(env, "def [true] 1").unwrap_err().etype,
+            LerrType::WrongType
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_does_things_in_order() {
+        let env = &mut init_env();
+        let shown = |env: &mut Lenv, input| format!("{:?}", eval_str(env, input).unwrap());
+
+        eval_str(env, "def [seen] []").unwrap();
+        assert_eq!(
+            shown(
+                env,
+                "do (def [seen] (join seen [a])) (def [seen] (join seen [b])) (len seen)"
+            ),
+            "2"
+        );
+        assert_eq!(shown(env, "seen"), "[a b]");
+        assert_eq!(
+            shown(env, "(\\ [x] [do (def [t] (* x 2)) (+ t 1)]) 5"),
+            "11"
+        );
+        assert_eq!(shown(env, "(do)"), "nil");
+        assert_eq!(shown(env, "do [1] 2"), "2");
+
+        // stops at the first error
+        eval_str(env, "def [hit] 1").unwrap();
+        assert_eq!(
+            eval_str(env, "do (def [hit] 2) (nope) (def [hit] 3)")
+                .unwrap_err()
+                .etype,
+            LerrType::UnboundSymbol
+        );
+        assert_eq!(shown(env, "hit"), "2");
+
+        // the last expression is a tail call
+        eval_str(
+            env,
+            "fun [down n] [do (def [at] n) (if (== n 0) [at] [down (- n 1)])]",
+        )
+        .unwrap();
+        assert_eq!(shown(env, "down 5000"), "0");
+
+        // and as a value it's an ordinary function
+        eval_str(env, "def [seq] do").unwrap();
+        assert_eq!(shown(env, "seq 1 2 3"), "3");
+        assert_eq!(shown(env, "seq"), "builtin");
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_only_evaluates_the_branch_if_takes() {
+        let env = &mut init_env();
+        let num = |n: f64| Ok(Lval::Num(n));
+
+        // the other branch would fail with an unbound symbol
+        assert_eq!(eval_str(env, "if (> 2 1) [+ 1 1] [nope]"), num(2_f64));
+        assert_eq!(eval_str(env, "if (< 2 1) [nope] [+ 2 2]"), num(4_f64));
+        assert_eq!(eval_str(env, "if 1 (+ 1 2) (nope)"), num(3_f64));
+        assert_eq!(
+            eval_str(env, "if 0 [nope] [nope]").unwrap_err().etype,
+            LerrType::UnboundSymbol
+        );
+
+        // only 0, [], () and nil are false
+        for (condition, expected) in &[
+            ("0", 2_f64),
+            ("[]", 2_f64),
+            ("()", 2_f64),
+            ("nil", 2_f64),
+            ("-1", 1_f64),
+            ("[0]", 1_f64),
+            ("\"\"", 1_f64),
+            (":no", 1_f64),
+        ] {
+            let program = format!("if {} [1] [2]", condition);
+            assert_eq!(eval_str(env, &program), num(*expected), "{}", condition);
+        }
+
+        let err = eval_str(env, "if 1 [2]").unwrap_err();
+        assert_eq!(err.etype, LerrType::IncorrectParamCount);
+
+        // as a value it's an ordinary function, with its operands evaluated
+        eval_str(env, "def [choose] if").unwrap();
+        assert_eq!(eval_str(env, "choose 0 [1] [+ 1 1]"), num(2_f64));
+        assert_eq!(
+            eval_str(env, "choose 1 [1] (nope)").unwrap_err().etype,
+            LerrType::UnboundSymbol
+        );
+
+        // and a lambda called if is just a lambda
+        eval_str(env, "def! [if] (\\ [a b c] [+ a b c])").unwrap();
+        assert_eq!(eval_str(env, "if 1 2 3"), num(6_f64));
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_stops_at_its_fuel_and_depth() {
+        let env = &mut init_env();
+        // the call, `+`, `1` and `2`
+        env.set_fuel(Some(4));
+        assert_eq!(eval_str(env, "+ 1 2"), Ok(Lval::Num(3_f64)));
+        assert_eq!(env.fuel(), Some(0));
+        env.set_fuel(Some(3));
+        assert_eq!(eval_str(env, "+ 1 2").unwrap_err().etype, LerrType::Budget);
+
+        env.set_fuel(None);
+        env.set_max_nesting(Some(32));
+        eval_str(env, "def [deeper] (\\ [n] [+ 1 (deeper n)])").unwrap();
+        assert_eq!(
+            eval_str(env, "deeper 0").unwrap_err().etype,
+            LerrType::TooDeep
+        );
+        assert_eq!(eval_str(env, "+ 1 2"), Ok(Lval::Num(3_f64)));
+
+        // a tail call doesn't nest any deeper, so only fuel stops this one
+        eval_str(env, "def [forever] (\\ [n] [forever (+ n 1)])").unwrap();
+        env.set_fuel(Some(100_000));
+        assert_eq!(
+            eval_str(env, "forever 0").unwrap_err().etype,
+            LerrType::Budget
+        );
+        env.set_fuel(None);
+
+        // a builtin with nothing to work on fails rather than panicking
+        assert_eq!(
+            apply(env, &env.get("+").unwrap(), vec![])
+                .unwrap_err()
+                .etype,
+            LerrType::IncorrectParamCount
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_stops_at_its_step_budget() {
+        let env = &mut init_env();
+        env.set_max_steps(Some(10_000));
+        eval_str(env, "def [f] (\\ [] [f])").unwrap();
+        let err = eval_str(env, "f").unwrap_err();
+        assert_eq!(err.etype, LerrType::Budget);
+        assert_eq!(err.message(), "evaluation ran out of fuel");
+
+        // each evaluation from the top gets all of it again
+        eval_str(
+            env,
+            "def [count] (\\ [n] [if (== n 0) [0] [count (- n 1)]])",
+        )
+        .unwrap();
+        for _ in 0..3 {
+            assert_eq!(eval_str(env, "count 500"), Ok(Lval::Int(0)));
+        }
+        assert_eq!(
+            eval_str(env, "count 5000").unwrap_err().etype,
+            LerrType::Budget
+        );
+
+        env.set_max_steps(None);
+        env.set_fuel(None);
+        assert_eq!(eval_str(env, "count 5000"), Ok(Lval::Int(0)));
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_lets_deep_recursion_run_by_default() {
+        // non-tail calls are stack-bound, so give them room
+        let deep = std::thread::Builder::new()
+            .stack_size(64 << 20)
+            .spawn(|| {
+                let env = &mut init_env();
+                eval_str(
+                    env,
+                    "def [sum] (\\ [n] [if (== n 0) [0] [+ n (sum (- n 1))]])",
+                )
+                .unwrap();
+                eval_str(env, "sum 1000").map(|sum| sum.to_string())
+            })
+            .unwrap();
+        assert_eq!(deep.join().unwrap(), Ok("500500".to_string()));
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_handles_keywords() {
+        let env = &mut init_env();
+        let width = Lval::Keyword(String::from("width"));
+        assert_eq!(eval_str(env, ":width").unwrap(), width);
+        assert_eq!(
+            eval_str(env, "list :width 8").unwrap(),
+            Lval::Qexpr(vec![width.clone(), Lval::Num(8_f64)])
+        );
+        assert_eq!(format!("{:?}", width), ":width");
+
+        assert_eq!(eval_str(env, "== :width :width").unwrap(), Lval::Bool(true));
+        assert_eq!(
+            eval_str(env, "== :width :height").unwrap(),
+            Lval::Bool(false)
+        );
+        assert_eq!(
+            eval_str(env, "== :width \"width\"").unwrap(),
+            Lval::Bool(false)
+        );
+
+        // as the keys of an option list
+        eval_str(
+            env,
+            "def [option] (\\ [key opts] [if (== key (eval (head opts))) \
+             [eval (head (tail opts))] [option key (tail (tail opts))]])",
+        )
+        .unwrap();
+        assert_eq!(
+            eval_str(env, "option :width [:precision 2 :width 8]").unwrap(),
+            Lval::Num(8_f64)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_closes_over_where_lambdas_are_made() {
+        let env = &mut init_env();
+        eval_str(env, "def [make-adder] (\\ [x] [\\ [y] [+ x y]])").unwrap();
+        assert_eq!(eval_str(env, "(make-adder 2) 3").unwrap(), Lval::Num(5_f64));
+
+        // each closure keeps the item it was made for
+        let scaled = eval_str(
+            env,
+            "map (\\ [f] [f 10]) (map (\\ [k] [\\ [v] [* k v]]) [1 2 3])",
+        )
+        .unwrap();
+        assert_eq!(scaled.to_string(), "[10 20 30]");
+
+        // globals are seen as they are now, and a caller's locals not at all
+        eval_str(env, "def [n] 1").unwrap();
+        eval_str(env, "def [get-n] (\\ [] [n])").unwrap();
+        eval_str(env, "def [n] 2").unwrap();
+        assert_eq!(
+            eval_str(env, "(\\ [n] [get-n]) 5").unwrap(),
+            Lval::Num(2_f64)
+        );
+        eval_str(env, "def [get-m] (\\ [] [m])").unwrap();
+        assert_eq!(
+            eval_str(env, "(\\ [m] [get-m]) 5").unwrap_err().etype,
+            LerrType::UnboundSymbol
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_binds_the_rest_of_the_arguments() {
+        let env = &mut init_env();
+        let shown = |env: &mut Lenv, input| format!("{:?}", eval_str(env, input).unwrap());
+        eval_str(env, "def [rest-of] (\\ [x : rest] [rest])").unwrap();
+        assert_eq!(shown(env, "rest-of 1"), "[]");
+        assert_eq!(shown(env, "rest-of 1 2"), "[2]");
+        assert_eq!(shown(env, "rest-of 1 2 3"), "[2 3]");
+        assert_eq!(shown(env, "(\\ [: all] [all])"), "[]");
+        // with only the first argument missing it waits for it
+        assert_eq!(shown(env, "(rest-of) 1"), "[]");
+        eval_str(env, "fun [tally : xs] [len xs]").unwrap();
+        assert_eq!(shown(env, "tally"), "0");
+
+        for input in [
+            "\\ [x :] [x]",
+            "\\ [: a b] [a]",
+            "\\ [x : :] [x]",
+            "\\ [: a : b] [a]",
+            "fun [f x :] [x]",
+            "case-lambda [[x :] [x]]",
+        ] {
+            let err = eval_str(env, input).unwrap_err();
+            assert_eq!(err.etype, LerrType::IncorrectParamCount, "{}", input);
+        }
+        assert_eq!(
+            eval_str(env, "\\ [x :] [x]").unwrap_err().message,
+            "Function \\ needed exactly one parameter after : but was given [x :]"
+        );
+    }
+
     #[test]
     fn it_handles_nested_lambdas() {
         let env = &mut init_env();
@@ -328,7 +1472,8 @@ mod tests {
         let partial = eval(env, f).unwrap();
         let partial_lambda = to_lambda(&partial).unwrap();
         assert_eq!(partial_lambda.args.len(), 1);
-        assert!(partial_lambda.env.get("+").is_some()); // we want the std lib
+        // globals are looked up when called rather than copied in
+        assert!(partial_lambda.env.get("+").is_none());
         assert!(partial_lambda.env.get("e").is_some()); // we want e to get defined
 
         assert_eq!(
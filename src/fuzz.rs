@@ -0,0 +1,118 @@
+//! Entry points for fuzzers like cargo-fuzz, which feed in arbitrary bytes.
+//! Neither ever panics, and both hold the input to strict limits so nothing
+//! it does can exhaust the stack, memory or time. Only built with the
+//! `fuzz-api` feature.
+use crate::{
+    env::init_env,
+    eval,
+    parser::{parse_located_with, span_tree, ParseLimits},
+    Lenv,
+};
+use std::panic::{self, AssertUnwindSafe};
+
+/// How an input fared, coarsely.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// it parsed, and evaluated without an error where it was evaluated
+    Ok,
+    SyntaxError,
+    /// it failed to evaluate, running out of fuel or depth included
+    EvalError,
+    /// something panicked, which is always a bug
+    Panicked,
+}
+
+/// Builtins that reach outside the process, left out of the environment
+/// inputs are evaluated in.
+pub const EXCLUDED_BUILTINS: [&str; 3] = ["repl", "load", "cache-clear"];
+
+/// The limits both entry points parse with.
+pub fn limits() -> ParseLimits {
+    ParseLimits {
+        max_depth: 64,
+        max_input_len: 64 * 1024,
+        max_atom_len: 1024,
+        allow_special_floats: true,
+    }
+}
+
+/// Reads `data`, made UTF-8 by replacing whatever isn't.
+pub fn parse_no_panic(data: &[u8]) -> Outcome {
+    let source = String::from_utf8_lossy(data);
+    guarded(|| match parse_located_with(&source, &limits()) {
+        Ok(_) => Outcome::Ok,
+        Err(_) => Outcome::SyntaxError,
+    })
+}
+
+/// Reads `data` as `parse_no_panic` does and evaluates it as one line, in a
+/// fresh environment, with at most `fuel` evaluations and `depth` of them
+/// under way at once.
+pub fn eval_no_panic(data: &[u8], fuel: u64, depth: usize) -> Outcome {
+    let source = String::from_utf8_lossy(data);
+    guarded(|| {
+        let limits = limits();
+        let ast = match parse_located_with(&source, &limits) {
+            Ok(ast) => ast,
+            Err(_) => return Outcome::SyntaxError,
+        };
+        let mut env = sandbox(fuel, depth);
+        match eval::eval_spanned(&mut env, ast, span_tree(&source, &limits)) {
+            Ok(_) => Outcome::Ok,
+            Err(_) => Outcome::EvalError,
+        }
+    })
+}
+
+fn sandbox(fuel: u64, depth: usize) -> Lenv {
+    let mut env = init_env();
+    for name in EXCLUDED_BUILTINS.iter() {
+        env.remove_last(name);
+    }
+    env.set_max_bindings(Some(10_000));
+    env.set_fuel(Some(fuel));
+    env.set_max_nesting(Some(depth));
+    env
+}
+
+// whatever is dropped inside `f` is dropped under the guard too
+fn guarded(f: impl FnOnce() -> Outcome) -> Outcome {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(Outcome::Panicked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // inputs that used to panic, overflow the stack or never finish
+    const CORPUS: &[&[u8]] = &[
+        b"(def [f] (\\ [] [f])) (f)",
+        b"(def [f] (\\ [n] [+ 1 (f n)])) (f 1)",
+        b"eval (list + )",
+        b"(string-repeat (string-repeat \"ab\" 1000000) 1000000)",
+        b"nth 1e300 [1 2]",
+        b"(bytes-slice #bytes[00ff] 2 1)",
+        b"repl",
+        b"\xff\xfe(+ 1 \xc3",
+    ];
+
+    #[test]
+    fn it_never_panics_on_the_corpus() {
+        for input in CORPUS {
+            let shown = String::from_utf8_lossy(input);
+            assert_ne!(parse_no_panic(input), Outcome::Panicked, "{}", shown);
+            assert_ne!(
+                eval_no_panic(input, 10_000, 64),
+                Outcome::Panicked,
+                "{}",
+                shown
+            );
+        }
+
+        let deep = format!("{}1{}", "(".repeat(1000), ")".repeat(1000));
+        assert_eq!(parse_no_panic(deep.as_bytes()), Outcome::SyntaxError);
+        assert_eq!(eval_no_panic(b"(+ 1 2)", 10, 8), Outcome::Ok);
+        assert_eq!(eval_no_panic(CORPUS[0], 10_000, 64), Outcome::EvalError);
+        assert_eq!(eval_no_panic(CORPUS[6], 10_000, 64), Outcome::EvalError);
+    }
+}
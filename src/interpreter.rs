@@ -0,0 +1,1095 @@
+#[cfg(feature = "clone-audit")]
+use crate::clone_audit::CloneAuditReport;
+#[cfg(feature = "parser")]
+use crate::{
+    completion::{self, CompletionItem, Signature},
+    env::{Lookup, Origin, ScopeGuard},
+    parser::{parse_located_with, span_tree, Incremental, ParseLimits, Span},
+    LerrType,
+};
+use crate::{
+    env::{init_env, Counters, EvalStats},
+    eval, Lenv, Lerr, Lval, SpanTree,
+};
+#[cfg(feature = "parser")]
+use std::{fmt, fs, path::Path};
+
+/// An environment with every builtin registered, ready to evaluate code.
+pub struct Interpreter {
+    env: Lenv,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter { env: init_env() }
+    }
+
+    /// A prototype with just the builtins registered. To include a prelude,
+    /// evaluate it and then call `into_prototype`.
+    pub fn prototype() -> Prototype {
+        Interpreter::new().into_prototype()
+    }
+
+    /// Freezes everything defined so far for new interpreters to start from.
+    pub fn into_prototype(mut self) -> Prototype {
+        self.env.share_globals();
+        Prototype { env: self.env }
+    }
+
+    /// A new interpreter starting with the prototype's globals. Those are
+    /// shared rather than copied, so this allocates next to nothing, and
+    /// it's copied only if the new interpreter redefines or removes one.
+    pub fn from_prototype(prototype: &Prototype) -> Self {
+        let mut env = prototype.env.clone();
+        env.reseed();
+        Interpreter { env }
+    }
+
+    pub fn env(&self) -> &Lenv {
+        &self.env
+    }
+
+    pub fn env_mut(&mut self) -> &mut Lenv {
+        &mut self.env
+    }
+
+    pub fn eval(&mut self, lval: Lval) -> Result<Lval, Lerr> {
+        self.eval_with_spans(lval, None)
+    }
+
+    /// Reads `source` as one expression and evaluates it, as `eval_line`
+    /// does.
+    #[cfg(feature = "parser")]
+    pub fn eval_str(&mut self, source: &str) -> Result<Lval, Lerr> {
+        self.env.reset_stats();
+        eval_line(&mut self.env, source)
+    }
+
+    /// Like `eval_str` with `bindings` in a frame of their own over the
+    /// globals, as `with-env` does. The frame is gone afterwards, whether
+    /// or not the evaluation failed.
+    #[cfg(feature = "parser")]
+    pub fn eval_with_bindings(
+        &mut self,
+        source: &str,
+        bindings: &[(&str, Lval)],
+    ) -> Result<Lval, Lerr> {
+        self.env.reset_stats();
+        let mut frame = Lookup::new();
+        for (name, value) in bindings {
+            frame.insert(name.to_string(), value.clone());
+        }
+        let mut scope = ScopeGuard::new(&mut self.env, frame);
+        eval_line(&mut scope, source)
+    }
+
+    // with `spans`, errors say where in the source they happened
+    fn eval_with_spans(&mut self, lval: Lval, spans: Option<SpanTree>) -> Result<Lval, Lerr> {
+        self.env.reset_stats();
+        match spans {
+            Some(spans) => eval::eval_spanned(&mut self.env, lval, spans),
+            None => eval::eval(&mut self.env, lval),
+        }
+    }
+
+    /// Scope bookkeeping for the most recent top level evaluation. Every
+    /// frame pushed should have been popped again. Always zero in release
+    /// builds.
+    pub fn last_eval_stats(&self) -> EvalStats {
+        self.env.stats()
+    }
+
+    /// Expressions evaluated, calls made, errors by type and the deepest the
+    /// scopes went, since the interpreter started or `(stats-reset)`.
+    pub fn stats(&self) -> Counters {
+        self.env.counters()
+    }
+
+    /// Large values deep-copied since the interpreter started or
+    /// `reset_clone_audit`, for tests guarding against accidental copies.
+    #[cfg(feature = "clone-audit")]
+    pub fn clone_audit_report(&self) -> CloneAuditReport {
+        self.env.clone_audit_report()
+    }
+
+    /// Starts the clone audit over, counting copies of values of at least
+    /// `threshold` bytes.
+    #[cfg(feature = "clone-audit")]
+    pub fn reset_clone_audit(&mut self, threshold: usize) {
+        self.env.reset_clone_audit(threshold)
+    }
+
+    /// Adds `fun` as a builtin called `name`, replacing any binding of that
+    /// name. Register before evaluating anything that uses it. A closure
+    /// can keep state, like output collected for the application.
+    pub fn register_builtin(
+        &mut self,
+        name: &str,
+        fun: impl Fn(&mut Lenv, Vec<Lval>) -> Result<Lval, Lerr> + 'static,
+    ) {
+        crate::add_native(&mut self.env, name, fun)
+    }
+
+    /// Names bound here starting with `prefix`, for an editor to offer.
+    #[cfg(feature = "parser")]
+    pub fn complete(&self, prefix: &str) -> Vec<CompletionItem> {
+        completion::complete(&self.env, prefix)
+    }
+
+    /// The parameters and doc comment of the function called `name`.
+    #[cfg(feature = "parser")]
+    pub fn signature_help(&self, name: &str) -> Option<Signature> {
+        completion::signature_help(&self.env, name)
+    }
+
+    /// The file and bytes of the form that last defined `name`, if it came
+    /// from a file.
+    #[cfg(feature = "parser")]
+    pub fn definition_span(&self, name: &str) -> Option<(String, Span)> {
+        completion::definition_span(&self.env, name)
+    }
+
+    /// Gives lisp code access to an application specific command through
+    /// `(host "name" args...)`. An `Err` from the handler surfaces as an
+    /// `Interrupt` error carrying its message. No handlers are registered
+    /// by default.
+    pub fn register_host_handler(
+        &mut self,
+        name: &str,
+        handler: impl Fn(Vec<Lval>) -> Result<Lval, String> + 'static,
+    ) {
+        self.env.register_host_handler(name, handler)
+    }
+
+    /// Evaluates every top level form in `source` in order. With
+    /// `ErrorPolicy::KeepGoing` a failing form, whether it failed to parse
+    /// or to evaluate, is recorded and the rest still run.
+    #[cfg(feature = "parser")]
+    pub fn eval_program(&mut self, source: &str, policy: ErrorPolicy) -> BatchReport {
+        self.eval_source(source, None, policy)
+    }
+
+    /// Like `eval_program` for the contents of a file, recording the file
+    /// and line of each global definition for `source-of`.
+    #[cfg(feature = "parser")]
+    pub fn eval_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        policy: ErrorPolicy,
+    ) -> Result<BatchReport, Lerr> {
+        self.eval_file_each(path, policy, |_, _| {})
+    }
+
+    /// Like `eval_file`, handing `on_form` where each form was in the file
+    /// and what it came to as soon as it has been evaluated, for reporting
+    /// progress as it is made.
+    #[cfg(feature = "parser")]
+    pub fn eval_file_each(
+        &mut self,
+        path: impl AsRef<Path>,
+        policy: ErrorPolicy,
+        mut on_form: impl FnMut(Span, &Result<Lval, Lerr>),
+    ) -> Result<BatchReport, Lerr> {
+        self.env.reset_stats();
+        eval_file_in(&mut self.env, path.as_ref(), policy, &mut on_form).map(|(_, report)| report)
+    }
+
+    #[cfg(feature = "parser")]
+    fn eval_source(
+        &mut self,
+        source: &str,
+        path: Option<&str>,
+        policy: ErrorPolicy,
+    ) -> BatchReport {
+        self.env.reset_stats();
+        eval_forms(&mut self.env, source, path, policy, &mut |_, _| {})
+    }
+}
+
+// the file's contents along with the report, for saying where a form failed
+#[cfg(feature = "parser")]
+fn eval_file_in(
+    env: &mut Lenv,
+    path: &Path,
+    policy: ErrorPolicy,
+    on_form: &mut dyn FnMut(Span, &Result<Lval, Lerr>),
+) -> Result<(String, BatchReport), Lerr> {
+    let source = fs::read_to_string(path).map_err(|e| {
+        Lerr::new(
+            LerrType::Io,
+            format!("could not read {}; {}", path.display(), e),
+        )
+    })?;
+
+    let previous = env.set_origin(Origin::Unknown);
+    let report = eval_forms(
+        env,
+        &source,
+        Some(&path.display().to_string()),
+        policy,
+        on_form,
+    );
+    env.set_origin(previous);
+    Ok((source, report))
+}
+
+#[cfg(feature = "parser")]
+fn eval_forms(
+    env: &mut Lenv,
+    source: &str,
+    path: Option<&str>,
+    policy: ErrorPolicy,
+    on_form: &mut dyn FnMut(Span, &Result<Lval, Lerr>),
+) -> BatchReport {
+    let limits = ParseLimits::for_env(env);
+    let mut forms = Incremental::with_limits(limits.clone());
+    forms.push_str(source);
+    forms.finish();
+    let forms = std::iter::from_fn(|| forms.next_spanned_form())
+        .map(|(span, form)| (span, form.map_err(Lerr::from)));
+    eval_each(env, source, path, policy, on_form, Some(&limits), forms)
+}
+
+// evaluates forms read from `source`, with `path` the file it came from if
+// any. With `limits` each form's spans are read back from the source so
+// errors can say where in it they happened
+#[cfg(feature = "parser")]
+fn eval_each(
+    env: &mut Lenv,
+    source: &str,
+    path: Option<&str>,
+    policy: ErrorPolicy,
+    on_form: &mut dyn FnMut(Span, &Result<Lval, Lerr>),
+    limits: Option<&ParseLimits>,
+    forms: impl Iterator<Item = (Span, Result<Lval, Lerr>)>,
+) -> BatchReport {
+    let mut report = BatchReport {
+        evaluated: 0,
+        failures: vec![],
+        last: None,
+    };
+
+    for (span, form) in forms {
+        let index = report.evaluated;
+        report.evaluated += 1;
+        if let Some(path) = path {
+            env.set_origin(Origin::File {
+                path: path.to_string(),
+                line: line_of(source, span),
+                span,
+                doc: doc_above(source, span),
+            });
+        }
+
+        let result = form.and_then(|form| {
+            let spans = limits.and_then(|limits| {
+                span_tree(&source[span.start..span.end], limits)
+                    .children
+                    .pop()
+                    .map(|spans| spans.shifted(span.start))
+            });
+            match spans {
+                Some(spans) => eval::eval_spanned(env, form, spans),
+                None => eval::eval(env, form),
+            }
+        });
+        on_form(span, &result);
+
+        match result {
+            Ok(lval) => report.last = Some(lval),
+            Err(error) => {
+                report.failures.push(FormFailure { index, span, error });
+                if policy == ErrorPolicy::StopAtFirst {
+                    break;
+                }
+            }
+        }
+    }
+
+    report
+}
+
+#[cfg(feature = "parser")]
+fn line_of(source: &str, span: Span) -> usize {
+    source[..span.start].matches('\n').count() + 1
+}
+
+// the run of `;` comment lines ending on the line before `span` starts
+#[cfg(feature = "parser")]
+fn doc_above(source: &str, span: Span) -> Option<String> {
+    let before = &source[..span.start];
+    let start_of_line = before.rfind('\n').map_or(0, |i| i + 1);
+    if !before[start_of_line..].trim().is_empty() {
+        return None;
+    }
+
+    let mut lines: Vec<&str> = before[..start_of_line]
+        .lines()
+        .rev()
+        .map(str::trim)
+        .take_while(|line| line.starts_with(';'))
+        .map(|line| line.trim_start_matches(';').trim())
+        .collect();
+    lines.reverse();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+/// Evaluates every top level form of the file at `path` in `env`, stopping
+/// at the first that fails, and gives the value of the last. Errors say
+/// which line of the file the failing form started on, since their spans
+/// would point into the file rather than the code that loaded it.
+#[cfg(feature = "parser")]
+pub fn load(env: &mut Lenv, path: &Path) -> Result<Lval, Lerr> {
+    env.record_load(path);
+    let (source, report) = load_forms(env, path)?;
+    match report.failures.into_iter().next() {
+        Some(FormFailure { span, error, .. }) => Err(Lerr {
+            message: format!(
+                "{}:{}: {}",
+                path.display(),
+                line_of(&source, error.span().unwrap_or(span)),
+                error.message
+            ),
+            span: None,
+            ..error
+        }),
+        None => Ok(report.last.unwrap_or(Lval::Nil)),
+    }
+}
+
+#[cfg(all(feature = "parser", not(feature = "cache")))]
+fn load_forms(env: &mut Lenv, path: &Path) -> Result<(String, BatchReport), Lerr> {
+    eval_file_in(env, path, ErrorPolicy::StopAtFirst, &mut |_, _| {})
+}
+
+// the forms come from the parse cache, which only parses the file if it
+// changed. Without spans inside the forms errors are put on the line their
+// form starts on. Files the cache can't give, because they don't parse or
+// can't be read, and environments reading special floats, which the cache
+// doesn't, load as without it
+#[cfg(feature = "cache")]
+fn load_forms(env: &mut Lenv, path: &Path) -> Result<(String, BatchReport), Lerr> {
+    let cached = match env.parse_cache() {
+        Some(cache) if !env.special_floats() => cache.borrow_mut().load_spanned(path).ok(),
+        _ => None,
+    };
+    let (source, forms) = match cached {
+        Some(cached) => cached,
+        None => return eval_file_in(env, path, ErrorPolicy::StopAtFirst, &mut |_, _| {}),
+    };
+
+    let previous = env.set_origin(Origin::Unknown);
+    let forms = forms.into_iter().map(|(span, form)| (span, Ok(form)));
+    let report = eval_each(
+        env,
+        &source,
+        Some(&path.display().to_string()),
+        ErrorPolicy::StopAtFirst,
+        &mut |_, _| {},
+        None,
+        forms,
+    );
+    env.set_origin(previous);
+    Ok((source, report))
+}
+
+/// An interpreter set up once, for any number of others to start from with
+/// `Interpreter::from_prototype`. Nothing they do changes it.
+pub struct Prototype {
+    env: Lenv,
+}
+
+impl Prototype {
+    pub fn env(&self) -> &Lenv {
+        &self.env
+    }
+}
+
+/// Reads all of `source` as one expression, the way a REPL line is read,
+/// so `+ 1 2` is a call, and evaluates it in `env`. Input that doesn't
+/// parse is a `Syntax` error saying where.
+#[cfg(feature = "parser")]
+pub fn eval_line(env: &mut Lenv, source: &str) -> Result<Lval, Lerr> {
+    let limits = ParseLimits::for_env(env);
+    let ast = parse_located_with(source, &limits)?;
+    eval::eval_spanned(env, ast, span_tree(source, &limits))
+}
+
+/// What `Interpreter::eval_program` does when a form fails.
+#[cfg(feature = "parser")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorPolicy {
+    StopAtFirst,
+    KeepGoing,
+}
+
+/// A top level form that failed, by its position in the program.
+#[cfg(feature = "parser")]
+#[derive(Debug)]
+pub struct FormFailure {
+    pub index: usize,
+    pub span: Span,
+    pub error: Lerr,
+}
+
+/// The outcome of evaluating a whole program.
+#[cfg(feature = "parser")]
+#[derive(Debug)]
+pub struct BatchReport {
+    /// how many forms were attempted
+    pub evaluated: usize,
+    pub failures: Vec<FormFailure>,
+    /// value of the last form that succeeded
+    pub last: Option<Lval>,
+}
+
+#[cfg(feature = "parser")]
+impl BatchReport {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[cfg(feature = "parser")]
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} form(s) evaluated, {} failed",
+            self.evaluated,
+            self.failures.len()
+        )?;
+        for failure in &self.failures {
+            write!(
+                f,
+                "\n  form {} (bytes {}..{}",
+                failure.index + 1,
+                failure.span.start,
+                failure.span.end,
+            )?;
+            match failure.error.span() {
+                Some(at) if at != failure.span => write!(
+                    f,
+                    ", failing at {}..{}): {:?}",
+                    at.start, at.end, failure.error
+                )?,
+                _ => write!(f, "): {:?}", failure.error)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "parser"))]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "(def [a] 1)
+(head [])
+(def [b] (+ a 1))
+(+ a \"two\")
+(def [c] (* b 10))";
+
+    #[test]
+    fn it_keeps_going_past_failures() {
+        let mut interpreter = Interpreter::new();
+        let report = interpreter.eval_program(PROGRAM, ErrorPolicy::KeepGoing);
+
+        assert!(!report.is_ok());
+        assert_eq!(report.evaluated, 5);
+        assert_eq!(
+            report
+                .failures
+                .iter()
+                .map(|failure| (failure.index, failure.span, failure.error.etype.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, Span { start: 12, end: 21 }, LerrType::EmptyList),
+                (3, Span { start: 40, end: 51 }, LerrType::BadNum),
+            ]
+        );
+
+        // the last form still ran
+        assert_eq!(interpreter.env().get("c"), Some(Lval::Num(20_f64)));
+        assert_eq!(report.last, Some(Lval::Nil));
+        assert!(report
+            .to_string()
+            .starts_with("5 form(s) evaluated, 2 failed\n  form 2 (bytes 12..21)"));
+    }
+
+    #[test]
+    fn it_says_where_in_a_form_an_error_happened() {
+        let source = "(def [f] (\\ [x] [head x]))\n(+ 1 (* 2 (head 5)))\n(+ 1 (f 5))\n(if 1 [- 2 (tail [])] [])";
+        let mut interpreter = Interpreter::new();
+        let report = interpreter.eval_program(source, ErrorPolicy::KeepGoing);
+
+        let failed_at = report
+            .failures
+            .iter()
+            .map(|failure| {
+                let span = failure.error.span().unwrap();
+                (failure.error.etype.clone(), &source[span.start..span.end])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            failed_at,
+            vec![
+                (LerrType::WrongType, "(head 5)"),
+                // nothing in the lambda's body has a place, so its call does
+                (LerrType::WrongType, "(f 5)"),
+                (LerrType::EmptyList, "(tail [])"),
+            ]
+        );
+        assert!(report
+            .to_string()
+            .contains("form 2 (bytes 27..47, failing at 37..45)"));
+        assert_eq!(interpreter.env().site_depth(), 0);
+
+        // evaluated by hand, without spans, there's no position
+        let error = interpreter
+            .eval(crate::parser::parse("(head 5)").unwrap().1)
+            .unwrap_err();
+        assert_eq!(error.span(), None);
+    }
+
+    #[test]
+    fn it_stops_at_the_first_failure() {
+        let mut interpreter = Interpreter::new();
+        let report = interpreter.eval_program(PROGRAM, ErrorPolicy::StopAtFirst);
+
+        assert_eq!(report.evaluated, 2);
+        assert_eq!(report.failures.len(), 1);
+        assert!(interpreter.env().get("a").is_some());
+        assert!(interpreter.env().get("b").is_none());
+    }
+
+    #[test]
+    fn it_reports_syntax_errors_per_form() {
+        let mut interpreter = Interpreter::new();
+        let report =
+            interpreter.eval_program("(def [x] 1) (+ 1 2] (def [y] 2)", ErrorPolicy::KeepGoing);
+
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].error.etype, LerrType::Syntax);
+        assert_eq!(interpreter.env().get("y"), Some(Lval::Num(2_f64)));
+    }
+
+    #[test]
+    fn it_starts_interpreters_from_a_prototype() {
+        let mut setup = Interpreter::new();
+        assert!(setup
+            .eval_program("(def [greeting] \"hi\")", ErrorPolicy::StopAtFirst)
+            .is_ok());
+        let prototype = setup.into_prototype();
+
+        let mut first = Interpreter::from_prototype(&prototype);
+        let mut second = Interpreter::from_prototype(&prototype);
+        let program = "(def [mine] 1) (def [greeting] \"bye\") (undef! [head])";
+        assert!(first
+            .eval_program(program, ErrorPolicy::StopAtFirst)
+            .is_ok());
+        assert!(second
+            .eval_program("(def [theirs] 2)", ErrorPolicy::StopAtFirst)
+            .is_ok());
+
+        assert_eq!(first.env().get("mine"), Some(Lval::Num(1_f64)));
+        assert_eq!(first.env().get("theirs"), None);
+        assert_eq!(first.env().get("greeting"), Some(Lval::Str("bye".into())));
+        assert_eq!(first.env().get("head"), None);
+        assert_eq!(second.env().get("mine"), None);
+        assert_eq!(second.env().get("theirs"), Some(Lval::Num(2_f64)));
+        assert_eq!(second.env().get("greeting"), Some(Lval::Str("hi".into())));
+        assert!(second.env().get("head").is_some());
+
+        // the prototype saw none of it
+        let env = prototype.env();
+        assert_eq!(env.get("mine"), None);
+        assert_eq!(env.get("theirs"), None);
+        assert_eq!(env.get("greeting"), Some(Lval::Str("hi".into())));
+        assert!(env.get("head").is_some());
+        assert_eq!(env.bindings(), Interpreter::new().env().bindings() + 1);
+
+        // listings keep builtins first, new names after
+        let symbols = second.env().symbols();
+        assert_eq!(symbols.last().map(String::as_str), Some("theirs"));
+        assert_eq!(symbols[..symbols.len() - 1], prototype.env().symbols()[..]);
+    }
+
+    #[test]
+    fn it_starts_from_a_prototype_without_copying() {
+        let prototype = Interpreter::prototype();
+        let before = counting::allocations();
+        let interpreter = Interpreter::from_prototype(&prototype);
+        let allocated = counting::allocations() - before;
+
+        // the global frame and the environment's state
+        assert!(allocated <= 2, "{} allocations", allocated);
+        assert!(interpreter.env().get("head").is_some());
+    }
+
+    #[test]
+    fn it_calls_lambdas_without_copying_globals() {
+        let call = Lval::Sexpr(vec![
+            Lval::Sym("add".into()),
+            Lval::Num(1_f64),
+            Lval::Num(2_f64),
+        ]);
+        let allocations = |globals: usize| {
+            let mut interpreter = Interpreter::new();
+            for i in 0..globals {
+                let env = interpreter.env_mut();
+                env.insert_last(&format!("g{}", i), Lval::Num(i as f64))
+                    .unwrap();
+            }
+            assert!(eval_str(&mut interpreter, "(def [add] (\\ [a b] [+ a b]))").is_ok());
+            let before = counting::allocations();
+            assert_eq!(interpreter.eval(call.clone()), Ok(Lval::Num(3_f64)));
+            counting::allocations() - before
+        };
+
+        // the call costs the same however much is defined
+        assert_eq!(allocations(10_000), allocations(0));
+    }
+
+    #[test]
+    fn it_runs_for_each_without_collecting_results() {
+        let mut interpreter = Interpreter::new();
+        let items = (0..10_000).map(|i| Lval::Num(i as f64)).collect();
+        interpreter
+            .env_mut()
+            .insert("items", Lval::Qexpr(items))
+            .unwrap();
+        let mut peak = |source: &str| {
+            let call = crate::parser::parse(source).unwrap().1;
+            counting::peak_during(|| assert!(interpreter.eval(call).is_ok()))
+        };
+
+        // looking the list up copies it either way
+        let lookup = peak("len items");
+        let map = peak("map (\\ [x] [list x]) items") - lookup;
+        let for_each = peak("for-each (\\ [x] [list x]) items") - lookup;
+        assert!(for_each < 16 * 1024, "{} bytes", for_each);
+        assert!(map > 100 * for_each, "{} bytes against {}", map, for_each);
+    }
+
+    #[test]
+    fn it_evaluates_with_bindings_from_rust() {
+        let mut interpreter = Interpreter::new();
+        let bindings = [("x", Lval::Num(2_f64)), ("y", Lval::Num(3_f64))];
+        assert_eq!(
+            interpreter.eval_with_bindings("* x y", &bindings),
+            Ok(Lval::Num(6_f64))
+        );
+        assert!(interpreter.eval_with_bindings("x z", &bindings).is_err());
+        assert_eq!(interpreter.env().get("x"), None);
+        assert_eq!(interpreter.env().depth(), 1);
+    }
+
+    #[test]
+    fn it_loads_files_into_the_current_environment() {
+        let path = std::env::temp_dir().join(format!("oolisp-load-{}.lsp", std::process::id()));
+        fs::write(&path, "(def [sq] (\\ [x] [* x x]))\n\n(sq 4)").unwrap();
+        let mut interpreter = Interpreter::new();
+        let load = format!("(load \"{}\")", path.display());
+        assert_eq!(eval_str(&mut interpreter, &load), Ok(Lval::Num(16_f64)));
+        assert_eq!(eval_str(&mut interpreter, "(sq 3)"), Ok(Lval::Num(9_f64)));
+
+        fs::write(&path, "(def [a] 1)\n(head [])\n(def [b] 2)").unwrap();
+        let err = eval_str(&mut interpreter, &load).unwrap_err();
+        let _ = fs::remove_file(&path);
+        assert_eq!(err.etype, LerrType::EmptyList);
+        assert!(err.message.starts_with(&format!("{}:2: ", path.display())));
+        // and it points at the load rather than into the file
+        assert_eq!(
+            err.span(),
+            Some(Span {
+                start: 0,
+                end: load.len()
+            })
+        );
+        // forms before the failure still ran
+        assert!(interpreter.env().get("a").is_some());
+        assert!(interpreter.env().get("b").is_none());
+
+        let etype = |interpreter: &mut Interpreter, source: &str| {
+            eval_str(interpreter, source).unwrap_err().etype
+        };
+        assert_eq!(etype(&mut interpreter, &load), LerrType::Io);
+        assert_eq!(etype(&mut interpreter, "(load 1)"), LerrType::WrongType);
+    }
+
+    #[test]
+    #[cfg(feature = "cache")]
+    fn it_loads_files_through_the_parse_cache() {
+        let dir = std::env::temp_dir().join(format!("oolisp-load-cache-{}", std::process::id()));
+        let path = dir.join("lib.lsp");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "(def [a] 1)\n\n(def [b]\n  (+ a 1))").unwrap();
+        let mut interpreter = Interpreter::new();
+        let cache = crate::cache::ParseCache::new(dir.join("cache"));
+        interpreter.env_mut().set_parse_cache(cache);
+        let parses = |interpreter: &mut Interpreter| {
+            interpreter
+                .env_mut()
+                .parse_cache()
+                .unwrap()
+                .borrow()
+                .parses()
+        };
+
+        let load = format!("(load \"{}\")", path.display());
+        assert_eq!(eval_str(&mut interpreter, &load), Ok(Lval::Nil));
+        assert_eq!(parses(&mut interpreter), 1);
+        assert_eq!(eval_str(&mut interpreter, &load), Ok(Lval::Nil));
+        assert_eq!(parses(&mut interpreter), 1);
+        // where things were defined is still known, once for each load
+        let origin = Lval::Str(format!("{}:3", path.display()));
+        assert_eq!(
+            eval_str(&mut interpreter, "(source-of [b])").unwrap(),
+            Lval::Qexpr(vec![origin.clone(), origin])
+        );
+
+        // an edit is parsed again, and errors still say the line
+        fs::write(&path, "(def [c] 3)\n(head [])").unwrap();
+        let err = eval_str(&mut interpreter, &load).unwrap_err();
+        assert_eq!(parses(&mut interpreter), 2);
+        assert!(err.message.starts_with(&format!("{}:2: ", path.display())));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn it_records_where_files_define_things() {
+        let path = std::env::temp_dir().join(format!("oolisp-origin-{}.lsp", std::process::id()));
+        fs::write(&path, "(def [a] 1)\n\n(def [b]\n  (+ a 1))").unwrap();
+
+        let mut interpreter = Interpreter::new();
+        assert!(interpreter
+            .eval_file(&path, ErrorPolicy::StopAtFirst)
+            .unwrap()
+            .is_ok());
+        let _ = fs::remove_file(&path);
+
+        let origins = interpreter.env().origins("b").unwrap();
+        assert_eq!(
+            origins,
+            vec![Origin::File {
+                path: path.display().to_string(),
+                line: 3,
+                span: Span { start: 13, end: 32 },
+                doc: None,
+            }]
+        );
+        assert_eq!(
+            eval_str(&mut interpreter, "(source-of [a])").map(|origins| format!("{:?}", origins)),
+            Ok(format!("[\"{}:1\"]", path.display()))
+        );
+        assert_eq!(
+            eval_str(&mut interpreter, "(source-of [head])")
+                .map(|origins| format!("{:?}", origins)),
+            Ok("[\"builtin\"]".to_string())
+        );
+
+        // definitions made afterwards aren't credited to the file
+        eval_str(&mut interpreter, "(def [c] 3)").unwrap();
+        assert_eq!(interpreter.env().origins("c"), Some(vec![]));
+        assert_eq!(
+            interpreter
+                .eval_file(path.with_extension("missing"), ErrorPolicy::StopAtFirst)
+                .unwrap_err()
+                .etype,
+            LerrType::Io
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn it_pops_every_scope_it_pushes() {
+        let mut interpreter = Interpreter::new();
+        eval_str(
+            &mut interpreter,
+            "(def [count] (\\ [n] [if (== n 0) [0] [+ 1 (count (- n 1))]]))",
+        )
+        .unwrap();
+
+        let cases = vec![
+            ("(+ 1 (* 2 3))", true, 1),
+            ("((\\ [a b] [+ a b]) 1 2)", true, 2),
+            ("((\\ [a] [head a]) [])", false, 2),
+            ("(count 50)", true, 2 + 50),
+        ];
+        for (source, ok, max_scope_depth) in cases {
+            assert_eq!(eval_str(&mut interpreter, source).is_ok(), ok, "{}", source);
+
+            let stats = interpreter.last_eval_stats();
+            assert_eq!(stats.frames_pushed, stats.frames_popped, "{}", source);
+            assert_eq!(stats.max_scope_depth, max_scope_depth, "{}", source);
+            assert_eq!(interpreter.env().depth(), 1);
+        }
+    }
+
+    #[test]
+    fn it_counts_what_it_does() {
+        let mut interpreter = Interpreter::new();
+        eval_str(&mut interpreter, "(def [add] (\\ [a b] [+ a b]))").unwrap();
+        interpreter.env_mut().reset_counters();
+
+        eval_str(&mut interpreter, "(+ 1 (* 2 3))").unwrap();
+        eval_str(&mut interpreter, "(add 1 2)").unwrap();
+        eval_str(&mut interpreter, "(head [])").unwrap_err();
+        assert_eq!(
+            interpreter.stats(),
+            Counters {
+                expressions: 7 + 8 + 3,
+                builtin_calls: 4,
+                lambda_calls: 1,
+                errors: vec![(LerrType::EmptyList, 1)],
+                peak_depth: 2,
+            }
+        );
+
+        // asking counts too, through the thunk and the builtin behind it
+        assert_eq!(
+            format!("{:?}", eval_str(&mut interpreter, "(stats)").unwrap()),
+            "[[:expressions 23] [:builtin-calls 5] [:lambda-calls 2] [:errors [[:EmptyList 1]]] [:peak-depth 2]]"
+        );
+
+        // an error is counted once however many evaluations it ends
+        eval_str(&mut interpreter, "(stats-reset)").unwrap();
+        eval_str(&mut interpreter, "(add 1 (eval [head []]))").unwrap_err();
+        let stats = interpreter.stats();
+        assert_eq!(stats.errors, vec![(LerrType::EmptyList, 1)]);
+        assert_eq!(stats.lambda_calls, 0);
+        // the reset ran inside the thunk's own scope
+        assert_eq!(stats.peak_depth, 2);
+    }
+
+    #[test]
+    #[cfg(feature = "clone-audit")]
+    fn it_audits_large_clones() {
+        use crate::clone_audit::DEFAULT_CLONE_THRESHOLD;
+
+        let numbers = |n: usize| (0..n).map(|i| i.to_string()).collect::<Vec<_>>().join(" ");
+        let mut interpreter = Interpreter::new();
+        eval_str(
+            &mut interpreter,
+            &format!("(def [big] [{}])", numbers(1000)),
+        )
+        .unwrap();
+        interpreter.reset_clone_audit(DEFAULT_CLONE_THRESHOLD);
+
+        // naming a big global copies all of it, until values are shared
+        eval_str(&mut interpreter, "(+ 1 (len big))").unwrap();
+        let report = interpreter.clone_audit_report();
+        assert_eq!(report.large_clones, 1);
+        assert_eq!(report.clones[0].hook, "eval_symbol");
+        assert_eq!(report.clones[0].span, Some(Span { start: 10, end: 13 }));
+
+        // arguments are moved into calls rather than copied
+        interpreter.reset_clone_audit(DEFAULT_CLONE_THRESHOLD);
+        eval_str(
+            &mut interpreter,
+            "(def [count] (\\ [n acc] [if (== n 0) [acc] [count (- n 1) (+ acc 1)]]))",
+        )
+        .unwrap();
+        assert_eq!(
+            eval_str(&mut interpreter, "(count 50 0)").unwrap(),
+            Lval::Num(50_f64)
+        );
+        let pipeline = format!("(len (tail (join [{}] [{}])))", numbers(500), numbers(500));
+        assert_eq!(
+            eval_str(&mut interpreter, &pipeline).unwrap(),
+            Lval::Num(999_f64)
+        );
+        assert!(interpreter.clone_audit_report().is_clean());
+    }
+
+    fn eval_str(interpreter: &mut Interpreter, source: &str) -> Result<Lval, Lerr> {
+        let report = interpreter.eval_program(source, ErrorPolicy::StopAtFirst);
+        match report.failures.into_iter().next() {
+            Some(failure) => Err(failure.error),
+            None => Ok(report.last.unwrap()),
+        }
+    }
+
+    #[test]
+    fn it_evaluates_lines_with_extra_builtins() {
+        fn double(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+            match operands.as_slice() {
+                [Lval::Int(n)] => Ok(Lval::Int(n * 2)),
+                _ => Err(Lerr::new(LerrType::BadNum, "double needs a number".into())),
+            }
+        }
+
+        let mut interpreter = Interpreter::new();
+        interpreter.register_builtin("double", double);
+        assert_eq!(interpreter.eval_str("+ 1 2"), Ok(Lval::Num(3_f64)));
+        assert_eq!(
+            interpreter.eval_str("(double (+ 20 1))"),
+            Ok(Lval::Num(42_f64))
+        );
+
+        let err = interpreter.eval_str("(+ 1").unwrap_err();
+        assert_eq!(err.etype, LerrType::Syntax);
+        assert!(format!("{:?}", err).starts_with("Error: Syntax - Could not parse the input;"));
+    }
+
+    #[test]
+    fn it_registers_closures_as_builtins() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let output = Rc::new(RefCell::new(vec![]));
+        let mut interpreter = Interpreter::new();
+        let lines = output.clone();
+        interpreter.register_builtin("emit", move |_, operands| {
+            lines
+                .borrow_mut()
+                .extend(operands.iter().map(|operand| operand.to_string()));
+            Ok(Lval::Sexpr(vec![]))
+        });
+        interpreter.register_builtin("explode", |_, _| panic!("kaboom"));
+
+        eval_str(
+            &mut interpreter,
+            "(emit 1 \"two\")\n(def [say] emit)\n(say [3])",
+        )
+        .unwrap();
+        assert_eq!(*output.borrow(), vec!["1", "\"two\"", "[3]"]);
+        assert_eq!(
+            eval_str(&mut interpreter, "(== emit say)"),
+            Ok(Lval::Bool(true))
+        );
+        assert_eq!(
+            eval_str(&mut interpreter, "(explode 1)")
+                .unwrap_err()
+                .message,
+            "builtin explode panicked: kaboom"
+        );
+    }
+
+    #[test]
+    fn it_calls_host_handlers() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_host_handler("count", |args| Ok(Lval::Num(args.len() as f64)));
+        interpreter.register_host_handler("lookup", |args| match args.as_slice() {
+            [Lval::Keyword(key)] if key == "answer" => Ok(Lval::Num(42_f64)),
+            [Lval::Qexpr(keys)] => Ok(Lval::Num(keys.len() as f64)),
+            _ => Err(format!("lookup can't handle {:?}", args)),
+        });
+
+        assert_eq!(
+            eval_str(&mut interpreter, "(host \"count\")").unwrap(),
+            Lval::Num(0_f64)
+        );
+        assert_eq!(
+            eval_str(&mut interpreter, "(host \"count\" 1 \"two\" [3] (+ 2 2))").unwrap(),
+            Lval::Num(4_f64)
+        );
+        assert_eq!(
+            eval_str(&mut interpreter, "(host \"lookup\" :answer)").unwrap(),
+            Lval::Num(42_f64)
+        );
+        assert_eq!(
+            eval_str(&mut interpreter, "(host \"lookup\" [:a :b])").unwrap(),
+            Lval::Num(2_f64)
+        );
+
+        let err = eval_str(&mut interpreter, "(host \"lookup\" 1 2)").unwrap_err();
+        assert_eq!(err.etype, LerrType::Interrupt);
+        assert_eq!(err.message, "lookup can't handle [1, 2]");
+    }
+
+    #[test]
+    fn it_reports_missing_host_handlers() {
+        let mut interpreter = Interpreter::new();
+        let err = eval_str(&mut interpreter, "(host \"db\" 1)").unwrap_err();
+        assert_eq!(err.etype, LerrType::UnboundSymbol);
+        assert!(err.message.ends_with("registered handlers: none"));
+
+        interpreter.register_host_handler("http", |_| Ok(Lval::Sexpr(vec![])));
+        interpreter.register_host_handler("clock", |_| Ok(Lval::Sexpr(vec![])));
+        let err = eval_str(&mut interpreter, "(host \"db\" 1)").unwrap_err();
+        assert!(err
+            .message
+            .ends_with("no handler named \"db\"; registered handlers: clock, http"));
+
+        let err = eval_str(&mut interpreter, "(host db)").unwrap_err();
+        assert_eq!(err.etype, LerrType::UnboundSymbol);
+        let err = eval_str(&mut interpreter, "(host 1)").unwrap_err();
+        assert_eq!(err.etype, LerrType::WrongType);
+    }
+
+    // counts this thread's allocations and the bytes they hold, so tests
+    // running alongside don't throw the numbers off. With `alloc-stats` the
+    // crate's own allocator is installed and does the counting.
+    #[cfg(feature = "alloc-stats")]
+    mod counting {
+        use crate::alloc;
+
+        pub use alloc::allocations;
+
+        pub fn peak_during(f: impl FnOnce()) -> isize {
+            alloc::reset();
+            f();
+            alloc::peak_bytes() as isize
+        }
+    }
+
+    #[cfg(not(feature = "alloc-stats"))]
+    mod counting {
+        use std::{
+            alloc::{GlobalAlloc, Layout, System},
+            cell::Cell,
+        };
+
+        struct Counting;
+
+        thread_local! {
+            static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+            static LIVE: Cell<isize> = const { Cell::new(0) };
+            static PEAK: Cell<isize> = const { Cell::new(0) };
+        }
+
+        unsafe impl GlobalAlloc for Counting {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                ALLOCATIONS.with(|count| count.set(count.get() + 1));
+                let live = LIVE.with(|live| {
+                    live.set(live.get() + layout.size() as isize);
+                    live.get()
+                });
+                PEAK.with(|peak| peak.set(peak.get().max(live)));
+                System.alloc(layout)
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                LIVE.with(|live| live.set(live.get() - layout.size() as isize));
+                System.dealloc(ptr, layout)
+            }
+        }
+
+        #[global_allocator]
+        static COUNTING: Counting = Counting;
+
+        pub fn allocations() -> usize {
+            ALLOCATIONS.with(Cell::get)
+        }
+
+        // the most bytes held at once during `f`, beyond what was held
+        // before it
+        pub fn peak_during(f: impl FnOnce()) -> isize {
+            let before = LIVE.with(Cell::get);
+            PEAK.with(|peak| peak.set(before));
+            f();
+            PEAK.with(Cell::get) - before
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Results as JavaScript values, for `lisp_eval_js`, with the `parser` and
+//! `wasm` features. A result is `{ ok: true, value, truncated, length }` or
+//! `{ ok: false, etype, message }`, either with `stats` when asked for.
+//! `value` is cut to the budget as `pretty::render` cuts the text, each list
+//! to `max_items` items and each string or bytes to `max_chars`, and
+//! `truncated` says whether the text was. `length` is how long the value is
+//! in full, `null` for anything but a list, string or bytes. Numbers are
+//! numbers, whole ones past
+//! what a JavaScript number holds exactly being `BigInt`s, strings are
+//! strings, booleans are booleans, `nil` is `null`, lists are arrays and
+//! bytes a `Uint8Array`. Symbols and keywords are strings of how they read,
+//! `x` and `:key`, and functions a string describing them.
+use crate::{
+    env::Counters,
+    pretty::{self, Budget},
+    Lerr, Lval,
+};
+use wasm_bindgen::JsValue;
+
+// the largest whole number a JavaScript number holds exactly
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// A value as it crosses to JavaScript. Built apart from any `JsValue` so it
+/// can be checked without a JavaScript engine.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Js {
+    Null,
+    Bool(bool),
+    Number(f64),
+    BigInt(i64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Js>),
+    Object(Vec<(&'static str, Js)>),
+}
+
+pub(crate) fn from_result(
+    result: &Result<Lval, Lerr>,
+    budget: &Budget,
+    stats: Option<&Counters>,
+) -> Js {
+    let mut fields = match result {
+        Ok(value) => {
+            let rendered = pretty::render(value, budget);
+            vec![
+                ("ok", Js::Bool(true)),
+                ("value", from_lval(value, budget)),
+                ("truncated", Js::Bool(rendered.truncated)),
+                (
+                    "length",
+                    rendered.length.map_or(Js::Null, |n| Js::Number(n as f64)),
+                ),
+            ]
+        }
+        Err(error) => vec![
+            ("ok", Js::Bool(false)),
+            ("etype", Js::Str(format!("{:?}", error.etype()))),
+            ("message", Js::Str(error.message().to_string())),
+        ],
+    };
+    if let Some(counters) = stats {
+        fields.push(("stats", from_counters(counters)));
+    }
+    Js::Object(fields)
+}
+
+// `{ expressions, builtinCalls, lambdaCalls, peakDepth, errors }`, errors
+// being `[etype, count]` pairs
+fn from_counters(counters: &Counters) -> Js {
+    let count = |n: u64| Js::Number(n as f64);
+    let errors = counters
+        .errors
+        .iter()
+        .map(|(etype, n)| Js::Array(vec![Js::Str(format!("{:?}", etype)), count(*n)]))
+        .collect();
+    Js::Object(vec![
+        ("expressions", count(counters.expressions)),
+        ("builtinCalls", count(counters.builtin_calls)),
+        ("lambdaCalls", count(counters.lambda_calls)),
+        ("peakDepth", count(counters.peak_depth)),
+        ("errors", Js::Array(errors)),
+    ])
+}
+
+fn from_lval(lval: &Lval, budget: &Budget) -> Js {
+    match lval {
+        Lval::Nil => Js::Null,
+        Lval::Bool(b) => Js::Bool(*b),
+        Lval::Num(n) => Js::Number(*n),
+        Lval::Int(n) if n.abs() <= MAX_SAFE_INTEGER => Js::Number(*n as f64),
+        Lval::Int(n) => Js::BigInt(*n),
+        Lval::Str(s) => Js::Str(s.chars().take(budget.max_chars).collect()),
+        Lval::Bytes(bytes) => Js::Bytes(bytes.iter().take(budget.max_chars).copied().collect()),
+        Lval::Sexpr(items) | Lval::Qexpr(items) => Js::Array(
+            items
+                .iter()
+                .take(budget.max_items)
+                .map(|item| from_lval(item, budget))
+                .collect(),
+        ),
+        Lval::Fun(_) | Lval::Native(_) => Js::Str("<builtin>".to_string()),
+        Lval::Sym(_) | Lval::Keyword(_) | Lval::Lambda(_) | Lval::CaseLambda(_) => {
+            Js::Str(lval.to_string())
+        }
+    }
+}
+
+impl Js {
+    pub(crate) fn into_js_value(self) -> JsValue {
+        match self {
+            Js::Null => JsValue::NULL,
+            Js::Bool(b) => JsValue::from_bool(b),
+            Js::Number(n) => JsValue::from_f64(n),
+            Js::BigInt(n) => js_sys::BigInt::from(n).into(),
+            Js::Str(s) => JsValue::from_str(&s),
+            Js::Bytes(bytes) => js_sys::Uint8Array::from(bytes.as_slice()).into(),
+            Js::Array(items) => items
+                .into_iter()
+                .map(Js::into_js_value)
+                .collect::<js_sys::Array>()
+                .into(),
+            Js::Object(fields) => {
+                let object = js_sys::Object::new();
+                for (key, value) in fields {
+                    // setting a property on a fresh plain object can't fail
+                    let _ = js_sys::Reflect::set(&object, &key.into(), &value.into_js_value());
+                }
+                object.into()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{env::init_env, interpreter, LerrType};
+
+    fn shape(input: &str) -> Js {
+        let result = interpreter::eval_line(&mut init_env(), input);
+        from_result(&result, &Budget::default(), None)
+    }
+
+    fn ok(value: Js) -> Js {
+        let length = match &value {
+            Js::Array(items) => Js::Number(items.len() as f64),
+            _ => Js::Null,
+        };
+        Js::Object(vec![
+            ("ok", Js::Bool(true)),
+            ("value", value),
+            ("truncated", Js::Bool(false)),
+            ("length", length),
+        ])
+    }
+
+    #[test]
+    fn it_shapes_results_for_javascript() {
+        let text = |s: &str| Js::Str(s.to_string());
+        assert_eq!(shape("+ 2 2"), ok(Js::Number(4.0)));
+        assert_eq!(shape("/ 1 4"), ok(Js::Number(0.25)));
+        assert_eq!(shape("def [x] 1"), ok(Js::Null));
+        assert_eq!(
+            shape("list 1 \"a\" [b :c] head"),
+            ok(Js::Array(vec![
+                Js::Number(1.0),
+                text("a"),
+                Js::Array(vec![text("b"), text(":c")]),
+                text("<builtin>"),
+            ]))
+        );
+        assert_eq!(shape("\\ [x] [x]"), ok(text("(\\ [x] [x])")));
+        assert_eq!(
+            shape("list 9007199254740991 9007199254740992"),
+            ok(Js::Array(vec![
+                Js::Number(9007199254740991.0),
+                Js::BigInt(9007199254740992),
+            ]))
+        );
+
+        let error = Err(Lerr::new(LerrType::UnboundSymbol, "nope".to_string()));
+        assert_eq!(
+            from_result(&error, &Budget::default(), None),
+            Js::Object(vec![
+                ("ok", Js::Bool(false)),
+                ("etype", text("UnboundSymbol")),
+                ("message", text("nope")),
+            ])
+        );
+    }
+
+    #[test]
+    fn it_cuts_big_results_short_and_says_so() {
+        let env = &mut init_env();
+        let budget = Budget {
+            max_items: 2,
+            max_chars: 100,
+        };
+        let numbers = |ns: &[f64]| Js::Array(ns.iter().map(|n| Js::Number(*n)).collect());
+        let result = interpreter::eval_line(env, "list 1 2 3 4 5");
+        assert_eq!(
+            from_result(&result, &budget, None),
+            Js::Object(vec![
+                ("ok", Js::Bool(true)),
+                ("value", numbers(&[1.0, 2.0])),
+                ("truncated", Js::Bool(true)),
+                ("length", Js::Number(5.0)),
+            ])
+        );
+        let result = interpreter::eval_line(env, "string-repeat \"ab\" 100");
+        match from_result(&result, &budget, None) {
+            Js::Object(fields) => {
+                assert_eq!(fields[1], ("value", Js::Str("ab".repeat(50))));
+                assert_eq!(fields[2], ("truncated", Js::Bool(true)));
+                assert_eq!(fields[3], ("length", Js::Number(200.0)));
+            }
+            other => panic!("{:?}", other),
+        }
+
+        env.reset_counters();
+        let result = interpreter::eval_line(env, "(\\ [x] [+ x 1]) 1");
+        let shaped = from_result(&result, &budget, Some(&env.counters()));
+        let stats = match shaped {
+            Js::Object(mut fields) => fields.pop().unwrap(),
+            other => panic!("{:?}", other),
+        };
+        let counters = env.counters();
+        assert_eq!(stats.0, "stats");
+        assert_eq!(
+            stats.1,
+            Js::Object(vec![
+                ("expressions", Js::Number(counters.expressions as f64)),
+                ("builtinCalls", Js::Number(2.0)),
+                ("lambdaCalls", Js::Number(1.0)),
+                ("peakDepth", Js::Number(counters.peak_depth as f64)),
+                ("errors", Js::Array(vec![])),
+            ])
+        );
+    }
+}
@@ -1,122 +1,707 @@
+#[cfg(feature = "alloc-stats")]
+pub mod alloc;
 pub mod builtin;
+#[cfg(feature = "cache")]
+pub mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "clone-audit")]
+pub mod clone_audit;
+#[cfg(feature = "parser")]
+pub mod completion;
+pub mod diff;
 pub mod env;
 pub mod eval;
+#[cfg(feature = "fuzz-api")]
+pub mod fuzz;
+pub mod interpreter;
+#[cfg(all(feature = "parser", feature = "wasm"))]
+mod js;
+#[cfg(feature = "parser")]
+pub mod lint;
+pub mod optimize;
+#[cfg(feature = "parser")]
 pub mod parser;
+#[cfg(feature = "parser")]
+pub mod pretty;
+#[cfg(feature = "parser")]
+pub mod repl;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod visitor;
+#[cfg(feature = "parser")]
+pub mod watch;
 
+#[cfg(feature = "wasm")]
 extern crate wasm_bindgen;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-use crate::env::{Lenv, Lookup};
-use std::{error::Error, fmt};
+use crate::env::{Frames, Lenv};
+use std::{
+    cmp::Ordering,
+    error::Error,
+    fmt::{self, Write as _},
+    rc::Rc,
+    slice,
+};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// the single source of truth for which cargo features were compiled in
+const FEATURE_FLAGS: [(&str, bool); 9] = [
+    ("parser", cfg!(feature = "parser")),
+    ("wasm", cfg!(feature = "wasm")),
+    ("cache", cfg!(feature = "cache")),
+    ("capi", cfg!(feature = "capi")),
+    ("clone-audit", cfg!(feature = "clone-audit")),
+    ("alloc-stats", cfg!(feature = "alloc-stats")),
+    ("fuzz-api", cfg!(feature = "fuzz-api")),
+    ("cli", cfg!(feature = "cli")),
+    ("serde", cfg!(feature = "serde")),
+];
+
+/// Names of the cargo features this build was compiled with.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn features() -> Vec<String> {
+    FEATURE_FLAGS
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// The crate version this build was compiled from.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn version() -> String {
+    VERSION.to_string()
+}
 
 #[derive(Clone)]
 pub enum Lval {
     Sym(String),
     Num(f64),
+    /// a whole number, written without a point or exponent. Arithmetic
+    /// keeps it whole until it meets a `Num`, divides with a remainder or
+    /// overflows
+    Int(i64),
     Sexpr(Vec<Lval>),
     Qexpr(Vec<Lval>),
     Fun(Lfun),
+    /// a builtin made from a closure, see `add_native`
+    Native(NativeFn),
     Lambda(Llambda),
+    /// a `case-lambda`: lambdas called by how many arguments they are given,
+    /// see `eval::call_case`
+    CaseLambda(Vec<Llambda>),
     Str(String),
+    Bytes(Vec<u8>),
+    /// `:name`, stored without the colon. Evaluates to itself.
+    Keyword(String),
+    /// `true` or `false`, what comparisons and `not` give
+    Bool(bool),
+    /// `nil`, what gives no value of its own gives, like `def`, `print`
+    /// and `()`
+    Nil,
 }
 
 impl PartialEq for Lval {
     fn eq(&self, other: &Self) -> bool {
-        match self {
-            Lval::Sym(a) => match other {
-                Lval::Sym(b) => a == b,
-                _ => false,
-            },
-            Lval::Num(a) => match other {
-                Lval::Num(b) => a == b,
-                _ => false,
-            },
-            Lval::Sexpr(a) => match other {
-                Lval::Sexpr(b) => a == b,
-                _ => false,
-            },
-            Lval::Qexpr(a) => match other {
-                Lval::Qexpr(b) => a == b,
-                _ => false,
-            },
-            Lval::Fun(_) => match other {
-                Lval::Fun(_) => true,
-                _ => false,
-            },
-            Lval::Str(_) => match other {
-                Lval::Str(_) => true,
-                _ => false,
-            },
-            Lval::Lambda(a) => match other {
-                Lval::Lambda(b) => a.body == b.body && a.args == b.args,
+        // walk both sides with an explicit stack instead of recursing so
+        // comparing deeply nested lists can't overflow
+        let mut pending = vec![(self, other)];
+
+        while let Some((a, b)) = pending.pop() {
+            // anything is equal to itself, no need to look inside
+            if std::ptr::eq(a, b) {
+                continue;
+            }
+
+            let same = match (a, b) {
+                (Lval::Sym(a), Lval::Sym(b)) => a == b,
+                (Lval::Num(a), Lval::Num(b)) => a == b,
+                (Lval::Int(a), Lval::Int(b)) => a == b,
+                (Lval::Int(i), Lval::Num(n)) | (Lval::Num(n), Lval::Int(i)) => {
+                    cmp_int_float(*i, *n) == Some(Ordering::Equal)
+                }
+                (Lval::Sexpr(a), Lval::Sexpr(b)) | (Lval::Qexpr(a), Lval::Qexpr(b)) => {
+                    // bail before touching any elements if lengths differ
+                    a.len() == b.len() && {
+                        pending.extend(a.iter().zip(b.iter()).rev());
+                        true
+                    }
+                }
+                (Lval::Fun(a), Lval::Fun(b)) => std::ptr::fn_addr_eq(*a, *b),
+                (Lval::Native(a), Lval::Native(b)) => Rc::ptr_eq(a, b),
+                (Lval::Str(a), Lval::Str(b)) => a == b,
+                (Lval::Bytes(a), Lval::Bytes(b)) => a == b,
+                (Lval::Keyword(a), Lval::Keyword(b)) => a == b,
+                (Lval::Bool(a), Lval::Bool(b)) => a == b,
+                (Lval::Nil, Lval::Nil) => true,
+                (Lval::Lambda(a), Lval::Lambda(b)) => {
+                    a.args == b.args && a.body.len() == b.body.len() && {
+                        pending.extend(a.body.iter().zip(b.body.iter()).rev());
+                        true
+                    }
+                }
+                (Lval::CaseLambda(a), Lval::CaseLambda(b)) => {
+                    a.len() == b.len()
+                        && a.iter()
+                            .zip(b.iter())
+                            .all(|(a, b)| a.args == b.args && a.body.len() == b.body.len())
+                        && {
+                            for (a, b) in a.iter().zip(b.iter()).rev() {
+                                pending.extend(a.body.iter().zip(b.body.iter()).rev());
+                            }
+                            true
+                        }
+                }
                 _ => false,
-            },
+            };
+
+            if !same {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl Lval {
+    /// Whether `if` and friends take this as true: anything but `false`,
+    /// `nil`, `0`, `[]` and `()`.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Lval::Bool(false) | Lval::Nil)
+            && !matches!(self, Lval::Num(n) if *n == 0_f64)
+            && !matches!(self, Lval::Int(0))
+            && !matches!(self, Lval::Qexpr(items) | Lval::Sexpr(items) if items.is_empty())
+    }
+
+    /// Orders any two values that aren't functions: `nil`, numbers, booleans,
+    /// symbols, keywords, strings, bytes, s-expressions and q-expressions.
+    /// Numbers go by value, whole or not, with NaN after everything, even
+    /// negative NaN, `false` before `true`, text and bytes
+    /// lexicographically, and lists element by element with a prefix
+    /// first. Functions can't be ordered and are an error.
+    pub fn cmp_total(&self, other: &Self) -> Result<Ordering, Lerr> {
+        // lists being compared, with the elements not yet looked at
+        let mut pending: Vec<(slice::Iter<Lval>, slice::Iter<Lval>)> = vec![];
+        let mut next = Some((self, other));
+
+        loop {
+            if let Some((a, b)) = next.take() {
+                let ordering = match (a, b) {
+                    (Lval::Sexpr(a), Lval::Sexpr(b)) | (Lval::Qexpr(a), Lval::Qexpr(b)) => {
+                        pending.push((a.iter(), b.iter()));
+                        Ordering::Equal
+                    }
+                    _ => cmp_leaves(a, b)?,
+                };
+                if ordering != Ordering::Equal {
+                    return Ok(ordering);
+                }
+            }
+
+            match pending.last_mut() {
+                None => return Ok(Ordering::Equal),
+                Some((a, b)) => match (a.next(), b.next()) {
+                    (Some(a), Some(b)) => next = Some((a, b)),
+                    (None, None) => {
+                        pending.pop();
+                    }
+                    (None, Some(_)) => return Ok(Ordering::Less),
+                    (Some(_), None) => return Ok(Ordering::Greater),
+                },
+            }
         }
     }
 }
 
+// compares values when at least one isn't a list
+fn cmp_leaves(a: &Lval, b: &Lval) -> Result<Ordering, Lerr> {
+    let rank = |lval: &Lval| match lval {
+        Lval::Nil => Ok(0),
+        Lval::Num(_) | Lval::Int(_) => Ok(1),
+        Lval::Bool(_) => Ok(2),
+        Lval::Sym(_) => Ok(3),
+        Lval::Keyword(_) => Ok(4),
+        Lval::Str(_) => Ok(5),
+        Lval::Bytes(_) => Ok(6),
+        Lval::Sexpr(_) => Ok(7),
+        Lval::Qexpr(_) => Ok(8),
+        Lval::Fun(_) | Lval::Native(_) | Lval::Lambda(_) | Lval::CaseLambda(_) => Err(Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "functions have no order but {} was compared",
+                lval.summary()
+            ),
+        )),
+    };
+
+    Ok(match (a, b) {
+        // NaN goes last whatever its sign, which `total_cmp` ignores
+        (Lval::Num(a), Lval::Num(b)) => match (a.is_nan(), b.is_nan()) {
+            (false, false) => a.total_cmp(b),
+            (a_nan, b_nan) => a_nan.cmp(&b_nan),
+        },
+        (Lval::Int(a), Lval::Int(b)) => a.cmp(b),
+        (Lval::Int(a), Lval::Num(b)) => cmp_int_float(*a, *b).unwrap_or(Ordering::Less),
+        (Lval::Num(a), Lval::Int(b)) => cmp_int_float(*b, *a)
+            .map(Ordering::reverse)
+            .unwrap_or(Ordering::Greater),
+        (Lval::Sym(a), Lval::Sym(b))
+        | (Lval::Keyword(a), Lval::Keyword(b))
+        | (Lval::Str(a), Lval::Str(b)) => a.cmp(b),
+        (Lval::Bytes(a), Lval::Bytes(b)) => a.cmp(b),
+        (Lval::Bool(a), Lval::Bool(b)) => a.cmp(b),
+        _ => rank(a)?.cmp(&rank(b)?),
+    })
+}
+
+/// How two numbers compare, whole or not, `None` when either is NaN or
+/// isn't a number.
+pub fn cmp_numbers(a: &Lval, b: &Lval) -> Option<Ordering> {
+    match (a, b) {
+        (Lval::Int(a), Lval::Int(b)) => Some(a.cmp(b)),
+        (Lval::Num(a), Lval::Num(b)) => a.partial_cmp(b),
+        (Lval::Int(a), Lval::Num(b)) => cmp_int_float(*a, *b),
+        (Lval::Num(a), Lval::Int(b)) => cmp_int_float(*b, *a).map(Ordering::reverse),
+        _ => None,
+    }
+}
+
+// `i` against `n` exactly, which casting either way can't do: not every
+// i64 is a float and not every float fits in an i64
+fn cmp_int_float(i: i64, n: f64) -> Option<Ordering> {
+    // 2^63, the first float past i64::MAX
+    const BOUND: f64 = 9_223_372_036_854_775_808.0;
+    if n.is_nan() {
+        None
+    } else if n >= BOUND {
+        Some(Ordering::Less)
+    } else if n < -BOUND {
+        Some(Ordering::Greater)
+    } else {
+        let whole = n.trunc();
+        Some(
+            i.cmp(&(whole as i64))
+                .then_with(|| 0.0.partial_cmp(&(n - whole)).unwrap_or(Ordering::Equal)),
+        )
+    }
+}
+
+/// The kinds of value the sequence builtins accept. Strings are sequences
+/// of one character strings and bytes sequences of numbers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeqKind {
+    Qexpr,
+    Str,
+    Bytes,
+}
+
+impl fmt::Display for SeqKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SeqKind::Qexpr => write!(f, "Qexpr"),
+            SeqKind::Str => write!(f, "String"),
+            SeqKind::Bytes => write!(f, "Bytes"),
+        }
+    }
+}
+
+pub fn seq_kind(lval: &Lval) -> Option<SeqKind> {
+    match lval {
+        Lval::Qexpr(_) => Some(SeqKind::Qexpr),
+        Lval::Str(_) => Some(SeqKind::Str),
+        Lval::Bytes(_) => Some(SeqKind::Bytes),
+        _ => None,
+    }
+}
+
+pub fn seq_len(lval: &Lval) -> Option<usize> {
+    match lval {
+        Lval::Qexpr(items) => Some(items.len()),
+        Lval::Str(s) => Some(s.chars().count()),
+        Lval::Bytes(bytes) => Some(bytes.len()),
+        _ => None,
+    }
+}
+
+/// The items of a sequence, `None` for anything else.
+pub fn seq_iter(lval: &Lval) -> Option<Box<dyn Iterator<Item = Lval> + '_>> {
+    match lval {
+        Lval::Qexpr(items) => Some(Box::new(items.iter().cloned())),
+        Lval::Str(s) => Some(Box::new(s.chars().map(|c| Lval::Str(c.to_string())))),
+        Lval::Bytes(bytes) => Some(Box::new(bytes.iter().map(|b| Lval::Int(i64::from(*b))))),
+        _ => None,
+    }
+}
+
+/// A sequence of `kind` made of `items`. Strings are joined, so items
+/// needn't be single characters, and bytes must be whole numbers from 0 to
+/// 255.
+pub fn seq_build(kind: SeqKind, items: Vec<Lval>) -> Result<Lval, Lerr> {
+    match kind {
+        SeqKind::Qexpr => Ok(Lval::Qexpr(items)),
+        SeqKind::Str => items
+            .into_iter()
+            .map(|item| match item {
+                Lval::Str(s) => Ok(s),
+                other => Err(Lerr::new(
+                    LerrType::WrongType,
+                    format!(
+                        "a String can only be built from Strings, not {}",
+                        other.summary()
+                    ),
+                )),
+            })
+            .collect::<Result<String, Lerr>>()
+            .map(Lval::Str),
+        SeqKind::Bytes => items
+            .into_iter()
+            .map(|item| match item {
+                Lval::Num(n) if n.fract() == 0.0 && (0.0..=255.0).contains(&n) => Ok(n as u8),
+                Lval::Int(n) if (0..=255).contains(&n) => Ok(n as u8),
+                other => Err(Lerr::new(
+                    LerrType::BadNum,
+                    format!(
+                        "Bytes can only be built from 0 to 255, not {}",
+                        other.summary()
+                    ),
+                )),
+            })
+            .collect::<Result<Vec<u8>, Lerr>>()
+            .map(Lval::Bytes),
+    }
+}
+
+/// Whether `seq_build` can put `item` in a sequence of `kind`.
+pub fn seq_holds(kind: SeqKind, item: &Lval) -> bool {
+    match (kind, item) {
+        (SeqKind::Qexpr, _) | (SeqKind::Str, Lval::Str(_)) => true,
+        (SeqKind::Bytes, Lval::Int(n)) => (0..=255).contains(n),
+        (SeqKind::Bytes, Lval::Num(n)) => n.fract() == 0.0 && (0.0..=255.0).contains(n),
+        _ => false,
+    }
+}
+
+/// The one error for a builtin given something that isn't a sequence.
+pub fn not_a_seq(sym: &str, lval: &Lval) -> Lerr {
+    Lerr::new(
+        LerrType::WrongType,
+        format!(
+            "Function {} needed a Qexpr, String or Bytes but was given {}",
+            sym,
+            lval.summary()
+        ),
+    )
+}
+
+// how many bytes of an `Lval::Bytes` `Debug` prints
+const BYTES_SHOWN: usize = 32;
+
+/// How many lists deep `Debug` goes before writing `…` for whatever is
+/// further in. A precision overrides it: `{:.2?}` stops two lists down.
+pub const DEBUG_DEPTH: usize = 256;
+
+/// How many characters of a value `Lval::summary` keeps.
+pub const SUMMARY_CHARS: usize = 120;
+
 impl fmt::Debug for Lval {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self {
-            Lval::Sym(s) => write!(f, "{}", s),
-            Lval::Num(n) => write!(f, "{}", n),
-            Lval::Sexpr(s) => write!(
-                f,
-                "({})",
-                s.into_iter()
-                    .map(|x| match x {
-                        Lval::Sym(sym) => format!("{}", sym),
-                        _ => format!("{:?}", x),
-                    })
-                    .collect::<Vec<String>>()
-                    .join(" ")
-            ),
-            Lval::Qexpr(q) => write!(
-                f,
-                "[{}]",
-                q.into_iter()
-                    .map(|x| match x {
-                        Lval::Sym(sym) => format!("{}", sym),
-                        _ => format!("{:?}", x),
-                    })
-                    .collect::<Vec<String>>()
-                    .join(" ")
-            ),
-            Lval::Fun(_) => write!(f, "builtin"),
-            Lval::Str(s) => write!(f, "\"{}\"", s),
-            Lval::Lambda(l) => write!(
-                f,
-                "(\\ [{}] [{}])",
-                l.args.join(" "),
-                l.body
-                    .to_owned()
-                    .into_iter()
-                    .map(|x| match x {
-                        Lval::Sym(sym) => format!("{}", sym),
-                        _ => format!("{:?}", x),
-                    })
+        let depth = f.precision().unwrap_or(DEBUG_DEPTH);
+        write_lval(f, self, false, Some(depth))
+    }
+}
+
+/// Writes the value the way it would be typed in, so what a literal
+/// evaluates to reads back the same. Unlike `Debug`, bytes are written in
+/// full, nothing is cut off however deep it is, and builtins show as
+/// `<builtin>`, which can't be read back.
+impl fmt::Display for Lval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_lval(f, self, true, None)
+    }
+}
+
+impl Lval {
+    /// `Debug` cut short at `SUMMARY_CHARS` characters, ending in `…` when
+    /// it was, for putting values in error messages. It stops writing once
+    /// it has enough, so summing up a huge list costs no more than a small
+    /// one.
+    pub fn summary(&self) -> String {
+        let mut summary = Bounded {
+            text: String::new(),
+            left: SUMMARY_CHARS,
+        };
+        if fmt::write(&mut summary, format_args!("{:?}", self)).is_err() {
+            summary.text.push('…');
+        }
+        summary.text
+    }
+}
+
+// takes characters until it has had enough, then refuses the rest
+struct Bounded {
+    text: String,
+    left: usize,
+}
+
+impl fmt::Write for Bounded {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.left == 0 {
+                return Err(fmt::Error);
+            }
+            self.text.push(c);
+            self.left -= 1;
+        }
+        Ok(())
+    }
+}
+
+// what is left to write, the next piece last
+enum Piece<'a> {
+    Value(&'a Lval, usize),
+    Text(&'static str),
+    // a lambda's parameters, as `[a b] `
+    Params(&'a [String]),
+}
+
+// `whole` for `Display`, `Debug` otherwise. Lists deeper than `max_depth`
+// are written as `…`. Works through an explicit stack rather than
+// recursing, so values nested any depth can be written
+fn write_lval(
+    f: &mut fmt::Formatter<'_>,
+    lval: &Lval,
+    whole: bool,
+    max_depth: Option<usize>,
+) -> fmt::Result {
+    let mut pending = vec![Piece::Value(lval, 0)];
+
+    while let Some(piece) = pending.pop() {
+        let (lval, depth) = match piece {
+            Piece::Text(text) => {
+                f.write_str(text)?;
+                continue;
+            }
+            Piece::Params(args) => {
+                write!(f, "[{}] ", args.join(" "))?;
+                continue;
+            }
+            Piece::Value(lval, depth) => (lval, depth),
+        };
+
+        let nested = matches!(
+            lval,
+            Lval::Sexpr(_) | Lval::Qexpr(_) | Lval::Lambda(_) | Lval::CaseLambda(_)
+        );
+        if nested && max_depth.is_some_and(|max| depth >= max) {
+            f.write_str("…")?;
+            continue;
+        }
+
+        match lval {
+            Lval::Sym(s) => write!(f, "{}", s)?,
+            Lval::Num(n) if n.is_nan() => write!(f, "nan")?,
+            Lval::Num(n) if n.is_infinite() => {
+                write!(f, "{}inf", if *n > 0.0 { "+" } else { "-" })?
+            }
+            // `Debug` keeps the point on whole floats, so `3.0` reads back
+            // as a `Num` and `3` as an `Int`
+            Lval::Num(n) => write!(f, "{:?}", n)?,
+            Lval::Int(n) => write!(f, "{}", n)?,
+            Lval::Sexpr(items) => match builtin::as_values(lval) {
+                // written as the call that makes them
+                Some(values) => {
+                    pending.push(Piece::Text(")"));
+                    for value in values.iter().rev() {
+                        pending.push(Piece::Value(value, depth + 1));
+                        pending.push(Piece::Text(" "));
+                    }
+                    pending.push(Piece::Text("(values"));
+                }
+                None => push_items(&mut pending, "(", items, ")", depth),
+            },
+            Lval::Qexpr(items) => push_items(&mut pending, "[", items, "]", depth),
+            Lval::Fun(_) | Lval::Native(_) if whole => write!(f, "<builtin>")?,
+            Lval::Fun(_) | Lval::Native(_) => write!(f, "builtin")?,
+            Lval::Str(s) => write_string(f, s)?,
+            Lval::Keyword(k) => write!(f, ":{}", k)?,
+            Lval::Bool(b) => write!(f, "{}", b)?,
+            Lval::Nil => write!(f, "nil")?,
+            Lval::Bytes(bytes) => {
+                // pairs of bytes in hex, cut short for big buffers
+                let shown = if whole {
+                    &bytes[..]
+                } else {
+                    &bytes[..bytes.len().min(BYTES_SHOWN)]
+                };
+                let hex = shown
+                    .chunks(2)
+                    .map(|pair| pair.iter().map(|b| format!("{:02x}", b)).collect())
                     .collect::<Vec<String>>()
-                    .join(" ")
-            ),
+                    .join(" ");
+                let more = if shown.len() < bytes.len() { "…" } else { "" };
+                write!(f, "#bytes[\"{}{}\"]", hex, more)?
+            }
+            Lval::Lambda(l) => {
+                pending.push(Piece::Text(")"));
+                push_items(&mut pending, "[", &l.body, "]", depth);
+                pending.push(Piece::Params(&l.args));
+                pending.push(Piece::Text("(\\ "));
+            }
+            Lval::CaseLambda(clauses) => {
+                pending.push(Piece::Text(")"));
+                for l in clauses.iter().rev() {
+                    pending.push(Piece::Text("]"));
+                    push_items(&mut pending, "[", &l.body, "]", depth);
+                    pending.push(Piece::Params(&l.args));
+                    pending.push(Piece::Text(" ["));
+                }
+                pending.push(Piece::Text("(case-lambda"));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// `s` quoted as the reader takes it back, with quotes, backslashes, newlines
+// and tabs escaped
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+// queues `items` between `open` and `close`, a level below `depth`
+fn push_items<'a>(
+    pending: &mut Vec<Piece<'a>>,
+    open: &'static str,
+    items: &'a [Lval],
+    close: &'static str,
+    depth: usize,
+) {
+    pending.push(Piece::Text(close));
+    for (i, item) in items.iter().enumerate().rev() {
+        pending.push(Piece::Value(item, depth + 1));
+        if i > 0 {
+            pending.push(Piece::Text(" "));
         }
     }
+    pending.push(Piece::Text(open));
 }
 
 #[derive(Clone)]
 pub struct Llambda {
     args: Vec<String>,
     body: Vec<Lval>,
-    env: Lenv,
+    // where it was made, and the arguments it has been given so far
+    env: Frames,
+    // checks on the values given for some of the args
+    contracts: Vec<(String, Contract)>,
+}
+
+/// A check a value has to pass: any one argument function, passed when it
+/// returns `true` or a nonzero number.
+#[derive(Clone, Debug)]
+pub struct Contract {
+    /// the predicate as it was written, for messages
+    pub label: String,
+    pub predicate: Lval,
 }
 
 impl Llambda {
-    fn new(args: Vec<String>, body: Vec<Lval>, lookup: Lookup) -> Self {
-        let mut lenv = Lenv::new();
-        lenv.push(lookup);
+    fn new(args: Vec<String>, body: Vec<Lval>, env: Frames) -> Self {
         Llambda {
             args,
             body,
-            env: lenv,
+            env,
+            contracts: vec![],
+        }
+    }
+
+    /// The contracts arguments are checked against on every call, by
+    /// parameter name.
+    pub fn contracts(&self) -> &[(String, Contract)] {
+        &self.contracts
+    }
+
+    fn contract(&self, arg: &str) -> Option<&Contract> {
+        self.contracts
+            .iter()
+            .find(|(name, _)| name == arg)
+            .map(|(_, contract)| contract)
+    }
+
+    /// Parameters still to be bound, so a partially applied lambda only
+    /// reports what is left. A `:` marks the parameter that collects the
+    /// rest of the arguments.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn body(&self) -> &[Lval] {
+        &self.body
+    }
+
+    /// The least and most arguments a call can take, no maximum for
+    /// variadic lambdas.
+    pub fn arity(&self) -> Arity {
+        match self.args.iter().position(|arg| arg == ":") {
+            Some(rest) => (rest, None),
+            None => (self.args.len(), Some(self.args.len())),
+        }
+    }
+
+    // `2`, or `2 or more` when variadic, for messages
+    fn arity_label(&self) -> String {
+        match self.arity() {
+            (min, Some(_)) => min.to_string(),
+            (min, None) => format!("{} or more", min),
+        }
+    }
+}
+
+/// A byte range in the source text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Where a parsed expression came from, and where each of its items did if
+/// it is a list, mirroring the `Lval` it was read as.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpanTree {
+    pub span: Span,
+    pub children: Vec<Rc<SpanTree>>,
+}
+
+impl SpanTree {
+    /// The same tree with every span moved `by` bytes along.
+    pub fn shifted(&self, by: usize) -> SpanTree {
+        SpanTree {
+            span: Span {
+                start: self.span.start + by,
+                end: self.span.end + by,
+            },
+            children: self
+                .children
+                .iter()
+                .map(|child| Rc::new(child.shifted(by)))
+                .collect(),
         }
     }
 }
@@ -126,8 +711,17 @@ pub struct Lerr {
     etype: LerrType,
     details: String,
     message: String,
+    // the innermost call with a known position that the error came out of
+    span: Option<Span>,
+    // the calls it came up through, innermost first, and how many more
+    // there were past `TRACE_FRAMES`
+    trace: Vec<String>,
+    untraced: usize,
 }
 
+/// How many of the calls an error comes up through it keeps.
+pub const TRACE_FRAMES: usize = 64;
+
 impl Lerr {
     fn new(etype: LerrType, message: String) -> Lerr {
         let msg = match &etype {
@@ -139,29 +733,97 @@ impl Lerr {
             LerrType::EmptyList => "Empty List passed to function",
             LerrType::UnboundSymbol => "This Symbol has not been Defined",
             LerrType::Interrupt => "User defined Error",
+            LerrType::Syntax => "Could not parse the input",
+            LerrType::Cancelled => "Evaluation was cancelled",
+            LerrType::Io => "Could not read the file",
+            LerrType::EnvLimit => "Too many bindings in the environment",
+            LerrType::Timeout => "Evaluation ran out of time",
+            LerrType::ContractViolation => "A value broke its contract",
+            LerrType::Internal => "A builtin failed unexpectedly",
+            LerrType::TooDeep => "Evaluation nested too deeply",
+            LerrType::SizeLimit => "A value would be too large",
+            LerrType::ReservedSymbol => "This Symbol belongs to a builtin",
+            LerrType::Budget => "Evaluation ran out of fuel",
         };
 
         Lerr {
             details: msg.to_string(),
             message,
             etype,
+            span: None,
+            trace: vec![],
+            untraced: 0,
+        }
+    }
+
+    /// The calls the error came up through, innermost first, each as the
+    /// name it was called by, or the function itself if it had none. Only
+    /// the innermost `TRACE_FRAMES` are kept.
+    pub fn trace(&self) -> &[String] {
+        &self.trace
+    }
+
+    // records that the error came out of a call to `frame`
+    fn called_from(mut self, frame: impl FnOnce() -> String) -> Lerr {
+        if self.trace.len() < TRACE_FRAMES {
+            self.trace.push(frame());
+        } else {
+            self.untraced += 1;
         }
+        self
+    }
+
+    /// Where in the source the error happened, when what was being
+    /// evaluated had been read with `eval::eval_spanned`: the innermost call
+    /// or symbol that failed, or the call a failing lambda was applied by.
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    pub fn etype(&self) -> &LerrType {
+        &self.etype
+    }
+
+    /// What went wrong in particular, where `Display` gives only the kind
+    /// of error.
+    pub fn message(&self) -> &str {
+        &self.message
     }
 }
 
+/// One line, or with `{:#?}` followed by the calls the error came up
+/// through, as `Display` lists them.
 impl fmt::Debug for Lerr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
             "Error: {:?} - {}; {}",
             self.etype, self.details, self.message
-        )
+        )?;
+        if f.alternate() {
+            self.write_trace(f)?;
+        }
+        Ok(())
     }
 }
 
+/// The message, then a line for each call the error came up through.
 impl fmt::Display for Lerr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.details)
+        write!(f, "{}", self.message)?;
+        self.write_trace(f)
+    }
+}
+
+impl Lerr {
+    fn write_trace(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for frame in &self.trace {
+            write!(f, "\n  called from {}", frame)?;
+        }
+        if self.untraced > 0 {
+            write!(f, "\n  … and {} more", self.untraced)?;
+        }
+        Ok(())
     }
 }
 
@@ -172,6 +834,7 @@ impl Error for Lerr {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LerrType {
     DivZero,
     BadOp,
@@ -181,19 +844,74 @@ pub enum LerrType {
     WrongType,
     UnboundSymbol,
     Interrupt,
+    Syntax,
+    Cancelled,
+    Io,
+    EnvLimit,
+    Timeout,
+    ContractViolation,
+    Internal,
+    TooDeep,
+    SizeLimit,
+    ReservedSymbol,
+    Budget,
 }
 
 pub type Lfun = fn(&mut Lenv, Vec<Lval>) -> Result<Lval, Lerr>;
 
-pub fn add_builtin(env: &mut Lenv, sym: &str, fun: Lfun) {
-    env.insert(sym, Lval::Fun(fun));
+/// The least and most arguments a function takes, no maximum meaning any
+/// number.
+pub type Arity = (usize, Option<usize>);
+
+/// A builtin that can capture state, unlike an `Lfun`.
+pub type NativeFn = Rc<dyn Fn(&mut Lenv, Vec<Lval>) -> Result<Lval, Lerr>>;
+
+/// Binds a builtin in the innermost frame and reserves its name, so only
+/// `def!` can define it over again. `arity` is what `fn-arity`, completion
+/// and the arity lint say it takes; `(0, None)` claims nothing.
+pub fn add_builtin(env: &mut Lenv, sym: &str, fun: Lfun, arity: Arity) {
+    add_value(env, sym, Lval::Fun(fun));
+    env.set_arity(fun, arity);
+    env.reserve(sym);
+}
+
+/// Like `add_builtin` for a closure, which can hold on to state of its own.
+/// It counts as impure, and can't stand behind a special form like `if`.
+pub fn add_native(
+    env: &mut Lenv,
+    sym: &str,
+    fun: impl Fn(&mut Lenv, Vec<Lval>) -> Result<Lval, Lerr> + 'static,
+) {
+    add_value(env, sym, Lval::Native(Rc::new(fun)));
+    env.reserve(sym);
+}
+
+/// Like `add_builtin`, also marking `fun` as pure: the same arguments always
+/// give the same result, with no other effect.
+pub fn add_pure_builtin(env: &mut Lenv, sym: &str, fun: Lfun, arity: Arity) {
+    add_builtin(env, sym, fun, arity);
+    env.mark_pure(fun);
+}
+
+/// Binds a builtin value in the innermost frame. Like `add_builtin` this
+/// isn't held to the environment's binding cap.
+pub fn add_value(env: &mut Lenv, sym: &str, lval: Lval) {
+    if let Some(mut lookup) = env.peek_mut() {
+        lookup.insert(sym.to_owned(), lval);
+        lookup.record_origin(sym, env::Origin::Builtin);
+    }
+}
+
+// what predicates and comparisons give back
+fn truth(b: bool) -> Lval {
+    Lval::Bool(b)
 }
 
 fn to_num(expr: Lval) -> Option<f64> {
-    if let Lval::Num(n) = expr {
-        Some(n)
-    } else {
-        None
+    match expr {
+        Lval::Num(n) => Some(n),
+        Lval::Int(n) => Some(n as f64),
+        _ => None,
     }
 }
 
@@ -213,6 +931,14 @@ fn to_str(expr: Lval) -> Option<String> {
     }
 }
 
+fn to_bytes(expr: Lval) -> Option<Vec<u8>> {
+    if let Lval::Bytes(b) = expr {
+        Some(b)
+    } else {
+        None
+    }
+}
+
 fn to_qexpr(expr: Lval) -> Option<Vec<Lval>> {
     if let Lval::Qexpr(s) = expr {
         Some(s.clone())
@@ -230,18 +956,418 @@ fn to_lambda(expr: &Lval) -> Option<Llambda> {
     }
 }
 
-#[wasm_bindgen]
+#[cfg(feature = "parser")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn lisp(env: &mut Lenv, input: &str) -> String {
     if "env" == input {
-        return format!("{:#?}", env.peek().unwrap());
+        return env
+            .visible_symbols()
+            .into_iter()
+            .map(|(name, _)| format!("{}: {:?}", name, env.get(&name).unwrap()))
+            .collect::<Vec<_>>()
+            .join("\n");
     }
 
-    let ast = parser::parse(input);
-    match ast {
-        Ok(tree) => match eval::eval(env, tree.1) {
-            Ok(r) => format!("{:?}", r),
-            Err(r) => format!("{:?}", r),
-        },
-        Err(e) => format!("Error: Parsing Error - Could not parse the input; {}", e),
+    match interpreter::eval_line(env, input) {
+        Ok(r) => format!("{}", r),
+        Err(r) => format!("{:#?}", r),
+    }
+}
+
+/// Like `lisp`, except a result with more than `max_items` items in a list
+/// or `max_chars` characters overall is cut short, so a huge value never
+/// has to cross to JavaScript whole. The value is kept for `render_last`.
+#[cfg(feature = "parser")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn lisp_within(env: &mut Lenv, input: &str, max_items: usize, max_chars: usize) -> String {
+    let budget = pretty::Budget {
+        max_items,
+        max_chars,
+    };
+    match interpreter::eval_line(env, input) {
+        Ok(r) => {
+            let rendered = pretty::render(&r, &budget);
+            env.set_last_result(r);
+            rendered.text
+        }
+        Err(r) => format!("{:#?}", r),
+    }
+}
+
+/// Like `lisp`, except the result comes back as a JavaScript object instead
+/// of text: `{ ok: true, value, truncated, length }`, with numbers, strings
+/// and lists as their JavaScript counterparts, or
+/// `{ ok: false, etype, message }`. A value is cut short as the REPL cuts
+/// what it prints, with `truncated` set and the value kept for
+/// `render_last`.
+#[cfg(all(feature = "parser", feature = "wasm"))]
+#[wasm_bindgen]
+pub fn lisp_eval_js(env: &mut Lenv, input: &str) -> JsValue {
+    eval_js(env, input, pretty::Budget::default(), false)
+}
+
+/// Like `lisp_eval_js` within the limits `lisp_within` takes, and with
+/// `stats` what the evaluation took, as `stats` gives it, in a `stats`
+/// field. The counters start over for it.
+#[cfg(all(feature = "parser", feature = "wasm"))]
+#[wasm_bindgen]
+pub fn lisp_eval_js_within(
+    env: &mut Lenv,
+    input: &str,
+    max_items: usize,
+    max_chars: usize,
+    stats: bool,
+) -> JsValue {
+    let budget = pretty::Budget {
+        max_items,
+        max_chars,
+    };
+    eval_js(env, input, budget, stats)
+}
+
+#[cfg(all(feature = "parser", feature = "wasm"))]
+fn eval_js(env: &mut Lenv, input: &str, budget: pretty::Budget, stats: bool) -> JsValue {
+    if stats {
+        env.reset_counters();
+    }
+    let result = interpreter::eval_line(env, input);
+    let counters = stats.then(|| env.counters());
+    let shaped = js::from_result(&result, &budget, counters.as_ref());
+    if let Ok(value) = result {
+        env.set_last_result(value);
+    }
+    shaped.into_js_value()
+}
+
+/// The last result `lisp_within` cut short, or any other kept with
+/// `set_last_result`, in full. `nil` if there isn't one.
+#[cfg(feature = "parser")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn render_last(env: &Lenv) -> String {
+    env.last_result()
+        .map_or_else(|| "nil".to_string(), Lval::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nest(depth: usize, leaf: Lval) -> Lval {
+        let mut lval = leaf;
+        for _ in 0..depth {
+            lval = Lval::Qexpr(vec![Lval::Int(1), lval]);
+        }
+        lval
+    }
+
+    // taking a deep structure apart one level at a time, since the
+    // default drop glue recurses
+    fn dismantle(mut lval: Lval) {
+        while let Lval::Qexpr(mut items) = lval {
+            lval = items.pop().unwrap_or(Lval::Sexpr(vec![]));
+        }
+    }
+
+    #[test]
+    fn it_compares_strings_and_builtins_by_what_they_are() {
+        let text = |s: &str| Lval::Str(s.to_string());
+        assert_eq!(text("a"), text("a"));
+        assert_ne!(text("a"), text("b"));
+        assert_ne!(Lval::Qexpr(vec![text("a")]), Lval::Qexpr(vec![text("b")]));
+
+        let env = env::init_env();
+        assert_eq!(env.get("head"), env.get("head"));
+        assert_ne!(env.get("head"), env.get("tail"));
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_displays_values_as_they_read() {
+        let env = &mut env::init_env();
+        let read = |source: &str| parser::parse(source).unwrap().1;
+        let long = format!("#bytes[{}]", "ab".repeat(40));
+
+        for literal in [
+            "[1 2.5 -3 1e300 :key sym \"two words\" [] [[nested (s expr)]]]",
+            long.as_str(),
+            "(\\ [a b] [+ a (* b 2)])",
+        ] {
+            let value = eval::eval(env, read(literal)).unwrap();
+            let shown = value.to_string();
+            let again = eval::eval(env, read(&shown)).unwrap();
+            assert_eq!(again.to_string(), shown, "{}", literal);
+        }
+
+        // whole floats keep their point, so they read back as floats
+        assert_eq!(
+            eval::eval(env, read("[1.0 1 (+ 1 2)]"))
+                .unwrap()
+                .to_string(),
+            "[1.0 1 (+ 1 2)]"
+        );
+        assert_eq!(
+            eval::eval(env, read("head")).unwrap().to_string(),
+            "<builtin>"
+        );
+        assert_eq!(
+            format!("{:?}", eval::eval(env, read("head")).unwrap()),
+            "builtin"
+        );
+        assert_eq!(lisp(env, "list head 1"), "[<builtin> 1]");
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_round_trips_strings_with_escapes() {
+        let env = &mut env::init_env();
+        let read = |source: &str| parser::parse(source).unwrap().1;
+
+        for literal in [
+            r#""say \"hi\"""#,
+            r#""C:\\lisp\\""#,
+            r#""two\nlines\tand a tab""#,
+            r#"[a "\"" "\\" "\\\"" b]"#,
+        ] {
+            let value = eval::eval(env, read(literal)).unwrap();
+            let shown = value.to_string();
+            assert_eq!(shown, literal);
+            assert_eq!(eval::eval(env, read(&shown)).unwrap(), value, "{}", literal);
+        }
+        assert_eq!(Lval::Str(String::from("\"a\"")).to_string(), r#""\"a\"""#);
+    }
+
+    #[test]
+    #[cfg(feature = "parser")]
+    fn it_keeps_truncated_results_to_render_again() {
+        let env = &mut env::init_env();
+        assert_eq!(render_last(env), "nil");
+
+        let program = "def [xs] [1 2 3 4 5 6]";
+        lisp(env, program);
+        assert_eq!(lisp_within(env, "xs", 2, 100), "[1 2 … (4 more)]");
+        assert_eq!(render_last(env), "[1 2 3 4 5 6]");
+        assert_eq!(lisp_within(env, "xs", 10, 4), "[1 2…");
+
+        // errors aren't results, so the last one is still there
+        assert!(lisp_within(env, "(head [])", 2, 100).contains("EmptyList"));
+        assert_eq!(render_last(env), "[1 2 3 4 5 6]");
+
+        // nothing is cut, bytes past what `Debug` shows included
+        let long = format!("#bytes[\"{}\"]", "abcd ".repeat(50).trim_end());
+        lisp_within(env, &long, 2, 10);
+        assert_eq!(render_last(env), long);
+    }
+
+    #[test]
+    fn it_reports_compiled_features() {
+        let features = features();
+        let compiled = [
+            ("parser", cfg!(feature = "parser")),
+            ("wasm", cfg!(feature = "wasm")),
+            ("cache", cfg!(feature = "cache")),
+            ("capi", cfg!(feature = "capi")),
+            ("clone-audit", cfg!(feature = "clone-audit")),
+            ("alloc-stats", cfg!(feature = "alloc-stats")),
+            ("fuzz-api", cfg!(feature = "fuzz-api")),
+            ("cli", cfg!(feature = "cli")),
+            ("serde", cfg!(feature = "serde")),
+        ];
+        for (name, enabled) in compiled {
+            assert_eq!(features.contains(&name.to_string()), enabled, "{}", name);
+        }
+
+        // every feature Cargo.toml declares is listed
+        let manifest = include_str!("../Cargo.toml");
+        let declared = manifest
+            .split("[features]")
+            .nth(1)
+            .unwrap()
+            .lines()
+            .take_while(|line| !line.starts_with('['))
+            .filter_map(|line| line.split_once(" = "))
+            .map(|(name, _)| name.trim())
+            .filter(|&name| name != "default");
+        for name in declared {
+            assert!(
+                FEATURE_FLAGS.iter().any(|(flag, _)| *flag == name),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(version(), env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn it_compares_deeply_nested_lists() {
+        let a = nest(100_000, Lval::Sym(String::from("leaf")));
+        let b = nest(100_000, Lval::Sym(String::from("leaf")));
+        let c = nest(100_000, Lval::Sym(String::from("other")));
+
+        assert!(a == b);
+        assert!(a != c);
+
+        dismantle(a);
+        dismantle(b);
+        dismantle(c);
+    }
+
+    #[test]
+    fn it_cuts_debug_off_past_a_depth() {
+        let lval = nest(3, Lval::Sym(String::from("leaf")));
+        assert_eq!(format!("{:?}", lval), "[1 [1 [1 leaf]]]");
+        assert_eq!(format!("{:.2?}", lval), "[1 [1 …]]");
+        assert_eq!(format!("{:.0?}", lval), "…");
+        // `Display` always writes everything
+        assert_eq!(format!("{:.2}", lval), "[1 [1 [1 leaf]]]");
+
+        let deep = nest(100_000, Lval::Sym(String::from("leaf")));
+        let shown = format!("{:?}", deep);
+        assert!(shown.starts_with("[1 [1 "));
+        assert_eq!(shown.matches('[').count(), DEBUG_DEPTH);
+        let written = deep.to_string();
+        assert_eq!(written.matches('[').count(), 100_000);
+        assert!(written.contains(" [1 leaf]]]"));
+        dismantle(deep);
+    }
+
+    #[test]
+    fn it_bounds_the_summary_of_a_value() {
+        assert_eq!(Lval::Int(1).summary(), "1");
+        let big = Lval::Qexpr(vec![Lval::Int(12); 1_000_000]);
+        let summary = big.summary();
+        assert_eq!(summary.chars().count(), SUMMARY_CHARS + 1);
+        assert!(summary.starts_with("[12 12 ") && summary.ends_with('…'));
+    }
+
+    #[test]
+    fn it_skips_comparing_a_value_against_itself() {
+        // NaN is unequal to everything, so this only holds if the
+        // elements are never visited
+        let a = Lval::Qexpr(vec![Lval::Num(f64::NAN), Lval::Num(1_f64)]);
+        assert!(a == a);
+        assert!(a != a.clone());
+    }
+
+    #[test]
+    fn it_orders_any_values() {
+        let num = |n: f64| Lval::Num(n);
+        let sym = |s: &str| Lval::Sym(s.to_string());
+        let text = |s: &str| Lval::Str(s.to_string());
+        let list = |items: Vec<Lval>| Lval::Qexpr(items);
+        let cases = vec![
+            // across kinds
+            (num(1e300), sym("a"), Ordering::Less),
+            (sym("zzz"), Lval::Keyword("a".to_string()), Ordering::Less),
+            (Lval::Keyword("z".to_string()), text(""), Ordering::Less),
+            (text("zzz"), Lval::Bytes(vec![]), Ordering::Less),
+            (Lval::Bytes(vec![255]), Lval::Sexpr(vec![]), Ordering::Less),
+            (Lval::Sexpr(vec![num(9.0)]), list(vec![]), Ordering::Less),
+            (text(""), list(vec![]), Ordering::Less),
+            // within kinds
+            (num(-0.5), num(2.0), Ordering::Less),
+            (num(1.0), num(f64::NAN), Ordering::Less),
+            (num(f64::INFINITY), num(f64::NAN), Ordering::Less),
+            (sym("abc"), sym("abd"), Ordering::Less),
+            (text("B"), text("a"), Ordering::Less),
+            (text("ab"), text("abc"), Ordering::Less),
+            (text(""), text(""), Ordering::Equal),
+            // lists element by element, a prefix first
+            (
+                list(vec![num(1.0)]),
+                list(vec![num(1.0), num(0.0)]),
+                Ordering::Less,
+            ),
+            (
+                list(vec![num(2.0)]),
+                list(vec![num(1.0), num(5.0)]),
+                Ordering::Greater,
+            ),
+            (
+                list(vec![list(vec![sym("a")]), num(1.0)]),
+                list(vec![list(vec![sym("a")]), num(1.0)]),
+                Ordering::Equal,
+            ),
+            (
+                list(vec![list(vec![]), num(9.0)]),
+                list(vec![list(vec![num(0.0)])]),
+                Ordering::Less,
+            ),
+        ];
+
+        for (a, b, expected) in cases {
+            assert_eq!(a.cmp_total(&b), Ok(expected), "{:?} vs {:?}", a, b);
+            assert_eq!(b.cmp_total(&a), Ok(expected.reverse()));
+        }
+    }
+
+    #[test]
+    fn it_refuses_to_order_functions() {
+        let fun = Lval::Fun(|_, _| Ok(Lval::Sexpr(vec![])));
+        assert!(fun.cmp_total(&Lval::Num(1_f64)).is_err());
+        assert!(Lval::Qexpr(vec![Lval::Num(1_f64), fun.clone()])
+            .cmp_total(&Lval::Qexpr(vec![Lval::Num(1_f64), Lval::Num(2_f64)]))
+            .is_err());
+
+        // nothing is compared after the lists already differ
+        assert_eq!(
+            Lval::Qexpr(vec![Lval::Num(1_f64), fun.clone()])
+                .cmp_total(&Lval::Qexpr(vec![Lval::Num(2_f64), fun])),
+            Ok(Ordering::Less)
+        );
+
+        // deep lists are compared without recursing
+        let (a, b) = (
+            nest(100_000, Lval::Num(1_f64)),
+            nest(100_000, Lval::Num(2_f64)),
+        );
+        assert_eq!(a.cmp_total(&b), Ok(Ordering::Less));
+        dismantle(a);
+        dismantle(b);
+    }
+
+    #[test]
+    fn it_finds_ordinary_inequality() {
+        let a = Lval::Qexpr(vec![Lval::Num(1_f64), Lval::Num(2_f64)]);
+        assert!(a != Lval::Qexpr(vec![Lval::Num(1_f64)]));
+        assert!(a != Lval::Qexpr(vec![Lval::Num(1_f64), Lval::Num(3_f64)]));
+        assert!(a != Lval::Sexpr(vec![Lval::Num(1_f64), Lval::Num(2_f64)]));
+        assert!(Lval::Num(1_f64) != Lval::Sym(String::from("1")));
+    }
+}
+
+#[cfg(all(test, not(feature = "parser")))]
+mod evaluator_only_tests {
+    use super::*;
+    use crate::env::init_env;
+
+    // stands in for a program that was parsed ahead of time and shipped to
+    // an evaluator-only build: (def [sq] (\ [x] [* x x])) (sq 7)
+    fn preparsed_program() -> Vec<Lval> {
+        vec![
+            Lval::Sexpr(vec![
+                Lval::Sym(String::from("def")),
+                Lval::Qexpr(vec![Lval::Sym(String::from("sq"))]),
+                Lval::Sexpr(vec![
+                    Lval::Sym(String::from("\\")),
+                    Lval::Qexpr(vec![Lval::Sym(String::from("x"))]),
+                    Lval::Qexpr(vec![
+                        Lval::Sym(String::from("*")),
+                        Lval::Sym(String::from("x")),
+                        Lval::Sym(String::from("x")),
+                    ]),
+                ]),
+            ]),
+            Lval::Sexpr(vec![Lval::Sym(String::from("sq")), Lval::Num(7_f64)]),
+        ]
+    }
+
+    #[test]
+    fn it_evaluates_without_the_parser() {
+        let env = &mut init_env();
+        let mut result = Lval::Sexpr(vec![]);
+        for expr in preparsed_program() {
+            result = eval::eval(env, expr).unwrap();
+        }
+        assert_eq!(result, Lval::Num(49_f64));
     }
 }
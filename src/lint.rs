@@ -0,0 +1,605 @@
+//! Static checks over a program's source, made without running any of it.
+use crate::{
+    parser::{parse_located_with, span_tree, ParseDiagnostic, ParseLimits},
+    visitor::{walk, ListKind, LvalVisitor},
+    Lenv, Lval, Span, SpanTree,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+/// What a finding is about. Each has a code to report it by and to deny it
+/// with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// a symbol neither the file nor the environment defines, likely a typo
+    UnknownSymbol,
+    /// a definition nothing refers to
+    UnusedDef,
+    /// a parameter hiding the builtin of the same name
+    ShadowedBuiltin,
+    /// a builtin called with the wrong number of arguments
+    Arity,
+    /// a function with nothing in its body
+    EmptyBody,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 5] = [
+        Lint::UnknownSymbol,
+        Lint::UnusedDef,
+        Lint::ShadowedBuiltin,
+        Lint::Arity,
+        Lint::EmptyBody,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Lint::UnknownSymbol => "unknown-symbol",
+            Lint::UnusedDef => "unused-def",
+            Lint::ShadowedBuiltin => "shadowed-builtin",
+            Lint::Arity => "arity",
+            Lint::EmptyBody => "empty-body",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Lint> {
+        Lint::ALL.iter().copied().find(|lint| lint.code() == code)
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+/// Something `lint` found, at a 1-based line and column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub lint: Lint,
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Finding {
+    /// `path:line:column: code: message`, the way compilers report.
+    pub fn located(&self, path: &str) -> String {
+        format!(
+            "{}:{}:{}: {}: {}",
+            path, self.line, self.column, self.lint, self.message
+        )
+    }
+}
+
+/// Whether any of `findings` is one of the `denied` lints, which should
+/// fail whatever ran the linter.
+pub fn denied(findings: &[Finding], denied: &[Lint]) -> bool {
+    findings
+        .iter()
+        .any(|finding| denied.contains(&finding.lint))
+}
+
+/// Checks every top level form in `source`, in source order. Anything
+/// bound in `env`, the builtins and whatever a prelude defined, counts as
+/// defined. Only fails if `source` doesn't parse.
+pub fn lint(env: &Lenv, source: &str) -> Result<Vec<Finding>, ParseDiagnostic> {
+    let limits = ParseLimits::for_env(env);
+    let forms = match parse_located_with(source, &limits)? {
+        Lval::Sexpr(forms) => forms,
+        form => vec![form],
+    };
+    let spans = span_tree(source, &limits);
+
+    let mut names = Names::default();
+    for form in &forms {
+        walk(form, &mut names);
+    }
+
+    let mut linter = Linter {
+        env,
+        names: &names,
+        scopes: vec![],
+        definitions: vec![],
+        findings: vec![],
+    };
+    for (i, form) in forms.iter().enumerate() {
+        linter.code(form, child(Some(&spans), forms.len(), i));
+    }
+
+    // a name is used if it turns up anywhere besides where it's defined
+    let mut reported = HashSet::new();
+    for (name, span) in linter.definitions.clone() {
+        let defined = linter
+            .definitions
+            .iter()
+            .filter(|(n, _)| *n == name)
+            .count();
+        if names.mentions.get(&name).copied().unwrap_or(0) <= defined
+            && reported.insert(name.clone())
+        {
+            linter.report(
+                Lint::UnusedDef,
+                span,
+                format!("{} is defined but never used", name),
+            );
+        }
+    }
+
+    let mut findings = linter.findings;
+    findings.sort_by_key(|finding| finding.span.start);
+    for finding in &mut findings {
+        let before = &source[..finding.span.start];
+        finding.line = before.matches('\n').count() + 1;
+        finding.column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    }
+    Ok(findings)
+}
+
+// every name the file defines and how often each symbol turns up
+#[derive(Default)]
+struct Names {
+    defined: HashSet<String>,
+    mentions: HashMap<String, usize>,
+}
+
+impl LvalVisitor for Names {
+    fn visit_sym(&mut self, sym: &str) {
+        *self.mentions.entry(sym.to_string()).or_default() += 1;
+    }
+
+    // a lambda's body is a Q-Expression run as a call, so both kinds count
+    fn visit_list(&mut self, _kind: ListKind, items: &[Lval]) {
+        for (name, _) in defined_by(items) {
+            self.defined.insert(name.to_string());
+        }
+    }
+}
+
+// the names a definition form binds, with the path of indices to each
+// from the form: `(def [a b] ..)`, `(fun [name args] ..)` and the like
+fn defined_by(items: &[Lval]) -> Vec<(&str, Vec<usize>)> {
+    let form = match (items.first(), items.get(1)) {
+        (Some(Lval::Sym(head)), Some(Lval::Qexpr(names))) => (head.as_str(), names),
+        _ => return vec![],
+    };
+
+    match form {
+        ("def" | "def!" | "=", patterns) => {
+            let mut names = vec![];
+            pattern_names(patterns, vec![1], &mut names);
+            names
+        }
+        ("fun" | "fun-checked" | "def-checked", names) => names
+            .first()
+            .and_then(sym)
+            .map(|name| (name, vec![1, 0]))
+            .into_iter()
+            .collect(),
+        ("letfn", helpers) => helpers
+            .iter()
+            .enumerate()
+            .step_by(2)
+            .filter_map(|(j, signature)| match signature {
+                Lval::Qexpr(signature) => signature
+                    .first()
+                    .and_then(sym)
+                    .map(|name| (name, vec![1, j, 0])),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+// the symbols in `def` patterns, which can be lists of patterns to unpack
+fn pattern_names<'a>(
+    patterns: &'a [Lval],
+    path: Vec<usize>,
+    names: &mut Vec<(&'a str, Vec<usize>)>,
+) {
+    for (j, pattern) in patterns.iter().enumerate() {
+        let mut path = path.clone();
+        path.push(j);
+        match pattern {
+            Lval::Sym(name) => names.push((name, path)),
+            Lval::Qexpr(inner) => pattern_names(inner, path, names),
+            _ => {}
+        }
+    }
+}
+
+fn sym(lval: &Lval) -> Option<&str> {
+    match lval {
+        Lval::Sym(sym) => Some(sym),
+        _ => None,
+    }
+}
+
+// the spans of item `i` of a `len` item list, if they line up
+fn child(spans: Option<&SpanTree>, len: usize, i: usize) -> Option<&SpanTree> {
+    spans
+        .filter(|spans| spans.children.len() == len)
+        .map(|spans| &*spans.children[i])
+}
+
+struct Linter<'a> {
+    env: &'a Lenv,
+    names: &'a Names,
+    // parameters in scope, innermost last
+    scopes: Vec<Vec<String>>,
+    definitions: Vec<(String, Span)>,
+    findings: Vec<Finding>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, lint: Lint, span: Span, message: String) {
+        self.findings.push(Finding {
+            lint,
+            span,
+            line: 0,
+            column: 0,
+            message,
+        });
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.scopes
+            .iter()
+            .any(|scope| scope.iter().any(|n| n == name))
+    }
+
+    fn is_builtin(&self, name: &str) -> bool {
+        matches!(self.env.get(name), Some(Lval::Fun(_) | Lval::Native(_)))
+    }
+
+    // `lval` evaluated as code. Q-Expressions are taken to be data unless
+    // a form is known to run them
+    fn code(&mut self, lval: &Lval, spans: Option<&SpanTree>) {
+        match lval {
+            Lval::Sym(sym) => {
+                let known = self.is_local(sym)
+                    || self.names.defined.contains(sym)
+                    || self.env.get(sym).is_some();
+                if let (false, Some(spans)) = (known, spans) {
+                    let message = format!("{} is not defined in this file or a builtin", sym);
+                    self.report(Lint::UnknownSymbol, spans.span, message);
+                }
+            }
+            Lval::Sexpr(items) => self.call(items, spans),
+            _ => {}
+        }
+    }
+
+    fn call(&mut self, items: &[Lval], spans: Option<&SpanTree>) {
+        let at = |i: usize| child(spans, items.len(), i);
+        for (name, path) in defined_by(items) {
+            let span = path.iter().try_fold(spans, |spans, i| {
+                spans.map(|spans| spans.children.get(*i).map(|child| &**child))
+            });
+            if let Some(Some(span)) = span {
+                self.definitions.push((name.to_string(), span.span));
+            }
+        }
+
+        let head = match items.first() {
+            Some(Lval::Sym(head)) if !self.is_local(head) => head.as_str(),
+            _ => return self.all_code(items, spans),
+        };
+        match (head, items.len()) {
+            ("\\", 3) => self.function("lambda", &items[1], at(1), &items[2], at(2)),
+            ("fun" | "fun-checked", 3) => match &items[1] {
+                Lval::Qexpr(signature) if !signature.is_empty() => {
+                    let name = sym(&signature[0]).unwrap_or("function").to_string();
+                    let params = Lval::Qexpr(signature[1..].to_vec());
+                    // the params' spans, without the name's
+                    let param_spans = at(1).map(|spans| SpanTree {
+                        span: spans.span,
+                        children: spans.children.iter().skip(1).cloned().collect(),
+                    });
+                    self.function(&name, &params, param_spans.as_ref(), &items[2], at(2));
+                }
+                _ => self.all_code(items, spans),
+            },
+            ("def" | "def!" | "=", _) if matches!(items.get(1), Some(Lval::Qexpr(_))) => {
+                for (i, item) in items.iter().enumerate().skip(2) {
+                    self.code(item, at(i));
+                }
+            }
+            ("def-checked", 3) => {
+                if let Lval::Qexpr(signature) = &items[1] {
+                    for (j, contract) in signature.iter().enumerate().skip(1) {
+                        self.code(contract, child(at(1), signature.len(), j));
+                    }
+                }
+                self.code(&items[2], at(2));
+            }
+            ("letfn", 3) => self.letfn(&items[1], at(1), &items[2], at(2)),
+            ("let", 3) => self.let_(&items[1], at(1), &items[2], at(2)),
+            ("if", 4) => {
+                self.code(&items[1], at(1));
+                for (i, branch) in items.iter().enumerate().skip(2) {
+                    match branch {
+                        Lval::Qexpr(branch) => self.call(branch, at(i)),
+                        other => self.code(other, at(i)),
+                    }
+                }
+            }
+            _ => {
+                self.check_arity(head, items, spans);
+                self.all_code(items, spans);
+            }
+        }
+    }
+
+    fn all_code(&mut self, items: &[Lval], spans: Option<&SpanTree>) {
+        for (i, item) in items.iter().enumerate() {
+            self.code(item, child(spans, items.len(), i));
+        }
+    }
+
+    fn check_arity(&mut self, head: &str, items: &[Lval], spans: Option<&SpanTree>) {
+        // `(f)` on its own is the function, not a call
+        if items.len() == 1 || self.names.defined.contains(head) || !self.is_builtin(head) {
+            return;
+        }
+        let (min, max) = match self.env.get(head) {
+            Some(Lval::Fun(fun)) => self.env.arity(fun).unwrap_or((0, None)),
+            _ => return,
+        };
+        let given = items.len() - 1;
+        let wanted = match max {
+            Some(max) if max == min => min.to_string(),
+            Some(max) => format!("{} to {}", min, max),
+            None => format!("at least {}", min),
+        };
+        if let (true, Some(spans)) = (given < min || max.is_some_and(|max| given > max), spans) {
+            let message = format!(
+                "{} takes {} argument(s) but is given {}",
+                head, wanted, given
+            );
+            self.report(Lint::Arity, spans.span, message);
+        }
+    }
+
+    // a function called `name` taking `params` and running `body`
+    fn function(
+        &mut self,
+        name: &str,
+        params: &Lval,
+        param_spans: Option<&SpanTree>,
+        body: &Lval,
+        body_spans: Option<&SpanTree>,
+    ) {
+        let (params, body) = match (params, body) {
+            (Lval::Qexpr(params), Lval::Qexpr(body)) => (params, body),
+            _ => {
+                self.code(params, param_spans);
+                return self.code(body, body_spans);
+            }
+        };
+
+        let mut scope = vec![];
+        for (i, param) in params.iter().enumerate() {
+            let spans = child(param_spans, params.len(), i);
+            let name = match param {
+                Lval::Sym(name) => name,
+                // a `[name contract]` pair from `fun-checked`
+                Lval::Qexpr(pair) if pair.len() == 2 => {
+                    self.code(&pair[1], child(spans, 2, 1));
+                    match &pair[0] {
+                        Lval::Sym(name) => name,
+                        _ => continue,
+                    }
+                }
+                _ => continue,
+            };
+            if name != ":" && self.is_builtin(name) {
+                if let Some(spans) = spans {
+                    let message = format!("parameter {} hides the builtin of the same name", name);
+                    self.report(Lint::ShadowedBuiltin, spans.span, message);
+                }
+            }
+            scope.push(name.clone());
+        }
+
+        if body.is_empty() {
+            if let Some(spans) = body_spans {
+                self.report(
+                    Lint::EmptyBody,
+                    spans.span,
+                    format!("{} has an empty body", name),
+                );
+            }
+        }
+        self.scopes.push(scope);
+        self.call(body, body_spans);
+        self.scopes.pop();
+    }
+
+    // `(let [pattern value ...] [body])`, each value seeing the names bound
+    // before it
+    fn let_(
+        &mut self,
+        bindings: &Lval,
+        binding_spans: Option<&SpanTree>,
+        body: &Lval,
+        body_spans: Option<&SpanTree>,
+    ) {
+        let bindings = match bindings {
+            Lval::Qexpr(bindings) => bindings,
+            other => {
+                self.code(other, binding_spans);
+                return self.code(body, body_spans);
+            }
+        };
+
+        self.scopes.push(vec![]);
+        for (i, pair) in bindings.chunks(2).enumerate() {
+            if let [pattern, value] = pair {
+                self.code(value, child(binding_spans, bindings.len(), 2 * i + 1));
+                let mut names = vec![];
+                pattern_names(std::slice::from_ref(pattern), vec![], &mut names);
+                let scope = self.scopes.last_mut().unwrap();
+                scope.extend(names.into_iter().map(|(name, _)| name.to_string()));
+            }
+        }
+        match body {
+            Lval::Qexpr(body) => self.call(body, body_spans),
+            other => self.code(other, body_spans),
+        }
+        self.scopes.pop();
+    }
+
+    // `(letfn [[name args] [body] ...] [body])`, whose helpers can all see
+    // each other
+    fn letfn(
+        &mut self,
+        helpers: &Lval,
+        helper_spans: Option<&SpanTree>,
+        body: &Lval,
+        body_spans: Option<&SpanTree>,
+    ) {
+        let helpers = match helpers {
+            Lval::Qexpr(helpers) => helpers,
+            other => {
+                self.code(other, helper_spans);
+                return self.code(body, body_spans);
+            }
+        };
+
+        let names = helpers
+            .iter()
+            .step_by(2)
+            .filter_map(|signature| match signature {
+                Lval::Qexpr(signature) => signature.first().and_then(sym).map(String::from),
+                _ => None,
+            })
+            .collect();
+        self.scopes.push(names);
+
+        for i in (0..helpers.len().saturating_sub(1)).step_by(2) {
+            let spans = child(helper_spans, helpers.len(), i);
+            if let Lval::Qexpr(signature) = &helpers[i] {
+                let name = signature
+                    .first()
+                    .and_then(sym)
+                    .unwrap_or("helper")
+                    .to_string();
+                let params = Lval::Qexpr(signature.iter().skip(1).cloned().collect());
+                let param_spans = spans.map(|spans| SpanTree {
+                    span: spans.span,
+                    children: spans.children.iter().skip(1).cloned().collect(),
+                });
+                let body_spans = child(helper_spans, helpers.len(), i + 1);
+                self.function(
+                    &name,
+                    &params,
+                    param_spans.as_ref(),
+                    &helpers[i + 1],
+                    body_spans,
+                );
+            }
+        }
+        match body {
+            Lval::Qexpr(body) => self.call(body, body_spans),
+            other => self.code(other, body_spans),
+        }
+        self.scopes.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::init_env;
+
+    const CLEAN: &str = "; nothing to find here
+(def [square] (\\ [x] [* x x]))
+(def [total] (\\ [xs] [if (== (len xs) 0) [0] [+ (square (head xs)) (total (tail xs))]]))
+(letfn [[twice f x] [f (f x)]] [twice square 3])
+(def [count-all] (\\ [: rest] [len rest]))
+(def [[q r]] (div-mod 17 5))
+(count-all (total [1 2 3]) [a b c] q r)
+(def-checked [limit (\\ [n] [> n 0])] 10)
+(if (> limit 1) [= [local] 2] [local])
+(let [n (square 2) [a b] (div-mod n 3)] [+ a b n])";
+
+    const MESSY: &str = "(def [helper] (\\ [x] [+ x 1]))
+(def [main] (\\ [head] [lenght head]))
+(main (tail 1 2))
+(\\ [x] [])";
+
+    #[test]
+    fn it_finds_nothing_in_a_clean_file() {
+        assert_eq!(lint(&init_env(), CLEAN).unwrap(), vec![]);
+
+        let findings = lint(&init_env(), "(let [n 1] [+ n m])").unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            findings[0].message,
+            "m is not defined in this file or a builtin"
+        );
+    }
+
+    #[test]
+    fn it_checks_builtins_against_the_arity_they_were_registered_with() {
+        let messages = |source| {
+            lint(&init_env(), source)
+                .unwrap()
+                .into_iter()
+                .map(|finding| finding.message)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            messages("(range 1 2 3) (+ 1 2 3 4) (list)"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            messages("(range 1 2 3 4) (join [1]) (sort [1] < 2)"),
+            vec![
+                "range takes 1 to 3 argument(s) but is given 4",
+                "join takes at least 2 argument(s) but is given 1",
+                "sort takes 1 to 2 argument(s) but is given 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reports_each_lint_where_it_happened() {
+        let findings = lint(&init_env(), MESSY).unwrap();
+        assert_eq!(
+            findings
+                .iter()
+                .map(|finding| finding.located("messy.lisp"))
+                .collect::<Vec<_>>(),
+            vec![
+                "messy.lisp:1:7: unused-def: helper is defined but never used",
+                "messy.lisp:2:17: shadowed-builtin: parameter head hides the builtin of the same name",
+                "messy.lisp:2:24: unknown-symbol: lenght is not defined in this file or a builtin",
+                "messy.lisp:3:7: arity: tail takes 1 argument(s) but is given 2",
+                "messy.lisp:4:8: empty-body: lambda has an empty body",
+            ]
+        );
+
+        assert!(denied(&findings, &[Lint::Arity]));
+        assert!(!denied(&findings[..1], &[Lint::Arity]));
+        assert_eq!(Lint::from_code("unused-def"), Some(Lint::UnusedDef));
+        assert_eq!(Lint::from_code("typo"), None);
+    }
+
+    #[test]
+    fn it_counts_the_environment_as_defined() {
+        let mut env = init_env();
+        assert_eq!(
+            lint(&env, "(greet 1)").unwrap()[0].lint,
+            Lint::UnknownSymbol
+        );
+        env.insert("greet", Lval::Num(1_f64)).unwrap();
+        assert_eq!(lint(&env, "(greet 1)").unwrap(), vec![]);
+        assert!(lint(&env, "(greet").is_err());
+    }
+}
@@ -0,0 +1,737 @@
+//! The `lisp` command: `lisp file.lsp` runs a program and exits, and
+//! `lisp prompt` starts a REPL, as does `lisp` alone, with
+//! `--preload file.lsp` loading a file into it first. The lines entered at
+//! the prompt are kept in `~/.lisp_history` between sessions.
+//! `lisp --watch file.lsp` runs the program again each time it or a file
+//! it loads is saved. `lisp fmt file.lsp` rewrites files in the standard
+//! style, or with `--check` only says which aren't in it, and
+//! `lisp lint file.lsp` reports likely mistakes in them without running
+//! them. Only built with the `cli` feature.
+use argh::FromArgs;
+use oolisp::{
+    builtin,
+    interpreter::{ErrorPolicy, Interpreter},
+    lint, pretty,
+    repl::{self, Chrome, Console, DEFAULT_PROMPT},
+    watch::{SystemClock, Watch},
+    Lerr, Llambda, Lval, Span,
+};
+use serde_json::{json, Value};
+use std::{
+    cell::RefCell,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    process,
+    rc::Rc,
+    sync::atomic::AtomicBool,
+};
+
+/// Runs a lisp program, or starts a prompt.
+#[derive(FromArgs)]
+struct Args {
+    /// the program to run
+    #[argh(positional)]
+    program: Option<String>,
+
+    /// the program to run, as an option
+    #[argh(option)]
+    file: Option<String>,
+
+    /// how to report a program's results: `text`, the default, or `json`
+    /// for one object per top level form
+    #[argh(option, default = "Output::Text", from_str_fn(output_format))]
+    output: Output,
+
+    /// evaluate every form even after one fails, then print how many did
+    /// and what went wrong, failing if anything did
+    #[argh(switch)]
+    keep_going: bool,
+
+    /// run the program again whenever it or a file it loads changes, until
+    /// interrupted
+    #[argh(switch)]
+    watch: bool,
+
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Output {
+    Text,
+    Json,
+}
+
+impl Args {
+    fn policy(&self) -> ErrorPolicy {
+        match self.keep_going {
+            true => ErrorPolicy::KeepGoing,
+            false => ErrorPolicy::StopAtFirst,
+        }
+    }
+}
+
+fn output_format(value: &str) -> Result<Output, String> {
+    match value {
+        "text" => Ok(Output::Text),
+        "json" => Ok(Output::Json),
+        _ => Err(format!("expected `text` or `json`, not `{}`", value)),
+    }
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Prompt(Prompt),
+    Fmt(Fmt),
+    Lint(Lint),
+}
+
+/// Starts an interactive prompt.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "prompt")]
+struct Prompt {
+    /// a file to load before the first prompt, can be given more than once
+    #[argh(option)]
+    preload: Vec<String>,
+
+    /// where to keep the lines entered between sessions, `~/.lisp_history`
+    /// by default
+    #[argh(option)]
+    history_file: Option<PathBuf>,
+
+    /// how many lines of history to keep, 1000 by default
+    #[argh(option, default = "repl::HISTORY_CAP")]
+    history_size: usize,
+
+    /// print how long each line takes to parse and to evaluate, as `:time`
+    /// turns on
+    #[argh(switch)]
+    stats: bool,
+
+    /// the prompt to show, `lisp> ` by default
+    #[argh(option)]
+    prompt: Option<String>,
+
+    /// the prompt's color: black, red, green, yellow, blue, magenta, cyan
+    /// or white
+    #[argh(option)]
+    prompt_color: Option<String>,
+
+    /// greet with the version and features, as by default
+    #[argh(switch)]
+    banner: bool,
+
+    /// start without the greeting
+    #[argh(switch)]
+    no_banner: bool,
+
+    /// print each line as it was parsed before its result
+    #[argh(switch)]
+    echo_ast: bool,
+
+    /// print nothing for results that are `nil`
+    #[argh(switch)]
+    hide_unit: bool,
+}
+
+impl Prompt {
+    // how the console should look, or why these options can't go together
+    fn chrome(&self) -> Result<Chrome, String> {
+        let mut chrome = Chrome::default();
+        if self.prompt.is_some() || self.prompt_color.is_some() {
+            let text = self.prompt.as_deref().unwrap_or(DEFAULT_PROMPT);
+            chrome.set_prompt(text, self.prompt_color.as_deref())?;
+        }
+        if self.banner && self.no_banner {
+            return Err("give either --banner or --no-banner, not both".to_string());
+        }
+        chrome.banner = !self.no_banner;
+        chrome.echo_ast = self.echo_ast;
+        chrome.hide_unit = self.hide_unit;
+        Ok(chrome)
+    }
+}
+
+/// Rewrites lisp files in the standard style.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "fmt")]
+struct Fmt {
+    /// the files to format
+    #[argh(positional)]
+    files: Vec<PathBuf>,
+
+    /// only list the files that aren't formatted, failing if there are any,
+    /// without rewriting them
+    #[argh(switch)]
+    check: bool,
+}
+
+/// Checks lisp files for likely mistakes without running them.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "lint")]
+struct Lint {
+    /// the files to check
+    #[argh(positional)]
+    files: Vec<PathBuf>,
+
+    /// a lint to fail on, by its code such as `unknown-symbol`, can be
+    /// given more than once
+    #[argh(option, from_str_fn(lint_code))]
+    deny: Vec<lint::Lint>,
+}
+
+fn lint_code(value: &str) -> Result<lint::Lint, String> {
+    lint::Lint::from_code(value).ok_or_else(|| {
+        let codes = lint::Lint::ALL.map(lint::Lint::code);
+        format!("expected one of {}, not `{}`", codes.join(", "), value)
+    })
+}
+
+#[cfg(feature = "alloc-stats")]
+#[global_allocator]
+static ALLOC: oolisp::alloc::Tracing = oolisp::alloc::Tracing;
+
+fn main() {
+    let args: Args = argh::from_env();
+    let policy = args.policy();
+    let mut interpreter = Interpreter::new();
+
+    match (args.program.or(args.file), args.command) {
+        (Some(_), Some(_)) => fail("give either a program to run or `prompt`, not both"),
+        (Some(_), None) if args.watch && args.output == Output::Json => {
+            fail("--watch only writes text")
+        }
+        (Some(path), None) if args.watch => {
+            let stdout = io::stdout();
+            Watch::new(path).run(&SystemClock, &mut stdout.lock(), &AtomicBool::new(false))
+        }
+        (Some(path), None) => run(&mut interpreter, &path, args.output, policy),
+        (None, Some(Command::Prompt(prompt))) => start_prompt(&mut interpreter, prompt),
+        (None, Some(Command::Fmt(fmt))) => {
+            let stdout = io::stdout();
+            if !format_files(&fmt, &mut stdout.lock(), &mut io::stderr()) {
+                process::exit(1)
+            }
+        }
+        (None, Some(Command::Lint(lint))) => {
+            let stdout = io::stdout();
+            if !lint_files(&interpreter, &lint, &mut stdout.lock(), &mut io::stderr()) {
+                process::exit(1)
+            }
+        }
+        (None, None) => start_prompt(
+            &mut interpreter,
+            Prompt::from_args(&["prompt"], &[]).unwrap(),
+        ),
+    }
+}
+
+fn start_prompt(interpreter: &mut Interpreter, prompt: Prompt) {
+    for path in &prompt.preload {
+        run(interpreter, path, Output::Text, ErrorPolicy::StopAtFirst);
+    }
+
+    let mut console = Console::stdio();
+    console.chrome = prompt.chrome().unwrap_or_else(|e| fail(&e));
+    if let Some(path) = prompt.history_file.or_else(repl::default_history_file) {
+        console.persist_history(path, prompt.history_size);
+    }
+    console.set_timing(prompt.stats);
+    repl::run(interpreter.env_mut(), &console);
+}
+
+fn run(interpreter: &mut Interpreter, path: &str, output: Output, policy: ErrorPolicy) {
+    let ok = match output {
+        Output::Json => {
+            let stdout = io::stdout();
+            let stderr = Rc::new(RefCell::new(io::stderr()));
+            run_json(interpreter, path, policy, &mut stdout.lock(), stderr)
+        }
+        Output::Text => run_text(interpreter, path, policy, &mut io::stderr()),
+    };
+    if !ok {
+        process::exit(1)
+    }
+}
+
+// writes the report to `err` when a form fails, or whatever happens with
+// `KeepGoing`, which stops for nothing. Whether every form succeeded.
+fn run_text(
+    interpreter: &mut Interpreter,
+    path: &str,
+    policy: ErrorPolicy,
+    err: &mut dyn Write,
+) -> bool {
+    match interpreter.eval_file(path, policy) {
+        Ok(report) => {
+            if !report.is_ok() || policy == ErrorPolicy::KeepGoing {
+                let _ = writeln!(err, "lisp: {}: {}", path, report);
+            }
+            report.is_ok()
+        }
+        Err(error) => {
+            let _ = writeln!(err, "lisp: {:?}", error);
+            false
+        }
+    }
+}
+
+// formats each file in place, or with `--check` writes the name of each
+// one that would change to `out`. Files that can't be read, written or
+// parsed are reported to `err` and the rest still formatted. Whether every
+// file was, or already was, formatted.
+fn format_files(fmt: &Fmt, out: &mut dyn Write, err: &mut dyn Write) -> bool {
+    let mut ok = true;
+    for path in &fmt.files {
+        let shown = path.display();
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                let _ = writeln!(err, "lisp: {}: {}", shown, e);
+                ok = false;
+                continue;
+            }
+        };
+        let checked = match fmt.check {
+            true => pretty::is_formatted(&source).map(|formatted| (formatted, None)),
+            false => pretty::format_source(&source)
+                .map(|formatted| (formatted == source, Some(formatted))),
+        };
+        match checked {
+            Ok((true, _)) => {}
+            Ok((false, None)) => {
+                let _ = writeln!(out, "{}", shown);
+                ok = false;
+            }
+            Ok((false, Some(formatted))) => {
+                if let Err(e) = fs::write(path, formatted) {
+                    let _ = writeln!(err, "lisp: {}: {}", shown, e);
+                    ok = false;
+                }
+            }
+            Err(diagnostic) => {
+                let _ = writeln!(err, "lisp: {}: {}", shown, diagnostic);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+// writes each file's findings to `out` as `file:line:column: code: message`,
+// with the builtins counting as defined. Files that can't be read or parsed
+// are reported to `err`. Whether they all could be and none of the findings
+// is a denied lint.
+fn lint_files(
+    interpreter: &Interpreter,
+    lint: &Lint,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> bool {
+    let mut ok = true;
+    let mut findings = vec![];
+    for path in &lint.files {
+        let shown = path.display().to_string();
+        let checked = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| lint::lint(interpreter.env(), &source).map_err(|e| e.to_string()));
+        match checked {
+            Ok(found) => {
+                for finding in &found {
+                    let _ = writeln!(out, "{}", finding.located(&shown));
+                }
+                findings.extend(found);
+            }
+            Err(e) => {
+                let _ = writeln!(err, "lisp: {}: {}", shown, e);
+                ok = false;
+            }
+        }
+    }
+    ok && !lint::denied(&findings, &lint.deny)
+}
+
+// writes a line of json to `out` for each form as it is evaluated, and
+// anything else the program has to say, what it prints and its warnings,
+// to `err`, so `out` can be read a line at a time. A file that can't be
+// read is one failed form without a span. Whether every form succeeded.
+fn run_json(
+    interpreter: &mut Interpreter,
+    path: &str,
+    policy: ErrorPolicy,
+    out: &mut dyn Write,
+    err: Rc<RefCell<dyn Write>>,
+) -> bool {
+    let printed = err.clone();
+    interpreter.env_mut().set_writer(move |text| {
+        let _ = printed.borrow_mut().write_all(text.as_bytes());
+    });
+
+    let mut written = Ok(());
+    let result = interpreter.eval_file_each(path, policy, |_, result| {
+        if written.is_ok() {
+            written = writeln!(out, "{}", form_json(result));
+        }
+    });
+    let ok = match result {
+        Ok(report) => report.is_ok(),
+        Err(error) => {
+            written = writeln!(out, "{}", form_json(&Err(error)));
+            false
+        }
+    };
+
+    let mut err = err.borrow_mut();
+    for warning in interpreter.env_mut().take_warnings() {
+        let _ = writeln!(err, "lisp: warning: {}", warning);
+    }
+    if let Err(e) = written {
+        let _ = writeln!(err, "lisp: could not write the results; {}", e);
+        return false;
+    }
+    ok
+}
+
+// `{"ok":true,"value":...}` with the value as it serializes, or
+// `{"ok":false,"error":{"code","message","span"}}`
+fn form_json(result: &Result<Lval, Lerr>) -> Value {
+    match result {
+        Ok(value) => json!({ "ok": true, "value": value_json(value) }),
+        Err(error) => json!({
+            "ok": false,
+            "error": {
+                "code": format!("{:?}", error.etype()),
+                "message": error.message(),
+                "span": error.span().map(|Span { start, end }| json!({ "start": start, "end": end })),
+            },
+        }),
+    }
+}
+
+// the value as it serializes, except that JSON has no infinities or NaN, so
+// rather than turn them into `null` they are written as the strings
+// `"inf"`, `"-inf"` and `"nan"`, as in `{"Num":"inf"}`
+fn value_json(value: &Lval) -> Value {
+    let all = |items: &[Lval]| items.iter().map(value_json).collect::<Vec<_>>();
+    let lambda = |lambda: &Llambda| json!({ "args": lambda.args(), "body": all(lambda.body()) });
+    match value {
+        Lval::Num(n) if n.is_nan() => json!({ "Num": "nan" }),
+        Lval::Num(n) if n.is_infinite() => {
+            json!({ "Num": if *n > 0.0 { "inf" } else { "-inf" } })
+        }
+        Lval::Sexpr(items) => match builtin::as_values(value) {
+            Some(values) => json!({ "Values": all(values) }),
+            None => json!({ "Sexpr": all(items) }),
+        },
+        Lval::Qexpr(items) => json!({ "Qexpr": all(items) }),
+        Lval::Lambda(l) => json!({ "Lambda": lambda(l) }),
+        Lval::CaseLambda(clauses) => {
+            json!({ "CaseLambda": clauses.iter().map(lambda).collect::<Vec<_>>() })
+        }
+        other => json!(other),
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("lisp: {}", message);
+    process::exit(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    // runs `lisp` with `args` as the command line would, giving what it
+    // wrote to stdout and stderr and whether it succeeded
+    fn run_command(args: &[&str]) -> (String, String, bool) {
+        let args = Args::from_args(&["lisp"], args).unwrap();
+        let policy = args.policy();
+        let (mut out, err) = (vec![], Rc::new(RefCell::new(vec![])));
+        let ok = match (args.program, args.command) {
+            (_, Some(Command::Fmt(fmt))) => format_files(&fmt, &mut out, &mut *err.borrow_mut()),
+            (_, Some(Command::Lint(lint))) => {
+                lint_files(&Interpreter::new(), &lint, &mut out, &mut *err.borrow_mut())
+            }
+            (Some(path), None) if args.output == Output::Json => run_json(
+                &mut Interpreter::new(),
+                &path,
+                policy,
+                &mut out,
+                err.clone(),
+            ),
+            (Some(path), None) => run_text(
+                &mut Interpreter::new(),
+                &path,
+                policy,
+                &mut *err.borrow_mut(),
+            ),
+            _ => panic!("not a command that finishes"),
+        };
+        let err = err.borrow().clone();
+        let text = |bytes| String::from_utf8(bytes).unwrap();
+        (text(out), text(err), ok)
+    }
+
+    // the lines written to `out` and `err`, and whether every form succeeded
+    fn run_source(name: &str, source: &str) -> (Vec<Value>, String, bool) {
+        let path = env::temp_dir().join(format!("oolisp-cli-{}-{}.lsp", name, process::id()));
+        fs::write(&path, source).unwrap();
+        let (mut out, err) = (vec![], Rc::new(RefCell::new(vec![])));
+        let ok = run_json(
+            &mut Interpreter::new(),
+            path.to_str().unwrap(),
+            ErrorPolicy::StopAtFirst,
+            &mut out,
+            err.clone(),
+        );
+        fs::remove_file(&path).unwrap();
+        let err = err.borrow().clone();
+
+        let lines = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        (lines, String::from_utf8(err).unwrap(), ok)
+    }
+
+    #[test]
+    fn it_writes_a_json_line_for_each_form() {
+        let (lines, err, ok) = run_source("forms", "(def [x] 2)\n(+ x 1)\n(list x :k \"s\")");
+        assert!(ok);
+        assert!(err.is_empty());
+        assert_eq!(
+            lines,
+            vec![
+                json!({ "ok": true, "value": "Nil" }),
+                json!({ "ok": true, "value": { "Int": 3 } }),
+                json!({
+                    "ok": true,
+                    "value": { "Qexpr": [{ "Int": 2 }, { "Keyword": "k" }, { "Str": "s" }] },
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_writes_the_results_of_values_together() {
+        let (lines, err, ok) = run_source("values", "(values 1 2)");
+        assert!(ok && err.is_empty());
+        assert_eq!(
+            lines,
+            vec![json!({ "ok": true, "value": { "Values": [{ "Int": 1 }, { "Int": 2 }] } })]
+        );
+    }
+
+    #[test]
+    fn it_reports_errors_as_json() {
+        let (lines, _, ok) = run_source("error", "(+ 1 1)\n(+ 1 nope)\n(+ 2 2)");
+        assert!(!ok);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["ok"], json!(false));
+        assert_eq!(lines[1]["error"]["code"], json!("UnboundSymbol"));
+        assert_eq!(lines[1]["error"]["span"], json!({ "start": 13, "end": 17 }));
+        assert!(lines[1]["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("nope"));
+
+        let mut out = vec![];
+        assert!(!run_json(
+            &mut Interpreter::new(),
+            "/no/such/file.lsp",
+            ErrorPolicy::StopAtFirst,
+            &mut out,
+            Rc::new(RefCell::new(vec![]))
+        ));
+        let line: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(line["error"]["code"], json!("Io"));
+        assert_eq!(line["error"]["span"], Value::Null);
+    }
+
+    #[test]
+    fn it_writes_infinities_and_nan_as_strings() {
+        let (lines, _, ok) = run_source(
+            "floats",
+            "1e400\n(list (- 0 1e400) (- 1e400 1e400) 1.5)\n(\\ [x] [+ x 1e400])",
+        );
+        assert!(ok);
+        assert_eq!(lines[0], json!({ "ok": true, "value": { "Num": "inf" } }));
+        assert_eq!(
+            lines[1]["value"],
+            json!({ "Qexpr": [{ "Num": "-inf" }, { "Num": "nan" }, { "Num": 1.5 }] })
+        );
+        assert_eq!(
+            lines[2]["value"]["Lambda"]["body"],
+            json!([{ "Sym": "+" }, { "Sym": "x" }, { "Num": "inf" }])
+        );
+    }
+
+    #[test]
+    fn it_keeps_what_programs_say_out_of_the_json() {
+        let (lines, err, ok) = run_source(
+            "print",
+            "(println \"hello\" 1)\n(* 1e308 10)\n(print \"no newline\")\n(+ 1 2)",
+        );
+        assert!(ok);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], json!({ "ok": true, "value": "Nil" }));
+        assert_eq!(lines[3], json!({ "ok": true, "value": { "Int": 3 } }));
+        assert!(
+            err.starts_with("hello 1\nno newlinelisp: warning: "),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn it_formats_files_in_place_or_checks_them() {
+        let dir = env::temp_dir();
+        let messy = dir.join(format!("oolisp-cli-messy-{}.lsp", process::id()));
+        let tidy = dir.join(format!("oolisp-cli-tidy-{}.lsp", process::id()));
+        let broken = dir.join(format!("oolisp-cli-broken-{}.lsp", process::id()));
+        fs::write(&messy, "(def [x]   1)(+ x\n 1)").unwrap();
+        fs::write(&tidy, "(def [x] 1)\n").unwrap();
+        fs::write(&broken, "(+ 1").unwrap();
+        let (messy_arg, tidy_arg) = (messy.to_str().unwrap(), tidy.to_str().unwrap());
+
+        // --check names what would change and fails, rewriting nothing
+        let (out, err, ok) = run_command(&["fmt", "--check", messy_arg, tidy_arg]);
+        assert!(!ok);
+        assert_eq!(out, format!("{}\n", messy_arg));
+        assert!(err.is_empty());
+        assert_eq!(
+            fs::read_to_string(&messy).unwrap(),
+            "(def [x]   1)(+ x\n 1)"
+        );
+        assert_eq!(
+            run_command(&["fmt", "--check", tidy_arg]),
+            ("".into(), "".into(), true)
+        );
+
+        let (out, _, ok) = run_command(&["fmt", messy_arg, tidy_arg]);
+        assert!(ok);
+        assert!(out.is_empty());
+        assert_eq!(
+            fs::read_to_string(&messy).unwrap(),
+            "(def [x] 1)\n\n(+ x 1)\n"
+        );
+        assert!(run_command(&["fmt", "--check", messy_arg]).2);
+
+        // a file that doesn't parse is reported and left alone
+        let (_, err, ok) = run_command(&["fmt", broken.to_str().unwrap()]);
+        assert!(!ok);
+        assert!(
+            err.starts_with(&format!("lisp: {}: ", broken.display())),
+            "{}",
+            err
+        );
+        assert_eq!(fs::read_to_string(&broken).unwrap(), "(+ 1");
+
+        for path in [messy, tidy, broken] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn it_lints_files_and_fails_on_denied_lints() {
+        let path = env::temp_dir().join(format!("oolisp-cli-lint-{}.lsp", process::id()));
+        fs::write(&path, "(def [unused] 1)\n(tail [1] [2])").unwrap();
+        let arg = path.to_str().unwrap();
+
+        let (out, err, ok) = run_command(&["lint", arg]);
+        assert!(ok);
+        assert!(err.is_empty());
+        assert_eq!(
+            out,
+            format!(
+                "{0}:1:7: unused-def: unused is defined but never used\n\
+                 {0}:2:1: arity: tail takes 1 argument(s) but is given 2\n",
+                arg
+            )
+        );
+
+        assert!(run_command(&["lint", "--deny", "unknown-symbol", arg]).2);
+        let (_, _, ok) = run_command(&["lint", "--deny", "unknown-symbol", "--deny", "arity", arg]);
+        assert!(!ok);
+        assert!(Args::from_args(&["lisp"], &["lint", "--deny", "typo", arg]).is_err());
+
+        fs::write(&path, "(tail").unwrap();
+        let (_, err, ok) = run_command(&["lint", arg]);
+        assert!(!ok);
+        assert!(err.starts_with(&format!("lisp: {}: ", arg)), "{}", err);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_keeps_going_past_failures_when_asked() {
+        let path = env::temp_dir().join(format!("oolisp-cli-keep-{}.lsp", process::id()));
+        fs::write(&path, "(+ 1 nope)\n(def [x] 1)\n(head [])").unwrap();
+        let arg = path.to_str().unwrap();
+
+        let (_, err, ok) = run_command(&[arg]);
+        assert!(!ok);
+        assert!(err.starts_with(&format!("lisp: {}: 1 form(s) evaluated, 1 failed", arg)));
+
+        let (_, err, ok) = run_command(&["--keep-going", arg]);
+        assert!(!ok);
+        assert!(err.starts_with(&format!("lisp: {}: 3 form(s) evaluated, 2 failed", arg)));
+        assert!(
+            err.contains("\n  form 1 ") && err.contains("\n  form 3 "),
+            "{}",
+            err
+        );
+
+        let (out, _, ok) = run_command(&["--output", "json", "--keep-going", arg]);
+        assert!(!ok);
+        assert_eq!(out.lines().count(), 3);
+
+        // the report comes even when nothing failed
+        fs::write(&path, "(+ 1 2)").unwrap();
+        let (_, err, ok) = run_command(&["--keep-going", arg]);
+        assert!(ok);
+        assert_eq!(
+            err,
+            format!("lisp: {}: 1 form(s) evaluated, 0 failed\n", arg)
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_sets_up_the_prompt_from_options() {
+        let chrome = |args: &[&str]| match Args::from_args(&["lisp"], args).unwrap().command {
+            Some(Command::Prompt(prompt)) => prompt.chrome(),
+            _ => panic!("not a prompt"),
+        };
+        assert_eq!(chrome(&["prompt"]), Ok(Chrome::default()));
+
+        let mut expected = Chrome::default();
+        expected.set_prompt("λ ", Some("green")).unwrap();
+        expected.banner = false;
+        expected.echo_ast = true;
+        expected.hide_unit = true;
+        let args = [
+            "prompt",
+            "--prompt",
+            "λ ",
+            "--prompt-color",
+            "green",
+            "--no-banner",
+            "--echo-ast",
+            "--hide-unit",
+        ];
+        assert_eq!(chrome(&args), Ok(expected));
+
+        // a color alone colors the usual prompt
+        let mut expected = Chrome::default();
+        expected.set_prompt(DEFAULT_PROMPT, Some("red")).unwrap();
+        assert_eq!(chrome(&["prompt", "--prompt-color", "red"]), Ok(expected));
+
+        assert!(chrome(&["prompt", "--prompt-color", "mauve"]).is_err());
+        assert!(chrome(&["prompt", "--prompt", "a\tb"]).is_err());
+        assert!(chrome(&["prompt", "--banner", "--no-banner"]).is_err());
+    }
+}
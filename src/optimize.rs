@@ -0,0 +1,207 @@
+//! Rewrites that can be done to a program before it runs, relying on which
+//! builtins were registered as pure.
+use crate::{
+    eval::call_builtin,
+    visitor::{map_unquoted, walk, LvalVisitor},
+    Lenv, Lfun, Llambda, Lval,
+};
+
+/// Evaluates applications of pure builtins to constant arguments, ones with
+/// no symbols in them, ahead of time, innermost first, so `(+ (* 2 3) (random))` becomes `(+ 6 (random))`.
+/// Q-Expressions are left alone since they may be data, or code run later
+/// under other bindings, and calls that fail are kept so the error still
+/// happens when the program runs.
+pub fn fold_constants(env: &mut Lenv, lval: Lval) -> Lval {
+    map_unquoted(lval, &mut |lval| match lval {
+        Lval::Sexpr(items) => fold(env, items),
+        other => other,
+    })
+}
+
+fn fold(env: &mut Lenv, items: Vec<Lval>) -> Lval {
+    let fun = match items.first() {
+        Some(Lval::Sym(sym)) => match env.get(sym) {
+            Some(Lval::Fun(fun)) if env.is_pure(fun) => fun,
+            _ => return Lval::Sexpr(items),
+        },
+        _ => return Lval::Sexpr(items),
+    };
+    if !items[1..].iter().all(is_data) {
+        return Lval::Sexpr(items);
+    }
+
+    match call_builtin(env, fun, items[1..].to_vec()) {
+        Ok(value) if is_constant(&value) => value,
+        _ => Lval::Sexpr(items),
+    }
+}
+
+// values that evaluate to themselves
+fn is_constant(lval: &Lval) -> bool {
+    matches!(
+        lval,
+        Lval::Num(_)
+            | Lval::Int(_)
+            | Lval::Str(_)
+            | Lval::Bytes(_)
+            | Lval::Keyword(_)
+            | Lval::Bool(_)
+            | Lval::Nil
+            | Lval::Qexpr(_)
+    )
+}
+
+// constants that don't name anything, so a builtin like `if` that
+// evaluates its arguments can't reach other code through them
+fn is_data(lval: &Lval) -> bool {
+    let mut references = References::default();
+    walk(lval, &mut references);
+    is_constant(lval)
+        && references.syms.is_empty()
+        && references.funs.is_empty()
+        && references.bodies.is_empty()
+}
+
+/// The names `body` uses that are bound to impure builtins, directly or
+/// through the functions they call, in the order they first appear. Unbound
+/// names, such as a lambda's own parameters, aren't counted. This is a
+/// static scan, so it errs towards impure: a name counts even if the code
+/// using it never runs.
+pub fn impure_references(env: &Lenv, body: &Lval) -> Vec<String> {
+    let mut names = References::default();
+    walk(body, &mut names);
+
+    let mut impure: Vec<String> = vec![];
+    for name in names.syms {
+        if !impure.contains(&name) && !bound_to_pure(env, &name) {
+            impure.push(name);
+        }
+    }
+    impure
+}
+
+/// Whether calling `lambda` can only reach pure builtins.
+pub fn lambda_is_pure(env: &Lenv, lambda: &Llambda) -> bool {
+    let body = Lval::Qexpr(lambda.body().to_vec());
+    // builtins can sit in a body directly, as they do in thunks like `random`
+    let mut references = References::default();
+    walk(&body, &mut references);
+    references.funs.iter().all(|&fun| env.is_pure(fun)) && impure_references(env, &body).is_empty()
+}
+
+// follows `name` through every function it reaches, including recursion
+fn bound_to_pure(env: &Lenv, name: &str) -> bool {
+    let mut seen = vec![name.to_string()];
+    let mut pending = vec![name.to_string()];
+
+    while let Some(name) = pending.pop() {
+        let mut references = References::default();
+        match env.get(&name) {
+            Some(lval) => walk(&lval, &mut references),
+            None => continue,
+        }
+        while let Some(body) = references.bodies.pop() {
+            walk(&body, &mut references);
+        }
+
+        if references.funs.iter().any(|&fun| !env.is_pure(fun)) {
+            return false;
+        }
+        for sym in references.syms {
+            if !seen.contains(&sym) {
+                seen.push(sym.clone());
+                pending.push(sym);
+            }
+        }
+    }
+    true
+}
+
+#[derive(Default)]
+struct References {
+    syms: Vec<String>,
+    funs: Vec<Lfun>,
+    // lambda bodies still to look through, since `walk` doesn't
+    bodies: Vec<Lval>,
+}
+
+impl LvalVisitor for References {
+    fn visit_sym(&mut self, sym: &str) {
+        self.syms.push(sym.to_string());
+    }
+
+    fn visit_fun(&mut self, fun: Lfun) {
+        self.funs.push(fun);
+    }
+
+    fn visit_lambda(&mut self, lambda: &Llambda) {
+        self.bodies.push(Lval::Qexpr(lambda.body().to_vec()));
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "parser")]
+mod tests {
+    use super::*;
+    use crate::{env::init_env, parser::parse_located};
+
+    fn read(source: &str) -> Lval {
+        match parse_located(source).unwrap() {
+            Lval::Sexpr(mut forms) => forms.remove(0),
+            other => other,
+        }
+    }
+
+    fn folded(env: &mut Lenv, source: &str) -> String {
+        format!("{:?}", fold_constants(env, read(source)))
+    }
+
+    #[test]
+    fn it_folds_pure_applications() {
+        let env = &mut init_env();
+        assert_eq!(folded(env, "(+ 1 2)"), "3");
+        assert_eq!(folded(env, "(* 2 (+ 1 (- 4 2)))"), "6");
+        assert_eq!(folded(env, "(concat \"a\" \"b\")"), "\"ab\"");
+        assert_eq!(folded(env, "(tail [1 2])"), "[2]");
+        assert_eq!(folded(env, "(if (== 1 1) [1] [2])"), "1");
+        assert_eq!(folded(env, "(if false [1] [2])"), "2");
+    }
+
+    #[test]
+    fn it_leaves_impure_applications() {
+        let env = &mut init_env();
+        assert_eq!(folded(env, "(+ (+ 1 2) (random))"), "(+ 3 (random))");
+        assert_eq!(folded(env, "(shuffle [1 2 3])"), "(shuffle [1 2 3])");
+        assert_eq!(folded(env, "(def [x] (* 2 3))"), "(def [x] 6)");
+
+        // symbols, quoted code and errors all wait for the program to run
+        assert_eq!(folded(env, "(+ x 1)"), "(+ x 1)");
+        assert_eq!(folded(env, "(if 1 [random] [2])"), "(if 1 [random] [2])");
+        assert_eq!(
+            folded(env, "(\\ [n] [+ n (* 2 3)])"),
+            "(\\ [n] [+ n (* 2 3)])"
+        );
+        assert_eq!(folded(env, "(head [])"), "(head [])");
+
+        // a pure builtin rebound to something else isn't folded
+        let lambda = crate::eval::eval(env, read("(\\ [a b] [random])")).unwrap();
+        env.insert_last("+", lambda).unwrap();
+        assert_eq!(folded(env, "(+ 1 2)"), "(+ 1 2)");
+    }
+
+    #[test]
+    fn it_finds_impure_references() {
+        let env = &mut init_env();
+        let lambda = crate::eval::eval(env, read("(\\ [n] [+ n (random)])")).unwrap();
+        env.insert_last("noisy", lambda).unwrap();
+
+        assert_eq!(
+            impure_references(env, &read("[def [y] (+ (noisy 1) (seed n) (head [1]))]")),
+            vec!["def", "noisy", "seed"]
+        );
+        assert_eq!(
+            impure_references(env, &read("[+ n (head [1 2])]")),
+            Vec::<String>::new()
+        );
+    }
+}
@@ -1,19 +1,38 @@
-use crate::Lval;
+use crate::{Lenv, Lerr, LerrType, Lval};
+pub use crate::{Span, SpanTree};
 use nom::{
     branch::alt,
-    character::complete::{char, multispace0, none_of, one_of},
+    bytes::complete::{take_while, take_while1},
+    character::complete::char,
     combinator::{all_consuming, map},
     error::{ErrorKind, ParseError},
-    multi::{many0, many1},
+    multi::many0,
     number::complete::double,
     sequence::{delimited, preceded},
     IResult,
 };
+use std::{collections::VecDeque, fmt, rc::Rc};
 
 #[derive(Debug, PartialEq)]
 pub enum SyntaxError<I> {
     InvalidArguments,
     InvalidSymbol,
+    InputTooLong(usize),
+    NestedTooDeep(I, usize),
+    AtomTooLong(I, usize),
+    MisplacedSeparator(I),
+    UnknownTag(I, String),
+    BadBytes(I, &'static str),
+    /// a list from its opening bracket on, never closed before the end
+    Unclosed(I, char),
+    /// something that can't start an expression, found where one or the
+    /// closing bracket of the list it is in was expected
+    Unexpected(I, Option<char>),
+    /// a string from its opening quote on, never closed
+    UnterminatedString(I),
+    /// a `\\` in a string from there on, followed by something that isn't
+    /// one of the escapes
+    BadEscape(I),
     Nom(I, ErrorKind),
 }
 
@@ -27,86 +46,1061 @@ impl<I> ParseError<I> for SyntaxError<I> {
     }
 }
 
+/// Bounds on what the parser will accept, so untrusted input can't exhaust
+/// the stack or the heap. Exceeding any of them is a `SyntaxError`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseLimits {
+    /// deepest allowed nesting of sexprs and qexprs
+    pub max_depth: usize,
+    /// longest allowed input, in bytes
+    pub max_input_len: usize,
+    /// longest allowed number, symbol or string, in bytes
+    pub max_atom_len: usize,
+    /// whether `+inf`, `-inf` and `nan` are numbers rather than symbols
+    pub allow_special_floats: bool,
+}
+
+impl ParseLimits {
+    /// The default limits, reading what `env` has opted into.
+    pub fn for_env(env: &Lenv) -> Self {
+        ParseLimits {
+            allow_special_floats: env.special_floats(),
+            ..ParseLimits::default()
+        }
+    }
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits {
+            max_depth: 256,
+            max_input_len: 4 * 1024 * 1024,
+            max_atom_len: 64 * 1024,
+            allow_special_floats: false,
+        }
+    }
+}
+
+// skips whitespace and `;` comments, which run to the end of their line
+fn ws_or_comment(s: &str) -> IResult<&str, &str, SyntaxError<&str>> {
+    let rest = skip_ws(s);
+    Ok((rest, &s[..s.len() - rest.len()]))
+}
+
+fn skip_ws(s: &str) -> &str {
+    let is_space = |c: char| " \t\r\n".contains(c);
+    let mut rest = s.trim_start_matches(is_space);
+    while let Some(comment) = rest.strip_prefix(';') {
+        rest = comment
+            .find('\n')
+            .map_or("", |end| &comment[end..])
+            .trim_start_matches(is_space);
+    }
+    rest
+}
+
+pub(crate) fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_+\\:-*/=<>|!&%?".contains(c)
+}
+
 fn parse_number(s: &str) -> IResult<&str, Lval, SyntaxError<&str>> {
-    map(preceded(multispace0, double), |n| Lval::Num(n))(s)
+    let (t, _) = ws_or_comment(s)?;
+    let literal = &t[..number_len(t)];
+
+    if !literal.contains('_') {
+        if let Some(n) = parse_int(literal) {
+            return Ok((&t[literal.len()..], Lval::Int(n)));
+        }
+        return map(double, Lval::Num)(t);
+    }
+
+    // separators only ever sit between two digits
+    let bytes = literal.as_bytes();
+    for (i, b) in bytes.iter().enumerate() {
+        let between_digits = i > 0
+            && bytes[i - 1].is_ascii_digit()
+            && bytes.get(i + 1).is_some_and(|b| b.is_ascii_digit());
+        if *b == b'_' && !between_digits {
+            return Err(nom::Err::Failure(SyntaxError::MisplacedSeparator(&t[i..])));
+        }
+    }
+
+    let digits = literal.replace('_', "");
+    if let Some(n) = parse_int(&digits) {
+        return Ok((&t[literal.len()..], Lval::Int(n)));
+    }
+    match digits.parse::<f64>() {
+        Ok(n) => Ok((&t[literal.len()..], Lval::Num(n))),
+        Err(_) => Err(nom::Err::Error(SyntaxError::Nom(t, ErrorKind::Float))),
+    }
 }
 
+// a literal with no point or exponent, unless it is too big for an `Int`
+// and has to be a `Num`
+fn parse_int(literal: &str) -> Option<i64> {
+    let digits = literal.trim_start_matches(['+', '-']);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    literal.parse().ok()
+}
+
+// length of what looks like a numeric literal at the start of `s`,
+// underscores included
+fn number_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    if let Some(b'+') | Some(b'-') = bytes.first() {
+        i += 1;
+    }
+    match bytes.get(i) {
+        Some(b) if b.is_ascii_digit() || *b == b'.' => {}
+        _ => return 0,
+    }
+
+    let digits = |i: &mut usize| {
+        while let Some(b) = bytes.get(*i) {
+            if b.is_ascii_digit() || *b == b'_' || *b == b'.' {
+                *i += 1;
+            } else {
+                break;
+            }
+        }
+    };
+
+    digits(&mut i);
+    if let Some(b'e') | Some(b'E') = bytes.get(i) {
+        i += 1;
+        if let Some(b'+') | Some(b'-') = bytes.get(i) {
+            i += 1;
+        }
+        digits(&mut i);
+    }
+    i
+}
+
+// `+inf`, `-inf` and `nan` spelled out whole, so `nano` is still a symbol
+fn parse_special_float(s: &str) -> IResult<&str, Lval, SyntaxError<&str>> {
+    let (t, _) = ws_or_comment(s)?;
+    let len = t.find(|c| !is_symbol_char(c)).unwrap_or(t.len());
+    let n = match &t[..len] {
+        "+inf" => f64::INFINITY,
+        "-inf" => f64::NEG_INFINITY,
+        "nan" => f64::NAN,
+        _ => return Err(nom::Err::Error(SyntaxError::Nom(t, ErrorKind::Float))),
+    };
+    Ok((&t[len..], Lval::Num(n)))
+}
+
+// a leading `:` makes a keyword, though `:` alone is still the symbol
+// used for rest args
 fn parse_symbol(s: &str) -> IResult<&str, Lval, SyntaxError<&str>> {
     map(
-        preceded(
-            multispace0,
-            many1(map(
-                one_of(
-                    "_+\\:-*/=<>|!&%abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890",
-                ),
-                |c| format!("{}", c),
-            )),
-        ),
-        |o| Lval::Sym(o.join("")),
+        preceded(ws_or_comment, take_while1(is_symbol_char)),
+        |o: &str| match (o, o.strip_prefix(':')) {
+            ("true", _) => Lval::Bool(true),
+            ("false", _) => Lval::Bool(false),
+            ("nil", _) => Lval::Nil,
+            (_, Some(name)) if !name.is_empty() => Lval::Keyword(name.to_string()),
+            _ => Lval::Sym(o.to_string()),
+        },
     )(s)
 }
 
 fn parse_string(s: &str) -> IResult<&str, Lval, SyntaxError<&str>> {
-    map(
-        delimited(
-            preceded(multispace0, char('"')),
-            many0(map(none_of("\""), |c| format!("{}", c))),
-            preceded(multispace0, char('"')),
-        ),
-        |o| Lval::Str(o.join("")),
-    )(s)
+    let (t, _) = ws_or_comment(s)?;
+    let (rest, _) = char('"')(t)?;
+    let end = string_end(rest).ok_or(nom::Err::Failure(SyntaxError::UnterminatedString(t)))?;
+    let text = unescape(&rest[..end])
+        .map_err(|at| nom::Err::Failure(SyntaxError::BadEscape(&rest[at..])))?;
+    Ok((&rest[end + 1..], Lval::Str(text)))
+}
+
+/// Where the closing quote of a string is, given what follows its opening
+/// one, skipping over escaped quotes. `None` if it is never closed.
+pub(crate) fn string_end(body: &str) -> Option<usize> {
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some(i),
+            '\\' => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    None
 }
 
+// the text a string's body stands for, with `\"`, `\\`, `\n` and `\t`
+// read as what they escape, or where an escape that isn't one of those is
+fn unescape(body: &str) -> Result<String, usize> {
+    let mut text = String::with_capacity(body.len());
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        text.push(match c {
+            '\\' => match chars.next() {
+                Some((_, '"')) => '"',
+                Some((_, '\\')) => '\\',
+                Some((_, 'n')) => '\n',
+                Some((_, 't')) => '\t',
+                _ => return Err(i),
+            },
+            c => c,
+        });
+    }
+    Ok(text)
+}
+
+// `#` starts a literal for a value with no syntax of its own, the tag after
+// it saying which: `#bytes["dead beef"]` (the quotes are optional) or
+// `#b64"3q2+7w=="`
+fn parse_tagged(s: &str) -> IResult<&str, Lval, SyntaxError<&str>> {
+    let (t, _) = preceded(ws_or_comment, char('#'))(s)?;
+    let tag_len = t
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(t.len());
+    let (tag, rest) = t.split_at(tag_len);
+    let bad = |message| nom::Err::Failure(SyntaxError::BadBytes(t, message));
+
+    match tag {
+        "bytes" => {
+            let (rest, body) = delimited(char('['), take_while(|c| c != ']'), char(']'))(rest)
+                .map_err(|_: nom::Err<SyntaxError<&str>>| bad("#bytes needs a closing ]"))?;
+            let body = body.trim();
+            let hex = body
+                .strip_prefix('"')
+                .and_then(|body| body.strip_suffix('"'))
+                .unwrap_or(body);
+            let digits = hex
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect::<Vec<char>>();
+            if digits.contains(&'…') {
+                return Err(bad(
+                    "#bytes was cut short when printed and can't be read back",
+                ));
+            }
+            if !digits.len().is_multiple_of(2) || !digits.iter().all(char::is_ascii_hexdigit) {
+                return Err(bad("#bytes needs pairs of hex digits"));
+            }
+            let bytes = digits
+                .chunks(2)
+                .map(|pair| {
+                    let pair = pair.iter().collect::<String>();
+                    u8::from_str_radix(&pair, 16).unwrap_or_default()
+                })
+                .collect();
+            Ok((rest, Lval::Bytes(bytes)))
+        }
+        "b64" => {
+            let (rest, text) = delimited(char('"'), take_while(|c| c != '"'), char('"'))(rest)
+                .map_err(|_: nom::Err<SyntaxError<&str>>| bad("#b64 needs a quoted string"))?;
+            match crate::builtin::base64_decode(text) {
+                Some(bytes) => Ok((rest, Lval::Bytes(bytes))),
+                None => Err(bad("#b64 needs valid base64")),
+            }
+        }
+        _ => {
+            let shown = if tag.is_empty() {
+                t.chars().next().map(String::from).unwrap_or_default()
+            } else {
+                tag.to_string()
+            };
+            Err(nom::Err::Failure(SyntaxError::UnknownTag(
+                skip_ws(s),
+                shown,
+            )))
+        }
+    }
+}
+
+fn parse_atom<'a>(
+    s: &'a str,
+    limits: &ParseLimits,
+) -> IResult<&'a str, Lval, SyntaxError<&'a str>> {
+    let special_float = |i| {
+        if limits.allow_special_floats {
+            parse_special_float(i)
+        } else {
+            Err(nom::Err::Error(SyntaxError::Nom(i, ErrorKind::Float)))
+        }
+    };
+    let (rest, atom) = alt((
+        parse_tagged,
+        special_float,
+        parse_number,
+        parse_symbol,
+        parse_string,
+    ))(s)?;
+
+    let start = skip_ws(s);
+    if start.len() - rest.len() > limits.max_atom_len {
+        return Err(nom::Err::Failure(SyntaxError::AtomTooLong(
+            start,
+            limits.max_atom_len,
+        )));
+    }
+
+    Ok((rest, atom))
+}
+
+fn parse_list<'a>(
+    s: &'a str,
+    limits: &ParseLimits,
+    depth: usize,
+    open: char,
+    close: char,
+) -> IResult<&'a str, Vec<Lval>, SyntaxError<&'a str>> {
+    let start = s;
+    let (rest, _) = preceded(ws_or_comment, char(open))(s)?;
+
+    // refuse to recurse any further than we were told to
+    if depth >= limits.max_depth {
+        return Err(nom::Err::Failure(SyntaxError::NestedTooDeep(
+            skip_ws(s),
+            limits.max_depth,
+        )));
+    }
+
+    let (s, list) = many0(|i| parse_expression_with(i, limits, depth + 1))(rest)?;
+    // having got past the opening bracket this has to be a list, so a
+    // failure here is reported where it happened rather than backtracked
+    let s = skip_ws(s);
+    match s.strip_prefix(close) {
+        Some(s) => Ok((s, list)),
+        None if s.is_empty() => Err(nom::Err::Failure(SyntaxError::Unclosed(
+            skip_ws(start),
+            close,
+        ))),
+        None => Err(nom::Err::Failure(SyntaxError::Unexpected(s, Some(close)))),
+    }
+}
+
+#[cfg(test)]
 fn parse_sexpression(s: &str) -> IResult<&str, Lval, SyntaxError<&str>> {
-    delimited(
-        preceded(multispace0, char('(')),
-        map(many0(parse_expression), |e| Lval::Sexpr(e)),
-        preceded(multispace0, char(')')),
+    map(
+        |i| parse_list(i, &ParseLimits::default(), 0, '(', ')'),
+        Lval::Sexpr,
     )(s)
 }
 
+#[cfg(test)]
 fn parse_qexpression(s: &str) -> IResult<&str, Lval, SyntaxError<&str>> {
-    delimited(
-        preceded(multispace0, char('[')),
-        map(many0(parse_expression), |e| Lval::Qexpr(e)),
-        preceded(multispace0, char(']')),
+    map(
+        |i| parse_list(i, &ParseLimits::default(), 0, '[', ']'),
+        Lval::Qexpr,
     )(s)
 }
 
+#[cfg(test)]
 fn parse_expression(s: &str) -> IResult<&str, Lval, SyntaxError<&str>> {
+    parse_expression_with(s, &ParseLimits::default(), 0)
+}
+
+fn parse_expression_with<'a>(
+    s: &'a str,
+    limits: &ParseLimits,
+    depth: usize,
+) -> IResult<&'a str, Lval, SyntaxError<&'a str>> {
     alt((
-        parse_number,
-        parse_symbol,
-        parse_string,
-        parse_sexpression,
-        parse_qexpression,
+        |i| parse_atom(i, limits),
+        map(|i| parse_list(i, limits, depth, '(', ')'), Lval::Sexpr),
+        map(|i| parse_list(i, limits, depth, '[', ']'), Lval::Qexpr),
     ))(s)
 }
 
 pub fn parse(s: &str) -> IResult<&str, Lval, SyntaxError<&str>> {
+    parse_with_limits(s, &ParseLimits::default())
+}
+
+pub fn parse_with_limits<'a>(
+    s: &'a str,
+    limits: &ParseLimits,
+) -> IResult<&'a str, Lval, SyntaxError<&'a str>> {
+    if s.len() > limits.max_input_len {
+        return Err(nom::Err::Failure(SyntaxError::InputTooLong(
+            limits.max_input_len,
+        )));
+    }
+
     all_consuming(delimited(
-        multispace0,
-        map(many0(parse_expression), |e| Lval::Sexpr(e)),
-        multispace0,
+        ws_or_comment,
+        map(many0(|i| parse_expression_with(i, limits, 0)), Lval::Sexpr),
+        ws_or_comment,
     ))(s)
 }
 
+/// Like `parse`, with a failure located in the source.
+pub fn parse_located(s: &str) -> Result<Lval, ParseDiagnostic> {
+    parse_located_with(s, &ParseLimits::default())
+}
+
+pub fn parse_located_with(s: &str, limits: &ParseLimits) -> Result<Lval, ParseDiagnostic> {
+    parse_with_limits(s, limits)
+        .map(|(_, lval)| lval)
+        .map_err(|e| ParseDiagnostic::from_nom(s, e))
+}
+
+/// Reads `s` with the default limits, saying where and why it failed if it
+/// does: the line and column, what was expected there, and the line itself
+/// with a caret under the spot when displayed.
+pub fn parse_with_errors(s: &str) -> Result<Lval, ParseDiagnostic> {
+    parse_located(s)
+}
+
+/// A `;` comment running to the end of its line, as kept by
+/// `parse_with_comments`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub span: Span,
+    /// the comment including its `;`, without trailing whitespace
+    pub text: String,
+}
+
+/// Like `parse_located`, also handing the `;` comments back in source
+/// order, for tools that need to put them back again.
+pub fn parse_with_comments(s: &str) -> Result<(Lval, Vec<Comment>), ParseDiagnostic> {
+    let mut blanked = String::with_capacity(s.len());
+    let mut comments = vec![];
+    let mut in_string = false;
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' && in_string {
+            // whatever is escaped stays in the string
+            blanked.push(c);
+            if let Some((_, escaped)) = chars.next() {
+                blanked.push(escaped);
+            }
+            continue;
+        }
+
+        if c == ';' && !in_string {
+            let end = s[i..].find('\n').map_or(s.len(), |newline| i + newline);
+            comments.push(Comment {
+                span: Span { start: i, end },
+                text: s[i..end].trim_end().to_string(),
+            });
+            // blank the comment out byte for byte so offsets still line up
+            blanked.extend(std::iter::repeat_n(' ', end - i));
+            while chars.peek().is_some_and(|(j, _)| *j < end) {
+                chars.next();
+            }
+            continue;
+        }
+
+        if c == '"' {
+            in_string = !in_string;
+        }
+        blanked.push(c);
+    }
+
+    parse_located(&blanked).map(|forms| (forms, comments))
+}
+
+/// Where each top level form in `s` came from, and everything inside it,
+/// for source that parses. Mirrors the `Sexpr` of forms `parse` gives.
+pub fn span_tree(s: &str, limits: &ParseLimits) -> SpanTree {
+    let mut children = vec![];
+    let mut at = 0;
+    while let Some((tree, end)) = scan_expression(s, at, limits) {
+        children.push(Rc::new(tree));
+        at = end;
+    }
+    SpanTree {
+        span: Span {
+            start: 0,
+            end: s.len(),
+        },
+        children,
+    }
+}
+
+// the spans of the expression at or after `at` in `s`, and where it ends
+fn scan_expression(s: &str, at: usize, limits: &ParseLimits) -> Option<(SpanTree, usize)> {
+    let start = s.len() - skip_ws(&s[at..]).len();
+    let close = match s[start..].chars().next()? {
+        '(' => ')',
+        '[' => ']',
+        _ => {
+            let (rest, _) = parse_atom(&s[start..], limits).ok()?;
+            let end = s.len() - rest.len();
+            let span = Span { start, end };
+            return Some((
+                SpanTree {
+                    span,
+                    children: vec![],
+                },
+                end,
+            ));
+        }
+    };
+
+    let mut children = vec![];
+    let mut at = start + 1;
+    loop {
+        let next = s.len() - skip_ws(&s[at..]).len();
+        if s[next..].starts_with(close) {
+            let span = Span {
+                start,
+                end: next + 1,
+            };
+            return Some((SpanTree { span, children }, next + 1));
+        }
+        let (child, end) = scan_expression(s, next, limits)?;
+        children.push(Rc::new(child));
+        at = end;
+    }
+}
+
+/// A parse failure, located by its byte offset into the source and by
+/// line and column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseDiagnostic {
+    pub offset: usize,
+    /// 1-based
+    pub line: usize,
+    /// 1-based, in characters
+    pub column: usize,
+    pub expected: Option<Expected>,
+    pub message: String,
+    // the failing line, or as much of it around the failure as is at hand,
+    // and how many characters into it the failure is
+    excerpt: String,
+    caret: usize,
+    // whether the source only ran out, so more of it could still parse
+    incomplete: bool,
+}
+
+/// What the parser was looking for where it failed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expected {
+    /// the bracket closing a list, or another expression before it
+    Close(char),
+    /// the `"` ending a string
+    Quote,
+    /// another expression, or the end of the input
+    Expression,
+}
+
+// characters of the failing line shown either side of the caret
+const EXCERPT_CHARS: usize = 40;
+
+impl ParseDiagnostic {
+    /// A failure at byte `offset` of `source`, which it is located in.
+    pub fn new(source: &str, offset: usize, message: impl Into<String>) -> Self {
+        let (line, column) = locate(source, offset);
+        let at = clamp(source, offset);
+        let line_start = source[..at].rfind('\n').map_or(0, |newline| newline + 1);
+        let line_end = source[at..].find('\n').map_or(source.len(), |end| at + end);
+
+        let before = &source[line_start..at];
+        let skipped = before.chars().count().saturating_sub(EXCERPT_CHARS);
+        let excerpt = before
+            .chars()
+            .skip(skipped)
+            .chain(source[at..line_end].chars().take(EXCERPT_CHARS))
+            .collect();
+
+        ParseDiagnostic {
+            offset,
+            line,
+            column,
+            expected: None,
+            message: message.into(),
+            excerpt,
+            caret: column - 1 - skipped,
+            incomplete: false,
+        }
+    }
+
+    fn expecting(mut self, expected: Expected) -> Self {
+        self.expected = Some(expected);
+        self
+    }
+
+    /// Whether the source failed only by ending too soon, in an open list
+    /// or string, rather than by holding something invalid. Such input may
+    /// parse once more of it follows.
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete
+    }
+
+    fn incomplete(mut self) -> Self {
+        self.incomplete = true;
+        self
+    }
+
+    // for a form that began `offset` bytes into a stream, on `line` and
+    // `column` characters along it
+    fn shifted(mut self, offset: usize, line: usize, column: usize) -> Self {
+        self.offset += offset;
+        if self.line == 1 {
+            self.column += column;
+        }
+        self.line += line - 1;
+        self
+    }
+
+    fn from_nom(source: &str, err: nom::Err<SyntaxError<&str>>) -> Self {
+        let at = |rest: &str| source.len() - rest.len();
+        let e = match err {
+            nom::Err::Incomplete(_) => {
+                return ParseDiagnostic::new(source, source.len(), "incomplete input").incomplete()
+            }
+            nom::Err::Error(e) | nom::Err::Failure(e) => e,
+        };
+
+        match e {
+            SyntaxError::InvalidArguments => ParseDiagnostic::new(source, 0, "invalid arguments"),
+            SyntaxError::InvalidSymbol => ParseDiagnostic::new(source, 0, "invalid symbol"),
+            SyntaxError::InputTooLong(n) => {
+                ParseDiagnostic::new(source, 0, format!("input is longer than {} bytes", n))
+            }
+            SyntaxError::NestedTooDeep(rest, n) => ParseDiagnostic::new(
+                source,
+                at(rest),
+                format!("expressions are nested deeper than {} levels", n),
+            ),
+            SyntaxError::AtomTooLong(rest, n) => {
+                ParseDiagnostic::new(source, at(rest), format!("atom is longer than {} bytes", n))
+            }
+            SyntaxError::MisplacedSeparator(rest) => ParseDiagnostic::new(
+                source,
+                at(rest),
+                "'_' in a number has to sit between two digits",
+            ),
+            SyntaxError::UnknownTag(rest, tag) => {
+                ParseDiagnostic::new(source, at(rest), format!("unknown reader tag #{}", tag))
+            }
+            SyntaxError::BadBytes(rest, problem) => ParseDiagnostic::new(source, at(rest), problem),
+            SyntaxError::Unclosed(rest, close) => {
+                let open = rest.chars().next().unwrap_or(close);
+                ParseDiagnostic::new(
+                    source,
+                    at(rest),
+                    format!(
+                        "unclosed '{}', expected '{}' before the end of the input",
+                        open, close
+                    ),
+                )
+                .expecting(Expected::Close(close))
+                .incomplete()
+            }
+            SyntaxError::UnterminatedString(rest) => ParseDiagnostic::new(
+                source,
+                at(rest),
+                "unterminated string, expected '\"' before the end of the input",
+            )
+            .expecting(Expected::Quote)
+            .incomplete(),
+            SyntaxError::BadEscape(rest) => ParseDiagnostic::new(
+                source,
+                at(rest),
+                "unknown escape in a string, expected \\\", \\\\, \\n or \\t",
+            ),
+            SyntaxError::Unexpected(rest, close) => unexpected(source, at(rest), close),
+            // `all_consuming` stopping short of the end
+            SyntaxError::Nom(rest, ErrorKind::Eof) => unexpected(source, at(rest), None),
+            SyntaxError::Nom(rest, kind) => ParseDiagnostic::new(
+                source,
+                at(rest),
+                format!("could not parse the input ({:?})", kind),
+            ),
+        }
+    }
+}
+
+// what `c` is, found at `offset` where nothing could start with it
+fn unexpected(source: &str, offset: usize, close: Option<char>) -> ParseDiagnostic {
+    let found = source[offset..].chars().next().unwrap_or(' ');
+    let in_symbol = source[..offset]
+        .chars()
+        .next_back()
+        .is_some_and(is_symbol_char);
+    let message = match close {
+        Some(close) if ")]".contains(found) => {
+            format!("expected '{}' but found '{}'", close, found)
+        }
+        None if ")]".contains(found) => format!("unexpected '{}' with no list open", found),
+        _ if in_symbol => format!("invalid character '{}' in a symbol", found),
+        _ => format!("invalid character '{}'", found),
+    };
+    let expected = close.map_or(Expected::Expression, Expected::Close);
+    ParseDiagnostic::new(source, offset, message).expecting(expected)
+}
+
+/// The 1-based line and column, in characters, of byte `offset` of
+/// `source`. Offsets past the end count as the end.
+pub fn locate(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..clamp(source, offset)];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+// `offset` moved back onto `source`, and onto a character boundary
+fn clamp(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// The message and where it happened, then the line it happened on with a
+/// `^` under the spot.
+impl fmt::Display for ParseDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}\n{}\n{}^",
+            self.message,
+            self.line,
+            self.column,
+            self.excerpt,
+            " ".repeat(self.caret)
+        )
+    }
+}
+
+/// A `Syntax` error carrying the diagnostic's message, line and column.
+impl From<ParseDiagnostic> for Lerr {
+    fn from(diagnostic: ParseDiagnostic) -> Self {
+        Lerr::new(
+            LerrType::Syntax,
+            format!(
+                "{} at line {}, column {}",
+                diagnostic.message, diagnostic.line, diagnostic.column
+            ),
+        )
+    }
+}
+
+/// Parses a stream of source text that arrives in pieces, handing back each
+/// top level form as soon as it has been closed.
+///
+/// Only the text of the form currently being read is buffered. Call
+/// `finish` once the stream has ended so a trailing atom can be completed
+/// and an unclosed form reported.
+pub struct Incremental {
+    buffer: String,
+    // stream offset of the first byte still in the buffer, and the line
+    // and how many characters along it that byte is
+    consumed: usize,
+    line: usize,
+    column: usize,
+    // how far into the buffer the current form has been scanned
+    scanned: usize,
+    depth: usize,
+    in_string: bool,
+    // the last character in the string was a `\\`
+    escaped: bool,
+    in_atom: bool,
+    // the atom is a `#` tag, whose literal carries on into a [] or ""
+    in_tag: bool,
+    in_comment: bool,
+    finished: bool,
+    ready: VecDeque<(Span, Result<Lval, ParseDiagnostic>)>,
+    limits: ParseLimits,
+}
+
+impl Default for Incremental {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Incremental {
+    pub fn new() -> Self {
+        Self::with_limits(ParseLimits::default())
+    }
+
+    pub fn with_limits(limits: ParseLimits) -> Self {
+        Incremental {
+            buffer: String::new(),
+            consumed: 0,
+            line: 1,
+            column: 0,
+            scanned: 0,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            in_atom: false,
+            in_tag: false,
+            in_comment: false,
+            finished: false,
+            ready: VecDeque::new(),
+            limits,
+        }
+    }
+
+    pub fn push_str(&mut self, chunk: &str) {
+        self.buffer.push_str(chunk);
+    }
+
+    /// Marks the end of the stream.
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    /// The next complete top level form, or `None` until more text is
+    /// pushed (or, once finished, when the stream is exhausted).
+    pub fn next_form(&mut self) -> Option<Result<Lval, ParseDiagnostic>> {
+        self.next_spanned_form().map(|(_, form)| form)
+    }
+
+    /// Like `next_form`, along with where in the stream the form came from.
+    pub fn next_spanned_form(&mut self) -> Option<(Span, Result<Lval, ParseDiagnostic>)> {
+        loop {
+            if let Some(form) = self.ready.pop_front() {
+                return Some(form);
+            }
+
+            match self.scan() {
+                Some(end) => self.take_form(end),
+                None if self.buffer.len() > self.limits.max_input_len => {
+                    let message =
+                        format!("input is longer than {} bytes", self.limits.max_input_len);
+                    let start = self.buffer.len() - skip_ws(&self.buffer).len();
+                    let diagnostic = self.diagnostic(start, message);
+                    return Some((self.discard(), Err(diagnostic)));
+                }
+                None if self.finished && !self.buffer.trim().is_empty() => {
+                    let diagnostic = self.diagnostic(self.buffer.len(), "unexpected end of input");
+                    return Some((self.discard(), Err(diagnostic)));
+                }
+                None => return None,
+            }
+        }
+    }
+
+    // finds where the form at the front of the buffer ends, picking up
+    // from wherever the previous call left off
+    fn scan(&mut self) -> Option<usize> {
+        for (i, c) in self.buffer[self.scanned..].char_indices() {
+            let i = self.scanned + i;
+
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                    continue;
+                }
+                if c == '\\' {
+                    self.escaped = true;
+                } else if c == '"' {
+                    self.in_string = false;
+                    if self.depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+                continue;
+            }
+
+            if self.in_comment {
+                if c == '\n' {
+                    self.in_comment = false;
+                    // a comment between forms is taken on its own
+                    if self.depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+                continue;
+            }
+
+            if self.in_atom && self.in_tag && (c == '[' || c == '"') {
+                self.in_atom = false;
+                self.in_tag = false;
+            } else if self.in_atom {
+                if c.is_whitespace() || "()[]\";".contains(c) {
+                    return Some(i);
+                }
+                continue;
+            }
+
+            match c {
+                '"' => self.in_string = true,
+                ';' => self.in_comment = true,
+                '(' | '[' => self.depth += 1,
+                ')' | ']' => {
+                    // a stray closer is a form of its own for the parser
+                    // to complain about
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+                c if c.is_whitespace() => {}
+                _ => {
+                    if self.depth == 0 {
+                        self.in_atom = true;
+                        self.in_tag = c == '#';
+                    }
+                }
+            }
+        }
+
+        self.scanned = self.buffer.len();
+        if (self.in_atom || self.in_comment && self.depth == 0) && self.finished {
+            Some(self.buffer.len())
+        } else {
+            None
+        }
+    }
+
+    fn take_form(&mut self, end: usize) {
+        let text = self.buffer[..end].to_string();
+        let span = self.span_of(end);
+        match parse_with_limits(&text, &self.limits) {
+            Ok((_, Lval::Sexpr(forms))) => self
+                .ready
+                .extend(forms.into_iter().map(|form| (span, Ok(form)))),
+            Ok((_, form)) => self.ready.push_back((span, Ok(form))),
+            Err(e) => {
+                let diagnostic = ParseDiagnostic::from_nom(&text, e).shifted(
+                    self.consumed,
+                    self.line,
+                    self.column,
+                );
+                self.ready.push_back((span, Err(diagnostic)));
+            }
+        }
+
+        self.advance(end);
+    }
+
+    fn discard(&mut self) -> Span {
+        let span = self.span_of(self.buffer.len());
+        self.advance(self.buffer.len());
+        span
+    }
+
+    // a failure `offset` bytes into the buffer, located in the stream
+    fn diagnostic(&self, offset: usize, message: impl Into<String>) -> ParseDiagnostic {
+        ParseDiagnostic::new(&self.buffer, offset, message).shifted(
+            self.consumed,
+            self.line,
+            self.column,
+        )
+    }
+
+    // drops the first `end` bytes of the buffer, now read
+    fn advance(&mut self, end: usize) {
+        let read = &self.buffer[..end];
+        match read.rfind('\n') {
+            Some(newline) => {
+                self.line += read.matches('\n').count();
+                self.column = read[newline + 1..].chars().count();
+            }
+            None => self.column += read.chars().count(),
+        }
+        self.buffer.drain(..end);
+        self.consumed += end;
+        self.reset();
+    }
+
+    // the span of the buffer up to `end`, leaving out leading whitespace and
+    // comments
+    fn span_of(&self, end: usize) -> Span {
+        let text = &self.buffer[..end];
+        Span {
+            start: self.consumed + text.len() - skip_ws(text).len(),
+            end: self.consumed + end,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.scanned = 0;
+        self.depth = 0;
+        self.in_string = false;
+        self.escaped = false;
+        self.in_atom = false;
+        self.in_tag = false;
+        self.in_comment = false;
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn it_parses_numbers() {
-        assert_eq!(parse_number("1"), Ok(("", Lval::Num(1.0_f64))));
+        // equality holds across whole numbers and floats, so match the type
+        assert!(matches!(parse_number("1"), Ok(("", Lval::Int(1)))));
+        assert!(matches!(parse_number("1.0"), Ok(("", Lval::Num(_)))));
+        assert!(matches!(parse_number("1e3"), Ok(("", Lval::Num(_)))));
+        assert!(matches!(parse_number("+7 x"), Ok((" x", Lval::Int(7)))));
+        // too big to be whole
+        assert!(matches!(
+            parse_number("99999999999999999999"),
+            Ok(("", Lval::Num(n))) if n == 1e20
+        ));
         assert_eq!(
             parse_number("1.000001-1"),
             Ok(("-1", Lval::Num(1.000001_f64)))
         );
         assert_eq!(parse_number("123E-02"), Ok(("", Lval::Num(1.23_f64))));
-        assert_eq!(parse_number("-12302"), Ok(("", Lval::Num(-12302_f64))));
+        assert!(matches!(
+            parse_number("-12302"),
+            Ok(("", Lval::Int(-12302)))
+        ));
         assert_eq!(parse_number("  \t1"), Ok(("", Lval::Num(1_f64))));
     }
 
+    #[test]
+    fn it_parses_numbers_with_separators() {
+        assert!(matches!(
+            parse_number("1_000_000"),
+            Ok(("", Lval::Int(1_000_000)))
+        ));
+        assert_eq!(parse_number("-1_000"), Ok(("", Lval::Num(-1e3_f64))));
+        assert_eq!(
+            parse_number("12.345_678"),
+            Ok(("", Lval::Num(12.345678_f64)))
+        );
+        assert_eq!(
+            parse_number("6.022_140_76e23"),
+            Ok(("", Lval::Num(6.02214076e23_f64)))
+        );
+        assert_eq!(parse_number("1e1_0"), Ok(("", Lval::Num(1e10_f64))));
+        assert_eq!(parse_number("25e-0_2 x"), Ok((" x", Lval::Num(0.25_f64))));
+
+        // underscores elsewhere are still symbols
+        assert_eq!(
+            parse_expression("_1"),
+            Ok(("", Lval::Sym(String::from("_1"))))
+        );
+        assert_eq!(
+            parse_expression("a_1"),
+            Ok(("", Lval::Sym(String::from("a_1"))))
+        );
+    }
+
+    #[test]
+    fn it_rejects_misplaced_separators() {
+        let misplaced = |input| match parse(input) {
+            Err(nom::Err::Failure(SyntaxError::MisplacedSeparator(rest))) => {
+                input.len() - rest.len()
+            }
+            other => panic!("{} gave {:?}", input, other),
+        };
+
+        assert_eq!(misplaced("1_"), 1);
+        assert_eq!(misplaced("+ 10__0"), 4);
+        assert_eq!(misplaced("1_.5"), 1);
+        assert_eq!(misplaced("1._5"), 2);
+        assert_eq!(misplaced("2_e10"), 1);
+        assert_eq!(misplaced("2e_10"), 2);
+        assert_eq!(misplaced("(+ 1 2.5_)"), 8);
+
+        let diagnostic = ParseDiagnostic::from_nom("1_000_", parse("1_000_").unwrap_err());
+        assert_eq!(diagnostic.offset, 5);
+    }
+
     #[test]
     fn it_parses_all_symbols() {
         assert_eq!(parse_symbol("+"), Ok(("", Lval::Sym(String::from("+")))));
@@ -123,6 +1117,124 @@ mod test {
         );
     }
 
+    #[test]
+    fn it_parses_predicate_names() {
+        assert_eq!(
+            parse_symbol("pure?"),
+            Ok(("", Lval::Sym(String::from("pure?"))))
+        );
+    }
+
+    #[test]
+    fn it_parses_keywords() {
+        assert_eq!(
+            parse_symbol(" :precision"),
+            Ok(("", Lval::Keyword(String::from("precision"))))
+        );
+        assert_eq!(parse_symbol(":"), Ok(("", Lval::Sym(String::from(":")))));
+        assert_eq!(
+            parse_symbol("a:b"),
+            Ok(("", Lval::Sym(String::from("a:b"))))
+        );
+        assert_eq!(
+            parse("[:precision 2 :width 8]").unwrap().1,
+            Lval::Sexpr(vec![Lval::Qexpr(vec![
+                Lval::Keyword(String::from("precision")),
+                Lval::Num(2_f64),
+                Lval::Keyword(String::from("width")),
+                Lval::Num(8_f64),
+            ])])
+        );
+    }
+
+    #[test]
+    fn it_parses_booleans() {
+        assert_eq!(parse_symbol(" true"), Ok(("", Lval::Bool(true))));
+        assert_eq!(parse_symbol("false)"), Ok((")", Lval::Bool(false))));
+        assert_eq!(parse_symbol("nil]"), Ok(("]", Lval::Nil)));
+        assert_eq!(
+            parse_symbol("trueish"),
+            Ok(("", Lval::Sym(String::from("trueish"))))
+        );
+        assert_eq!(
+            parse_symbol(":true"),
+            Ok(("", Lval::Keyword(String::from("true"))))
+        );
+    }
+
+    #[test]
+    fn it_parses_tagged_literals() {
+        let bytes = |b: &[u8]| Ok(("", Lval::Bytes(b.to_vec())));
+        assert_eq!(
+            parse_expression("#bytes[\"dead beef\"]"),
+            bytes(&[0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(
+            parse_expression(" #bytes[DEAD be ef]"),
+            bytes(&[0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(parse_expression("#bytes[]"), bytes(&[]));
+        assert_eq!(parse_expression("#bytes[\"\"]"), bytes(&[]));
+        assert_eq!(
+            parse_expression("#b64\"3q2+7w==\""),
+            bytes(&[0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(
+            parse("[#bytes[01] :k #b64\"\"]").unwrap().1,
+            Lval::Sexpr(vec![Lval::Qexpr(vec![
+                Lval::Bytes(vec![1]),
+                Lval::Keyword(String::from("k")),
+                Lval::Bytes(vec![]),
+            ])])
+        );
+    }
+
+    #[test]
+    fn it_rejects_bad_tagged_literals() {
+        let diagnose = |input| {
+            let diagnostic = ParseDiagnostic::from_nom(input, parse(input).unwrap_err());
+            (diagnostic.offset, diagnostic.message)
+        };
+
+        assert_eq!(
+            diagnose("(f #bytes[abc])").1,
+            "#bytes needs pairs of hex digits"
+        );
+        assert_eq!(diagnose("#bytes[zz]").1, "#bytes needs pairs of hex digits");
+        assert_eq!(diagnose("#bytes[00").1, "#bytes needs a closing ]");
+        assert_eq!(diagnose("#b64\"!!\"").1, "#b64 needs valid base64");
+        assert_eq!(
+            diagnose("#bytes[\"00…\"]").1,
+            "#bytes was cut short when printed and can't be read back"
+        );
+        assert_eq!(
+            diagnose("1 #x[1]"),
+            (2, String::from("unknown reader tag #x"))
+        );
+        assert_eq!(
+            diagnose("[#{:a 1}]"),
+            (1, String::from("unknown reader tag #{"))
+        );
+    }
+
+    #[test]
+    fn it_reads_printed_values_back() {
+        let values = vec![
+            Lval::Bytes(vec![]),
+            Lval::Bytes(vec![0, 1, 127, 128, 255]),
+            Lval::Bytes((0..=31).collect()),
+            Lval::Keyword(String::from("width")),
+            Lval::Qexpr(vec![Lval::Bytes(vec![7]), Lval::Keyword(String::from("k"))]),
+            Lval::Str(String::from("say \"hi\"")),
+            Lval::Str(String::from("C:\\lisp\\")),
+            Lval::Str(String::from("two\nlines\tand a tab")),
+        ];
+        for value in values {
+            let printed = format!("{:?}", value);
+            assert_eq!(parse_expression(&printed), Ok(("", value)), "{}", printed);
+        }
+    }
+
     #[test]
     fn it_parses_sexpr() {
         assert_eq!(
@@ -269,4 +1381,370 @@ mod test {
             ))
         );
     }
+
+    fn limits() -> ParseLimits {
+        ParseLimits {
+            max_depth: 4,
+            max_input_len: 32,
+            max_atom_len: 8,
+            allow_special_floats: false,
+        }
+    }
+
+    #[test]
+    fn it_maps_where_expressions_came_from() {
+        let source = "(+ 1 [a \"b c\"]) ; note\n  #b64\"AA==\"";
+        let tree = span_tree(source, &ParseLimits::default());
+        let spans = |tree: &SpanTree| {
+            tree.children
+                .iter()
+                .map(|child| &source[child.span.start..child.span.end])
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(spans(&tree), vec!["(+ 1 [a \"b c\"])", "#b64\"AA==\""]);
+        assert_eq!(spans(&tree.children[0]), vec!["+", "1", "[a \"b c\"]"]);
+        assert_eq!(spans(&tree.children[0].children[2]), vec!["a", "\"b c\""]);
+        assert_eq!(
+            tree.children[1].shifted(2).span,
+            Span { start: 27, end: 37 }
+        );
+    }
+
+    #[test]
+    fn it_reads_special_floats_only_when_allowed() {
+        let allowed = ParseLimits {
+            allow_special_floats: true,
+            ..ParseLimits::default()
+        };
+        let atoms = |input, limits: &ParseLimits| match parse_with_limits(input, limits) {
+            Ok(("", Lval::Sexpr(atoms))) => atoms,
+            other => panic!("{:?} gave {:?}", input, other),
+        };
+
+        match &atoms("+inf -inf nan nano", &allowed)[..] {
+            [Lval::Num(a), Lval::Num(b), Lval::Num(c), Lval::Sym(d)] => {
+                assert_eq!(*a, f64::INFINITY);
+                assert_eq!(*b, f64::NEG_INFINITY);
+                assert!(c.is_nan());
+                assert_eq!(d, "nano");
+            }
+            other => panic!("read {:?}", other),
+        }
+        assert_eq!(
+            atoms("+inf -inf nan", &ParseLimits::default()),
+            vec![
+                Lval::Sym(String::from("+inf")),
+                Lval::Sym(String::from("-inf")),
+                Lval::Sym(String::from("nan")),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_skips_comments() {
+        let forms = |input| match parse(input) {
+            Ok(("", Lval::Sexpr(forms))) => forms,
+            other => panic!("{:?} gave {:?}", input, other),
+        };
+        let sum = |a: f64, b: f64| {
+            Lval::Sexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(a),
+                Lval::Num(b),
+            ])
+        };
+
+        assert_eq!(forms("(+ 1 2) ; three"), vec![sum(1.0, 2.0)]);
+        assert_eq!(
+            forms("; adds things\n;; twice\n(+ 1 2)\n\n; and more\n(+ 3 4)\n"),
+            vec![sum(1.0, 2.0), sum(3.0, 4.0)]
+        );
+        assert_eq!(forms("(+ 1 ; two\n 2)"), vec![sum(1.0, 2.0)]);
+        assert_eq!(
+            forms("[(+ 1 ; ] not a closer\n 2) ;\n]"),
+            vec![Lval::Qexpr(vec![sum(1.0, 2.0)])]
+        );
+        assert_eq!(forms("; only a comment"), vec![]);
+
+        // but not inside strings, and atoms end where a comment starts
+        assert_eq!(
+            forms("\"a ; b\" x;y"),
+            vec![
+                Lval::Str(String::from("a ; b")),
+                Lval::Sym(String::from("x"))
+            ]
+        );
+
+        // errors are still located in the original text
+        let diagnostic = parse_located("; first\n(+ 1 2_)").unwrap_err();
+        assert_eq!(diagnostic.offset, 14);
+    }
+
+    #[test]
+    fn it_limits_nesting_depth() {
+        assert!(parse_with_limits("((((1))))", &limits()).is_ok());
+        assert_eq!(
+            parse_with_limits("(([[(1)]]))", &limits()),
+            Err(nom::Err::Failure(SyntaxError::NestedTooDeep("(1)]]))", 4)))
+        );
+
+        // adversarial input is rejected well before the stack is at risk
+        let deep = "(".repeat(100_000);
+        assert_eq!(
+            parse(&deep),
+            Err(nom::Err::Failure(SyntaxError::NestedTooDeep(
+                &deep[256..],
+                256
+            )))
+        );
+    }
+
+    #[test]
+    fn it_limits_input_length() {
+        let at_limit = format!("+{}", " 1".repeat(15));
+        assert_eq!(at_limit.len(), 31);
+        assert!(parse_with_limits(&format!("{} ", at_limit), &limits()).is_ok());
+        assert_eq!(
+            parse_with_limits(&format!("{}  ", at_limit), &limits()),
+            Err(nom::Err::Failure(SyntaxError::InputTooLong(32)))
+        );
+    }
+
+    #[test]
+    fn it_limits_atom_length() {
+        assert!(parse_with_limits("abcdefgh", &limits()).is_ok());
+        assert!(parse_with_limits("\"abcdef\"", &limits()).is_ok());
+        assert!(parse_with_limits("12345678", &limits()).is_ok());
+        assert_eq!(
+            parse_with_limits("+ abcdefghi", &limits()),
+            Err(nom::Err::Failure(SyntaxError::AtomTooLong("abcdefghi", 8)))
+        );
+        assert_eq!(
+            parse_with_limits("[\"abcdefg\"]", &limits()),
+            Err(nom::Err::Failure(SyntaxError::AtomTooLong(
+                "\"abcdefg\"]",
+                8
+            )))
+        );
+        assert_eq!(
+            parse_with_limits("123456789", &limits()),
+            Err(nom::Err::Failure(SyntaxError::AtomTooLong("123456789", 8)))
+        );
+    }
+
+    fn parse_incrementally(chunks: &[&str]) -> Vec<Result<Lval, ParseDiagnostic>> {
+        let mut incremental = Incremental::new();
+        let mut forms = vec![];
+        for chunk in chunks {
+            incremental.push_str(chunk);
+            while let Some(form) = incremental.next_form() {
+                forms.push(form);
+            }
+        }
+        incremental.finish();
+        while let Some(form) = incremental.next_form() {
+            forms.push(form);
+        }
+        forms
+    }
+
+    #[test]
+    fn it_reads_escapes_in_strings() {
+        assert_eq!(
+            parse_expression(r#""a \"b\" \\ c\nd\te""#),
+            Ok(("", Lval::Str(String::from("a \"b\" \\ c\nd\te"))))
+        );
+        // an escaped quote doesn't end the string, or a comment start one
+        let (forms, comments) = parse_with_comments(r#""x\";y" ; z"#).unwrap();
+        assert_eq!(forms, Lval::Sexpr(vec![Lval::Str(String::from("x\";y"))]));
+        assert_eq!(comments.len(), 1);
+
+        let failure = parse_located(r#"(+ "ab\q")"#).unwrap_err();
+        assert_eq!((failure.line, failure.column), (1, 7));
+        assert_eq!(
+            failure.message,
+            "unknown escape in a string, expected \\\", \\\\, \\n or \\t"
+        );
+        assert!(parse_located(r#""ab\""#).unwrap_err().is_incomplete());
+    }
+
+    #[test]
+    fn it_keeps_comments_aside() {
+        let source = "; area of a circle\n(def [area] ; one arg\n  (\\ [r] [* 3.14 r r]))\n\"a;b\"";
+        let (forms, comments) = parse_with_comments(source).unwrap();
+
+        assert_eq!(
+            forms,
+            parse("(def [area] (\\ [r] [* 3.14 r r])) \"a;b\"")
+                .unwrap()
+                .1
+        );
+        assert_eq!(
+            comments,
+            vec![
+                Comment {
+                    span: Span { start: 0, end: 18 },
+                    text: "; area of a circle".to_string(),
+                },
+                Comment {
+                    span: Span { start: 31, end: 40 },
+                    text: "; one arg".to_string(),
+                },
+            ]
+        );
+
+        // errors are still located in the original source
+        assert_eq!(parse_with_comments("; é\n(+ 1 2_)").unwrap_err().offset, 11);
+    }
+
+    #[test]
+    fn it_parses_incrementally_at_every_split() {
+        let program = "(def [add] (\\ [a b] [+ a b]))\n[1 2 (+ 3 4)] \"ceci n'est\" \"q\\\"uo;te\\\\\" abc\t12.5 (add 1 (- 4 2))  -7 #bytes[\"dead beef\"]#b64\"3q0=\" [#bytes[00]] ; the end\n; really (\n[a;b\n c]";
+        let expected = match parse(program) {
+            Ok((_, Lval::Sexpr(forms))) => forms.into_iter().map(Ok).collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+
+        for split in 0..=program.len() {
+            let (a, b) = program.split_at(split);
+            assert_eq!(parse_incrementally(&[a, b]), expected, "split at {}", split);
+        }
+
+        let chars = program
+            .char_indices()
+            .map(|(i, c)| &program[i..i + c.len_utf8()])
+            .collect::<Vec<_>>();
+        assert_eq!(parse_incrementally(&chars), expected);
+    }
+
+    #[test]
+    fn it_yields_forms_as_soon_as_they_close() {
+        let mut incremental = Incremental::new();
+        incremental.push_str("(+ 1 2) (+ 3");
+        assert_eq!(
+            incremental.next_form(),
+            Some(Ok(Lval::Sexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Num(2_f64),
+            ])))
+        );
+        assert_eq!(incremental.next_form(), None);
+
+        // an atom might still be growing until something terminates it
+        incremental.push_str(" 4) 12");
+        assert!(incremental.next_form().unwrap().is_ok());
+        assert_eq!(incremental.next_form(), None);
+        incremental.push_str("3; no newline before the end");
+        incremental.finish();
+        assert_eq!(incremental.next_form(), Some(Ok(Lval::Num(123_f64))));
+        assert_eq!(incremental.next_form(), None);
+    }
+
+    #[test]
+    fn it_tracks_spans_of_incremental_forms() {
+        let mut incremental = Incremental::new();
+        incremental.push_str("  (+ 1 2)\n  abc [1");
+        incremental.finish();
+
+        let spans = std::iter::from_fn(|| incremental.next_spanned_form())
+            .map(|(span, _)| span)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                Span { start: 2, end: 9 },
+                Span { start: 12, end: 15 },
+                Span { start: 16, end: 18 },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_explains_where_and_why_parsing_failed() {
+        let failure = |input| {
+            let d = parse_with_errors(input).unwrap_err();
+            (d.line, d.column, d.expected, d.message)
+        };
+
+        assert_eq!(
+            failure("(+ 1 (* 2 3)"),
+            (
+                1,
+                1,
+                Some(Expected::Close(')')),
+                "unclosed '(', expected ')' before the end of the input".to_string()
+            )
+        );
+        assert_eq!(
+            failure("(def [x]\n  (concat \"ab 1))"),
+            (
+                2,
+                11,
+                Some(Expected::Quote),
+                "unterminated string, expected '\"' before the end of the input".to_string()
+            )
+        );
+        assert_eq!(
+            failure("(+ ab@c 1)"),
+            (
+                1,
+                6,
+                Some(Expected::Close(')')),
+                "invalid character '@' in a symbol".to_string()
+            )
+        );
+        assert_eq!(
+            failure("(+ 1 2) 3)"),
+            (
+                1,
+                10,
+                Some(Expected::Expression),
+                "unexpected ')' with no list open".to_string()
+            )
+        );
+        assert_eq!(failure("[1 2)").3, "expected ']' but found ')'");
+        assert_eq!(failure("1 @").3, "invalid character '@'");
+
+        // only input that ran out could parse once there is more of it
+        let incomplete = |input| parse_with_errors(input).unwrap_err().is_incomplete();
+        assert!(incomplete("(+ 1 (* 2 3)"));
+        assert!(incomplete("[\"ab"));
+        assert!(!incomplete("(+ ab@c 1"));
+        assert!(!incomplete("[1 2)"));
+
+        // columns count characters, not bytes
+        let diagnostic = parse_with_errors("(list 1\n  [2 \"é\" 3)").unwrap_err();
+        assert_eq!(
+            diagnostic.to_string(),
+            "expected ']' but found ')' at line 2, column 11\n  [2 \"é\" 3)\n          ^"
+        );
+        assert_eq!(
+            Lerr::from(diagnostic).message,
+            "expected ']' but found ')' at line 2, column 11"
+        );
+
+        // only the neighbourhood of a failure on a long line is shown
+        let long = format!("({} @)", "x ".repeat(500));
+        let shown = parse_with_errors(&long).unwrap_err().to_string();
+        let lines = shown.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "invalid character '@' at line 1, column 1003");
+        assert_eq!(lines[1].chars().count(), EXCERPT_CHARS + "@)".len());
+        assert_eq!(lines[1].chars().nth(lines[2].len() - 1), Some('@'));
+    }
+
+    #[test]
+    fn it_reports_incremental_errors() {
+        let forms = parse_incrementally(&["1 (+ 2", " 3] (+ 4"]);
+        assert_eq!(forms.len(), 3);
+        assert_eq!(forms[0], Ok(Lval::Num(1_f64)));
+        let mismatched = forms[1].as_ref().unwrap_err();
+        assert_eq!(mismatched.offset, 8);
+        assert_eq!(mismatched.message, "expected ')' but found ']'");
+        let end = forms[2].as_ref().unwrap_err();
+        assert_eq!(end.offset, 14);
+        assert_eq!((end.line, end.column), (1, 15));
+        assert_eq!(end.message, "unexpected end of input");
+    }
 }
@@ -0,0 +1,475 @@
+use crate::{
+    builtin::as_values,
+    parser::{parse_with_comments, string_end, Comment, ParseDiagnostic},
+    seq_len, Lval,
+};
+
+/// The line width `format_source` aims for.
+pub const DEFAULT_WIDTH: usize = 80;
+
+// the source as the formatter sees it: atoms keep their original spelling
+// and comments are items like any other
+#[derive(Debug)]
+enum Node {
+    Atom(String),
+    List(char, Vec<Node>),
+    Comment(String),
+}
+
+/// Renders `lval` across as many lines as it needs to stay within `width`.
+pub fn pretty(lval: &Lval, width: usize) -> String {
+    let mut out = String::new();
+    layout(&to_node(lval), 0, width, &mut out);
+    out
+}
+
+/// How much of a value `render` shows before cutting it short.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Budget {
+    /// items shown from each list, the rest summed up as `… (n more)`
+    pub max_items: usize,
+    /// characters shown overall, the rest replaced by `…`
+    pub max_chars: usize,
+}
+
+impl Budget {
+    /// No limits, for showing a value in full.
+    pub fn unlimited() -> Self {
+        Budget {
+            max_items: usize::MAX,
+            max_chars: usize::MAX,
+        }
+    }
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget {
+            max_items: 100,
+            max_chars: 4096,
+        }
+    }
+}
+
+/// A value rendered within a `Budget`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rendered {
+    pub text: String,
+    /// whether anything was left out
+    pub truncated: bool,
+    /// how long the value is in full: items of a list, characters of a
+    /// string or bytes of a buffer
+    pub length: Option<usize>,
+}
+
+/// Renders `lval` on one line as `{}` would, showing at most
+/// `budget.max_items` items of each list and `budget.max_chars` characters
+/// in all, so a huge result can be printed without printing all of it.
+/// `[1 2 3 4 5]` with two items to a list shows as `[1 2 … (3 more)]`.
+pub fn render(lval: &Lval, budget: &Budget) -> Rendered {
+    let mut out = String::new();
+    let mut chars = 0;
+    let mut truncated = false;
+    let mut pending = vec![Piece::Value(lval)];
+
+    while let Some(piece) = pending.pop() {
+        let text = match piece {
+            Piece::Text(text) => text,
+            Piece::Value(list @ (Lval::Sexpr(items) | Lval::Qexpr(items))) => match as_values(list)
+            {
+                // written as the call that makes them, as `{}` does
+                Some(values) => {
+                    truncated |= values.len() > budget.max_items;
+                    open_list('(', values, budget, &mut pending);
+                    if !values.is_empty() {
+                        pending.push(Piece::Text(" ".to_string()));
+                    }
+                    "(values".to_string()
+                }
+                None => {
+                    truncated |= items.len() > budget.max_items;
+                    let open = if matches!(list, Lval::Sexpr(_)) {
+                        '('
+                    } else {
+                        '['
+                    };
+                    open_list(open, items, budget, &mut pending)
+                }
+            },
+            Piece::Value(leaf) => leaf.to_string(),
+        };
+
+        let len = text.chars().count();
+        if chars + len > budget.max_chars {
+            let fits = budget.max_chars - chars;
+            out.extend(text.chars().take(fits));
+            out.push('…');
+            truncated = true;
+            break;
+        }
+        out.push_str(&text);
+        chars += len;
+    }
+
+    Rendered {
+        text: out,
+        truncated,
+        length: match lval {
+            Lval::Sexpr(items) => Some(as_values(lval).map_or(items.len(), <[Lval]>::len)),
+            other => seq_len(other),
+        },
+    }
+}
+
+// part of a value `render` has still to write
+enum Piece<'a> {
+    Value(&'a Lval),
+    Text(String),
+}
+
+// queues up a list's items for `render`, giving back its opening bracket
+fn open_list<'a>(
+    open: char,
+    items: &'a [Lval],
+    budget: &Budget,
+    pending: &mut Vec<Piece<'a>>,
+) -> String {
+    pending.push(Piece::Text(close(open).to_string()));
+    let shown = items.len().min(budget.max_items);
+    if shown < items.len() {
+        let more = format!("… ({} more)", items.len() - shown);
+        if shown > 0 {
+            pending.push(Piece::Text(format!(" {}", more)));
+        } else {
+            pending.push(Piece::Text(more));
+        }
+    }
+    for (i, item) in items[..shown].iter().enumerate().rev() {
+        pending.push(Piece::Value(item));
+        if i > 0 {
+            pending.push(Piece::Text(" ".to_string()));
+        }
+    }
+    open.to_string()
+}
+
+/// Reformats lisp source in the standard style: a blank line between top
+/// level forms, comments kept where they were, lists that don't fit on one
+/// line broken with two space indents and closing delimiters on the last
+/// line. Formatting formatted source changes nothing.
+pub fn format_source(source: &str) -> Result<String, ParseDiagnostic> {
+    format_source_with_width(source, DEFAULT_WIDTH)
+}
+
+pub fn format_source_with_width(source: &str, width: usize) -> Result<String, ParseDiagnostic> {
+    let (_, comments) = parse_with_comments(source)?;
+    let nodes = read_nodes(source, &comments);
+
+    // comments go with the form after them, so blocks are split after forms
+    let mut blocks = vec![];
+    let mut block = String::new();
+    for node in &nodes {
+        layout(node, 0, width, &mut block);
+        block.push('\n');
+        if !matches!(node, Node::Comment(_)) {
+            blocks.push(std::mem::take(&mut block));
+        }
+    }
+    if !block.is_empty() {
+        blocks.push(block);
+    }
+
+    Ok(blocks.join("\n"))
+}
+
+/// Whether `source` is already formatted, for checking without rewriting.
+pub fn is_formatted(source: &str) -> Result<bool, ParseDiagnostic> {
+    format_source(source).map(|formatted| formatted == source)
+}
+
+fn to_node(lval: &Lval) -> Node {
+    if let Some(values) = as_values(lval) {
+        let values = values.iter().map(to_node);
+        return Node::List(
+            '(',
+            std::iter::once(Node::Atom("values".to_string()))
+                .chain(values)
+                .collect(),
+        );
+    }
+    match lval {
+        Lval::Sexpr(items) => Node::List('(', items.iter().map(to_node).collect()),
+        Lval::Qexpr(items) => Node::List('[', items.iter().map(to_node).collect()),
+        leaf => Node::Atom(format!("{:?}", leaf)),
+    }
+}
+
+// splits source the parser has already accepted into nodes, taking where
+// the comments are from the parser
+fn read_nodes(source: &str, comments: &[Comment]) -> Vec<Node> {
+    let mut comments = comments.iter().peekable();
+    let mut stack: Vec<(char, Vec<Node>)> = vec![];
+    let mut top = vec![];
+    let mut i = 0;
+
+    while i < source.len() {
+        let rest = &source[i..];
+        let c = rest.chars().next().unwrap_or_default();
+
+        let (node, len) = match comments.next_if(|comment| comment.span.start == i) {
+            Some(comment) => (
+                Some(Node::Comment(comment.text.clone())),
+                comment.span.end - i,
+            ),
+            None => match c {
+                '(' | '[' => {
+                    stack.push((c, vec![]));
+                    (None, 1)
+                }
+                ')' | ']' => {
+                    let (open, items) = stack.pop().unwrap_or(('(', vec![]));
+                    (Some(Node::List(open, items)), 1)
+                }
+                '"' => {
+                    let len = string_end(&rest[1..]).map_or(rest.len(), |end| end + 2);
+                    (Some(Node::Atom(rest[..len].to_string())), len)
+                }
+                c if c.is_whitespace() => (None, c.len_utf8()),
+                _ => {
+                    let mut len = rest
+                        .find(|c: char| c.is_whitespace() || "()[]\";".contains(c))
+                        .unwrap_or(rest.len());
+                    // a tagged literal like #bytes[...] keeps its body
+                    if c == '#' {
+                        let closer = match rest[len..].chars().next() {
+                            Some('[') => Some(']'),
+                            Some('"') => Some('"'),
+                            _ => None,
+                        };
+                        if let Some(closer) = closer {
+                            len = rest[len + 1..]
+                                .find(closer)
+                                .map_or(rest.len(), |end| len + end + 2);
+                        }
+                    }
+                    (Some(Node::Atom(rest[..len].to_string())), len)
+                }
+            },
+        };
+
+        if let Some(node) = node {
+            match stack.last_mut() {
+                Some((_, items)) => items.push(node),
+                None => top.push(node),
+            }
+        }
+        i += len;
+    }
+
+    top
+}
+
+// the node on one line, unless a comment forces a break
+fn flat(node: &Node) -> Option<String> {
+    match node {
+        Node::Atom(atom) => Some(atom.clone()),
+        Node::Comment(_) => None,
+        Node::List(open, items) => {
+            let items = items.iter().map(flat).collect::<Option<Vec<_>>>()?;
+            Some(format!("{}{}{}", open, items.join(" "), close(*open)))
+        }
+    }
+}
+
+fn close(open: char) -> char {
+    if open == '(' {
+        ')'
+    } else {
+        ']'
+    }
+}
+
+// writes `node` starting at `column`, with anything that overflows indented
+// two past it
+fn layout(node: &Node, column: usize, width: usize, out: &mut String) {
+    if let Some(flat) = flat(node) {
+        if column + flat.chars().count() <= width || !matches!(node, Node::List(..)) {
+            return out.push_str(&flat);
+        }
+    }
+
+    let (open, items) = match node {
+        Node::List(open, items) => (*open, items),
+        Node::Atom(atom) => return out.push_str(atom),
+        Node::Comment(comment) => return out.push_str(comment),
+    };
+
+    out.push(open);
+    let indent = " ".repeat(column + 2);
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+            out.push_str(&indent);
+            layout(item, column + 2, width, out);
+        } else {
+            layout(item, column + 1, width, out);
+        }
+    }
+
+    // a comment runs to the end of the line, so the closer can't follow it
+    if let Some(Node::Comment(_)) = items.last() {
+        out.push('\n');
+        out.push_str(&" ".repeat(column));
+    }
+    out.push(close(open));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn formats(source: &str, expected: &str) {
+        assert_eq!(format_source_with_width(source, 30).unwrap(), expected);
+    }
+
+    #[test]
+    fn it_pretty_prints_values() {
+        let lval = match parse("(def [area] (\\ [r] [* 3.14 (* r r)]))").unwrap().1 {
+            Lval::Sexpr(mut forms) => forms.remove(0),
+            other => other,
+        };
+        assert_eq!(pretty(&lval, 80), "(def [area] (\\ [r] [* 3.14 (* r r)]))");
+        assert_eq!(
+            pretty(&lval, 24),
+            "(def\n  [area]\n  (\\\n    [r]\n    [* 3.14 (* r r)]))"
+        );
+    }
+
+    #[test]
+    fn it_renders_within_a_budget() {
+        let numbers = |n: usize| Lval::Qexpr((1..=n).map(|i| Lval::Int(i as i64)).collect());
+        let within = |lval: &Lval, max_items, max_chars| {
+            let rendered = render(
+                lval,
+                &Budget {
+                    max_items,
+                    max_chars,
+                },
+            );
+            (rendered.text, rendered.truncated)
+        };
+
+        let big = numbers(1_000_000);
+        assert_eq!(
+            within(&big, 3, 100),
+            ("[1 2 3 … (999997 more)]".to_string(), true)
+        );
+        assert_eq!(
+            within(&big, 0, 100),
+            ("[… (1000000 more)]".to_string(), true)
+        );
+        assert_eq!(within(&big, 1000, 10), ("[1 2 3 4 5…".to_string(), true));
+        assert_eq!(render(&big, &Budget::default()).length, Some(1_000_000));
+
+        // small values come out just as {} has them
+        let nested = Lval::Sexpr(vec![
+            Lval::Sym("f".into()),
+            numbers(2),
+            Lval::Str("a b".into()),
+            Lval::Qexpr(vec![]),
+        ]);
+        assert_eq!(within(&nested, 4, 100), (nested.to_string(), false));
+        assert_eq!(
+            within(&nested, 1, 100),
+            ("(f … (3 more))".to_string(), true)
+        );
+        assert_eq!(
+            within(&nested, 2, 100),
+            ("(f [1 2] … (2 more))".to_string(), true)
+        );
+        assert_eq!(within(&nested, 4, 9), ("(f [1 2] …".to_string(), true));
+
+        let long = Lval::Str("x".repeat(50));
+        assert_eq!(within(&long, 10, 5), ("\"xxxx…".to_string(), true));
+        assert_eq!(render(&long, &Budget::unlimited()).length, Some(50));
+        assert_eq!(render(&Lval::Num(1_f64), &Budget::default()).length, None);
+
+        // the results of values are written as the call that makes them
+        let results = crate::builtin::values(vec![Lval::Int(1), numbers(2)]);
+        assert_eq!(
+            within(&results, 4, 100),
+            ("(values 1 [1 2])".to_string(), false)
+        );
+        assert_eq!(
+            within(&results, 1, 100),
+            ("(values 1 … (1 more))".to_string(), true)
+        );
+        assert_eq!(render(&results, &Budget::default()).length, Some(2));
+        assert_eq!(pretty(&results, 80), "(values 1 [1 2])");
+    }
+
+    #[test]
+    fn it_formats_source() {
+        formats("  (+ 1   2)(+ 3 4)\n\n\n", "(+ 1 2)\n\n(+ 3 4)\n");
+        formats(
+            "(def [fib] (\\ [n] [if (< n 2) [n] [+ (fib (- n 1)) (fib (- n 2))]]))",
+            "(def\n  [fib]\n  (\\\n    [n]\n    [if\n      (< n 2)\n      [n]\n      [+\n        (fib (- n 1))\n        (fib (- n 2))]]))\n",
+        );
+        // atoms keep their spelling
+        formats("[1_000 2.50 :key \"a  b\"]", "[1_000 2.50 :key \"a  b\"]\n");
+        formats(r#"(f "a \" ) b"  1)"#, "(f \"a \\\" ) b\" 1)\n");
+        formats(
+            "(f #bytes[\"de ad\"] #b64\"3q0=\")",
+            "(f #bytes[\"de ad\"] #b64\"3q0=\")\n",
+        );
+        formats("", "");
+    }
+
+    #[test]
+    fn it_keeps_comments_in_place() {
+        formats(
+            "; squares\n(def [sq] (\\ [x] [* x x])) ; inline\n(sq 2)\n; the end",
+            "; squares\n(def [sq] (\\ [x] [* x x]))\n\n; inline\n(sq 2)\n\n; the end\n",
+        );
+        formats(
+            "(+ 1 ; one\n 2 ; two\n)",
+            "(+\n  1\n  ; one\n  2\n  ; two\n)\n",
+        );
+        formats("[; first\n a]", "[; first\n  a]\n");
+    }
+
+    #[test]
+    fn it_is_idempotent() {
+        let corpus = vec![
+            "(+ 1 2)",
+            "(def [fib] (\\ [n] [if (< n 2) [n] [+ (fib (- n 1)) (fib (- n 2))]]))",
+            "; header\n\n\n(def [a] 1) ; trailing\n(def [b] [1 2 3 4 5 6 7 8 9 10 11 12 13 14 15])",
+            "(+ 1 ; one\n 2 ; two\n)",
+            "[[[[[[1 2 3] [4 5 6]] [[7 8 9]]]]] \"str;not a comment\" :kw]",
+            "(fun [greet name] [concat \"hello, \" name \"; welcome\"])\n\n\n(greet \"you\")",
+            "[; first\n a ; second\n]",
+        ];
+
+        for source in corpus {
+            for width in &[10, 30, 80] {
+                let once = format_source_with_width(source, *width).unwrap();
+                let twice = format_source_with_width(&once, *width).unwrap();
+                assert_eq!(once, twice, "formatting {:?} at width {}", source, width);
+
+                // and the program itself is untouched
+                assert_eq!(
+                    parse_with_comments(&once).unwrap().0,
+                    parse_with_comments(source).unwrap().0
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_checks_formatting() {
+        assert_eq!(is_formatted("(+ 1 2)\n"), Ok(true));
+        assert_eq!(is_formatted("(+ 1  2)"), Ok(false));
+        assert_eq!(is_formatted("(+ 1 2_)").unwrap_err().offset, 6);
+    }
+}
@@ -0,0 +1,1456 @@
+use crate::{
+    builtin, completion,
+    env::Origin,
+    eval, features, lisp,
+    parser::{is_symbol_char, locate, parse_located_with, span_tree, ParseDiagnostic, ParseLimits},
+    pretty::{render, Budget},
+    version, Lenv, Lerr, LerrType, Lval,
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/// Where a session reads its lines from.
+pub trait LineSource {
+    /// Shows `prompt` and reads the next line, `None` once input runs out.
+    fn read_line(&mut self, prompt: &str) -> Option<String>;
+
+    /// Puts `buffer` into the next line for the user to edit, where the
+    /// source supports it.
+    fn prefill(&mut self, _buffer: &str) {}
+
+    /// Like `read_line`, for a source that can complete the names visible
+    /// in `env` and step back through `history` as the line is typed.
+    fn edit_line(&mut self, prompt: &str, _env: &Lenv, _history: &History) -> Option<String> {
+        self.read_line(prompt)
+    }
+}
+
+/// Reads from the terminal, printing the prompt to stdout first.
+pub struct Stdin;
+
+impl LineSource for Stdin {
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        print!("{}", prompt);
+        io::stdout().flush().ok()?;
+
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    }
+}
+
+/// Reads from the terminal through a line editor, with the `cli` feature:
+/// tab completes names through `complete_at`, the arrow keys step through
+/// the history, and a line put back by `prefill` comes up ready to edit.
+#[cfg(feature = "cli")]
+#[derive(Default)]
+pub struct LineEditor {
+    prefill: String,
+}
+
+#[cfg(feature = "cli")]
+impl LineEditor {
+    // a fresh editor for each line, since what it completes from borrows
+    // the environment the line is read in
+    fn edit(
+        &mut self,
+        prompt: &str,
+        helper: Option<Names>,
+        history: &[(usize, &str)],
+    ) -> Option<String> {
+        use rustyline::{error::ReadlineError, history::DefaultHistory, Config, Editor};
+
+        let config = Config::builder()
+            .max_history_size(history.len().max(1))
+            .ok()?
+            .build();
+        let mut editor = Editor::<Names, DefaultHistory>::with_config(config).ok()?;
+        editor.set_helper(helper);
+        for (_, entry) in history {
+            editor.add_history_entry(*entry).ok()?;
+        }
+        let prefill = std::mem::take(&mut self.prefill);
+        match editor.readline_with_initial(prompt, (&prefill, "")) {
+            Ok(line) => Some(line),
+            // ^C gives up on the line rather than the session
+            Err(ReadlineError::Interrupted) => Some(String::new()),
+            Err(_) => None,
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl LineSource for LineEditor {
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        self.edit(prompt, None, &[])
+    }
+
+    fn prefill(&mut self, buffer: &str) {
+        self.prefill = buffer.to_string();
+    }
+
+    fn edit_line(&mut self, prompt: &str, env: &Lenv, history: &History) -> Option<String> {
+        self.edit(prompt, Some(Names(env)), &history.last(history.len()))
+    }
+}
+
+// what the line editor completes from
+#[cfg(feature = "cli")]
+struct Names<'a>(&'a Lenv);
+
+#[cfg(feature = "cli")]
+impl rustyline::completion::Completer for Names<'_> {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete_at(self.0, line, pos))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl rustyline::hint::Hinter for Names<'_> {
+    type Hint = String;
+}
+
+#[cfg(feature = "cli")]
+impl rustyline::highlight::Highlighter for Names<'_> {}
+
+#[cfg(feature = "cli")]
+impl rustyline::validate::Validator for Names<'_> {}
+
+#[cfg(feature = "cli")]
+impl rustyline::Helper for Names<'_> {}
+
+/// A fixed list of lines, for driving a session without a terminal.
+pub struct Scripted {
+    lines: VecDeque<String>,
+}
+
+impl Scripted {
+    pub fn new(lines: &[&str]) -> Self {
+        Scripted {
+            lines: lines.iter().map(|line| line.to_string()).collect(),
+        }
+    }
+}
+
+impl LineSource for Scripted {
+    fn read_line(&mut self, _prompt: &str) -> Option<String> {
+        self.lines.pop_front()
+    }
+}
+
+/// How many lines `History` keeps by default.
+pub const HISTORY_CAP: usize = 1000;
+
+/// The lines entered at the REPL, numbered from 1 in the order they were
+/// entered. Numbers stay put when old lines are trimmed off the front.
+#[derive(Clone, Debug)]
+pub struct History {
+    entries: VecDeque<String>,
+    // the number of the oldest entry still kept
+    first: usize,
+    cap: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::with_cap(HISTORY_CAP)
+    }
+}
+
+impl History {
+    pub fn with_cap(cap: usize) -> Self {
+        History {
+            entries: VecDeque::new(),
+            first: 1,
+            cap,
+        }
+    }
+
+    /// Records `line`, dropping the oldest entry when full. Returns the
+    /// line's number.
+    pub fn append(&mut self, line: &str) -> usize {
+        self.entries.push_back(line.to_string());
+        while self.entries.len() > self.cap {
+            self.entries.pop_front();
+            self.first += 1;
+        }
+        self.first + self.entries.len() - 1
+    }
+
+    /// Entry number `index`, if it hasn't been trimmed.
+    pub fn get(&self, index: usize) -> Option<&str> {
+        index
+            .checked_sub(self.first)
+            .and_then(|offset| self.entries.get(offset))
+            .map(String::as_str)
+    }
+
+    /// The last `n` entries with their numbers, oldest first.
+    pub fn last(&self, n: usize) -> Vec<(usize, &str)> {
+        let skip = self.entries.len().saturating_sub(n);
+        self.numbered().skip(skip).collect()
+    }
+
+    /// Entries containing `needle` with their numbers, most recent first.
+    pub fn search(&self, needle: &str) -> Vec<(usize, &str)> {
+        self.numbered()
+            .rev()
+            .filter(|(_, line)| line.contains(needle))
+            .collect()
+    }
+
+    /// The history saved at `path`, keeping its last `cap` entries. A file
+    /// that is missing, can't be read or isn't a saved history starts a
+    /// fresh one instead.
+    pub fn load(path: &Path, cap: usize) -> Self {
+        let mut history = History::with_cap(cap);
+        let entries = fs::read_to_string(path)
+            .ok()
+            .and_then(|saved| saved.lines().map(unescape).collect::<Option<Vec<_>>>());
+        for entry in entries.unwrap_or_default() {
+            history.append(&entry);
+        }
+        history
+    }
+
+    /// Writes the entries to `path` one a line, with the newlines and
+    /// backslashes in them escaped.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut saved = String::new();
+        for entry in &self.entries {
+            saved.push_str(&entry.replace('\\', "\\\\").replace('\n', "\\n"));
+            saved.push('\n');
+        }
+        fs::write(path, saved)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn numbered(&self) -> impl DoubleEndedIterator<Item = (usize, &str)> {
+        let first = self.first;
+        self.entries
+            .iter()
+            .enumerate()
+            .map(move |(offset, line)| (first + offset, line.as_str()))
+    }
+}
+
+// a saved entry as it was entered, `None` for an escape `save` doesn't write
+fn unescape(saved: &str) -> Option<String> {
+    let mut entry = String::with_capacity(saved.len());
+    let mut chars = saved.chars();
+    while let Some(c) = chars.next() {
+        entry.push(match c {
+            '\\' => match chars.next()? {
+                'n' => '\n',
+                '\\' => '\\',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(entry)
+}
+
+/// Where `History` is saved by default, `~/.lisp_history`.
+pub fn default_history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".lisp_history"))
+}
+
+/// The input and output a session talks to. Both halves are shared so a
+/// `(repl)` typed at a break can open a nested session on the same console.
+#[derive(Clone)]
+pub struct Console {
+    input: Rc<RefCell<dyn LineSource>>,
+    output: Rc<RefCell<dyn Write>>,
+    history: Rc<RefCell<History>>,
+    // where the history is saved after each line, if anywhere
+    history_file: Option<PathBuf>,
+    timing: Rc<Cell<bool>>,
+    /// whether the terminal is fancy enough for the caret under parse errors
+    /// to be shown in red
+    pub color: bool,
+    pub chrome: Chrome,
+}
+
+impl Console {
+    pub fn new(input: impl LineSource + 'static, output: impl Write + 'static) -> Self {
+        Console {
+            input: Rc::new(RefCell::new(input)),
+            output: Rc::new(RefCell::new(output)),
+            history: Rc::new(RefCell::new(History::default())),
+            history_file: None,
+            timing: Rc::new(Cell::new(false)),
+            color: false,
+            chrome: Chrome::default(),
+        }
+    }
+
+    /// The terminal, through a `LineEditor` with the `cli` feature when
+    /// both ends of it are a terminal, and read plainly otherwise.
+    pub fn stdio() -> Self {
+        #[cfg(feature = "cli")]
+        {
+            use std::io::IsTerminal;
+            if io::stdin().is_terminal() && io::stdout().is_terminal() {
+                return Console::new(LineEditor::default(), io::stdout());
+            }
+        }
+        Console::new(Stdin, io::stdout())
+    }
+
+    /// The lines entered on this console, shared with anything else that
+    /// wants them, like a completer.
+    pub fn history(&self) -> Rc<RefCell<History>> {
+        self.history.clone()
+    }
+
+    /// Loads the history saved at `path`, keeping up to `cap` entries, and
+    /// saves it back there after every line entered from now on, so however
+    /// the session ends nothing entered is lost.
+    pub fn persist_history(&mut self, path: impl Into<PathBuf>, cap: usize) {
+        let path = path.into();
+        *self.history.borrow_mut() = History::load(&path, cap);
+        self.history_file = Some(path);
+    }
+
+    /// Whether to print, after each result, how long the line took to parse
+    /// and to evaluate, and with the `alloc-stats` feature what each
+    /// allocated. `:time` turns it on and off.
+    pub fn set_timing(&self, on: bool) {
+        self.timing.set(on)
+    }
+
+    fn read_line(&self, prompt: &str, env: &Lenv) -> Option<String> {
+        let history = self.history.borrow();
+        self.input.borrow_mut().edit_line(prompt, env, &history)
+    }
+
+    fn prefill(&self, buffer: &str) {
+        self.input.borrow_mut().prefill(buffer)
+    }
+
+    fn write_line(&self, line: &str) {
+        // a console that can't be written to has nowhere to report that either
+        let _ = writeln!(self.output.borrow_mut(), "{}", line);
+    }
+}
+
+/// How a session ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SessionEnd {
+    /// `:continue` was entered
+    Continue,
+    /// `:abort` was entered
+    Abort,
+    /// the input ran out
+    Eof,
+}
+
+thread_local! {
+    static CONSOLE: RefCell<Option<Console>> = const { RefCell::new(None) };
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Sets the console that `(repl)` breaks talk to, returning the previous
+/// one. With none set they use stdin and stdout.
+pub fn set_console(console: Option<Console>) -> Option<Console> {
+    CONSOLE.with(|current| current.replace(console))
+}
+
+/// The prompt a console shows unless given another.
+pub const DEFAULT_PROMPT: &str = "lisp> ";
+
+/// The colors a prompt can be shown in.
+pub const PROMPT_COLORS: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// How a console's sessions present themselves. The default is the plain
+/// `lisp> ` prompt with results and nothing else.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chrome {
+    prompt: String,
+    // the ANSI color code
+    prompt_color: Option<u8>,
+    /// print each line as it was parsed before its result
+    pub echo_ast: bool,
+    /// print nothing for results that are `nil`
+    pub hide_unit: bool,
+    /// greet with the version and features when `run` starts
+    pub banner: bool,
+    /// how much of a result to print, `:full` printing the last one whole
+    pub output_budget: Budget,
+}
+
+impl Default for Chrome {
+    fn default() -> Self {
+        Chrome {
+            prompt: DEFAULT_PROMPT.to_string(),
+            prompt_color: None,
+            echo_ast: false,
+            hide_unit: false,
+            banner: true,
+            output_budget: Budget::default(),
+        }
+    }
+}
+
+impl Chrome {
+    /// Sets the prompt, and optionally its color by one of the
+    /// `PROMPT_COLORS`. Control characters aren't allowed in the text since
+    /// they would throw off line editing.
+    pub fn set_prompt(&mut self, text: &str, color: Option<&str>) -> Result<(), String> {
+        if let Some(c) = text.chars().find(|c| c.is_control()) {
+            return Err(format!(
+                "the prompt can't contain the control character {:?}",
+                c
+            ));
+        }
+        let code = match color {
+            None => None,
+            Some(color) => match PROMPT_COLORS.iter().position(|name| *name == color) {
+                Some(i) => Some(30 + i as u8),
+                None => {
+                    return Err(format!(
+                        "unknown prompt color {:?}, expected one of {}",
+                        color,
+                        PROMPT_COLORS.join(", ")
+                    ))
+                }
+            },
+        };
+
+        self.prompt = text.to_string();
+        self.prompt_color = code;
+        Ok(())
+    }
+
+    /// The prompt for a session `depth` breaks deep, with the depth before
+    /// any closing `>`. Color codes are wrapped in `\x01` and `\x02` so line
+    /// editors know they take up no room.
+    pub fn prompt(&self, depth: usize) -> String {
+        let text = if depth == 0 {
+            self.prompt.clone()
+        } else {
+            match self.prompt.trim_end().strip_suffix('>') {
+                Some(base) => format!("{}[{}]> ", base, depth),
+                None => format!("{}[{}] ", self.prompt.trim_end(), depth),
+            }
+        };
+
+        self.colored(text)
+    }
+
+    /// The prompt for the lines after the first of a form that spans more
+    /// than one, in the prompt's color.
+    pub fn continuation(&self) -> String {
+        self.colored(CONTINUATION.to_string())
+    }
+
+    fn colored(&self, text: String) -> String {
+        match self.prompt_color {
+            Some(code) => format!("\x01\x1b[{}m\x02{}\x01\x1b[0m\x02", code, text),
+            None => text,
+        }
+    }
+}
+
+const CONTINUATION: &str = "...> ";
+
+/// The prompt for a session `depth` breaks deep.
+pub fn prompt(depth: usize) -> String {
+    Chrome::default().prompt(depth)
+}
+
+/// What `run` greets with.
+pub fn banner() -> String {
+    format!("oolisp {} ({})", version(), features().join(", "))
+}
+
+/// Starts a top level session, greeting first if the console's chrome
+/// says to.
+pub fn run(env: &mut Lenv, console: &Console) -> SessionEnd {
+    if console.chrome.banner {
+        console.write_line(&banner());
+    }
+    run_session(env, console, 0)
+}
+
+/// Reads lines from `console` and evaluates them in `env` until the input
+/// runs out or a meta command ends the session. Besides `:continue` and
+/// `:abort`, `:env` lists the bindings visible from here, `:history [n]`
+/// lists the last n lines entered, `:!k` runs line k again, `:where name`
+/// tells where name was defined, `:complete text` lists what the word
+/// ending text could be completed to, `:time` turns timing each line on
+/// or off, `:bench n expr` times n runs of expr and
+/// `:full` prints the last result without cutting it short. A line that
+/// leaves a list or string open is continued on the lines after it until
+/// the form is whole, or dropped at a blank line.
+pub fn run_session(env: &mut Lenv, console: &Console, depth: usize) -> SessionEnd {
+    while let Some(line) = console.read_line(&console.chrome.prompt(depth), env) {
+        let line = line.trim();
+        match line {
+            "" => continue,
+            ":continue" => return SessionEnd::Continue,
+            ":abort" => return SessionEnd::Abort,
+            ":env" => {
+                console.write_line(&format!(
+                    "{} bindings, ~{} bytes",
+                    env.bindings(),
+                    env.approximate_bytes()
+                ));
+                console.write_line(&lisp(env, "env"));
+            }
+            _ if line == ":history" || line.starts_with(":history ") => {
+                match line[":history".len()..].trim() {
+                    "" => list_history(console, HISTORY_SHOWN),
+                    n => match n.parse() {
+                        Ok(n) => list_history(console, n),
+                        Err(_) => console.write_line(&format!(
+                            "Error: :history needs a count but was given {}",
+                            n
+                        )),
+                    },
+                }
+            }
+            ":time" => {
+                console.timing.set(!console.timing.get());
+                console.write_line(match console.timing.get() {
+                    true => "timing on",
+                    false => "timing off",
+                });
+            }
+            ":full" => match env.last_result() {
+                Some(lval) => console.write_line(&lval.to_string()),
+                None => console.write_line("nil"),
+            },
+            _ if line.starts_with(":where ") => {
+                let name = line[":where".len()..].trim();
+                console.write_line(&describe_origins(name, env.origins(name)));
+            }
+            _ if line.starts_with(":complete ") => {
+                let text = line[":complete".len()..].trim_start();
+                let (_, names) = complete_at(env, text, text.len());
+                match names.is_empty() {
+                    true => console.write_line("no completions"),
+                    false => console.write_line(&names.join(" ")),
+                }
+            }
+            _ if line.starts_with(":bench ") => {
+                bench_line(env, console, line[":bench".len()..].trim());
+            }
+            _ if line.starts_with(":!") => {
+                let entry = line[2..]
+                    .parse()
+                    .ok()
+                    .and_then(|index| console.history.borrow().get(index).map(str::to_string));
+                match entry {
+                    Some(entry) => {
+                        console.write_line(&format!("{}{}", console.chrome.prompt(depth), entry));
+                        eval_line(env, console, &entry);
+                    }
+                    None => console
+                        .write_line(&format!("Error: there is no history entry {}", &line[2..])),
+                }
+            }
+            _ => {
+                if let Some(form) = read_form(env, console, line) {
+                    eval_line(env, console, &form);
+                }
+            }
+        }
+    }
+    SessionEnd::Eof
+}
+
+// reads lines onto `line` for as long as it is a form that was left open,
+// `None` if a blank line gives up on it. Input running out leaves it to
+// fail to parse.
+fn read_form(env: &Lenv, console: &Console, line: &str) -> Option<String> {
+    let limits = ParseLimits::for_env(env);
+    let mut form = line.to_string();
+    while matches!(parse_located_with(&form, &limits), Err(d) if d.is_incomplete()) {
+        match console.read_line(&console.chrome.continuation(), env) {
+            Some(next) if next.trim().is_empty() => return None,
+            Some(next) => {
+                form.push('\n');
+                form.push_str(next.trim_end());
+            }
+            None => break,
+        }
+    }
+    Some(form)
+}
+
+/// What the word ending at byte `cursor` of `line` could be completed to,
+/// for a line editor: where the word starts, and the names visible in `env`
+/// starting with it, sorted. Names defined during the session are offered
+/// as soon as they are. Nothing is offered inside a string or a comment.
+pub fn complete_at(env: &Lenv, line: &str, cursor: usize) -> (usize, Vec<String>) {
+    let before = &line[..cursor];
+    let start = before.rfind(|c| !is_symbol_char(c)).map_or(0, |i| {
+        i + before[i..].chars().next().map_or(1, char::len_utf8)
+    });
+    if in_string_or_comment(&before[..start]) {
+        return (start, vec![]);
+    }
+    let names = completion::complete(env, &before[start..])
+        .into_iter()
+        .map(|item| item.name)
+        .collect();
+    (start, names)
+}
+
+// whether the end of `text` is within a string or a comment
+fn in_string_or_comment(text: &str) -> bool {
+    let (mut string, mut comment) = (false, false);
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => comment = false,
+            _ if comment => {}
+            '\\' if string => {
+                chars.next();
+            }
+            '"' => string = !string,
+            ';' if !string => comment = true,
+            _ => {}
+        }
+    }
+    string || comment
+}
+
+// how many entries a bare `:history` lists
+const HISTORY_SHOWN: usize = 20;
+
+fn list_history(console: &Console, n: usize) {
+    for (index, line) in console.history.borrow().last(n) {
+        console.write_line(&format!("{:>5}  {}", index, line));
+    }
+}
+
+// `:bench n expr` prints the timings and then the result
+fn bench_line(env: &mut Lenv, console: &Console, args: &str) {
+    let (count, expr) = args.split_once(' ').unwrap_or((args, ""));
+    let iterations = match count.parse() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            return console.write_line(&format!(
+                "Error: :bench needs a positive count but was given {}",
+                count
+            ))
+        }
+    };
+    let forms = match parse_located_with(expr, &ParseLimits::for_env(env)) {
+        Ok(Lval::Sexpr(forms)) => forms,
+        Ok(form) => vec![form],
+        Err(diagnostic) => return console.write_line(&format!("{:?}", Lerr::from(diagnostic))),
+    };
+
+    match builtin::bench(env, iterations, &Lval::Qexpr(forms)) {
+        Ok(bench) => {
+            console.write_line(&format!(
+                "{} runs: min {:.3} ms, max {:.3} ms, mean {:.3} ms, median {:.3} ms",
+                iterations, bench.min, bench.max, bench.mean, bench.median
+            ));
+            console.write_line(&format!("{:?}", bench.result));
+        }
+        Err(e) => console.write_line(&format!("{:?}", e)),
+    }
+}
+
+/// What `:where` prints for `name`.
+pub fn describe_origins(name: &str, origins: Option<Vec<Origin>>) -> String {
+    let origins = match origins {
+        None => return format!("{} is not defined", name),
+        Some(origins) if origins.is_empty() => return format!("{}: unknown", name),
+        Some(origins) => origins,
+    };
+
+    let mut description = format!("{}: {}", name, origins[0]);
+    if origins.len() > 1 {
+        let previous = origins[1..]
+            .iter()
+            .map(Origin::to_string)
+            .collect::<Vec<_>>();
+        description.push_str(&format!(", previously {}", previous.join(", ")));
+    }
+    description
+}
+
+// records `line` in the history and prints what it evaluates to, crediting
+// any definitions to the history entry
+fn eval_line(env: &mut Lenv, console: &Console, line: &str) {
+    let index = console.history.borrow_mut().append(line);
+    if let Some(path) = &console.history_file {
+        // a history that can't be saved is no reason to stop the session
+        let _ = console.history.borrow().save(path);
+    }
+    if line == "env" {
+        return console.write_line(&lisp(env, line));
+    }
+
+    let limits = ParseLimits::for_env(env);
+    let parsing = Timer::start(env);
+    let parsed = parse_located_with(line, &limits).map(|ast| (ast, span_tree(line, &limits)));
+    let parsing = parsing.report(env);
+    match parsed {
+        Ok((ast, spans)) => {
+            if console.chrome.echo_ast {
+                console.write_line(&format!("{:?}", ast));
+            }
+            let previous = env.set_origin(Origin::Repl(index));
+            let evaluating = Timer::start(env);
+            let evaluated = eval::eval_spanned(env, ast, spans);
+            let evaluating = evaluating.report(env);
+            let result = match evaluated {
+                Ok(lval) => {
+                    let rendered = render(&lval, &console.chrome.output_budget);
+                    env.set_last_result(lval);
+                    rendered.text
+                }
+                Err(e) => format!("{:#?}", e),
+            };
+            env.set_origin(previous);
+            for warning in env.take_warnings() {
+                console.write_line(&format!("Warning: {}", warning));
+            }
+            if !(console.chrome.hide_unit && result == "nil") {
+                console.write_line(&result);
+            }
+            if console.timing.get() {
+                console.write_line(&format!("parse {}, eval {}", parsing, evaluating));
+            }
+        }
+        Err(diagnostic) => {
+            let shown = format!("Error: Syntax - {}", diagnostic);
+            match shown.rsplit_once('\n') {
+                Some((located, caret)) if console.color => {
+                    console.write_line(located);
+                    console.write_line(&caret.replace('^', "\x1b[31m^\x1b[0m"));
+                }
+                _ => console.write_line(&shown),
+            }
+            console.prefill(&recover(line, &diagnostic).buffer);
+        }
+    }
+}
+
+// times a step of running a line by the environment's clock, counting
+// allocations too with the `alloc-stats` feature
+struct Timer(f64);
+
+impl Timer {
+    fn start(env: &Lenv) -> Self {
+        #[cfg(feature = "alloc-stats")]
+        crate::alloc::reset();
+        Timer(env.now())
+    }
+
+    // what the step took, read before anything is allocated to say so
+    fn report(self, env: &Lenv) -> String {
+        #[cfg(feature = "alloc-stats")]
+        let counted = (crate::alloc::allocations(), crate::alloc::peak_bytes());
+        let text = format!("{:.3} ms", env.now() - self.0);
+        #[cfg(feature = "alloc-stats")]
+        let text = format!(
+            "{} ({} allocations, peak {} bytes)",
+            text, counted.0, counted.1
+        );
+        text
+    }
+}
+
+/// How to help the user fix input that failed to parse. Input that parsed
+/// but failed to evaluate was valid, so it gets no recovery.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recovery {
+    /// the failed input, to edit rather than retype
+    pub buffer: String,
+    /// 1-based line of the input the error is on
+    pub line: usize,
+    /// 1-based column of the error on that line, in characters
+    pub column: usize,
+    /// a `^` under the error column
+    pub caret: String,
+}
+
+impl Recovery {
+    /// The line of `input` the error is on.
+    pub fn source_line<'a>(&self, input: &'a str) -> &'a str {
+        input.lines().nth(self.line - 1).unwrap_or("")
+    }
+}
+
+/// Locates `diagnostic` within `input` and pre-fills the failed input.
+pub fn recover(input: &str, diagnostic: &ParseDiagnostic) -> Recovery {
+    let (line, column) = locate(input, diagnostic.offset);
+    Recovery {
+        buffer: input.to_string(),
+        line,
+        column,
+        caret: format!("{}^", " ".repeat(column - 1)),
+    }
+}
+
+/// Opens a nested session on the live environment, one level deeper than
+/// whichever session is already running. `:abort` becomes a Cancelled
+/// error so the code around the break unwinds.
+pub fn break_session(env: &mut Lenv) -> Result<Lval, Lerr> {
+    let console = CONSOLE
+        .with(|current| current.borrow().clone())
+        .unwrap_or_else(Console::stdio);
+
+    let depth = DEPTH.with(|depth| depth.get()) + 1;
+    DEPTH.with(|current| current.set(depth));
+    let end = run_session(env, &console, depth);
+    DEPTH.with(|current| current.set(depth - 1));
+
+    match end {
+        SessionEnd::Continue | SessionEnd::Eof => Ok(Lval::Nil),
+        SessionEnd::Abort => Err(Lerr::new(
+            LerrType::Cancelled,
+            format!("the break at depth {} was aborted", depth),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        env::init_env,
+        interpreter::{ErrorPolicy, Interpreter},
+        parser::{parse, parse_located},
+    };
+
+    /// Collects everything a session prints.
+    #[derive(Clone, Default)]
+    struct Transcript(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Transcript {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transcript {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.borrow().clone())
+                .unwrap()
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    fn run_with(lines: &[&str], program: &str) -> (Interpreter, Result<Lval, Lerr>, Transcript) {
+        let transcript = Transcript::default();
+        set_console(Some(Console::new(Scripted::new(lines), transcript.clone())));
+
+        let mut interpreter = Interpreter::new();
+        let mut report = interpreter.eval_program(program, ErrorPolicy::StopAtFirst);
+
+        set_console(None);
+        let result = match report.failures.pop() {
+            Some(failure) => Err(failure.error),
+            None => Ok(report.last.unwrap()),
+        };
+        (interpreter, result, transcript)
+    }
+
+    #[test]
+    fn it_runs_a_session() {
+        let mut env = init_env();
+        let transcript = Transcript::default();
+        let console = Console::new(
+            Scripted::new(&["(+ 1 2)", "", "(head [])", ":continue", "(+ 3 4)"]),
+            transcript.clone(),
+        );
+
+        assert_eq!(run_session(&mut env, &console, 0), SessionEnd::Continue);
+        assert_eq!(transcript.lines().len(), 3);
+        assert_eq!(transcript.lines()[0], "3");
+        assert!(transcript.lines()[1].starts_with("Error: EmptyList"));
+        assert_eq!(transcript.lines()[2], "  called from head");
+
+        // the rest of the input is left for whoever reads next
+        assert_eq!(run_session(&mut env, &console, 0), SessionEnd::Eof);
+        assert_eq!(transcript.lines()[3], "7");
+    }
+
+    #[test]
+    fn it_survives_a_panicking_builtin() {
+        let mut env = init_env();
+        crate::add_builtin(&mut env, "explode", |_, _| panic!("kaboom"), (0, None));
+        let transcript = Transcript::default();
+        let console = Console::new(
+            Scripted::new(&["(explode 1)", "(+ 1 2)"]),
+            transcript.clone(),
+        );
+
+        assert_eq!(run_session(&mut env, &console, 0), SessionEnd::Eof);
+        assert!(transcript.lines()[0].starts_with("Error: Internal"));
+        assert!(transcript.lines()[0].contains("builtin explode panicked: kaboom"));
+        assert_eq!(transcript.lines()[1], "  called from explode");
+        assert_eq!(transcript.lines()[2], "3");
+    }
+
+    #[test]
+    fn it_assembles_prompts() {
+        let mut chrome = Chrome::default();
+        assert_eq!(chrome.prompt(0), "lisp> ");
+        assert_eq!(chrome.prompt(2), "lisp[2]> ");
+
+        chrome.set_prompt("λ> ", Some("blue")).unwrap();
+        assert_eq!(chrome.prompt(0), "\x01\x1b[34m\x02λ> \x01\x1b[0m\x02");
+        chrome.set_prompt("$ ", None).unwrap();
+        assert_eq!(chrome.prompt(1), "$[1] ");
+
+        assert_eq!(
+            chrome.set_prompt("> ", Some("mauve")).unwrap_err(),
+            "unknown prompt color \"mauve\", expected one of black, red, green, yellow, blue, magenta, cyan, white"
+        );
+        assert!(chrome.set_prompt("\x1b[31m> ", None).is_err());
+        assert!(chrome.set_prompt("two\nlines> ", None).is_err());
+        // a rejected prompt leaves the last one in place
+        assert_eq!(chrome.prompt(0), "$ ");
+        assert_eq!(chrome.continuation(), "...> ");
+    }
+
+    #[test]
+    fn it_continues_open_forms_on_the_next_lines() {
+        let mut env = init_env();
+        let transcript = Transcript::default();
+        let console = Console::new(
+            Scripted::new(&[
+                "(def [double]",
+                "  (\\ [x]",
+                "     [* x 2]))",
+                "(double 4)",
+                "(concat \"a",
+                "b\")",
+                "(+ 1",
+                "",
+                "(+ 2 3)",
+                "(+ 4",
+            ]),
+            transcript.clone(),
+        );
+
+        run_session(&mut env, &console, 0);
+        assert_eq!(transcript.lines()[..4], ["nil", "8", "\"a\\nb\"", "5"]);
+        // running out of input leaves the form to fail
+        assert!(transcript.lines()[4].starts_with("Error: Syntax - unclosed '('"));
+
+        let history = console.history();
+        let history = history.borrow();
+        assert_eq!(history.len(), 5);
+        assert_eq!(
+            history.get(1),
+            Some("(def [double]\n  (\\ [x]\n     [* x 2]))")
+        );
+        assert_eq!(history.get(4), Some("(+ 2 3)"));
+    }
+
+    #[test]
+    fn it_dresses_sessions_up() {
+        let mut env = init_env();
+        let transcript = Transcript::default();
+        let mut console = Console::new(
+            Scripted::new(&["(def [a] 1)", "(+ a 1)"]),
+            transcript.clone(),
+        );
+        console.chrome.echo_ast = true;
+        console.chrome.hide_unit = true;
+
+        assert_eq!(run(&mut env, &console), SessionEnd::Eof);
+        assert_eq!(
+            transcript.lines(),
+            [
+                banner(),
+                "((def [a] 1))".to_string(),
+                "((+ a 1))".to_string(),
+                "2".to_string()
+            ]
+        );
+        assert!(banner().starts_with(&format!("oolisp {} (", version())));
+    }
+
+    #[test]
+    fn it_cuts_long_results_short() {
+        let mut env = init_env();
+        let transcript = Transcript::default();
+        let mut console = Console::new(
+            Scripted::new(&[":full", "[1 2 3 4 5]", "(head [])", ":full"]),
+            transcript.clone(),
+        );
+        console.chrome.banner = false;
+        console.chrome.output_budget = Budget {
+            max_items: 3,
+            max_chars: 100,
+        };
+
+        run(&mut env, &console);
+        assert_eq!(
+            transcript.lines()[..4],
+            [
+                "nil",
+                "[1 2 3 … (2 more)]",
+                "Error: EmptyList - Empty List passed to function; Function head was given empty list",
+                "  called from head",
+            ]
+        );
+        assert_eq!(transcript.lines()[4], "[1 2 3 4 5]");
+    }
+
+    #[test]
+    fn it_shows_every_byte_in_full() {
+        let long = format!("#bytes[\"{}\"]", "abcd ".repeat(50).trim_end());
+        let transcript = Transcript::default();
+        let mut console = Console::new(Scripted::new(&[&long, ":full"]), transcript.clone());
+        console.chrome.banner = false;
+
+        run(&mut init_env(), &console);
+        assert_eq!(transcript.lines()[1], long);
+    }
+
+    #[test]
+    fn it_prints_the_results_of_values_as_the_call() {
+        let transcript = Transcript::default();
+        let mut console = Console::new(
+            Scripted::new(&["(values 1 2)", "(values [a] \"b\")"]),
+            transcript.clone(),
+        );
+        console.chrome.banner = false;
+
+        run(&mut init_env(), &console);
+        assert_eq!(
+            transcript.lines(),
+            vec!["(values 1 2)", "(values [a] \"b\")"]
+        );
+    }
+
+    #[test]
+    fn it_keeps_history_numbers_when_trimming() {
+        let mut history = History::with_cap(3);
+        for (i, line) in ["(+ 1 1)", "(def [a] 2)", "(* a a)", "(+ a 1)"]
+            .iter()
+            .enumerate()
+        {
+            assert_eq!(history.append(line), i + 1);
+        }
+
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.get(1), None);
+        assert_eq!(history.get(2), Some("(def [a] 2)"));
+        assert_eq!(history.get(4), Some("(+ a 1)"));
+        assert_eq!(history.get(5), None);
+        assert_eq!(history.last(2), vec![(3, "(* a a)"), (4, "(+ a 1)")]);
+        assert_eq!(history.last(10).len(), 3);
+    }
+
+    #[test]
+    fn it_saves_history_between_sessions() {
+        let path = std::env::temp_dir().join(format!("oolisp-history-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut console = Console::new(
+            Scripted::new(&["(def [a] 2)", "(concat \"a\\\\", "b\")", ":abort"]),
+            Transcript::default(),
+        );
+        console.persist_history(&path, 2);
+        assert!(console.history().borrow().is_empty());
+        run_session(&mut init_env(), &console, 0);
+
+        let mut console = Console::new(Scripted::new(&["(+ 1 1)"]), Transcript::default());
+        console.persist_history(&path, 2);
+        assert_eq!(
+            console.history().borrow().last(2),
+            vec![(1, "(def [a] 2)"), (2, "(concat \"a\\\\\nb\")")]
+        );
+        run_session(&mut init_env(), &console, 0);
+        // only the last two are kept
+        let history = History::load(&path, 2);
+        assert_eq!(history.get(1), Some("(concat \"a\\\\\nb\")"));
+        assert_eq!(history.get(2), Some("(+ 1 1)"));
+
+        fs::write(&path, "(+ 1 1)\n\\q\n").unwrap();
+        assert!(History::load(&path, 10).is_empty());
+        fs::write(&path, [0xff, 0xfe]).unwrap();
+        assert!(History::load(&path, 10).is_empty());
+        fs::remove_file(&path).unwrap();
+        assert!(History::load(&path, 10).is_empty());
+    }
+
+    #[test]
+    fn it_searches_history_most_recent_first() {
+        let mut history = History::default();
+        for line in &["(def [a] 1)", "(+ a 1)", "(def [b] a)", "(head [a])"] {
+            history.append(line);
+        }
+
+        assert_eq!(
+            history.search("def"),
+            vec![(3, "(def [b] a)"), (1, "(def [a] 1)")]
+        );
+        assert_eq!(history.search("[a]").len(), 2);
+        assert!(history.search("tail").is_empty());
+    }
+
+    #[test]
+    fn it_lists_and_reruns_history() {
+        let mut env = init_env();
+        let transcript = Transcript::default();
+        let console = Console::new(
+            Scripted::new(&[
+                "(def [a] 20)",
+                "(+ a 1)",
+                ":history",
+                "(def [a] 40)",
+                ":!2",
+                ":!9",
+                ":history 1",
+            ]),
+            transcript.clone(),
+        );
+
+        run_session(&mut env, &console, 1);
+        assert_eq!(
+            transcript.lines()[2..],
+            [
+                "    1  (def [a] 20)",
+                "    2  (+ a 1)",
+                "nil",
+                "lisp[1]> (+ a 1)",
+                "41",
+                "Error: there is no history entry 9",
+                "    4  (+ a 1)",
+            ]
+        );
+        assert_eq!(console.history().borrow().len(), 4);
+    }
+
+    #[test]
+    fn it_completes_the_word_before_the_cursor() {
+        let mut env = init_env();
+        assert_eq!(complete_at(&env, "(hea", 4), (1, vec!["head".to_string()]));
+        assert_eq!(complete_at(&env, "(+ 1 nope)", 9).1, Vec::<String>::new());
+        // only what is before the cursor counts
+        assert!(complete_at(&env, "(head [1])", 3)
+            .1
+            .contains(&"head".to_string()));
+        assert!(complete_at(&env, "(concat \"hea", 12).1.is_empty());
+        assert!(complete_at(&env, "; hea", 5).1.is_empty());
+        assert_eq!(complete_at(&env, "(concat \"a\\\"\" hea", 17).1, ["head"]);
+
+        let transcript = Transcript::default();
+        let console = Console::new(
+            Scripted::new(&[":complete (my", "(def [my-thing] 1)", ":complete (+ my"]),
+            transcript.clone(),
+        );
+        run_session(&mut env, &console, 0);
+        assert_eq!(transcript.lines(), ["no completions", "nil", "my-thing"]);
+    }
+
+    #[test]
+    fn it_tells_where_things_were_defined() {
+        let mut env = init_env();
+        let transcript = Transcript::default();
+        let console = Console::new(
+            Scripted::new(&[
+                "(def [a] 1)",
+                ":where a",
+                "(+ a 1)",
+                "(def [a] 2)",
+                ":where a",
+                ":where head",
+                ":where nope",
+            ]),
+            transcript.clone(),
+        );
+
+        run_session(&mut env, &console, 0);
+        assert_eq!(
+            transcript.lines(),
+            [
+                "nil",
+                "a: repl entry #1",
+                "2",
+                "nil",
+                "a: repl entry #3, previously repl entry #1",
+                "head: builtin",
+                "nope is not defined",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_benches_from_the_repl() {
+        let mut env = init_env();
+        let ticks = Rc::new(Cell::new(0_f64));
+        let clock = ticks.clone();
+        env.set_clock(move || {
+            clock.set(clock.get() + 0.5);
+            clock.get()
+        });
+        let transcript = Transcript::default();
+        let console = Console::new(
+            Scripted::new(&[":bench 4 (* 6 7)", ":bench x (+ 1 1)", ":bench 2 (+ 1"]),
+            transcript.clone(),
+        );
+
+        run_session(&mut env, &console, 0);
+        let lines = transcript.lines();
+        assert_eq!(
+            lines[0],
+            "4 runs: min 0.500 ms, max 0.500 ms, mean 0.500 ms, median 0.500 ms"
+        );
+        assert_eq!(lines[1], "42");
+        assert_eq!(
+            lines[2],
+            "Error: :bench needs a positive count but was given x"
+        );
+        assert!(lines[3].starts_with("Error: Syntax"));
+    }
+
+    #[test]
+    fn it_times_lines_when_asked() {
+        let mut env = init_env();
+        let ticks = Rc::new(Cell::new(0_f64));
+        let clock = ticks.clone();
+        env.set_clock(move || {
+            clock.set(clock.get() + 0.25);
+            clock.get()
+        });
+        let transcript = Transcript::default();
+        let console = Console::new(
+            Scripted::new(&["(+ 1 2)", ":time", "(+ 1 2)", ":time", "(+ 1 2)"]),
+            transcript.clone(),
+        );
+
+        run_session(&mut env, &console, 0);
+        let lines = transcript.lines();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[1], "timing on");
+        assert_eq!(lines[2], "3");
+        assert!(
+            lines[3].starts_with("parse 0.250 ms") && lines[3].contains(", eval 0.250 ms"),
+            "{}",
+            lines[3]
+        );
+        assert_eq!(lines[4..], ["timing off", "3"]);
+    }
+
+    fn diagnostic(input: &str) -> ParseDiagnostic {
+        parse_located(input).unwrap_err()
+    }
+
+    #[test]
+    fn it_recovers_from_parse_errors() {
+        let input = "(+ 1 2_)";
+        assert_eq!(
+            recover(input, &diagnostic(input)),
+            Recovery {
+                buffer: input.to_string(),
+                line: 1,
+                column: 7,
+                caret: "      ^".to_string(),
+            }
+        );
+
+        // an error at the very start, and one past the end
+        let at_start = recover("]", &diagnostic("]"));
+        assert_eq!((at_start.line, at_start.column), (1, 1));
+        let past_end = recover("(+ 1", &ParseDiagnostic::new("(+ 1", 10, ""));
+        assert_eq!((past_end.line, past_end.column), (1, 5));
+
+        // continuation lines count from their own start
+        let input = "(def [x]\n  (+ 1 1__0))";
+        let recovery = recover(input, &diagnostic(input));
+        assert_eq!((recovery.line, recovery.column), (2, 9));
+        assert_eq!(recovery.source_line(input), "  (+ 1 1__0))");
+        assert_eq!(recovery.caret, "        ^");
+
+        // columns count characters, not bytes
+        let input = "(concat \"é\" 1_)";
+        let recovery = recover(input, &diagnostic(input));
+        assert_eq!(recovery.column, 14);
+    }
+
+    #[test]
+    fn it_prefills_only_after_parse_errors() {
+        struct Prefills(Rc<RefCell<Vec<String>>>, Scripted);
+
+        impl LineSource for Prefills {
+            fn read_line(&mut self, prompt: &str) -> Option<String> {
+                self.1.read_line(prompt)
+            }
+
+            fn prefill(&mut self, buffer: &str) {
+                self.0.borrow_mut().push(buffer.to_string());
+            }
+        }
+
+        let prefilled = Rc::new(RefCell::new(vec![]));
+        let transcript = Transcript::default();
+        let input = Prefills(
+            prefilled.clone(),
+            Scripted::new(&["(+ 1 2_)", "(head [])", "(+ 1 2)"]),
+        );
+        let mut console = Console::new(input, transcript.clone());
+        console.color = true;
+
+        run_session(&mut init_env(), &console, 0);
+        assert_eq!(*prefilled.borrow(), vec!["(+ 1 2_)"]);
+        assert_eq!(
+            transcript.lines()[..3],
+            [
+                "Error: Syntax - '_' in a number has to sit between two digits at line 1, column 7",
+                "(+ 1 2_)",
+                "      \x1b[31m^\x1b[0m"
+            ]
+        );
+        assert!(transcript.lines()[3].starts_with("Error: EmptyList"));
+        assert_eq!(transcript.lines()[5], "3");
+    }
+
+    #[test]
+    fn it_edits_lines_with_the_session_at_hand() {
+        // what the editor could complete `squ` to, and how far back it
+        // could step, as each line is read
+        type Seen = Rc<RefCell<Vec<(Vec<String>, usize)>>>;
+        struct Editing(Seen, Scripted);
+
+        impl LineSource for Editing {
+            fn read_line(&mut self, prompt: &str) -> Option<String> {
+                self.1.read_line(prompt)
+            }
+
+            fn edit_line(&mut self, prompt: &str, env: &Lenv, history: &History) -> Option<String> {
+                let (_, names) = complete_at(env, "(squ", 4);
+                self.0.borrow_mut().push((names, history.len()));
+                self.read_line(prompt)
+            }
+        }
+
+        let seen = Rc::new(RefCell::new(vec![]));
+        let input = Editing(
+            seen.clone(),
+            Scripted::new(&["(def [square] (\\ [x] [* x x]))", "(square 3)"]),
+        );
+        let console = Console::new(input, Transcript::default());
+        console.history().borrow_mut().append("(+ 1 2)");
+
+        run_session(&mut init_env(), &console, 0);
+        assert_eq!(
+            *seen.borrow(),
+            vec![
+                (vec![], 1),
+                (vec!["square".to_string()], 2),
+                (vec!["square".to_string()], 3)
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn it_completes_names_in_the_line_editor() {
+        use rustyline::{completion::Completer, history::DefaultHistory, Context};
+
+        let env = &mut init_env();
+        lisp(env, "def [square] (\\ [x] [* x x])");
+        let history = DefaultHistory::new();
+        let ctx = Context::new(&history);
+        assert_eq!(
+            Names(env).complete("(+ 1 (squ", 9, &ctx).unwrap(),
+            (6, vec!["square".to_string()])
+        );
+        assert_eq!(Names(env).complete("\"squ", 4, &ctx).unwrap(), (1, vec![]));
+    }
+
+    #[test]
+    fn it_resumes_with_definitions_made_at_the_break() {
+        let (interpreter, result, transcript) = run_with(
+            &["x", "(def [y] (* x 10))", ":continue"],
+            "(def [x] 4) (repl) (def [z] (+ x y))",
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(transcript.lines(), vec!["4", "nil"]);
+        assert_eq!(interpreter.env().get("z"), Some(Lval::Num(44_f64)));
+    }
+
+    #[test]
+    fn it_breaks_inside_the_current_scope() {
+        let (_, result, transcript) =
+            run_with(&["(+ a 1)", ":continue"], "(def [f] (\\ [a] [repl])) (f 9)");
+
+        assert!(result.is_ok());
+        assert_eq!(transcript.lines(), vec!["10"]);
+    }
+
+    #[test]
+    fn it_cancels_on_abort() {
+        let (interpreter, result, _) = run_with(&[":abort"], "(def [x] 1) (repl) (def [x] 2)");
+
+        assert_eq!(result.unwrap_err().etype, LerrType::Cancelled);
+        assert_eq!(interpreter.env().get("x"), Some(Lval::Num(1_f64)));
+    }
+
+    #[test]
+    fn it_nests_breaks() {
+        struct Prompts(Rc<RefCell<Vec<String>>>, Scripted);
+
+        impl LineSource for Prompts {
+            fn read_line(&mut self, prompt: &str) -> Option<String> {
+                self.0.borrow_mut().push(prompt.to_string());
+                self.1.read_line(prompt)
+            }
+        }
+
+        let seen = Rc::new(RefCell::new(vec![]));
+        let input = Prompts(
+            seen.clone(),
+            Scripted::new(&["(repl)", "(def [inner] 1)", ":continue", ":continue"]),
+        );
+        set_console(Some(Console::new(input, Transcript::default())));
+
+        let mut env = init_env();
+        let result = crate::eval::eval(&mut env, parse("(repl)").unwrap().1);
+        set_console(None);
+
+        assert!(result.is_ok());
+        assert_eq!(env.get("inner"), Some(Lval::Num(1_f64)));
+        assert_eq!(
+            *seen.borrow(),
+            vec!["lisp[1]> ", "lisp[2]> ", "lisp[2]> ", "lisp[1]> "]
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! `Serialize` and `Deserialize` for values and errors, with the `serde`
+//! feature. Values are externally tagged by variant, so `[1 "a"]` is
+//! `{"Qexpr":[{"Int":1},{"Str":"a"}]}`. Builtins can't be written out and
+//! serialize as their variant with a `"<builtin>"` placeholder, which fails
+//! to deserialize rather than coming back as something that can't be
+//! called. Lambdas are their parameters and body, without the frames they
+//! were made in or their contracts, so one read back sees only the globals,
+//! and a partially applied one has lost the arguments it was given. Errors
+//! keep their type, message, span and trace. The results of `values` are
+//! `{"Values":[..]}` rather than the call that makes them.
+use crate::{
+    builtin::{as_values, values},
+    env::Frames,
+    Lerr, LerrType, Llambda, Lval, Span,
+};
+use serde::{
+    de::{self, Deserializer, IgnoredAny},
+    ser::{Serialize, SerializeStruct, Serializer},
+    Deserialize,
+};
+
+const BUILTIN: &str = "<builtin>";
+
+impl Serialize for Lval {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(values) = as_values(self) {
+            return serializer.serialize_newtype_variant("Lval", 14, "Values", values);
+        }
+        match self {
+            Lval::Sym(s) => serializer.serialize_newtype_variant("Lval", 0, "Sym", s),
+            Lval::Num(n) => serializer.serialize_newtype_variant("Lval", 1, "Num", n),
+            Lval::Int(n) => serializer.serialize_newtype_variant("Lval", 2, "Int", n),
+            Lval::Sexpr(items) => serializer.serialize_newtype_variant("Lval", 3, "Sexpr", items),
+            Lval::Qexpr(items) => serializer.serialize_newtype_variant("Lval", 4, "Qexpr", items),
+            Lval::Fun(_) => serializer.serialize_newtype_variant("Lval", 5, "Fun", BUILTIN),
+            Lval::Native(_) => serializer.serialize_newtype_variant("Lval", 6, "Native", BUILTIN),
+            Lval::Lambda(lambda) => {
+                serializer.serialize_newtype_variant("Lval", 7, "Lambda", lambda)
+            }
+            Lval::CaseLambda(clauses) => {
+                serializer.serialize_newtype_variant("Lval", 8, "CaseLambda", clauses)
+            }
+            Lval::Str(s) => serializer.serialize_newtype_variant("Lval", 9, "Str", s),
+            Lval::Bytes(bytes) => serializer.serialize_newtype_variant("Lval", 10, "Bytes", bytes),
+            Lval::Keyword(s) => serializer.serialize_newtype_variant("Lval", 11, "Keyword", s),
+            Lval::Bool(b) => serializer.serialize_newtype_variant("Lval", 12, "Bool", b),
+            Lval::Nil => serializer.serialize_unit_variant("Lval", 13, "Nil"),
+        }
+    }
+}
+
+// what a value is read as, before the builtins are turned away
+#[derive(Deserialize)]
+#[serde(rename = "Lval")]
+enum Tagged {
+    Sym(String),
+    Num(f64),
+    Int(i64),
+    Sexpr(Vec<Lval>),
+    Qexpr(Vec<Lval>),
+    Fun(IgnoredAny),
+    Native(IgnoredAny),
+    Lambda(Llambda),
+    CaseLambda(Vec<Llambda>),
+    Str(String),
+    Bytes(Vec<u8>),
+    Keyword(String),
+    Bool(bool),
+    Nil,
+    Values(Vec<Lval>),
+}
+
+impl<'de> Deserialize<'de> for Lval {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match Tagged::deserialize(deserializer)? {
+            Tagged::Sym(s) => Lval::Sym(s),
+            Tagged::Num(n) => Lval::Num(n),
+            Tagged::Int(n) => Lval::Int(n),
+            Tagged::Sexpr(items) => Lval::Sexpr(items),
+            Tagged::Qexpr(items) => Lval::Qexpr(items),
+            Tagged::Fun(_) | Tagged::Native(_) => {
+                return Err(de::Error::custom(
+                    "a builtin can't be deserialized, only a placeholder for it was written",
+                ))
+            }
+            Tagged::Lambda(lambda) => Lval::Lambda(lambda),
+            Tagged::CaseLambda(clauses) => Lval::CaseLambda(clauses),
+            Tagged::Str(s) => Lval::Str(s),
+            Tagged::Bytes(bytes) => Lval::Bytes(bytes),
+            Tagged::Keyword(s) => Lval::Keyword(s),
+            Tagged::Bool(b) => Lval::Bool(b),
+            Tagged::Nil => Lval::Nil,
+            Tagged::Values(items) => values(items),
+        })
+    }
+}
+
+impl Serialize for Llambda {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut lambda = serializer.serialize_struct("Llambda", 2)?;
+        lambda.serialize_field("args", &self.args)?;
+        lambda.serialize_field("body", &self.body)?;
+        lambda.end()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Llambda")]
+struct LambdaFields {
+    args: Vec<String>,
+    body: Vec<Lval>,
+}
+
+impl<'de> Deserialize<'de> for Llambda {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let LambdaFields { args, body } = LambdaFields::deserialize(deserializer)?;
+        Ok(Llambda::new(args, body, Frames::globals()))
+    }
+}
+
+impl Serialize for Lerr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("Lerr", 5)?;
+        error.serialize_field("etype", &self.etype)?;
+        error.serialize_field("message", &self.message)?;
+        error.serialize_field("span", &self.span)?;
+        error.serialize_field("trace", &self.trace)?;
+        error.serialize_field("untraced", &self.untraced)?;
+        error.end()
+    }
+}
+
+// the details follow from the type, so they aren't written
+#[derive(Deserialize)]
+#[serde(rename = "Lerr")]
+struct ErrorFields {
+    etype: LerrType,
+    message: String,
+    #[serde(default)]
+    span: Option<Span>,
+    #[serde(default)]
+    trace: Vec<String>,
+    #[serde(default)]
+    untraced: usize,
+}
+
+impl<'de> Deserialize<'de> for Lerr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fields = ErrorFields::deserialize(deserializer)?;
+        let mut error = Lerr::new(fields.etype, fields.message);
+        error.span = fields.span;
+        error.trace = fields.trace;
+        error.untraced = fields.untraced;
+        Ok(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::init_env;
+
+    fn round_trip(lval: &Lval) -> Lval {
+        let json = serde_json::to_string(lval).unwrap();
+        serde_json::from_str(&json).unwrap_or_else(|e| panic!("{}: {}", json, e))
+    }
+
+    #[test]
+    fn it_round_trips_values_through_json() {
+        let values = [
+            Lval::Sym("+".to_string()),
+            Lval::Num(1.5),
+            Lval::Int(-7),
+            Lval::Sexpr(vec![Lval::Sym("+".to_string()), Lval::Int(1)]),
+            Lval::Qexpr(vec![Lval::Qexpr(vec![]), Lval::Str("a".to_string())]),
+            Lval::Str("two words".to_string()),
+            Lval::Bytes(vec![0, 255]),
+            Lval::Keyword("key".to_string()),
+            Lval::Bool(false),
+            Lval::Nil,
+        ];
+        for value in &values {
+            let again = round_trip(value);
+            assert_eq!(format!("{:?}", again), format!("{:?}", value));
+        }
+        assert_eq!(
+            serde_json::to_string(&Lval::Qexpr(vec![Lval::Int(1), Lval::Num(1.0)])).unwrap(),
+            r#"{"Qexpr":[{"Int":1},{"Num":1.0}]}"#
+        );
+
+        let body = vec![Lval::Sexpr(vec![
+            Lval::Sym("+".to_string()),
+            Lval::Sym("x".to_string()),
+            Lval::Int(1),
+        ])];
+        let lambda = Llambda::new(vec!["x".to_string()], body.clone(), Frames::globals());
+        match round_trip(&Lval::Lambda(lambda.clone())) {
+            Lval::Lambda(again) => {
+                assert_eq!(again.args(), ["x"]);
+                assert_eq!(again.body(), body.as_slice());
+            }
+            other => panic!("{:?}", other),
+        }
+        match round_trip(&Lval::CaseLambda(vec![lambda.clone(), lambda])) {
+            Lval::CaseLambda(clauses) => assert_eq!(clauses.len(), 2),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_writes_the_results_of_values_on_their_own() {
+        let results = values(vec![Lval::Int(1), Lval::Int(2)]);
+        let json = serde_json::to_string(&results).unwrap();
+        assert_eq!(json, r#"{"Values":[{"Int":1},{"Int":2}]}"#);
+        assert_eq!(as_values(&round_trip(&results)), as_values(&results));
+    }
+
+    #[test]
+    fn it_writes_builtins_as_placeholders_that_dont_read_back() {
+        let env = init_env();
+        let head = env.get("head").unwrap();
+        let json = serde_json::to_string(&head).unwrap();
+        assert_eq!(json, r#"{"Fun":"<builtin>"}"#);
+        assert!(serde_json::from_str::<Lval>(&json).is_err());
+        assert!(serde_json::from_str::<Lval>(r#"{"Native":"<builtin>"}"#).is_err());
+    }
+
+    #[test]
+    fn it_round_trips_errors_through_json() {
+        let mut error = Lerr::new(LerrType::UnboundSymbol, "nope".to_string());
+        error.span = Some(Span { start: 3, end: 7 });
+        error.trace = vec!["leaf".to_string(), "top".to_string()];
+        error.untraced = 2;
+
+        let json = serde_json::to_string(&error).unwrap();
+        assert!(json.contains(r#""etype":"UnboundSymbol""#), "{}", json);
+        let again: Lerr = serde_json::from_str(&json).unwrap();
+        assert!(again == error);
+        assert_eq!(format!("{:#?}", again), format!("{:#?}", error));
+
+        let bare: Lerr = serde_json::from_str(r#"{"etype":"DivZero","message":"m"}"#).unwrap();
+        assert_eq!(bare.etype(), &LerrType::DivZero);
+        assert_eq!(bare.span(), None);
+        assert!(serde_json::from_str::<Lerr>(r#"{"etype":"Nope","message":"m"}"#).is_err());
+    }
+}
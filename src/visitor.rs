@@ -0,0 +1,311 @@
+use crate::{Lfun, Llambda, Lval, NativeFn};
+
+/// Which kind of list a visitor is looking at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListKind {
+    Sexpr,
+    Qexpr,
+}
+
+/// Callbacks for each kind of value `walk` comes across. Every method does
+/// nothing by default, so implementors only pick out what they need and
+/// keep compiling as variants are added.
+pub trait LvalVisitor {
+    fn visit_num(&mut self, _num: f64) {}
+    fn visit_int(&mut self, _int: i64) {}
+    fn visit_sym(&mut self, _sym: &str) {}
+    fn visit_str(&mut self, _s: &str) {}
+    fn visit_bytes(&mut self, _bytes: &[u8]) {}
+    /// `keyword` is the name without the colon.
+    fn visit_keyword(&mut self, _keyword: &str) {}
+    fn visit_bool(&mut self, _b: bool) {}
+    fn visit_nil(&mut self) {}
+    fn visit_fun(&mut self, _fun: Lfun) {}
+    fn visit_native(&mut self, _native: &NativeFn) {}
+    /// Lambda bodies are not walked into. A case-lambda is visited as each
+    /// of its clauses.
+    fn visit_lambda(&mut self, _lambda: &Llambda) {}
+    /// Called before any of the items are visited.
+    fn visit_list(&mut self, _kind: ListKind, _items: &[Lval]) {}
+}
+
+/// Visits `lval` and everything inside it, parents before children and
+/// left to right. Nesting depth is only limited by memory.
+pub fn walk(lval: &Lval, visitor: &mut impl LvalVisitor) {
+    let mut pending = vec![lval];
+    while let Some(lval) = pending.pop() {
+        match lval {
+            Lval::Num(num) => visitor.visit_num(*num),
+            Lval::Int(int) => visitor.visit_int(*int),
+            Lval::Sym(sym) => visitor.visit_sym(sym),
+            Lval::Str(s) => visitor.visit_str(s),
+            Lval::Bytes(bytes) => visitor.visit_bytes(bytes),
+            Lval::Keyword(keyword) => visitor.visit_keyword(keyword),
+            Lval::Bool(b) => visitor.visit_bool(*b),
+            Lval::Nil => visitor.visit_nil(),
+            Lval::Fun(fun) => visitor.visit_fun(*fun),
+            Lval::Native(native) => visitor.visit_native(native),
+            Lval::Lambda(lambda) => visitor.visit_lambda(lambda),
+            Lval::CaseLambda(clauses) => {
+                for lambda in clauses {
+                    visitor.visit_lambda(lambda);
+                }
+            }
+            Lval::Sexpr(items) => {
+                visitor.visit_list(ListKind::Sexpr, items);
+                pending.extend(items.iter().rev());
+            }
+            Lval::Qexpr(items) => {
+                visitor.visit_list(ListKind::Qexpr, items);
+                pending.extend(items.iter().rev());
+            }
+        }
+    }
+}
+
+/// Rebuilds `lval` bottom up: `f` sees every value after its children have
+/// been mapped, finishing with the rebuilt `lval` itself. Lambdas are passed
+/// to `f` whole.
+pub fn map_lval(lval: Lval, f: &mut impl FnMut(Lval) -> Lval) -> Lval {
+    map_with(lval, true, f)
+}
+
+/// Like `map_lval`, except Q-Expressions are passed to `f` whole rather than
+/// rebuilt, for rewrites that mustn't touch quoted code or data.
+pub fn map_unquoted(lval: Lval, f: &mut impl FnMut(Lval) -> Lval) -> Lval {
+    map_with(lval, false, f)
+}
+
+fn map_with(lval: Lval, into_quotes: bool, f: &mut impl FnMut(Lval) -> Lval) -> Lval {
+    let mut frames = vec![];
+    let mut mapped = descend(lval, into_quotes, &mut frames, f);
+
+    loop {
+        if let Some(value) = mapped {
+            match frames.last_mut() {
+                Some(frame) => frame.mapped.push(value),
+                None => return value,
+            }
+        }
+
+        let next = frames.last_mut().and_then(|frame| frame.todo.pop());
+        mapped = match next {
+            Some(item) => descend(item, into_quotes, &mut frames, f),
+            None => frames.pop().map(|frame| f(frame.into_list())),
+        };
+    }
+}
+
+// a list part way through being rebuilt
+struct Frame {
+    kind: ListKind,
+    // reversed, so the next item pops off the end
+    todo: Vec<Lval>,
+    mapped: Vec<Lval>,
+}
+
+impl Frame {
+    fn into_list(self) -> Lval {
+        match self.kind {
+            ListKind::Sexpr => Lval::Sexpr(self.mapped),
+            ListKind::Qexpr => Lval::Qexpr(self.mapped),
+        }
+    }
+}
+
+// maps a leaf straight away, or starts a frame for a list
+fn descend(
+    lval: Lval,
+    into_quotes: bool,
+    frames: &mut Vec<Frame>,
+    f: &mut impl FnMut(Lval) -> Lval,
+) -> Option<Lval> {
+    let (kind, mut todo) = match lval {
+        Lval::Sexpr(items) => (ListKind::Sexpr, items),
+        Lval::Qexpr(items) if into_quotes => (ListKind::Qexpr, items),
+        leaf => return Some(f(leaf)),
+    };
+    todo.reverse();
+    frames.push(Frame {
+        kind,
+        mapped: Vec::with_capacity(todo.len()),
+        todo,
+    });
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sym(s: &str) -> Lval {
+        Lval::Sym(String::from(s))
+    }
+
+    fn num(n: f64) -> Lval {
+        Lval::Num(n)
+    }
+
+    fn int(n: i64) -> Lval {
+        Lval::Int(n)
+    }
+
+    // (def [area] (\\ [r] [* pi (* r r)])) with a string and an empty list
+    fn fixture() -> Lval {
+        Lval::Sexpr(vec![
+            sym("def"),
+            Lval::Qexpr(vec![sym("area")]),
+            Lval::Sexpr(vec![
+                sym("\\"),
+                Lval::Qexpr(vec![sym("r")]),
+                Lval::Qexpr(vec![
+                    sym("*"),
+                    sym("pi"),
+                    Lval::Sexpr(vec![sym("*"), sym("r"), sym("r")]),
+                ]),
+            ]),
+            Lval::Str(String::from("circle")),
+            Lval::Sexpr(vec![]),
+            int(2),
+        ])
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        nums: usize,
+        syms: Vec<String>,
+        strs: usize,
+        lists: Vec<(ListKind, usize)>,
+    }
+
+    impl LvalVisitor for Counter {
+        fn visit_num(&mut self, _num: f64) {
+            self.nums += 1;
+        }
+
+        fn visit_int(&mut self, _int: i64) {
+            self.nums += 1;
+        }
+
+        fn visit_sym(&mut self, sym: &str) {
+            self.syms.push(sym.to_string());
+        }
+
+        fn visit_str(&mut self, _s: &str) {
+            self.strs += 1;
+        }
+
+        fn visit_list(&mut self, kind: ListKind, items: &[Lval]) {
+            self.lists.push((kind, items.len()));
+        }
+    }
+
+    #[test]
+    fn it_walks_in_order() {
+        let mut counter = Counter::default();
+        walk(&fixture(), &mut counter);
+
+        assert_eq!(counter.nums, 1);
+        assert_eq!(counter.strs, 1);
+        assert_eq!(
+            counter.syms,
+            vec!["def", "area", "\\", "r", "*", "pi", "*", "r", "r"]
+        );
+        assert_eq!(
+            counter.lists,
+            vec![
+                (ListKind::Sexpr, 6),
+                (ListKind::Qexpr, 1),
+                (ListKind::Sexpr, 3),
+                (ListKind::Qexpr, 1),
+                (ListKind::Qexpr, 3),
+                (ListKind::Sexpr, 3),
+                (ListKind::Sexpr, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_renames_symbols() {
+        let renamed = map_lval(fixture(), &mut |lval| match lval {
+            Lval::Sym(s) if s == "r" => sym("radius"),
+            other => other,
+        });
+
+        let mut counter = Counter::default();
+        walk(&renamed, &mut counter);
+        assert_eq!(
+            counter.syms,
+            vec!["def", "area", "\\", "radius", "*", "pi", "*", "radius", "radius"]
+        );
+        assert_eq!(
+            format!("{:?}", renamed),
+            "(def [area] (\\ [radius] [* pi (* radius radius)]) \"circle\" () 2)"
+        );
+    }
+
+    #[test]
+    fn it_leaves_quotes_alone() {
+        let mut seen = vec![];
+        let renamed = map_unquoted(fixture(), &mut |lval| {
+            seen.push(format!("{:?}", lval));
+            match lval {
+                Lval::Sym(s) if s == "r" || s == "area" => sym("x"),
+                other => other,
+            }
+        });
+
+        assert_eq!(format!("{:?}", renamed), format!("{:?}", fixture()));
+        assert_eq!(seen[..4], ["def", "[area]", "\\", "[r]"]);
+    }
+
+    #[test]
+    fn it_maps_children_before_parents() {
+        let mut order = vec![];
+        let folded = map_lval(
+            Lval::Sexpr(vec![
+                sym("+"),
+                int(1),
+                Lval::Sexpr(vec![sym("*"), int(2), int(3)]),
+            ]),
+            &mut |lval| {
+                order.push(format!("{:?}", lval));
+                lval
+            },
+        );
+
+        assert_eq!(format!("{:?}", folded), "(+ 1 (* 2 3))");
+        assert_eq!(
+            order,
+            vec!["+", "1", "*", "2", "3", "(* 2 3)", "(+ 1 (* 2 3))"]
+        );
+    }
+
+    #[test]
+    fn it_handles_deep_nesting() {
+        let mut deep = num(1_f64);
+        for _ in 0..100_000 {
+            deep = Lval::Qexpr(vec![deep]);
+        }
+
+        let mut counter = Counter::default();
+        walk(&deep, &mut counter);
+        assert_eq!(counter.lists.len(), 100_000);
+
+        let mut deep = map_lval(deep, &mut |lval| match lval {
+            Lval::Num(n) => num(n + 1_f64),
+            other => other,
+        });
+
+        // take it apart by hand so dropping it doesn't recurse either
+        let mut depth = 0;
+        loop {
+            deep = match deep {
+                Lval::Qexpr(mut items) => items.pop().unwrap(),
+                leaf => break assert_eq!(leaf, num(2_f64)),
+            };
+            depth += 1;
+        }
+        assert_eq!(depth, 100_000);
+    }
+}
@@ -0,0 +1,304 @@
+use crate::interpreter::{ErrorPolicy, Interpreter};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Reports when a file was last modified.
+pub trait Probe {
+    /// `None` if the file is missing or can't be inspected.
+    fn modified(&self, path: &Path) -> Option<SystemTime>;
+}
+
+/// Asks the filesystem.
+pub struct FsProbe;
+
+impl Probe for FsProbe {
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|meta| meta.modified()).ok()
+    }
+}
+
+/// The passage of time, so polling can be driven without sleeping.
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+    fn sleep(&self, duration: Duration);
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// Re-evaluates a script in a fresh environment whenever it, any file it
+/// loaded the last time it ran, or any other watched file, changes on disk.
+pub struct Watch<P: Probe> {
+    script: PathBuf,
+    // every watched path with the modification time it was last run at
+    watched: Vec<(PathBuf, Option<SystemTime>)>,
+    probe: P,
+    pub interval: Duration,
+}
+
+impl Watch<FsProbe> {
+    pub fn new(script: impl Into<PathBuf>) -> Self {
+        Watch::with_probe(script, FsProbe)
+    }
+}
+
+impl<P: Probe> Watch<P> {
+    pub fn with_probe(script: impl Into<PathBuf>, probe: P) -> Self {
+        let script = script.into();
+        Watch {
+            watched: vec![(script.clone(), None)],
+            script,
+            probe,
+            interval: Duration::from_millis(250),
+        }
+    }
+
+    /// Also reruns the script when `path` changes, e.g. a file it loads.
+    pub fn watch_path(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        if !self.watched.iter().any(|(watched, _)| *watched == path) {
+            self.watched.push((path, None));
+        }
+    }
+
+    /// Whether anything changed since the last run, recording the new
+    /// modification times if so.
+    fn take_changes(&mut self) -> bool {
+        let mut changed = false;
+        for (path, seen) in self.watched.iter_mut() {
+            let modified = self.probe.modified(path);
+            if modified != *seen {
+                *seen = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Evaluates the script from scratch and renders the outcome, watching
+    /// whatever files it loads from then on.
+    pub fn run_once(&mut self) -> String {
+        let source = match fs::read_to_string(&self.script) {
+            Ok(source) => source,
+            Err(e) => return format!("Error: could not read {}; {}", self.script.display(), e),
+        };
+
+        let mut interpreter = Interpreter::new();
+        let report = interpreter.eval_program(&source, ErrorPolicy::StopAtFirst);
+        // as they are now, so a file isn't counted as changed for being new
+        for path in interpreter.env().loaded_files() {
+            if !self.watched.iter().any(|(watched, _)| watched == path) {
+                let modified = self.probe.modified(path);
+                self.watched.push((path.clone(), modified));
+            }
+        }
+        match (report.failures.first(), report.last) {
+            (Some(failure), _) => format!("{:?}", failure.error),
+            (None, Some(last)) => format!("{:?}", last),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Runs the script if it changed since the last poll, returning the
+    /// output under a divider stamped with the time of the run.
+    pub fn poll(&mut self, clock: &dyn Clock) -> Option<String> {
+        if !self.take_changes() {
+            return None;
+        }
+        Some(format!("{}\n{}", divider(clock.now()), self.run_once()))
+    }
+
+    /// Polls every `interval` until `stop` is set, e.g. by a Ctrl-C handler.
+    /// Errors in the script are printed like any other result.
+    pub fn run(&mut self, clock: &dyn Clock, out: &mut dyn Write, stop: &AtomicBool) {
+        while !stop.load(Ordering::SeqCst) {
+            if let Some(output) = self.poll(clock) {
+                if writeln!(out, "{}", output).is_err() {
+                    return;
+                }
+            }
+            clock.sleep(self.interval);
+        }
+    }
+}
+
+// `--- 14:03:07 ---` in UTC
+fn divider(at: SystemTime) -> String {
+    let secs = at
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    format!(
+        "--- {:02}:{:02}:{:02} ---",
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, collections::HashMap, env};
+
+    // modification times the test sets by hand
+    #[derive(Default)]
+    struct FakeFs(RefCell<HashMap<PathBuf, SystemTime>>);
+
+    impl FakeFs {
+        fn touch(&self, path: &Path, secs: u64) {
+            self.0
+                .borrow_mut()
+                .insert(path.to_path_buf(), UNIX_EPOCH + Duration::from_secs(secs));
+        }
+    }
+
+    impl Probe for &FakeFs {
+        fn modified(&self, path: &Path) -> Option<SystemTime> {
+            self.0.borrow().get(path).cloned()
+        }
+    }
+
+    // moves time forward on each sleep, stopping the loop at `stop_after`
+    struct FakeClock<'a> {
+        now: RefCell<u64>,
+        stop_after: u64,
+        stop: &'a AtomicBool,
+        on_sleep: &'a dyn Fn(u64),
+    }
+
+    impl Clock for FakeClock<'_> {
+        fn now(&self) -> SystemTime {
+            UNIX_EPOCH + Duration::from_secs(*self.now.borrow())
+        }
+
+        fn sleep(&self, duration: Duration) {
+            *self.now.borrow_mut() += duration.as_secs();
+            let now = *self.now.borrow();
+            (self.on_sleep)(now);
+            if now >= self.stop_after {
+                self.stop.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn script(name: &str, source: &str) -> PathBuf {
+        let path =
+            env::temp_dir().join(format!("oolisp-watch-{}-{}.lsp", name, std::process::id()));
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    #[test]
+    fn it_reruns_on_change() {
+        let path = script("rerun", "(def [a] 2) (* a 21)");
+        let fake = FakeFs::default();
+        fake.touch(&path, 1);
+        let mut watch = Watch::with_probe(&path, &fake);
+        let clock = SystemClock;
+
+        assert_eq!(
+            watch
+                .poll(&clock)
+                .map(|out| out.lines().last().unwrap().to_string()),
+            Some("42".to_string())
+        );
+        assert_eq!(watch.poll(&clock), None);
+
+        // a broken edit is reported, and fixing it recovers
+        fs::write(&path, "(head [])").unwrap();
+        fake.touch(&path, 2);
+        assert!(watch.poll(&clock).unwrap().contains("Error: EmptyList"));
+        fs::write(&path, "(+ 1 2)").unwrap();
+        fake.touch(&path, 3);
+        assert!(watch.poll(&clock).unwrap().ends_with("\n3"));
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn it_watches_extra_paths() {
+        let path = script("extra", "(+ 1 1)");
+        let library = path.with_extension("lib");
+        let fake = FakeFs::default();
+        fake.touch(&path, 1);
+        let mut watch = Watch::with_probe(&path, &fake);
+        watch.watch_path(&library);
+        watch.watch_path(&library);
+        let clock = SystemClock;
+
+        assert!(watch.poll(&clock).is_some());
+        fake.touch(&library, 5);
+        assert!(watch.poll(&clock).is_some());
+        assert_eq!(watch.poll(&clock), None);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn it_watches_what_the_script_loads() {
+        let library = script("loaded-lib", "(def [x] 1)");
+        let path = script("loader", &format!("(load {:?}) (+ x 1)", library));
+        let fake = FakeFs::default();
+        fake.touch(&path, 1);
+        fake.touch(&library, 1);
+        let mut watch = Watch::with_probe(&path, &fake);
+        let clock = SystemClock;
+
+        assert!(watch.poll(&clock).unwrap().ends_with("\n2"));
+        assert_eq!(watch.poll(&clock), None);
+        fs::write(&library, "(def [x] 41)").unwrap();
+        fake.touch(&library, 2);
+        assert!(watch.poll(&clock).unwrap().ends_with("\n42"));
+        assert_eq!(watch.poll(&clock), None);
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(library);
+    }
+
+    #[test]
+    fn it_polls_until_stopped() {
+        let path = script("loop", "(+ 1 1)");
+        let fake = FakeFs::default();
+        fake.touch(&path, 0);
+        let stop = AtomicBool::new(false);
+        // starts at 01:01:01, the script is saved during the first sleep
+        let clock = FakeClock {
+            now: RefCell::new(3661),
+            stop_after: 3665,
+            stop: &stop,
+            on_sleep: &|now| {
+                if now == 3662 {
+                    fake.touch(&path, now)
+                }
+            },
+        };
+        let mut out = vec![];
+
+        let mut watch = Watch::with_probe(&path, &fake);
+        watch.interval = Duration::from_secs(1);
+        watch.run(&clock, &mut out, &stop);
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "--- 01:01:01 ---\n2\n--- 01:01:02 ---\n2\n"
+        );
+
+        let _ = fs::remove_file(path);
+    }
+}